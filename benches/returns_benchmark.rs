@@ -1,5 +1,5 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use finsim::returns::{self, GenReturnsArgs, AccumulateArgs};
+use finsim::returns::{self, GenReturnsArgs, AccumulateArgs, RngKind, TimeBase};
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let gen_returns_args = GenReturnsArgs {
@@ -9,20 +9,51 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         yearly_mean: 1.0,
         yearly_stddev: 1.5,
         seed: None,
+        rng: RngKind::Std,
+        time_base: TimeBase::WallClock,
+        tail_df: None,
     };
     c.bench_function(
         "gen_returns 100000 data points",
-        |b| b.iter(|| returns::gen_returns(black_box(&gen_returns_args)).collect::<Vec<f64>>()),
+        |b| b.iter(|| returns::gen_returns(black_box(&gen_returns_args)).unwrap().collect::<Vec<f64>>()),
     );
 
+    // Multi-million-point runs, where drawing the returns dominates
+    let long_args = GenReturnsArgs { num_points: 5000000, seed: Some(1), ..gen_returns_args.clone() };
+    let mut group = c.benchmark_group("long series");
+    group.sample_size(10);
+    group.bench_function(
+        "gen_returns 5000000 data points",
+        |b| b.iter(|| returns::gen_returns(black_box(&long_args)).unwrap().collect::<Vec<f64>>()),
+    );
+    group.bench_function(
+        "gen_returns 5000000 data points as f32",
+        |b| b.iter(|| returns::gen_returns_as::<f32>(black_box(&long_args)).unwrap().collect::<Vec<f32>>()),
+    );
+    group.bench_function(
+        "gen_log_returns 5000000 data points",
+        |b| b.iter(|| returns::gen_log_returns(black_box(&long_args)).unwrap().collect::<Vec<f64>>()),
+    );
+    for (name, rng) in [("xoshiro256++", RngKind::Xoshiro256PlusPlus), ("pcg", RngKind::Pcg)] {
+        let args = GenReturnsArgs { rng, ..long_args.clone() };
+        group.bench_function(
+            format!("gen_returns 5000000 data points with {}", name),
+            |b| b.iter(|| returns::gen_returns(black_box(&args)).unwrap().collect::<Vec<f64>>()),
+        );
+    }
+    group.finish();
+
     let accumulate_args = AccumulateArgs {
         accumulate: true,
         start_value: 100.0,
         continuous_leverage: None,
         pointwise_leverage: None,
         initial_leverage: None,
+        log_values: false,
+        decimal_places: None,
+        financing: None,
     };
-    let ret_series = returns::gen_returns(black_box(&gen_returns_args)).collect::<Vec<f64>>();
+    let ret_series = returns::gen_returns(black_box(&gen_returns_args)).unwrap().collect::<Vec<f64>>();
     c.bench_function(
         "accumulate 100000 data points",
         |b| b.iter(|| returns::accumulate(black_box(ret_series.clone().into_iter()), &accumulate_args)),
@@ -0,0 +1,133 @@
+//! Python bindings, built with maturin (see `pyproject.toml`). Series are
+//! returned as numpy arrays.
+
+use finsim::model::{LogNormal, monte_carlo as mc};
+use finsim::output::Summary;
+use finsim::returns::{AccumulateArgs, GenReturnsArgs, RngKind, TimeBase, accumulate as acc, gen_returns as gen};
+use numpy::{IntoPyArray, PyArray1, PyReadonlyArray1};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+fn to_py_err(e: finsim::Error) -> PyErr {
+    match e {
+        finsim::Error::Io(e) => PyIOError::new_err(e.to_string()),
+        e => PyValueError::new_err(e.to_string()),
+    }
+}
+
+fn acc_args(
+    start_value: f64,
+    continuous_leverage: Option<f64>,
+    pointwise_leverage: Option<f64>,
+    initial_leverage: Option<f64>,
+) -> PyResult<AccumulateArgs> {
+    let args = AccumulateArgs {
+        accumulate: true,
+        start_value,
+        continuous_leverage,
+        pointwise_leverage,
+        initial_leverage,
+        log_values: false,
+        decimal_places: None,
+        financing: None,
+    };
+    args.validate().map_err(to_py_err)?;
+    Ok(args)
+}
+
+/// Generates `num_points` log-normal returns, `interval_seconds` apart or
+/// spread over `total_seconds`
+#[pyfunction]
+#[pyo3(signature = (num_points, interval_seconds=None, total_seconds=None, yearly_mean=1.0, yearly_stddev=1.5, seed=None))]
+fn gen_returns<'py>(
+    py: Python<'py>,
+    num_points: usize,
+    interval_seconds: Option<usize>,
+    total_seconds: Option<usize>,
+    yearly_mean: f64,
+    yearly_stddev: f64,
+    seed: Option<u64>,
+) -> PyResult<Bound<'py, PyArray1<f64>>> {
+    if interval_seconds.is_some() == total_seconds.is_some() {
+        return Err(PyValueError::new_err("give exactly one of interval_seconds and total_seconds"));
+    }
+    let args = GenReturnsArgs {
+        total_seconds,
+        interval_seconds,
+        num_points,
+        yearly_mean,
+        yearly_stddev,
+        seed,
+        rng: RngKind::Std,
+        time_base: TimeBase::WallClock,
+        tail_df: None,
+    };
+    let returns: Vec<f64> = gen(&args).map_err(to_py_err)?.collect();
+    Ok(returns.into_pyarray(py))
+}
+
+/// Accumulates returns into values starting from `start_value`, with at
+/// most one of the leverages
+#[pyfunction]
+#[pyo3(signature = (returns, start_value=1.0, continuous_leverage=None, pointwise_leverage=None, initial_leverage=None))]
+fn accumulate<'py>(
+    py: Python<'py>,
+    returns: PyReadonlyArray1<'py, f64>,
+    start_value: f64,
+    continuous_leverage: Option<f64>,
+    pointwise_leverage: Option<f64>,
+    initial_leverage: Option<f64>,
+) -> PyResult<Bound<'py, PyArray1<f64>>> {
+    let args = acc_args(start_value, continuous_leverage, pointwise_leverage, initial_leverage)?;
+    let values = acc(returns.as_array().iter().copied(), &args);
+    Ok(values.into_pyarray(py))
+}
+
+/// Simulates `paths` accumulated paths, with the seed of each path counting
+/// up from `seed`. Returns a dict of arrays with one element per path:
+/// `count`, `min`, `max`, `mean` and `last`, e.g. for `pandas.DataFrame`.
+#[pyfunction]
+#[pyo3(signature = (
+    paths, num_points, interval_seconds, yearly_mean=1.0, yearly_stddev=1.5, seed=None,
+    start_value=1.0, continuous_leverage=None, pointwise_leverage=None, initial_leverage=None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn monte_carlo<'py>(
+    py: Python<'py>,
+    paths: usize,
+    num_points: usize,
+    interval_seconds: f64,
+    yearly_mean: f64,
+    yearly_stddev: f64,
+    seed: Option<u64>,
+    start_value: f64,
+    continuous_leverage: Option<f64>,
+    pointwise_leverage: Option<f64>,
+    initial_leverage: Option<f64>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let args = acc_args(start_value, continuous_leverage, pointwise_leverage, initial_leverage)?;
+    let model = LogNormal { yearly_mean, yearly_stddev, rng: RngKind::Std, time_base: TimeBase::WallClock, tail_df: None };
+    let summaries = py
+        .detach(|| mc(&model, interval_seconds, num_points, &args, None, paths, seed).collect::<finsim::Result<Vec<Summary>>>())
+        .map_err(to_py_err)?;
+
+    let dict = PyDict::new(py);
+    dict.set_item("count", summaries.iter().map(|s| s.count).collect::<Vec<usize>>().into_pyarray(py))?;
+    type Column = (&'static str, fn(&Summary) -> f64);
+    let columns: [Column; 4] = [("min", |s| s.min), ("max", |s| s.max), ("mean", |s| s.mean), ("last", |s| s.last)];
+    for (name, column) in columns {
+        dict.set_item(name, summaries.iter().map(column).collect::<Vec<f64>>().into_pyarray(py))?;
+    }
+    Ok(dict)
+}
+
+/// Simulation of (leveraged) returns
+#[pymodule]
+#[pyo3(name = "finsim")]
+fn finsim_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(gen_returns, m)?)?;
+    m.add_function(wrap_pyfunction!(accumulate, m)?)?;
+    m.add_function(wrap_pyfunction!(monte_carlo, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,46 @@
+/* C interface of finsim, built with `cargo build --release --features ffi`
+ * (libfinsim.so / finsim.dll / libfinsim.dylib). See src/ffi.rs. */
+
+#ifndef FINSIM_H
+#define FINSIM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define FINSIM_LEVERAGE_NONE 0
+#define FINSIM_LEVERAGE_CONTINUOUS 1
+#define FINSIM_LEVERAGE_POINTWISE 2
+#define FINSIM_LEVERAGE_INITIAL 3
+
+typedef struct {
+    double yearly_mean;        /* e.g. 1.07 for +7% */
+    double yearly_stddev;      /* e.g. 1.15 for 15% */
+    uint64_t interval_seconds; /* time between data points */
+    double start_value;        /* value at t=0 of accumulated paths */
+    int leverage_kind;         /* one of FINSIM_LEVERAGE_* */
+    double leverage;
+} FinsimParams;
+
+/* The functions return 0 on success and -1 on error. */
+
+/* Writes num_points returns to out. */
+int finsim_gen_returns(const FinsimParams *params, uint64_t seed, size_t num_points, double *out);
+
+/* Writes paths accumulated paths of num_points values each to out (paths *
+ * num_points values), one after the other. Path i uses the seed derived from
+ * seed and i like in finsim mc, output i + 1 of SplitMix64 seeded with seed. */
+int finsim_gen_paths(const FinsimParams *params, uint64_t seed, size_t paths, size_t num_points, double *out);
+
+/* The message of the last error on this thread, or NULL. Valid until the
+ * next call on this thread. */
+const char *finsim_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
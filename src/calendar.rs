@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::input::parse_date;
+
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// Business days, Monday to Friday except holidays, for timestamping daily
+/// series like a market calendar instead of every `interval_seconds`
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct BusinessCalendar {
+    /// Days since 1970-01-01, sorted
+    holidays: Vec<i64>,
+}
+
+impl BusinessCalendar {
+    /// A calendar skipping the days of the unix times in `holidays` on top of
+    /// weekends
+    pub fn new(holidays: impl IntoIterator<Item = i64>) -> BusinessCalendar {
+        let mut holidays: Vec<i64> = holidays.into_iter().map(|t| t.div_euclid(SECONDS_PER_DAY)).collect();
+        holidays.sort_unstable();
+        holidays.dedup();
+        BusinessCalendar { holidays }
+    }
+
+    /// Parses one `YYYY-MM-DD` holiday per line. Blank lines and lines
+    /// starting with `#` are skipped.
+    pub fn parse(s: &str) -> Result<BusinessCalendar> {
+        let holidays = s
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .map(|l| parse_date(l).ok_or_else(|| Error::Parse(format!("invalid date '{}', expected YYYY-MM-DD", l))))
+            .collect::<Result<Vec<i64>>>()?;
+        Ok(BusinessCalendar::new(holidays))
+    }
+
+    /// Whether the day of the unix time `t` is a business day
+    pub fn is_business_day(&self, t: i64) -> bool {
+        let day = t.div_euclid(SECONDS_PER_DAY);
+        // 1970-01-01 was a Thursday, 0 is Sunday
+        let weekday = (day + 4).rem_euclid(7);
+        (1..=5).contains(&weekday) && self.holidays.binary_search(&day).is_err()
+    }
+
+    /// The unix times of the business days after the one of `start`, at the
+    /// same time of day: the timestamps of points 1, 2, ... of a daily series
+    /// starting at `start`
+    pub fn timestamps(&self, start: i64) -> impl Iterator<Item = i64> + '_ {
+        (1..).map(move |day| start + day * SECONDS_PER_DAY).filter(|&t| self.is_business_day(t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BusinessCalendar;
+
+    #[test]
+    fn business_days_skip_weekends_and_holidays() {
+        // Thursday 2023-12-21, with Christmas on the following Monday
+        let start = 1703116800 + 3600;
+        let calendar = BusinessCalendar::parse("# Christmas\n2023-12-25\n\n2023-12-26\n").unwrap();
+        let days: Vec<i64> = calendar.timestamps(start).take(3).map(|t| (t - start) / 86400).collect();
+        assert_eq!(vec![1, 6, 7], days);
+        assert!(calendar.timestamps(start).take(3).all(|t| t % 86400 == 3600));
+
+        let weekdays: Vec<i64> = BusinessCalendar::default().timestamps(start).take(3).map(|t| (t - start) / 86400).collect();
+        assert_eq!(vec![1, 4, 5], weekdays);
+        assert!(BusinessCalendar::parse("2023-13-01").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+
+use crate::calendar::BusinessCalendar;
+use crate::returns::TimeBase;
+use crate::stats::Moments;
+
+/// Parameters for `gen_returns` estimated from a price series
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Calibration {
+    pub yearly_mean: f64,
+    pub yearly_stddev: f64,
+    pub interval_seconds: f64,
+    /// The time base of `interval_seconds` and the annualization
+    #[serde(default)]
+    pub time_base: TimeBase,
+    pub num_returns: usize,
+    /// Sample skewness of the per-tick log returns
+    pub skewness: f64,
+    /// Sample excess kurtosis of the per-tick log returns
+    pub excess_kurtosis: f64,
+}
+
+impl Calibration {
+    /// The estimated parameters as `finsim` flags
+    pub fn to_flags(&self) -> String {
+        let flags = format!(
+            "--interval-seconds {} --yearly-mean {} --yearly-stddev {}",
+            self.interval_seconds.round().max(1.0),
+            self.yearly_mean,
+            self.yearly_stddev,
+        );
+        match self.time_base.to_flags() {
+            time_base if time_base.is_empty() => flags,
+            time_base => format!("{} {}", flags, time_base),
+        }
+    }
+}
+
+/// Estimates the yearly geometric mean and standard deviation of a price
+/// series sampled every `interval_seconds`, i.e. the inverse of `gen_returns`.
+/// The log returns are annualized the same way `gen_returns` de-annualizes,
+/// so the interval is in `time_base` time between prices (e.g. about 1.45
+/// days of wall-clock time or one session of trading time for daily bars of
+/// a market trading 252 days a year).
+pub fn calibrate(prices: &[f64], interval_seconds: f64, time_base: TimeBase) -> Calibration {
+    let moments: Moments = prices.windows(2).map(|w| (w[1] / w[0]).ln()).collect();
+
+    let ticks_per_year = time_base.ticks_per_year(interval_seconds.into());
+    Calibration {
+        yearly_mean: (moments.mean() * ticks_per_year).exp(),
+        yearly_stddev: (moments.variance() * ticks_per_year).sqrt().exp(),
+        interval_seconds,
+        time_base,
+        num_returns: moments.count(),
+        skewness: moments.skewness(),
+        excess_kurtosis: moments.excess_kurtosis(),
+    }
+}
+
+/// Average time in seconds of `time_base` between consecutive timestamps.
+/// Trading time counts a session for every weekday, so daily bars are a
+/// session apart whether or not a weekend lies between them.
+pub fn mean_interval_seconds(timestamps: &[i64], time_base: TimeBase) -> Option<f64> {
+    let elapsed = |first: i64, last: i64| match time_base {
+        TimeBase::WallClock | TimeBase::Days365 => (last - first) as f64,
+        TimeBase::TradingDays { hours_per_day, .. } => {
+            let weekdays = BusinessCalendar::default().timestamps(first).take_while(|&t| t <= last).count();
+            weekdays as f64 * hours_per_day * 3600.0
+        }
+    };
+    match (timestamps.first(), timestamps.last()) {
+        (Some(&first), Some(&last)) if timestamps.len() > 1 && last > first => {
+            Some(elapsed(first, last) / (timestamps.len() - 1) as f64)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use crate::returns::{GenReturnsArgs, RngKind, SECONDS_PER_YEAR, TimeBase, gen_returns};
+
+    #[test]
+    fn calibrate_known_series() {
+        // Log returns of +ln(2) and -ln(2) over one year each
+        let res = super::calibrate(&[1.0, 2.0, 1.0], SECONDS_PER_YEAR, TimeBase::WallClock);
+        assert_approx_eq!(1.0, res.yearly_mean);
+        assert_approx_eq!((2.0f64.ln() * 2.0f64.sqrt()).exp(), res.yearly_stddev);
+        assert_eq!(2, res.num_returns);
+        assert_approx_eq!(0.0, res.skewness);
+    }
+
+    #[test]
+    fn calibrate_recovers_gen_returns_parameters() {
+        let args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(86400),
+            num_points: 200_000,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(123456789),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let mut price = 1.0;
+        let prices: Vec<f64> = std::iter::once(1.0)
+            .chain(gen_returns(&args).unwrap().map(|r| {
+                price *= r;
+                price
+            }))
+            .collect();
+        let res = super::calibrate(&prices, 86400.0, TimeBase::WallClock);
+        assert_approx_eq!(1.1, res.yearly_mean, 0.05);
+        assert_approx_eq!(1.5, res.yearly_stddev, 0.01);
+        assert_approx_eq!(0.0, res.skewness, 0.05);
+        assert_approx_eq!(0.0, res.excess_kurtosis, 0.05);
+        assert!(res.to_flags().starts_with("--interval-seconds 86400 --yearly-mean "));
+        assert!(!res.to_flags().contains("--time-base"));
+
+        // The same prices as daily bars of a market open 252 days a year
+        let trading_days = TimeBase::TradingDays { days_per_year: 252.0, hours_per_day: 6.5 };
+        let res = super::calibrate(&prices, 23400.0, trading_days);
+        assert_approx_eq!(1.1f64.ln() * 252.0 / 365.2425, res.yearly_mean.ln(), 0.05);
+        assert!(res.to_flags().ends_with(" --time-base trading-days --trading-days-per-year 252 --trading-hours-per-day 6.5"));
+    }
+
+    #[test]
+    fn mean_interval_seconds_test() {
+        assert_eq!(Some(43200.0), super::mean_interval_seconds(&[0, 86400, 86400], TimeBase::WallClock));
+        assert_eq!(None, super::mean_interval_seconds(&[0], TimeBase::WallClock));
+        assert_eq!(None, super::mean_interval_seconds(&[], TimeBase::WallClock));
+
+        // Thursday 2024-01-04 to Friday and over the weekend to Monday
+        let bars = [1704326400, 1704412800, 1704672000];
+        let trading_days = TimeBase::TradingDays { days_per_year: 252.0, hours_per_day: 6.5 };
+        assert_eq!(Some(23400.0), super::mean_interval_seconds(&bars, trading_days));
+        assert_eq!(Some(2.0 * 86400.0), super::mean_interval_seconds(&bars, TimeBase::Days365));
+    }
+}
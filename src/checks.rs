@@ -0,0 +1,194 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::output::Summary;
+use crate::stats::percent;
+
+/// Exit code when more paths than --max-ruin are ruined
+pub const EXIT_RUIN: i32 = 3;
+/// Exit code when fewer paths than --min-target reach the target
+pub const EXIT_TARGET: i32 = 4;
+
+/// Thresholds on the simulated paths, reported on stderr and through the exit
+/// code
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CheckArgs {
+    /// A path is ruined once its accumulated value falls to or below this
+    pub ruin_level: Option<f64>,
+
+    /// Fail if more than this percentage of the paths are ruined
+    pub max_ruin: Option<f64>,
+
+    /// A path reaches the target if its final accumulated value is at least this
+    pub target: Option<f64>,
+
+    /// Fail if fewer than this percentage of the paths reach the target
+    pub min_target: Option<f64>,
+}
+
+/// A bound on the share of paths in percent
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Bound {
+    AtMost(f64),
+    AtLeast(f64),
+}
+
+/// The share of paths meeting a condition, compared to its threshold
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub hits: usize,
+    pub paths: usize,
+    pub threshold: Option<Bound>,
+    /// The exit code if the threshold isn't met
+    pub code: i32,
+}
+
+impl CheckArgs {
+    pub fn validate(&self) -> Result<()> {
+        let levels = [("--ruin-level", self.ruin_level), ("--target", self.target)];
+        for (flag, value) in levels {
+            if let Some(v) = value.filter(|v| !v.is_finite()) {
+                return Err(Error::Invalid(format!("{} must be a finite number, got {}", flag, v)));
+            }
+        }
+        let percents = [("--max-ruin", self.max_ruin), ("--min-target", self.min_target)];
+        for (flag, value) in percents {
+            if let Some(p) = value.filter(|p| !(0.0..=100.0).contains(p)) {
+                return Err(Error::Invalid(format!("{} must be between 0% and 100%, got {}%", flag, p)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the checks given by the flags on the summaries of the
+    /// accumulated paths, or of the logs of their values with `log_values`
+    pub fn run(&self, paths: &[Summary], log_values: bool) -> Vec<Check> {
+        let count = |hit: &dyn Fn(&Summary) -> bool| paths.iter().filter(|s| hit(s)).count();
+        // The levels are values either way, and the log is monotonic
+        let scale = |level: f64| if log_values { level.ln() } else { level };
+        let mut checks = Vec::new();
+        if let Some(level) = self.ruin_level.map(scale) {
+            checks.push(Check {
+                name: "ruin",
+                hits: count(&|s| s.min <= level),
+                paths: paths.len(),
+                threshold: self.max_ruin.map(Bound::AtMost),
+                code: EXIT_RUIN,
+            });
+        }
+        if let Some(target) = self.target.map(scale) {
+            checks.push(Check {
+                name: "target",
+                hits: count(&|s| s.last >= target),
+                paths: paths.len(),
+                threshold: self.min_target.map(Bound::AtLeast),
+                code: EXIT_TARGET,
+            });
+        }
+        checks
+    }
+}
+
+impl Check {
+    /// The share of paths meeting the condition in percent, 0 without paths
+    pub fn percent(&self) -> f64 {
+        percent(self.hits, self.paths)
+    }
+
+    pub fn passed(&self) -> bool {
+        match self.threshold {
+            None => true,
+            Some(Bound::AtMost(max)) => self.percent() <= max,
+            Some(Bound::AtLeast(min)) => self.percent() >= min,
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} of {} paths ({}%)", self.name, self.hits, self.paths, self.percent())?;
+        match self.threshold {
+            None => Ok(()),
+            Some(Bound::AtMost(max)) => write!(f, ", at most {}% allowed", max),
+            Some(Bound::AtLeast(min)) => write!(f, ", at least {}% required", min),
+        }
+    }
+}
+
+/// The exit code of the first failed check, 0 if all passed
+pub fn exit_code(checks: &[Check]) -> i32 {
+    checks.iter().find(|c| !c.passed()).map_or(0, |c| c.code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckArgs, EXIT_RUIN, EXIT_TARGET, exit_code};
+    use crate::output::Summary;
+
+    #[test]
+    fn run_checks() {
+        let paths: Vec<Summary> =
+            [vec![1.0, 0.4, 0.8], vec![1.0, 1.2, 1.5], vec![1.0, 0.9, 1.1], vec![0.5, 2.0, 2.5]]
+                .iter()
+                .map(|p| Summary::of(p))
+                .collect();
+        let mut args = CheckArgs {
+            ruin_level: Some(0.5),
+            max_ruin: Some(50.0),
+            target: Some(1.5),
+            min_target: None,
+        };
+        let checks = args.run(&paths, false);
+        assert_eq!(2, checks[0].hits);
+        assert_eq!(50.0, checks[0].percent());
+        assert_eq!("ruin: 2 of 4 paths (50%), at most 50% allowed", checks[0].to_string());
+        assert_eq!("target: 2 of 4 paths (50%)", checks[1].to_string());
+        assert_eq!(0, exit_code(&checks));
+
+        args.max_ruin = Some(25.0);
+        assert_eq!(EXIT_RUIN, exit_code(&args.run(&paths, false)));
+        args.max_ruin = None;
+        args.min_target = Some(75.0);
+        let checks = args.run(&paths, false);
+        assert_eq!("target: 2 of 4 paths (50%), at least 75% required", checks[1].to_string());
+        assert_eq!(EXIT_TARGET, exit_code(&checks));
+        assert_eq!(0, exit_code(&CheckArgs::default().run(&paths, false)));
+    }
+
+    #[test]
+    fn run_checks_on_log_values() {
+        let values = [vec![1.0, 0.4, 0.8], vec![1.0, 1.2, 1.5], vec![1.0, 0.9, 1.1], vec![0.5, 2.0, 2.5]];
+        let summaries = |f: fn(f64) -> f64| -> Vec<Summary> {
+            values.iter().map(|p| Summary::of(&p.iter().map(|&v| f(v)).collect::<Vec<f64>>())).collect()
+        };
+        let args = CheckArgs {
+            ruin_level: Some(0.9),
+            max_ruin: None,
+            target: Some(1.1),
+            min_target: None,
+        };
+        let plain = args.run(&summaries(|v| v), false);
+        let logs = args.run(&summaries(f64::ln), true);
+        assert_eq!(plain, logs);
+        assert_eq!((3, 3), (logs[0].hits, logs[1].hits));
+    }
+
+    #[test]
+    fn validate_checks() {
+        let args = CheckArgs {
+            max_ruin: Some(101.0),
+            ..CheckArgs::default()
+        };
+        assert!(args.validate().is_err());
+        let args = CheckArgs {
+            target: Some(f64::NAN),
+            ..CheckArgs::default()
+        };
+        assert!(args.validate().is_err());
+        assert!(CheckArgs::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,1077 @@
+use std::path::PathBuf;
+
+use clap::{Args, Command, ValueEnum};
+
+use crate::calendar::BusinessCalendar;
+use crate::checks::CheckArgs;
+use crate::config::env_var_name;
+use crate::dca::DcaArgs;
+#[cfg(feature = "fetch")]
+use crate::fetch::FetchArgs;
+use crate::futures::FuturesArgs;
+use crate::goal::GoalArgs;
+use crate::inflation::InflationArgs;
+use crate::input::SeriesKind;
+use crate::leverage::LeverageCompareArgs;
+use crate::mortgage::MortgageArgs;
+use crate::output::{OutputArgs, OutputFormat, OutputTarget, parse_tag};
+use crate::pricing::{Barrier, BarrierKind, OptionArgs, OptionKind, OptionStyle};
+use crate::retire::{AnnuityArgs, RetireArgs, SalaryArgs};
+use crate::returns::{AccumulateArgs, Financing, GenReturnsArgs, RngKind, SECONDS_PER_YEAR, TimeBase, geometric_mean, parse_count, parse_duration, parse_percent, parse_whole_seconds};
+use crate::selftest::SelftestArgs;
+use crate::tax::HarvestArgs;
+use crate::tail::TailArgs;
+
+#[derive(Args)]
+pub struct GenReturnsFlags {
+    /// Simulation time in seconds (from first data point to last), or with a unit like 30y.
+    /// Incomatiable with interval_seconds
+    #[arg(
+        short,
+        long,
+        visible_alias = "total",
+        value_parser = parse_whole_seconds,
+        conflicts_with("interval_seconds"),
+        required_unless_present("interval_seconds")
+    )]
+    pub total_seconds: Option<usize>,
+
+    /// Time between data points in seconds, or with a unit like 1d or 30m. Incomatiable with
+    /// --total-seconds
+    #[arg(
+        short,
+        long,
+        visible_alias = "interval",
+        value_parser = parse_whole_seconds,
+        conflicts_with("total_seconds"),
+        required_unless_present("total_seconds")
+    )]
+    pub interval_seconds: Option<usize>,
+
+    /// How many data points to generate (equally spaced in time), e.g. 1_000_000
+    #[arg(short, long, value_parser = parse_count)]
+    pub num_points: usize,
+
+    /// The yearly (geometric) mean return
+    #[arg(long, default_value_t = 1.0)]
+    pub yearly_mean: f64,
+
+    /// The yearly standard deviation (geometric)
+    #[arg(long, default_value_t = 1.5)]
+    pub yearly_stddev: f64,
+
+    /// How to read --yearly-mean and --yearly-return: as the geometric mean, the growth of the
+    /// median path, or as the arithmetic mean, the expected yearly return, which is higher by about
+    /// half the variance. Sources quoting a "7% average annual return" usually mean the latter
+    #[arg(long, value_enum, default_value_t = MeanTypeArg::Geometric)]
+    pub mean_type: MeanTypeArg,
+
+    /// The yearly mean return in percent, e.g. 7% for a --yearly-mean of 1.07
+    #[arg(long, value_parser = parse_percent, overrides_with("yearly_mean"), allow_hyphen_values(true))]
+    pub yearly_return: Option<f64>,
+
+    /// The yearly standard deviation in percent, e.g. 15% for a --yearly-stddev of 1.15
+    #[arg(long, value_parser = parse_percent, overrides_with("yearly_stddev"))]
+    pub yearly_vol: Option<f64>,
+
+    /// The seed to use for random number generation (for reproducible results)
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// The random number generator. The same seed gives a different series with each
+    /// generator, so pass the same --rng to reproduce a run
+    #[arg(long, value_enum, default_value_t = RngArg::Std)]
+    pub rng: RngArg,
+
+    /// Draw with the generator and normal sampling built into finsim, which give the same
+    /// series for a seed on every platform and with every version of its dependencies
+    #[arg(long, conflicts_with = "rng")]
+    pub portable_rng: bool,
+
+    #[command(flatten)]
+    pub time_base: TimeBaseFlags,
+
+    /// Draw the log returns from Student's t with this many degrees of freedom (above 2) instead
+    /// of the normal distribution, with the same stddev but heavier tails, e.g. 3 for the crashes
+    /// and spikes of crypto at short intervals. inf for normal returns
+    #[arg(long, value_name = "DF")]
+    pub tail_df: Option<f64>,
+}
+
+impl From<GenReturnsFlags> for GenReturnsArgs {
+    /// Resolves --yearly-return and --yearly-vol into the mean and stddev
+    /// factors, and an arithmetic mean into the geometric one
+    fn from(flags: GenReturnsFlags) -> GenReturnsArgs {
+        let yearly_mean = flags.yearly_return.map_or(flags.yearly_mean, |r| 1.0 + r / 100.0);
+        let yearly_stddev = flags.yearly_vol.map_or(flags.yearly_stddev, |v| 1.0 + v / 100.0);
+        GenReturnsArgs {
+            total_seconds: flags.total_seconds,
+            interval_seconds: flags.interval_seconds,
+            num_points: flags.num_points,
+            yearly_mean: match flags.mean_type {
+                MeanTypeArg::Geometric => yearly_mean,
+                MeanTypeArg::Arithmetic => geometric_mean(yearly_mean, yearly_stddev),
+            },
+            yearly_stddev,
+            seed: flags.seed,
+            rng: if flags.portable_rng { RngKind::Portable } else { flags.rng.into() },
+            time_base: flags.time_base.into(),
+            tail_df: flags.tail_df,
+        }
+    }
+}
+
+/// The generators of `RngKind`, for the help of --rng
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum RngArg {
+    /// ChaCha12 like Rust's StdRng. Needed for --save-state and --resume-state
+    Std,
+    /// xoshiro256++, faster
+    #[value(name = "xoshiro256++")]
+    Xoshiro256PlusPlus,
+    /// PCG XSL 128/64 (MCG), faster
+    Pcg,
+}
+
+impl From<RngArg> for RngKind {
+    fn from(rng: RngArg) -> RngKind {
+        match rng {
+            RngArg::Std => RngKind::Std,
+            RngArg::Xoshiro256PlusPlus => RngKind::Xoshiro256PlusPlus,
+            RngArg::Pcg => RngKind::Pcg,
+        }
+    }
+}
+
+/// How --yearly-mean is meant, see `returns::geometric_mean`
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum MeanTypeArg {
+    /// The yearly growth of the median path, as finsim uses it
+    Geometric,
+    /// The expected yearly return
+    Arithmetic,
+}
+
+#[derive(Args)]
+pub struct TimeBaseFlags {
+    /// The annualization convention: what a year of the yearly mean and stddev is made of
+    #[arg(long, value_enum, default_value_t = TimeBaseArg::WallClock)]
+    pub time_base: TimeBaseArg,
+
+    /// Trading days per year with --time-base trading-days
+    #[arg(long, default_value_t = 252.0)]
+    pub trading_days_per_year: f64,
+
+    /// Trading hours per day with --time-base trading-days
+    #[arg(long, default_value_t = 6.5)]
+    pub trading_hours_per_day: f64,
+}
+
+impl From<TimeBaseFlags> for TimeBase {
+    fn from(flags: TimeBaseFlags) -> TimeBase {
+        match flags.time_base {
+            TimeBaseArg::WallClock => TimeBase::WallClock,
+            TimeBaseArg::Days365 => TimeBase::Days365,
+            TimeBaseArg::TradingDays => TimeBase::TradingDays {
+                days_per_year: flags.trading_days_per_year,
+                hours_per_day: flags.trading_hours_per_day,
+            },
+        }
+    }
+}
+
+/// The kinds of `TimeBase`, for the help of --time-base
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum TimeBaseArg {
+    /// Years of 365.2425 days around the clock
+    WallClock,
+    /// Years of 365 days around the clock (ACT/365)
+    #[value(name = "days-365")]
+    Days365,
+    /// Years of --trading-days-per-year sessions of --trading-hours-per-day hours. Durations like
+    /// --interval count trading time, so daily bars are one session (e.g. 6.5h) apart
+    TradingDays,
+}
+
+#[derive(Args)]
+pub struct AccumulateFlags {
+    /// Whether to accumulate returns
+    #[arg(short, long, default_value_t = false)]
+    pub accumulate: bool,
+
+    /// The value to begin accumulating from at t=0
+    #[arg(long, default_value_t = 1.0)]
+    pub start_value: f64,
+
+    /// Leverage to be held constant over the entire series (releverages continuously between points).
+    /// Negative for inverse exposure, as for all leverages
+    #[arg(long, conflicts_with_all(["pointwise_leverage", "initial_leverage"]), allow_hyphen_values(true))]
+    pub continuous_leverage: Option<f64>,
+
+    /// Leverage to be held constant over the entire series (releverages discretely at every point)
+    #[arg(long, conflicts_with_all(["continuous_leverage", "initial_leverage"]), allow_hyphen_values(true))]
+    pub pointwise_leverage: Option<f64>,
+
+    /// Leverage at t=0, never releveraged. Negative leverage shorts the asset, holding the proceeds as
+    /// cash, and can lose more than the start value
+    #[arg(long, conflicts_with_all(["continuous_leverage", "pointwise_leverage"]), allow_hyphen_values(true))]
+    pub initial_leverage: Option<f64>,
+
+    /// Output the natural log of every value. Accumulated values are then tracked in log space,
+    /// so centuries-long or highly leveraged series neither overflow to inf nor collapse to 0
+    #[arg(long)]
+    pub log_values: bool,
+
+    /// Accumulate in exact decimals with this many places, e.g. 2 for an account balance in
+    /// cents, rounding half to even at every tick instead of drifting in binary floating point
+    #[arg(long, requires = "accumulate", conflicts_with_all(["continuous_leverage", "log_values"]))]
+    pub decimal_places: Option<u32>,
+
+    /// Yearly interest of a leverage in percent, e.g. 6%, paid on what it borrows and earned on its
+    /// cash, including the proceeds of a short as the short rebate
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, allow_hyphen_values(true))]
+    pub margin_rate: Option<f64>,
+
+    /// Yearly fee for borrowing the shares of a short, with a negative leverage, in percent of
+    /// their value
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    pub borrow_fee: Option<f64>,
+}
+
+impl AccumulateFlags {
+    /// The accumulation of the flags, on a series with `ticks_per_year`
+    /// points a year
+    pub fn into_args(self, ticks_per_year: f64) -> AccumulateArgs {
+        let financing = (self.margin_rate.is_some() || self.borrow_fee.is_some()).then(|| Financing {
+            yearly_margin_rate: 1.0 + self.margin_rate.unwrap_or(0.0) / 100.0,
+            yearly_borrow_fee: 1.0 + self.borrow_fee.unwrap_or(0.0) / 100.0,
+            ticks_per_year,
+        });
+        AccumulateArgs {
+            accumulate: self.accumulate,
+            start_value: self.start_value,
+            continuous_leverage: self.continuous_leverage,
+            pointwise_leverage: self.pointwise_leverage,
+            initial_leverage: self.initial_leverage,
+            log_values: self.log_values,
+            decimal_places: self.decimal_places,
+            financing,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct OutputFlags {
+    /// Where to write the results: `-` for stdout, `sqlite://<file>` for an SQLite database,
+    /// `xlsx://<file>` for an Excel workbook, `bin://<file>` for fixed-width binary records,
+    /// `ws://<host>:<port>` for a WebSocket server that waits for a client and sends it the lines
+    /// or `kafka://<broker>[,<broker>...]/<topic>[?key=<key>&partition=<n>]` for a Kafka topic
+    #[arg(short, long, default_value = "-")]
+    pub output: OutputTarget,
+
+    /// Format of the text written to stdout
+    #[arg(short, long, value_enum, default_value_t = OutputFormatArg::Plain)]
+    pub format: OutputFormatArg,
+
+    /// Measurement name used by the influx format
+    #[arg(long, default_value = "finsim")]
+    pub measurement: String,
+
+    /// Tag (key=value) added to every influx line, e.g. --tag scenario=base. Can be repeated
+    #[arg(long = "tag", value_parser = parse_tag)]
+    pub tags: Vec<(String, String)>,
+
+    /// Unix time in seconds of t=0 for the influx format (defaults to now)
+    #[arg(long)]
+    pub start_timestamp: Option<i64>,
+
+    /// Timestamp the influx points on consecutive business days (Monday to Friday) after
+    /// --start-timestamp, one point per day, like the bars of a market
+    #[arg(long)]
+    pub business_days: bool,
+
+    /// Holidays skipped by --business-days, a file with one YYYY-MM-DD date per line
+    #[arg(long, requires("business_days"))]
+    pub holidays: Option<PathBuf>,
+
+    /// Flush stdout after every line, so consumers reading from a pipe get data promptly
+    #[arg(long, conflicts_with("buffer_size"))]
+    pub unbuffered: bool,
+
+    /// Size in bytes of the stdout write buffer
+    #[arg(long, default_value_t = 64 * 1024)]
+    pub buffer_size: usize,
+
+    /// Output the per-tick return alongside each accumulated value
+    #[arg(long)]
+    pub with_returns: bool,
+
+    /// Generate, accumulate and write this many points at a time, so that memory use stays
+    /// bounded also for SQLite output, which otherwise collects the whole series first
+    #[arg(long, value_parser = parse_count)]
+    pub chunk_size: Option<usize>,
+
+    /// Write the data points in real time, one interval of the series apart, or SPEEDUP times
+    /// faster, e.g. --realtime 60 for a simulated minute per second. Like a live market-data
+    /// feed, on stdout or a WebSocket
+    #[arg(long, value_name = "SPEEDUP", num_args = 0..=1, default_missing_value = "1", conflicts_with("chunk_size"))]
+    pub realtime: Option<f64>,
+}
+
+impl From<OutputFlags> for OutputArgs {
+    fn from(flags: OutputFlags) -> OutputArgs {
+        OutputArgs {
+            output: flags.output,
+            format: flags.format.into(),
+            measurement: flags.measurement,
+            tags: flags.tags,
+            start_timestamp: flags.start_timestamp,
+            calendar: flags.business_days.then(BusinessCalendar::default),
+            unbuffered: flags.unbuffered,
+            buffer_size: flags.buffer_size,
+            with_returns: flags.with_returns,
+            with_inflation: false,
+            chunk_size: flags.chunk_size,
+            realtime: flags.realtime,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormatArg {
+    /// One value per line
+    Plain,
+    /// InfluxDB line protocol with nanosecond timestamps
+    Influx,
+    /// Tab-separated times and values under a header with the metadata of the series, which acc
+    /// and stats read back, for chaining commands with pipes
+    Stream,
+}
+
+impl From<OutputFormatArg> for OutputFormat {
+    fn from(format: OutputFormatArg) -> OutputFormat {
+        match format {
+            OutputFormatArg::Plain => OutputFormat::Plain,
+            OutputFormatArg::Influx => OutputFormat::Influx,
+            OutputFormatArg::Stream => OutputFormat::Stream,
+        }
+    }
+}
+
+/// Whether a series holds prices or returns
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum SeriesKindArg {
+    /// Guess from the data
+    Auto,
+    /// Gross returns, e.g. 1.01 for +1%
+    Returns,
+    /// Price levels
+    Prices,
+}
+
+impl From<SeriesKindArg> for SeriesKind {
+    fn from(kind: SeriesKindArg) -> SeriesKind {
+        match kind {
+            SeriesKindArg::Auto => SeriesKind::Auto,
+            SeriesKindArg::Returns => SeriesKind::Returns,
+            SeriesKindArg::Prices => SeriesKind::Prices,
+        }
+    }
+}
+
+/// The presets of `Preset`, for the help of --preset
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum PresetArg {
+    /// US stock market: mean 1.08, stddev 1.17
+    UsEquities,
+    /// World stock market: mean 1.07, stddev 1.16
+    GlobalEquities,
+    /// Investment grade bonds: mean 1.035, stddev 1.06
+    GlobalBonds,
+    /// Gold: mean 1.05, stddev 1.16
+    Gold,
+    /// Bitcoin: mean 1.3, stddev 1.8
+    Bitcoin,
+    /// Ether: mean 1.3, stddev 2.0, Student's t tails with 4 degrees of freedom, wall-clock years
+    Ethereum,
+    /// Smaller cryptocurrencies: mean 1.1, stddev 3.0, Student's t tails with 3 degrees of freedom,
+    /// wall-clock years
+    Altcoins,
+}
+
+#[derive(Args)]
+pub struct CalibrateFlags {
+    /// Column holding the prices, by header name or 0-based index
+    #[arg(long, default_value = "Close")]
+    pub price_column: String,
+
+    /// Column holding the dates (YYYY-MM-DD), by header name or 0-based index
+    #[arg(long, default_value = "Date")]
+    pub date_column: String,
+
+    /// Time between prices in seconds of the time base, or with a unit like 1d. Derived from the
+    /// dates if omitted
+    #[arg(long, visible_alias = "interval", value_parser = parse_duration)]
+    pub interval_seconds: Option<f64>,
+
+    #[command(flatten)]
+    pub time_base: TimeBaseFlags,
+
+    /// Also estimate skewness and excess kurtosis of the log returns
+    #[arg(long, default_value_t = false)]
+    pub moments: bool,
+}
+
+#[derive(Args)]
+pub struct CheckFlags {
+    /// A path is ruined once its accumulated value falls to or below VALUE. Needs --accumulate
+    #[arg(long, value_name = "VALUE", allow_hyphen_values(true), requires = "accumulate")]
+    pub ruin_level: Option<f64>,
+
+    /// Exit with code 3 if more than PERCENT of the paths are ruined
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, requires = "ruin_level")]
+    pub max_ruin: Option<f64>,
+
+    /// A path reaches the target if its final accumulated value is at least VALUE. Needs
+    /// --accumulate
+    #[arg(long, value_name = "VALUE", allow_hyphen_values(true), requires = "accumulate")]
+    pub target: Option<f64>,
+
+    /// Exit with code 4 if fewer than PERCENT of the paths reach --target
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, requires = "target")]
+    pub min_target: Option<f64>,
+}
+
+impl From<CheckFlags> for CheckArgs {
+    fn from(flags: CheckFlags) -> CheckArgs {
+        CheckArgs {
+            ruin_level: flags.ruin_level,
+            max_ruin: flags.max_ruin,
+            target: flags.target,
+            min_target: flags.min_target,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct MortgageFlags {
+    /// Outstanding balance of the loan at t=0
+    #[arg(long)]
+    pub loan_balance: f64,
+
+    /// Fixed yearly interest rate of the loan in percent, e.g. 4.5%
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    pub loan_rate: f64,
+
+    /// Time until the regular payments, one per interval, repay the loan, in seconds or with a
+    /// unit like 30y
+    #[arg(long, value_parser = parse_duration)]
+    pub loan_term: f64,
+
+    /// Cash paid every interval on top of the regular payment, towards the loan or into the
+    /// simulated asset
+    #[arg(long)]
+    pub extra_payment: f64,
+}
+
+impl MortgageFlags {
+    /// The loan of the flags, paid every `interval_seconds`
+    pub fn into_args(self, interval_seconds: f64) -> MortgageArgs {
+        MortgageArgs {
+            balance: self.loan_balance,
+            yearly_rate: 1.0 + self.loan_rate / 100.0,
+            term_ticks: (self.loan_term / interval_seconds).round() as usize,
+            extra_payment: self.extra_payment,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct FuturesFlags {
+    /// Time between rolls into the next contract, in seconds or with a unit like 1mo
+    #[arg(long, value_parser = parse_duration, default_value = "1mo")]
+    pub roll_every: f64,
+
+    /// Return of every roll in percent: negative for the drag of contango, e.g. -3%, positive for
+    /// the yield of backwardation
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, allow_hyphen_values(true))]
+    pub roll_yield: f64,
+}
+
+impl FuturesFlags {
+    /// The rolls of the flags, on a series with a point every `interval_seconds`
+    pub fn into_args(self, interval_seconds: f64) -> FuturesArgs {
+        FuturesArgs {
+            roll_ticks: (self.roll_every / interval_seconds).round() as usize,
+            roll_yield: 1.0 + self.roll_yield / 100.0,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct InflationFlags {
+    /// Simulate inflation alongside the series, with a yearly rate in percent that reverts to this
+    /// one, e.g. 2%
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, allow_hyphen_values(true))]
+    pub inflation: Option<f64>,
+
+    /// Yearly inflation at t=0 in percent, --inflation by default
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, allow_hyphen_values(true), requires("inflation"))]
+    pub inflation_start: Option<f64>,
+
+    /// Share of a deviation of the inflation rate from --inflation that is left after a year
+    #[arg(long, default_value_t = 0.8)]
+    pub inflation_persistence: f64,
+
+    /// Long-run standard deviation of the yearly inflation rate in percentage points
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "1%")]
+    pub inflation_vol: f64,
+
+    /// Output real values, in the money of t=0, instead of nominal ones: accumulated values
+    /// divided by the price level and returns by its change
+    #[arg(long, requires("inflation"))]
+    pub real: bool,
+}
+
+impl InflationFlags {
+    /// The inflation process of the flags, `None` without --inflation
+    pub fn args(&self) -> Option<InflationArgs> {
+        let target = 1.0 + self.inflation? / 100.0;
+        Some(InflationArgs {
+            target,
+            start: self.inflation_start.map_or(target, |start| 1.0 + start / 100.0),
+            persistence: self.inflation_persistence,
+            volatility: self.inflation_vol / 100.0,
+        })
+    }
+}
+
+#[derive(Args)]
+pub struct LeverageCompareFlags {
+    /// Leverage of both the ETF and the margin position, negative for an inverse ETF and a short
+    #[arg(long, default_value_t = 2.0, allow_hyphen_values(true))]
+    pub leverage: f64,
+
+    /// The value of the underlying and both positions at t=0
+    #[arg(short, long, default_value_t = 1.0)]
+    pub start_value: f64,
+
+    /// Yearly fee of the leveraged ETF in percent, e.g. 0.95%
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "0%")]
+    pub fee: f64,
+
+    /// Yearly interest on the margin loan in percent, e.g. 6%. With --leverage below 1 it's earned on
+    /// the cash instead, including the proceeds of a short
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "0%", allow_hyphen_values(true))]
+    pub margin_rate: f64,
+
+    /// Yearly fee for borrowing the shares of a short, with a negative --leverage, in percent of
+    /// their value
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "0%")]
+    pub borrow_fee: f64,
+}
+
+impl From<LeverageCompareFlags> for LeverageCompareArgs {
+    fn from(flags: LeverageCompareFlags) -> LeverageCompareArgs {
+        LeverageCompareArgs {
+            leverage: flags.leverage,
+            start_value: flags.start_value,
+            yearly_fee: 1.0 + flags.fee / 100.0,
+            yearly_margin_rate: 1.0 + flags.margin_rate / 100.0,
+            yearly_borrow_fee: 1.0 + flags.borrow_fee / 100.0,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct DcaFlags {
+    /// The sum to invest
+    #[arg(long, default_value_t = 1.0)]
+    pub amount: f64,
+
+    /// Number of intervals to spread the sum over, investing an equal part at the start of each
+    #[arg(long, value_parser = parse_count)]
+    pub periods: usize,
+
+    /// Yearly return in percent of the cash waiting to be invested
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "0%", allow_hyphen_values(true))]
+    pub cash_rate: f64,
+}
+
+impl From<DcaFlags> for DcaArgs {
+    fn from(flags: DcaFlags) -> DcaArgs {
+        DcaArgs { amount: flags.amount, periods: flags.periods, yearly_cash_rate: 1.0 + flags.cash_rate / 100.0 }
+    }
+}
+
+#[derive(Args)]
+pub struct HarvestFlags {
+    /// Tax on realized gains in percent, also saved on harvested losses
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "30%")]
+    pub tax_rate: f64,
+
+    /// Smallest loss harvested at the end of a year, in percent of the cost basis
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "5%")]
+    pub harvest_threshold: f64,
+}
+
+impl From<HarvestFlags> for HarvestArgs {
+    fn from(flags: HarvestFlags) -> HarvestArgs {
+        HarvestArgs { tax_rate: flags.tax_rate / 100.0, threshold: flags.harvest_threshold / 100.0 }
+    }
+}
+
+#[derive(Args)]
+pub struct TailFlags {
+    /// Share of the ticks with the largest losses to fit, in percent
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "5%")]
+    pub tail: f64,
+
+    /// Years between the losses to estimate, e.g. 100 for the tick loss exceeded once in 100 years
+    #[arg(long, value_name = "YEARS", value_delimiter = ',', default_value = "1,10,100")]
+    pub return_periods: Vec<f64>,
+}
+
+impl From<TailFlags> for TailArgs {
+    fn from(flags: TailFlags) -> TailArgs {
+        TailArgs { tail: flags.tail / 100.0, return_periods: flags.return_periods }
+    }
+}
+
+#[derive(Args)]
+pub struct RetireFlags {
+    /// Savings today
+    #[arg(long, default_value_t = 0.0)]
+    pub balance: f64,
+
+    /// Saved every year until retirement, in today's money
+    #[arg(long, default_value_t = 0.0)]
+    pub contribution: f64,
+
+    /// Salary in the first year, in today's money, of which --savings-rate is saved every year
+    /// until retirement instead of a fixed --contribution
+    #[arg(long, conflicts_with("contribution"))]
+    pub salary: Option<f64>,
+
+    /// Share of the salary saved in percent
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "15%")]
+    pub savings_rate: f64,
+
+    /// Yearly raise of the salary above inflation in percent
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "1%", allow_hyphen_values(true))]
+    pub salary_growth: f64,
+
+    /// Yearly standard deviation of the raise in percentage points, 0 for a fixed raise
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "0%")]
+    pub salary_vol: f64,
+
+    /// Withdrawn every year in retirement, in today's money
+    #[arg(long)]
+    pub withdrawal: f64,
+
+    /// Time until retirement, in seconds or with a unit like 25y
+    #[arg(long, value_parser = parse_duration)]
+    pub retire_in: f64,
+
+    /// Time the savings have to last from retirement, in seconds or with a unit like 30y
+    #[arg(long, value_parser = parse_duration, default_value = "30y")]
+    pub retirement_length: f64,
+
+    /// Yearly inflation in percent, which the contributions and withdrawals grow with. With
+    /// --inflation-vol the rate it reverts to
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "2%", allow_hyphen_values(true))]
+    pub inflation: f64,
+
+    /// Share of a deviation of the inflation rate from --inflation that is left after a year
+    #[arg(long, default_value_t = 0.8)]
+    pub inflation_persistence: f64,
+
+    /// Long-run standard deviation of the yearly inflation rate in percentage points, 0 for a
+    /// constant rate
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "0%")]
+    pub inflation_vol: f64,
+
+    /// The yearly (geometric) mean return of the savings in percent
+    #[arg(long, value_parser = parse_percent, default_value = "7%", allow_hyphen_values(true))]
+    pub yearly_return: f64,
+
+    /// The yearly standard deviation of the savings in percent
+    #[arg(long, value_parser = parse_percent, default_value = "15%")]
+    pub yearly_vol: f64,
+
+    /// Share of the savings at retirement in percent that buys an annuity, whose fixed payout
+    /// covers part of the withdrawals
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    pub annuitize: Option<f64>,
+
+    /// Yearly payout of the annuity in percent of its price
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "5%", requires("annuitize"))]
+    pub annuity_rate: f64,
+
+    /// Time between contributions and withdrawals, in seconds or with a unit like 1mo
+    #[arg(short, long, visible_alias = "interval", value_parser = parse_whole_seconds, default_value = "1mo")]
+    pub interval_seconds: usize,
+
+    /// The seed to use for random number generation (for reproducible results)
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+impl RetireFlags {
+    /// The savings' returns, one point per interval over both phases
+    pub fn gen_args(&self) -> GenReturnsArgs {
+        GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(self.interval_seconds),
+            num_points: self.ticks(self.retire_in) + self.ticks(self.retirement_length),
+            yearly_mean: 1.0 + self.yearly_return / 100.0,
+            yearly_stddev: 1.0 + self.yearly_vol / 100.0,
+            seed: self.seed,
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        }
+    }
+
+    /// The plan of the flags, with the yearly amounts spread over the intervals
+    pub fn into_args(self) -> RetireArgs {
+        let years_per_tick = self.interval_seconds as f64 / SECONDS_PER_YEAR;
+        RetireArgs {
+            balance: self.balance,
+            contribution: self.contribution * years_per_tick,
+            salary: self.salary.map(|salary| SalaryArgs {
+                start: salary * years_per_tick,
+                savings_rate: self.savings_rate / 100.0,
+                yearly_growth: 1.0 + self.salary_growth / 100.0,
+                yearly_vol: self.salary_vol / 100.0,
+            }),
+            withdrawal: self.withdrawal * years_per_tick,
+            accumulation_ticks: self.ticks(self.retire_in),
+            retirement_ticks: self.ticks(self.retirement_length),
+            inflation: InflationArgs {
+                persistence: self.inflation_persistence,
+                volatility: self.inflation_vol / 100.0,
+                ..InflationArgs::constant(1.0 + self.inflation / 100.0)
+            },
+            annuity: self.annuitize.map(|share| AnnuityArgs {
+                share: share / 100.0,
+                payout_rate: self.annuity_rate / 100.0 * years_per_tick,
+            }),
+        }
+    }
+
+    fn ticks(&self, seconds: f64) -> usize {
+        match self.interval_seconds {
+            0 => 0,
+            interval => (seconds / interval as f64).round() as usize,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct GoalFlags {
+    /// Savings to have at --horizon, in today's money
+    #[arg(long)]
+    pub target: f64,
+
+    /// Time to reach the target in, in seconds or with a unit like 10y
+    #[arg(long, value_parser = parse_duration)]
+    pub horizon: f64,
+
+    /// Savings today
+    #[arg(long, default_value_t = 0.0)]
+    pub balance: f64,
+
+    /// Saved every year, in today's money
+    #[arg(long, default_value_t = 0.0, conflicts_with("probability"))]
+    pub contribution: f64,
+
+    /// Solve for the yearly contribution that reaches the target on this share of the paths in
+    /// percent, e.g. 90%, instead of reporting how often a --contribution does
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    pub probability: Option<f64>,
+
+    /// Yearly inflation in percent, which the contributions grow with
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "2%", allow_hyphen_values(true))]
+    pub inflation: f64,
+
+    /// The yearly (geometric) mean return of the savings in percent
+    #[arg(long, value_parser = parse_percent, default_value = "7%", allow_hyphen_values(true))]
+    pub yearly_return: f64,
+
+    /// The yearly standard deviation of the savings in percent
+    #[arg(long, value_parser = parse_percent, default_value = "15%")]
+    pub yearly_vol: f64,
+
+    /// Time between contributions, in seconds or with a unit like 1mo
+    #[arg(short, long, visible_alias = "interval", value_parser = parse_whole_seconds, default_value = "1mo")]
+    pub interval_seconds: usize,
+
+    /// The seed to use for random number generation (for reproducible results)
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+impl GoalFlags {
+    /// The savings' returns, one point per interval until the horizon
+    pub fn gen_args(&self) -> GenReturnsArgs {
+        GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(self.interval_seconds),
+            num_points: self.ticks(),
+            yearly_mean: 1.0 + self.yearly_return / 100.0,
+            yearly_stddev: 1.0 + self.yearly_vol / 100.0,
+            seed: self.seed,
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        }
+    }
+
+    /// Years per interval, to turn yearly contributions into those of an interval and back
+    pub fn years_per_tick(&self) -> f64 {
+        self.interval_seconds as f64 / SECONDS_PER_YEAR
+    }
+
+    /// The goal of the flags, saving until the horizon with no retirement phase
+    pub fn into_args(self) -> GoalArgs {
+        let plan = RetireArgs {
+            balance: self.balance,
+            contribution: self.contribution * self.years_per_tick(),
+            salary: None,
+            withdrawal: 0.0,
+            accumulation_ticks: self.ticks(),
+            retirement_ticks: 0,
+            inflation: InflationArgs::constant(1.0 + self.inflation / 100.0),
+            annuity: None,
+        };
+        GoalArgs { plan, target: self.target }
+    }
+
+    fn ticks(&self) -> usize {
+        match self.interval_seconds {
+            0 => 0,
+            interval => (self.horizon / interval as f64).round() as usize,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct OptionFlags {
+    /// Call or put
+    #[arg(long, value_enum, default_value_t = OptionKindArg::Call)]
+    pub kind: OptionKindArg,
+
+    /// Which price the strike is compared to
+    #[arg(long, value_enum, default_value_t = OptionStyleArg::European)]
+    pub style: OptionStyleArg,
+
+    /// Price level of a barrier monitored at t=0 and at every point, see --barrier-kind
+    #[arg(long, value_name = "LEVEL", requires = "barrier_kind")]
+    pub barrier: Option<f64>,
+
+    /// What crossing the --barrier does
+    #[arg(long, value_enum, requires = "barrier")]
+    pub barrier_kind: Option<BarrierKindArg>,
+
+    /// The price at which the option may be exercised
+    #[arg(long)]
+    pub strike: f64,
+
+    /// The price of the underlying at t=0
+    #[arg(long, default_value_t = 100.0)]
+    pub spot: f64,
+
+    /// The risk-free yearly return in percent, e.g. 4%. The paths drift at it in place of
+    /// --yearly-mean, and the payoffs are discounted by it
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent, default_value = "0", allow_hyphen_values(true))]
+    pub rate: f64,
+}
+
+impl From<OptionFlags> for OptionArgs {
+    fn from(flags: OptionFlags) -> OptionArgs {
+        OptionArgs {
+            kind: match flags.kind {
+                OptionKindArg::Call => OptionKind::Call,
+                OptionKindArg::Put => OptionKind::Put,
+            },
+            style: match flags.style {
+                OptionStyleArg::European => OptionStyle::European,
+                OptionStyleArg::Asian => OptionStyle::Asian,
+            },
+            barrier: flags.barrier.zip(flags.barrier_kind).map(|(level, kind)| Barrier {
+                kind: match kind {
+                    BarrierKindArg::UpAndOut => BarrierKind::UpAndOut,
+                    BarrierKindArg::UpAndIn => BarrierKind::UpAndIn,
+                    BarrierKindArg::DownAndOut => BarrierKind::DownAndOut,
+                    BarrierKindArg::DownAndIn => BarrierKind::DownAndIn,
+                },
+                level,
+            }),
+            strike: flags.strike,
+            spot: flags.spot,
+            yearly_rate: 1.0 + flags.rate / 100.0,
+        }
+    }
+}
+
+/// The kinds of `OptionKind`
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OptionKindArg {
+    /// The right to buy at the strike
+    Call,
+    /// The right to sell at the strike
+    Put,
+}
+
+/// The styles of `OptionStyle`
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OptionStyleArg {
+    /// The price at expiry
+    European,
+    /// The arithmetic average of the prices at every point
+    Asian,
+}
+
+/// The kinds of `BarrierKind`
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum BarrierKindArg {
+    /// Cancelled once the price rises to the barrier
+    UpAndOut,
+    /// Worthless unless the price rises to the barrier
+    UpAndIn,
+    /// Cancelled once the price falls to the barrier
+    DownAndOut,
+    /// Worthless unless the price falls to the barrier
+    DownAndIn,
+}
+
+#[derive(Args)]
+pub struct SelftestFlags {
+    /// Number of returns generated for each case
+    #[arg(long, default_value_t = 2_000_000)]
+    pub num_points: usize,
+
+    /// Seed of the first case, the following cases use the following seeds
+    #[arg(long, default_value_t = 1)]
+    pub seed: u64,
+
+    /// Allowed deviation from the requested parameters, in standard errors of their estimates
+    #[arg(long, default_value_t = 4.0)]
+    pub tolerance: f64,
+}
+
+impl From<SelftestFlags> for SelftestArgs {
+    fn from(flags: SelftestFlags) -> SelftestArgs {
+        SelftestArgs {
+            num_points: flags.num_points,
+            seed: flags.seed,
+            tolerance: flags.tolerance,
+        }
+    }
+}
+
+#[cfg(feature = "fetch")]
+#[derive(Args)]
+pub struct FetchFlags {
+    /// Stooq ticker symbol, e.g. `spy.us`, `^spx` or `btcusd`
+    pub ticker: String,
+
+    /// First date to fetch (YYYY-MM-DD). Defaults to the start of the available history
+    #[arg(long)]
+    pub from: Option<String>,
+
+    /// Last date to fetch (YYYY-MM-DD). Defaults to the latest available price
+    #[arg(long)]
+    pub to: Option<String>,
+}
+
+#[cfg(feature = "fetch")]
+impl From<FetchFlags> for FetchArgs {
+    fn from(flags: FetchFlags) -> FetchArgs {
+        FetchArgs {
+            ticker: flags.ticker,
+            from: flags.from,
+            to: flags.to,
+        }
+    }
+}
+
+/// Lets every flag of the command and its subcommands be set from the
+/// environment variable named by `env_var_name`. Flags on the command line
+/// take precedence.
+pub fn with_env(mut cmd: Command) -> Command {
+    let flags: Vec<(clap::Id, String)> = cmd
+        .get_arguments()
+        .filter_map(|a| Some((a.get_id().clone(), a.get_long()?.to_string())))
+        .filter(|(_, long)| long != "help" && long != "version")
+        .collect();
+    for (id, long) in flags {
+        cmd = cmd.mut_arg(id, |a| a.env(env_var_name(&long)));
+    }
+    let subcommands: Vec<String> = cmd.get_subcommands().map(|s| s.get_name().to_string()).collect();
+    for name in subcommands {
+        cmd = cmd.mut_subcommand(name, with_env);
+    }
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+    use clap::{Arg, ArgAction, Command, Parser, ValueEnum};
+
+    use super::{GenReturnsFlags, PresetArg, with_env};
+    use crate::preset::Preset;
+    use crate::returns::GenReturnsArgs;
+
+    #[derive(Parser)]
+    #[command(no_binary_name = true, args_override_self = true)]
+    struct Flags {
+        #[command(flatten)]
+        gen_returns: GenReturnsFlags,
+    }
+
+    fn gen_returns_args(args: &[&str]) -> GenReturnsArgs {
+        Flags::try_parse_from(args).unwrap().gen_returns.into()
+    }
+
+    #[test]
+    fn percent_flags() {
+        let args = gen_returns_args(&["-n", "1", "-i", "1"]);
+        assert_eq!((1.0, 1.5), (args.yearly_mean, args.yearly_stddev));
+        let args = gen_returns_args(&["-n", "1", "-i", "1", "--yearly-return", "-3%", "--yearly-vol", "15"]);
+        assert_approx_eq!(0.97, args.yearly_mean);
+        assert_approx_eq!(1.15, args.yearly_stddev);
+        let args = gen_returns_args(&["-n", "1", "-i", "1", "--yearly-return", "3", "--yearly-mean", "1.1"]);
+        assert_approx_eq!(1.1, args.yearly_mean);
+        assert!(gen_returns_args(&["-n", "1", "-i", "1", "--yearly-vol=-20"]).validate().is_err());
+    }
+
+    #[test]
+    fn preset_args_match_presets() {
+        let names: Vec<String> =
+            PresetArg::value_variants().iter().map(|p| p.to_possible_value().unwrap().get_name().to_string()).collect();
+        assert_eq!(Preset::ALL.map(|p| p.name().to_string()).to_vec(), names);
+    }
+
+    #[test]
+    fn with_env_test() {
+        std::env::set_var("FINSIM_WITH_ENV_TEST_VALUE", "3");
+        std::env::set_var("FINSIM_WITH_ENV_TEST_FLAG", "true");
+        let cmd = with_env(
+            Command::new("finsim")
+                .arg(Arg::new("with_env_test_value").long("with-env-test-value"))
+                .subcommand(
+                    Command::new("sub")
+                        .arg(Arg::new("with_env_test_flag").long("with-env-test-flag").action(ArgAction::SetTrue)),
+                ),
+        );
+        let matches = cmd.clone().get_matches_from(["finsim"]);
+        assert_eq!(Some(&"3".to_string()), matches.get_one::<String>("with_env_test_value"));
+        let matches = cmd.clone().get_matches_from(["finsim", "--with-env-test-value", "4"]);
+        assert_eq!(Some(&"4".to_string()), matches.get_one::<String>("with_env_test_value"));
+        let matches = cmd.get_matches_from(["finsim", "sub"]);
+        assert!(matches.subcommand_matches("sub").unwrap().get_flag("with_env_test_flag"));
+    }
+}
@@ -0,0 +1,338 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+
+use crate::error::{Error, Result};
+
+/// Prefix of the environment variables that can set any flag, e.g.
+/// `FINSIM_NUM_POINTS` for `--num-points`
+pub const ENV_PREFIX: &str = "FINSIM_";
+
+/// Translates a TOML config file into the equivalent command line flags. Keys
+/// are flag names without the leading dashes (`num_points` or `num-points`).
+/// `true` booleans become bare flags, `false` ones are left out and arrays
+/// repeat the flag once per element. Keys whose environment variable (see
+/// `env_var_name`) is set are left out, since the environment takes precedence
+/// over config files.
+pub fn config_to_args(config: &str) -> Result<Vec<String>> {
+    let mut table = config.parse::<toml::Table>().map_err(|e| Error::Parse(e.to_string()))?;
+    table.retain(|key, _| env::var_os(env_var_name(key)).is_none());
+    table_to_args(table)
+}
+
+/// Translates a scenario file into the command line flags of each named
+/// scenario, in file order. Top level keys are shared by all scenarios and each
+/// `[scenarios.<name>]` table adds (or overrides) flags for one scenario, in
+/// the same format as `config_to_args`.
+pub fn scenarios_to_args(config: &str) -> Result<Vec<(String, Vec<String>)>> {
+    let mut table = config.parse::<toml::Table>().map_err(|e| Error::Parse(e.to_string()))?;
+    let scenarios = match table.remove("scenarios") {
+        Some(toml::Value::Table(scenarios)) if !scenarios.is_empty() => scenarios,
+        _ => return Err(Error::Parse("expected at least one [scenarios.<name>] table".to_string())),
+    };
+    let shared = table_to_args(table)?;
+    let mut res = Vec::new();
+    for (name, scenario) in scenarios {
+        let toml::Value::Table(scenario) = scenario else {
+            return Err(Error::Parse(format!("scenarios.{} must be a table", name)));
+        };
+        let mut args = shared.clone();
+        args.extend(table_to_args(scenario).map_err(|e| e.context(format!("scenario '{}'", name)))?);
+        res.push((name, args));
+    }
+    Ok(res)
+}
+
+/// One combination of a parameter sweep: the swept keys with their values
+/// (formatted as flag values) and the resulting command line flags
+pub type SweepPoint = (Vec<(String, String)>, Vec<String>);
+
+/// Expands a sweep file into the command line flags of every combination of
+/// the swept values. Top level keys are shared flags, in the format of
+/// `config_to_args`, and each key of the `[sweep]` table is a flag with
+/// either an array of values or an inclusive `{ from, to, step }` range. The
+/// first swept key varies slowest.
+pub fn sweep_to_args(config: &str) -> Result<Vec<SweepPoint>> {
+    let mut table = config.parse::<toml::Table>().map_err(|e| Error::Parse(e.to_string()))?;
+    let sweep = match table.remove("sweep") {
+        Some(toml::Value::Table(sweep)) if !sweep.is_empty() => sweep,
+        _ => return Err(Error::Parse("expected a [sweep] table with at least one key".to_string())),
+    };
+    let mut points: Vec<(Vec<(String, String)>, toml::Table)> = vec![(Vec::new(), table)];
+    for (key, value) in sweep {
+        let values = sweep_values(value).map_err(|e| e.context(format!("sweep.{}", key)))?;
+        let mut expanded = Vec::with_capacity(points.len() * values.len());
+        for (assignment, table) in &points {
+            for v in &values {
+                let mut assignment = assignment.clone();
+                assignment.push((key.clone(), scalar_to_arg(&key, v.clone())?));
+                let mut table = table.clone();
+                table.insert(key.clone(), v.clone());
+                expanded.push((assignment, table));
+            }
+        }
+        points = expanded;
+    }
+    points
+        .into_iter()
+        .map(|(assignment, table)| Ok((assignment, table_to_args(table)?)))
+        .collect()
+}
+
+fn sweep_values(value: toml::Value) -> Result<Vec<toml::Value>> {
+    match value {
+        toml::Value::Array(values) if !values.is_empty() => Ok(values),
+        toml::Value::Table(mut range) => {
+            let mut number = |key: &str| match range.remove(key) {
+                Some(toml::Value::Float(f)) => Ok(f),
+                Some(toml::Value::Integer(i)) => Ok(i as f64),
+                _ => Err(Error::Parse(format!("range needs a numeric '{}'", key))),
+            };
+            let (from, to, step) = (number("from")?, number("to")?, number("step")?);
+            if let Some(key) = range.keys().next() {
+                return Err(Error::Parse(format!("unknown range key '{}'", key)));
+            }
+            if [from, to, step].iter().any(|v| !v.is_finite()) || step <= 0.0 || to < from {
+                return Err(Error::Parse("range needs a positive step and 'to' at least 'from'".to_string()));
+            }
+            // Allow for rounding errors in the step count and the values
+            let steps = ((to - from) / step + 1e-9).floor() as usize;
+            Ok((0..=steps)
+                .map(|i| toml::Value::Float(((from + i as f64 * step) * 1e12).round() / 1e12))
+                .collect())
+        }
+        _ => Err(Error::Parse("expected a non-empty array or a { from, to, step } table".to_string())),
+    }
+}
+
+pub(crate) fn table_to_args(table: toml::Table) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let flag = format!("--{}", key.replace('_', "-"));
+        match value {
+            toml::Value::Boolean(true) => args.push(flag),
+            toml::Value::Boolean(false) => {}
+            toml::Value::Array(values) => {
+                for v in values {
+                    args.push(flag.clone());
+                    args.push(scalar_to_arg(&key, v)?);
+                }
+            }
+            v => {
+                args.push(flag);
+                args.push(scalar_to_arg(&key, v)?);
+            }
+        }
+    }
+    Ok(args)
+}
+
+fn scalar_to_arg(key: &str, value: toml::Value) -> Result<String> {
+    match value {
+        toml::Value::String(s) => Ok(s),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        toml::Value::Datetime(d) => Ok(d.to_string()),
+        _ => Err(Error::Parse(format!("unsupported value for '{}'", key))),
+    }
+}
+
+/// Name of the environment variable setting a flag (or config key), e.g.
+/// `FINSIM_NUM_POINTS` for `num-points` or `num_points`
+pub fn env_var_name(flag: &str) -> String {
+    format!("{}{}", ENV_PREFIX, flag.replace('-', "_").to_uppercase())
+}
+
+/// Expands `--config <file>` (or `--config=<file>`, or the `FINSIM_CONFIG`
+/// environment variable) into the flags from that file. They are inserted
+/// right after the subcommand, so with `args_override_self` any flag given
+/// explicitly on the command line wins.
+pub fn expand_config_args(args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let path = args.iter().enumerate().skip(1).find_map(|(i, a)| {
+        let a = a.to_str()?;
+        if a == "--config" {
+            args.get(i + 1).cloned()
+        } else {
+            a.strip_prefix("--config=").map(OsString::from)
+        }
+    });
+    let Some(path) = path.or_else(|| env::var_os(env_var_name("config"))) else {
+        return Ok(args);
+    };
+    let config = fs::read_to_string(&path)
+        .map_err(|e| Error::from(e).context(format!("failed to read config file {}", path.to_string_lossy())))?;
+    let config_args = config_to_args(&config)
+        .map_err(|e| e.context(format!("invalid config file {}", path.to_string_lossy())))?;
+
+    let mut expanded = args;
+    let at = (subcommand_index(&expanded) + 1).min(expanded.len());
+    expanded.splice(at..at, config_args.into_iter().map(OsString::from));
+    Ok(expanded)
+}
+
+/// Index of the subcommand, the first argument that isn't a global flag or
+/// the value of `--config` or `--preset`
+pub(crate) fn subcommand_index<T: AsRef<OsStr>>(args: &[T]) -> usize {
+    let mut i = 1;
+    while let Some(a) = args.get(i).and_then(|a| a.as_ref().to_str()) {
+        match a {
+            "--config" | "--preset" => i += 2,
+            a if a.starts_with('-') => i += 1,
+            _ => break,
+        }
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::{config_to_args, env_var_name, expand_config_args, scenarios_to_args, subcommand_index, sweep_to_args};
+
+    #[test]
+    fn config_to_args_test() {
+        let config = r#"
+            num_points = 1000
+            interval-seconds = 86400
+            yearly_mean = 1.07
+            accumulate = true
+            unbuffered = false
+            tag = ["scenario=base", "asset=spy"]
+            output = "-"
+        "#;
+        assert_eq!(
+            vec![
+                "--num-points",
+                "1000",
+                "--interval-seconds",
+                "86400",
+                "--yearly-mean",
+                "1.07",
+                "--accumulate",
+                "--tag",
+                "scenario=base",
+                "--tag",
+                "asset=spy",
+                "--output",
+                "-",
+            ],
+            config_to_args(config).unwrap()
+        );
+    }
+
+    #[test]
+    fn config_to_args_rejects_tables() {
+        assert!(config_to_args("[model]\nyearly_mean = 1.1").is_err());
+        assert!(config_to_args("num_points = ").is_err());
+    }
+
+    #[test]
+    fn scenarios_to_args_test() {
+        let config = r#"
+            num_points = 10
+            pointwise_leverage = 1.0
+
+            [scenarios.unlevered]
+
+            [scenarios.levered]
+            pointwise_leverage = 2.0
+        "#;
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("unlevered".to_string(), strings(&["--num-points", "10", "--pointwise-leverage", "1"])),
+                (
+                    "levered".to_string(),
+                    strings(&["--num-points", "10", "--pointwise-leverage", "1", "--pointwise-leverage", "2"])
+                ),
+            ],
+            scenarios_to_args(config).unwrap()
+        );
+        assert!(scenarios_to_args("num_points = 10").is_err());
+        assert!(scenarios_to_args("scenarios = 1").is_err());
+        assert!(scenarios_to_args("[scenarios]\nbase = 1").is_err());
+    }
+
+    #[test]
+    fn sweep_to_args_test() {
+        let config = r#"
+            num_points = 10
+
+            [sweep]
+            pointwise_leverage = { from = 1, to = 1.5, step = 0.25 }
+            yearly_stddev = [1.2, 1.5]
+        "#;
+        let points = sweep_to_args(config).unwrap();
+        assert_eq!(6, points.len());
+        let assignments: Vec<Vec<String>> =
+            points.iter().map(|(a, _)| a.iter().map(|(_, v)| v.clone()).collect()).collect();
+        assert_eq!(
+            vec![
+                vec!["1", "1.2"],
+                vec!["1", "1.5"],
+                vec!["1.25", "1.2"],
+                vec!["1.25", "1.5"],
+                vec!["1.5", "1.2"],
+                vec!["1.5", "1.5"],
+            ],
+            assignments
+        );
+        assert_eq!("pointwise_leverage", points[0].0[0].0);
+        assert_eq!(
+            vec!["--num-points", "10", "--pointwise-leverage", "1.25", "--yearly-stddev", "1.5"],
+            points[3].1
+        );
+
+        // Steps that don't divide the range evenly stop before `to`
+        let points = sweep_to_args("[sweep]\nyearly_mean = { from = 1.0, to = 1.35, step = 0.1 }").unwrap();
+        let values: Vec<&str> = points.iter().map(|(a, _)| a[0].1.as_str()).collect();
+        assert_eq!(vec!["1", "1.1", "1.2", "1.3"], values);
+
+        assert!(sweep_to_args("num_points = 10").is_err());
+        assert!(sweep_to_args("[sweep]\nseed = []").is_err());
+        assert!(sweep_to_args("[sweep]\nseed = { from = 1, to = 2 }").is_err());
+        assert!(sweep_to_args("[sweep]\nseed = { from = 2, to = 1, step = 1 }").is_err());
+        assert!(sweep_to_args("[sweep]\nseed = { from = 1, to = 2, step = 0 }").is_err());
+    }
+
+    #[test]
+    fn expand_config_args_test() {
+        let path = std::env::temp_dir().join(format!("finsim-test-{}.toml", std::process::id()));
+        std::fs::write(&path, "num_points = 10\nseed = 1\n").unwrap();
+        let args: Vec<OsString> = ["finsim", "gen", "-i", "60", "--config", path.to_str().unwrap(), "--seed", "2"]
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+        let expanded = expand_config_args(args.clone()).unwrap();
+        assert_eq!(&["finsim", "gen", "--num-points", "10", "--seed", "1"].map(OsString::from), &expanded[..6]);
+        assert_eq!(&args[2..], &expanded[6..]);
+
+        let config_arg = format!("--config={}", path.to_str().unwrap());
+        let args: Vec<OsString> = ["finsim", &config_arg, "mc", "--seed", "2"].into_iter().map(OsString::from).collect();
+        let expanded = expand_config_args(args.clone()).unwrap();
+        assert_eq!(&["finsim", &config_arg, "mc", "--num-points", "10"].map(OsString::from), &expanded[..5]);
+        let args = ["finsim", "-q", "--config", path.to_str().unwrap(), "-vv", "gen"].map(OsString::from);
+        let expanded = expand_config_args(args.to_vec()).unwrap();
+        assert_eq!(&["gen", "--num-points", "10"].map(OsString::from), &expanded[5..8]);
+        std::fs::remove_file(&path).unwrap();
+
+        let args: Vec<OsString> = ["finsim", "gen", "-n", "1"].into_iter().map(OsString::from).collect();
+        assert_eq!(args, expand_config_args(args.clone()).unwrap());
+        assert!(expand_config_args(vec!["finsim".into(), "--config=/nonexistent.toml".into()]).is_err());
+    }
+
+    #[test]
+    fn subcommand_index_test() {
+        assert_eq!(1, subcommand_index(&["finsim", "gen", "-q"]));
+        assert_eq!(5, subcommand_index(&["finsim", "-v", "--preset", "gold", "--quiet", "mc"]));
+        assert_eq!(2, subcommand_index(&["finsim", "--config=a.toml"]));
+    }
+
+    #[test]
+    fn env_var_name_test() {
+        assert_eq!("FINSIM_NUM_POINTS", env_var_name("num-points"));
+        assert_eq!("FINSIM_NUM_POINTS", env_var_name("num_points"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Investing a lump sum at once versus dollar-cost averaging it: investing
+//! equal parts at the start of each of the first ticks and keeping the rest in
+//! cash at a fixed rate until then. Both run on the same path, so they only
+//! differ in when the money goes in.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::model::{ReturnModel, path_seed};
+use crate::returns::GenReturnsArgs;
+use crate::stats::{percent, quantile};
+
+/// A sum to invest and how to spread it
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DcaArgs {
+    pub amount: f64,
+    /// Ticks the amount is spread over, 1 being the lump sum
+    pub periods: usize,
+    /// Yearly return of the cash waiting to be invested as a factor, e.g.
+    /// 1.03 for 3%
+    pub yearly_cash_rate: f64,
+}
+
+/// Values of both at the end of a path
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct DcaOutcome {
+    pub lump_sum: f64,
+    pub dca: f64,
+}
+
+/// The outcomes of both over many paths
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct DcaReport {
+    pub paths: usize,
+    pub lump_sum_median: f64,
+    pub lump_sum_p10: f64,
+    pub lump_sum_p90: f64,
+    pub dca_median: f64,
+    pub dca_p10: f64,
+    pub dca_p90: f64,
+    /// Paths on which the lump sum ended higher
+    pub lump_sum_wins: usize,
+    /// Paths on which averaging in ended higher
+    pub dca_wins: usize,
+}
+
+impl DcaArgs {
+    pub fn validate(&self) -> Result<()> {
+        if !self.amount.is_finite() || self.amount < 0.0 {
+            return Err(Error::Invalid(format!("--amount must be a non-negative number, got {}", self.amount)));
+        }
+        if self.periods == 0 {
+            return Err(Error::Invalid("--periods must be at least 1".to_string()));
+        }
+        if !self.yearly_cash_rate.is_finite() || self.yearly_cash_rate <= 0.0 {
+            return Err(Error::Invalid(format!(
+                "--cash-rate must be above -100%, got {}%",
+                (self.yearly_cash_rate - 1.0) * 100.0
+            )));
+        }
+        Ok(())
+    }
+
+    /// Both over one path of returns, with `ticks_per_year` ticks a year. A
+    /// path shorter than the periods leaves the rest of the amount in cash.
+    pub fn compare(&self, returns: impl Iterator<Item = f64>, ticks_per_year: f64) -> DcaOutcome {
+        let cash_growth = self.yearly_cash_rate.powf(1.0 / ticks_per_year);
+        let part = self.amount / self.periods as f64;
+        let (mut lump_sum, mut invested, mut cash) = (self.amount, 0.0, self.amount);
+        for (tick, r) in returns.enumerate() {
+            if tick < self.periods {
+                invested += part;
+                cash -= part;
+            }
+            lump_sum *= r;
+            invested *= r;
+            cash *= cash_growth;
+        }
+        DcaOutcome { lump_sum, dca: invested + cash }
+    }
+
+    /// `compare` over `paths` paths of `model`, with the interval, number of
+    /// points and time base of `gen_args` and path `i` seeded with
+    /// `path_seed(seed, i)`
+    pub fn compare_paths(
+        &self,
+        model: &dyn ReturnModel,
+        gen_args: &GenReturnsArgs,
+        paths: usize,
+        seed: Option<u64>,
+    ) -> Result<Vec<DcaOutcome>> {
+        let interval_seconds = gen_args.tick_interval_seconds();
+        let ticks_per_year = gen_args.time_base.ticks_per_year(gen_args.tick_interval());
+        (0..paths)
+            .map(|i| {
+                let returns = model.returns(interval_seconds, gen_args.num_points, seed.map(|s| path_seed(s, i)))?;
+                Ok(self.compare(returns, ticks_per_year))
+            })
+            .collect()
+    }
+}
+
+impl DcaReport {
+    pub fn of(outcomes: &[DcaOutcome]) -> DcaReport {
+        let lump_sum: Vec<f64> = outcomes.iter().map(|o| o.lump_sum).collect();
+        let dca: Vec<f64> = outcomes.iter().map(|o| o.dca).collect();
+        DcaReport {
+            paths: outcomes.len(),
+            lump_sum_median: quantile(&lump_sum, 0.5),
+            lump_sum_p10: quantile(&lump_sum, 0.1),
+            lump_sum_p90: quantile(&lump_sum, 0.9),
+            dca_median: quantile(&dca, 0.5),
+            dca_p10: quantile(&dca, 0.1),
+            dca_p90: quantile(&dca, 0.9),
+            lump_sum_wins: outcomes.iter().filter(|o| o.lump_sum > o.dca).count(),
+            dca_wins: outcomes.iter().filter(|o| o.dca > o.lump_sum).count(),
+        }
+    }
+
+    /// The share of paths on which the lump sum ended higher in percent
+    pub fn lump_sum_wins_percent(&self) -> f64 {
+        percent(self.lump_sum_wins, self.paths)
+    }
+
+    /// The share of paths on which averaging in ended higher in percent
+    pub fn dca_wins_percent(&self) -> f64 {
+        percent(self.dca_wins, self.paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{DcaArgs, DcaReport};
+    use crate::model::LogNormal;
+    use crate::returns::{GenReturnsArgs, RngKind, TimeBase};
+
+    #[test]
+    fn spreading_the_amount() {
+        let args = DcaArgs { amount: 100.0, periods: 2, yearly_cash_rate: 1.0 };
+        // Half is invested before the first return, all of it before the
+        // second
+        let outcome = args.compare([2.0, 1.5].into_iter(), 1.0);
+        assert_approx_eq!(300.0, outcome.lump_sum, 1e-12);
+        assert_approx_eq!(225.0, outcome.dca, 1e-12);
+        let outcome = args.compare([0.5, 1.0].into_iter(), 1.0);
+        assert!(outcome.dca > outcome.lump_sum);
+        // Cash earns its rate until invested
+        let args = DcaArgs { periods: 3, yearly_cash_rate: 1.1, ..args };
+        assert_approx_eq!(100.0 / 3.0 + 200.0 / 3.0 * 1.1, args.compare([1.0].into_iter(), 1.0).dca, 1e-9);
+        assert_eq!(100.0, DcaArgs { periods: 1, ..args }.compare([1.0].into_iter(), 1.0).dca);
+        assert!(DcaArgs { periods: 0, ..args }.validate().is_err());
+        assert!(DcaArgs { amount: f64::NAN, ..args }.validate().is_err());
+    }
+
+    #[test]
+    fn lump_sum_usually_wins_on_a_rising_asset() {
+        let gen_args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(2629746),
+            num_points: 120,
+            yearly_mean: 1.08,
+            yearly_stddev: 1.15,
+            seed: Some(2),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let model = LogNormal::from(&gen_args);
+        let args = DcaArgs { amount: 1.0, periods: 12, yearly_cash_rate: 1.0 };
+        let report = DcaReport::of(&args.compare_paths(&model, &gen_args, 500, Some(2)).unwrap());
+        assert_eq!(500, report.lump_sum_wins + report.dca_wins);
+        assert!(report.lump_sum_wins_percent() > 50.0);
+        assert!(report.lump_sum_median > report.dca_median);
+        // Time out of the market narrows the spread of the outcomes
+        assert!(report.lump_sum_p90 - report.lump_sum_p10 > report.dca_p90 - report.dca_p10);
+    }
+}
@@ -0,0 +1,123 @@
+//! Fixed-point decimal numbers for accumulating account balances in
+//! currency, see `AccumulateArgs::decimal_places`. A balance of binary
+//! floating point dollars can't hold most amounts of cents exactly, and the
+//! rounding drifts with every tick. A `Decimal` counts whole minor units
+//! instead and rounds every product back to them, the way a bank books
+//! interest.
+
+use std::fmt;
+
+/// The most decimal places, so that a balance of a trillion still fits the
+/// exact products of `Decimal::mul_f64`
+pub const MAX_PLACES: u32 = 9;
+
+/// A number of `10^-places` units, e.g. cents with 2 places
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decimal {
+    units: i128,
+    places: u32,
+}
+
+impl Decimal {
+    /// The multiple of `10^-places` nearest to `x`, ties to even
+    pub fn from_f64(x: f64, places: u32) -> Decimal {
+        Decimal { units: 10i128.pow(places), places }.mul_f64(x)
+    }
+
+    /// The product with `x` rounded to `self.places` places, ties to even
+    /// like IEEE 754 and banker's rounding. The product is exact before the
+    /// rounding while it stays below 2^127 minor units times the mantissa
+    /// of `x`, beyond which it's rounded in `f64`.
+    pub fn mul_f64(self, x: f64) -> Decimal {
+        Decimal { units: mul_round(self.units, x), places: self.places }
+    }
+
+    /// The nearest `f64`, which prints as the decimal itself up to 15
+    /// significant digits
+    pub fn to_f64(self) -> f64 {
+        self.units as f64 / 10i128.pow(self.places) as f64
+    }
+}
+
+impl std::ops::Sub for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: Decimal) -> Decimal {
+        Decimal { units: self.units - other.units, places: self.places }
+    }
+}
+
+impl fmt::Display for Decimal {
+    /// With all its places, e.g. `100.10`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scale = 10u128.pow(self.places);
+        let sign = if self.units < 0 { "-" } else { "" };
+        let (whole, fraction) = (self.units.unsigned_abs() / scale, self.units.unsigned_abs() % scale);
+        match self.places {
+            0 => write!(f, "{}{}", sign, whole),
+            places => write!(f, "{}{}.{:0width$}", sign, whole, fraction, width = places as usize),
+        }
+    }
+}
+
+/// `units * x` rounded to an integer, ties to even
+fn mul_round(units: i128, x: f64) -> i128 {
+    if !x.is_finite() || x == 0.0 {
+        return (units as f64 * x) as i128;
+    }
+    // x = mantissa * 2^exponent exactly
+    let bits = x.to_bits();
+    let biased = ((bits >> 52) & 0x7ff) as i32;
+    let (mantissa, exponent) = match biased {
+        0 => (bits & ((1 << 52) - 1), -1074),
+        _ => ((bits & ((1 << 52) - 1)) | (1 << 52), biased - 1075),
+    };
+    let negative = (units < 0) != (x < 0.0);
+    let Some(product) = units.unsigned_abs().checked_mul(u128::from(mantissa)).filter(|&p| p < 1 << 127) else {
+        return (units as f64 * x).round_ties_even() as i128;
+    };
+    let magnitude = if exponent >= 0 {
+        match product.checked_shl(exponent as u32).filter(|&p| p >> exponent == product && p < 1 << 127) {
+            Some(p) => p,
+            None => return (units as f64 * x).round_ties_even() as i128,
+        }
+    } else {
+        let shift = exponent.unsigned_abs();
+        if shift >= 128 {
+            0
+        } else {
+            let quotient = product >> shift;
+            let remainder = product - (quotient << shift);
+            let half = 1u128 << (shift - 1);
+            if remainder > half || (remainder == half && quotient % 2 == 1) {
+                quotient + 1
+            } else {
+                quotient
+            }
+        }
+    };
+    if negative { -(magnitude as i128) } else { magnitude as i128 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Decimal;
+
+    #[test]
+    fn products_round_to_the_minor_unit() {
+        // 100.1 is 100.099999999999994315658... as f64
+        let start = Decimal::from_f64(100.1, 2);
+        assert_eq!(10010, start.units);
+        assert_eq!("100.10", start.to_string());
+        // 10010 * 1.05 = 10510.5 in exact decimal, but 1.05 as f64 is slightly more
+        assert_eq!(10511, start.mul_f64(1.05).units);
+        // Exact ties go to even, and 0.025 as f64 is no tie
+        assert_eq!(3, Decimal::from_f64(0.025, 2).units);
+        assert_eq!(12, Decimal { units: 25, places: 2 }.mul_f64(0.5).units);
+        assert_eq!(-12, Decimal { units: -25, places: 2 }.mul_f64(0.5).units);
+        assert_eq!(3, Decimal { units: 25, places: 0 }.mul_f64(0.125).units);
+        assert_eq!("-0.25", Decimal { units: -25, places: 2 }.to_string());
+        assert_eq!(0, Decimal { units: 1, places: 2 }.mul_f64(f64::MIN_POSITIVE).units);
+        assert_eq!(0.1, Decimal::from_f64(0.1, 9).to_f64());
+    }
+}
@@ -0,0 +1,152 @@
+//! Drawdown episodes: every fall of a path below its running peak, from the
+//! peak to the first tick back at it, rather than only the deepest. Ticks
+//! count from t=0, when the path is at its start value, so tick `k` is the
+//! `k`th value of the path, `k` intervals in.
+//!
+//! `DrawdownRisk` sums the drawdowns up over every tick instead, into the
+//! Ulcer and pain indexes.
+
+use serde::Serialize;
+
+use crate::stats::KahanSum;
+
+/// A fall below the running peak and the recovery to it
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Drawdown {
+    /// Tick of the peak the drawdown falls from
+    pub start: usize,
+    /// Tick of the lowest value before the recovery
+    pub trough: usize,
+    /// First tick back at the peak, `None` if the path ends below it
+    pub recovery: Option<usize>,
+    /// The fall from the peak to the trough, e.g. 0.2 for 20%
+    pub depth: f64,
+    /// Ticks from the peak to the recovery, or to the end of the path
+    pub length: usize,
+}
+
+/// Finds the drawdowns of a path one value at a time, in constant memory
+/// besides the drawdowns found
+#[derive(Clone, Debug)]
+pub struct Drawdowns {
+    tick: usize,
+    peak: (usize, f64),
+    /// The lowest value since the peak, while below it
+    trough: Option<(usize, f64)>,
+    drawdowns: Vec<Drawdown>,
+}
+
+impl Drawdowns {
+    /// A path at `start_value` at tick 0
+    pub fn new(start_value: f64) -> Drawdowns {
+        Drawdowns { tick: 0, peak: (0, start_value), trough: None, drawdowns: Vec::new() }
+    }
+
+    /// Adds the value of the next tick
+    pub fn push(&mut self, value: f64) {
+        self.tick += 1;
+        if value >= self.peak.1 {
+            if let Some(trough) = self.trough.take() {
+                self.close(trough, Some(self.tick));
+            }
+            self.peak = (self.tick, value);
+        } else if self.trough.is_none_or(|(_, low)| value < low) {
+            self.trough = Some((self.tick, value));
+        }
+    }
+
+    /// The drawdowns in the order they started, the last one unrecovered if
+    /// the path ends below its peak
+    pub fn finish(mut self) -> Vec<Drawdown> {
+        if let Some(trough) = self.trough.take() {
+            self.close(trough, None);
+        }
+        self.drawdowns
+    }
+
+    fn close(&mut self, (trough, low): (usize, f64), recovery: Option<usize>) {
+        let (start, peak) = self.peak;
+        self.drawdowns.push(Drawdown {
+            start,
+            trough,
+            recovery,
+            depth: 1.0 - low / peak,
+            length: recovery.unwrap_or(self.tick) - start,
+        });
+    }
+}
+
+/// Risk measures of how deep and how long a path is below its running peak
+/// over all ticks: the Ulcer index (Peter Martin, 1987), the root mean square
+/// drawdown, and the pain index, the mean drawdown. Both are fractions like
+/// `Drawdown::depth`, and NaN for a path without values.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct DrawdownRisk {
+    pub ulcer_index: f64,
+    pub pain_index: f64,
+}
+
+impl DrawdownRisk {
+    /// The risk of a path starting at `start_value`, over the ticks after
+    /// the start
+    pub fn of(start_value: f64, values: impl IntoIterator<Item = f64>) -> DrawdownRisk {
+        let (mut peak, mut ticks) = (start_value, 0);
+        let (mut sum, mut squares) = (KahanSum::default(), KahanSum::default());
+        for value in values {
+            peak = peak.max(value);
+            let drawdown = 1.0 - value / peak;
+            sum.add(drawdown);
+            squares.add(drawdown * drawdown);
+            ticks += 1;
+        }
+        DrawdownRisk { ulcer_index: (squares.value() / ticks as f64).sqrt(), pain_index: sum.value() / ticks as f64 }
+    }
+
+    /// The Martin ratio, or Ulcer performance index: a yearly return in
+    /// excess of the risk-free rate, e.g. 0.05 for 5%, over the Ulcer index
+    pub fn martin_ratio(&self, excess_return: f64) -> f64 {
+        excess_return / self.ulcer_index
+    }
+
+    /// The pain ratio: a yearly excess return over the pain index
+    pub fn pain_ratio(&self, excess_return: f64) -> f64 {
+        excess_return / self.pain_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{Drawdown, DrawdownRisk, Drawdowns};
+
+    fn drawdowns(start_value: f64, values: impl IntoIterator<Item = f64>) -> Vec<Drawdown> {
+        let mut drawdowns = Drawdowns::new(start_value);
+        values.into_iter().for_each(|value| drawdowns.push(value));
+        drawdowns.finish()
+    }
+
+    #[test]
+    fn drawdown_episodes() {
+        let found = drawdowns(100.0, [90.0, 75.0, 100.0, 110.0, 99.0, 105.0]);
+        assert_eq!(2, found.len());
+        assert_eq!(Drawdown { start: 0, trough: 2, recovery: Some(3), depth: 0.25, length: 3 }, found[0]);
+        let last = found[1];
+        assert_eq!((4, 5, None, 2), (last.start, last.trough, last.recovery, last.length));
+        assert_approx_eq!(0.1, last.depth);
+
+        assert!(drawdowns(1.0, [1.0, 2.0, 3.0]).is_empty());
+        assert!(drawdowns(1.0, []).is_empty());
+    }
+
+    #[test]
+    fn ulcer_and_pain_indexes() {
+        // Drawdowns of 0, 0.5, 0 and 0.25
+        let risk = DrawdownRisk::of(1.0, [1.0, 0.5, 2.0, 1.5]);
+        assert_approx_eq!(0.1875, risk.pain_index);
+        assert_approx_eq!((0.3125f64 / 4.0).sqrt(), risk.ulcer_index);
+        assert_approx_eq!(0.1 / 0.1875, risk.pain_ratio(0.1));
+        assert_approx_eq!(0.1 / risk.ulcer_index, risk.martin_ratio(0.1));
+        assert!(DrawdownRisk::of(1.0, []).ulcer_index.is_nan());
+    }
+}
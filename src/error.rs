@@ -0,0 +1,66 @@
+use std::fmt;
+use std::io;
+
+use thiserror::Error;
+
+/// Errors of the library functions
+#[derive(Debug, Error)]
+pub enum Error {
+    /// Settings that don't describe a valid simulation, e.g. a yearly stddev
+    /// below 1 or a correlation matrix that isn't positive definite
+    #[error("{0}")]
+    Invalid(String),
+
+    /// Text that couldn't be parsed, e.g. a duration, a config file or an
+    /// input series
+    #[error("{0}")]
+    Parse(String),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    /// A failed download, or a response without price data
+    #[cfg(feature = "fetch")]
+    #[error("{0}")]
+    Fetch(String),
+
+    #[cfg(feature = "sqlite")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[cfg(feature = "xlsx")]
+    #[error(transparent)]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+
+    /// Another error with what it is about, e.g. the scenario or file
+    #[error("{context}: {source}")]
+    Context { context: String, source: Box<Error> },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Prefixes the message with `context`, e.g. the scenario it is about
+    pub fn context(self, context: impl fmt::Display) -> Error {
+        Error::Context {
+            context: context.to_string(),
+            source: Box::new(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::Error;
+
+    #[test]
+    fn context_test() {
+        let e = Error::Parse("invalid duration 'x'".to_string()).context("sweep.total_seconds");
+        assert_eq!("sweep.total_seconds: invalid duration 'x'", e.to_string());
+        assert!(matches!(e, Error::Context { ref source, .. } if matches!(**source, Error::Parse(_))));
+        let e = Error::from(io::Error::new(io::ErrorKind::NotFound, "no such file")).context("prices.csv");
+        assert_eq!("prices.csv: no such file", e.to_string());
+    }
+}
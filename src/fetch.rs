@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// What to download
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FetchArgs {
+    /// Stooq ticker symbol, e.g. `spy.us`, `^spx` or `btcusd`
+    pub ticker: String,
+
+    /// First date to fetch (YYYY-MM-DD), the start of the available history if `None`
+    pub from: Option<String>,
+
+    /// Last date to fetch (YYYY-MM-DD), the latest available price if `None`
+    pub to: Option<String>,
+}
+
+/// Stooq URL serving daily bars for `args.ticker` as CSV with a
+/// `Date,Open,High,Low,Close,Volume` header
+pub fn stooq_url(args: &FetchArgs) -> String {
+    let mut url = format!("https://stooq.com/q/d/l/?s={}&i=d", args.ticker.to_lowercase());
+    if let Some(from) = &args.from {
+        url.push_str(&format!("&d1={}", from.replace('-', "")));
+    }
+    if let Some(to) = &args.to {
+        url.push_str(&format!("&d2={}", to.replace('-', "")));
+    }
+    url
+}
+
+/// Downloads daily prices as CSV, in the format `calibrate` reads
+pub fn fetch_csv(args: &FetchArgs) -> Result<String> {
+    let csv = ureq::get(&stooq_url(args))
+        .call()
+        .and_then(|mut r| r.body_mut().read_to_string())
+        .map_err(|e| Error::Fetch(format!("failed to fetch {}: {}", args.ticker, e)))?;
+    // Stooq answers unknown tickers with a plain text body instead of an error status
+    if !csv.starts_with("Date,") {
+        return Err(Error::Fetch(format!("no price data for {}: {}", args.ticker, csv.trim())));
+    }
+    Ok(csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FetchArgs;
+
+    #[test]
+    fn stooq_url_test() {
+        let mut args = FetchArgs {
+            ticker: "SPY.US".to_string(),
+            from: None,
+            to: None,
+        };
+        assert_eq!("https://stooq.com/q/d/l/?s=spy.us&i=d", super::stooq_url(&args));
+        args.from = Some("2020-01-01".to_string());
+        args.to = Some("2020-12-31".to_string());
+        assert_eq!(
+            "https://stooq.com/q/d/l/?s=spy.us&i=d&d1=20200101&d2=20201231",
+            super::stooq_url(&args)
+        );
+    }
+}
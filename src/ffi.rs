@@ -0,0 +1,227 @@
+//! C ABI for embedding finsim in non-Rust applications, see
+//! `include/finsim.h`. The functions write into buffers owned by the caller
+//! and return 0 on success or -1 on error, with the message available from
+//! `finsim_last_error` on the same thread.
+
+use std::cell::RefCell;
+use std::ffi::{CString, c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use crate::error::{Error, Result};
+use crate::model::path_seed;
+use crate::returns::{AccumulateArgs, GenReturnsArgs, RngKind, TimeBase, accumulate_iter, gen_returns};
+
+pub const FINSIM_LEVERAGE_NONE: c_int = 0;
+pub const FINSIM_LEVERAGE_CONTINUOUS: c_int = 1;
+pub const FINSIM_LEVERAGE_POINTWISE: c_int = 2;
+pub const FINSIM_LEVERAGE_INITIAL: c_int = 3;
+
+/// Parameters of the simulated paths
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct FinsimParams {
+    pub yearly_mean: f64,
+    pub yearly_stddev: f64,
+    /// Time between data points in seconds
+    pub interval_seconds: u64,
+    /// Value at t=0 of accumulated paths
+    pub start_value: f64,
+    /// One of the `FINSIM_LEVERAGE_*` constants
+    pub leverage_kind: c_int,
+    pub leverage: f64,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+impl FinsimParams {
+    fn gen_args(&self, num_points: usize, seed: u64) -> GenReturnsArgs {
+        GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(self.interval_seconds as usize),
+            num_points,
+            yearly_mean: self.yearly_mean,
+            yearly_stddev: self.yearly_stddev,
+            seed: Some(seed),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        }
+    }
+
+    fn acc_args(&self) -> Result<AccumulateArgs> {
+        let leverage = Some(self.leverage);
+        let (continuous_leverage, pointwise_leverage, initial_leverage) = match self.leverage_kind {
+            FINSIM_LEVERAGE_NONE => (None, None, None),
+            FINSIM_LEVERAGE_CONTINUOUS => (leverage, None, None),
+            FINSIM_LEVERAGE_POINTWISE => (None, leverage, None),
+            FINSIM_LEVERAGE_INITIAL => (None, None, leverage),
+            kind => return Err(Error::Invalid(format!("unknown leverage kind {}", kind))),
+        };
+        let args = AccumulateArgs {
+            accumulate: true,
+            start_value: self.start_value,
+            continuous_leverage,
+            pointwise_leverage,
+            initial_leverage,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        args.validate()?;
+        Ok(args)
+    }
+}
+
+/// Turns the result into a return code, keeping the message of an error
+fn status(res: Result<()>) -> c_int {
+    match res {
+        Ok(()) => 0,
+        Err(e) => {
+            let message = CString::new(e.to_string().replace('\0', " ")).unwrap_or_default();
+            LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+            -1
+        }
+    }
+}
+
+/// The buffer of `len` values at `out`, an error if it is null
+unsafe fn buffer<'a>(out: *mut f64, len: usize) -> Result<&'a mut [f64]> {
+    if out.is_null() {
+        return Err(Error::Invalid("the output buffer is null".to_string()));
+    }
+    Ok(slice::from_raw_parts_mut(out, len))
+}
+
+/// The message of the last error on this thread, or null. Valid until the
+/// next call on this thread.
+#[no_mangle]
+pub extern "C" fn finsim_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Writes `num_points` returns to `out`. The start value and leverage are
+/// ignored.
+///
+/// # Safety
+///
+/// `params` must point to valid parameters and `out` to `num_points`
+/// writable values.
+#[no_mangle]
+pub unsafe extern "C" fn finsim_gen_returns(
+    params: *const FinsimParams,
+    seed: u64,
+    num_points: usize,
+    out: *mut f64,
+) -> c_int {
+    status((|| {
+        let params = params.as_ref().ok_or_else(|| Error::Invalid("params is null".to_string()))?;
+        let out = buffer(out, num_points)?;
+        for (o, r) in out.iter_mut().zip(gen_returns(&params.gen_args(num_points, seed))?) {
+            *o = r;
+        }
+        Ok(())
+    })())
+}
+
+/// Writes `paths` accumulated paths of `num_points` values each to `out`,
+/// one path after the other. Path i is generated with seed
+/// `model::path_seed(seed, i)`, like `finsim mc`.
+///
+/// # Safety
+///
+/// `params` must point to valid parameters and `out` to `paths * num_points`
+/// writable values.
+#[no_mangle]
+pub unsafe extern "C" fn finsim_gen_paths(
+    params: *const FinsimParams,
+    seed: u64,
+    paths: usize,
+    num_points: usize,
+    out: *mut f64,
+) -> c_int {
+    status((|| {
+        let params = params.as_ref().ok_or_else(|| Error::Invalid("params is null".to_string()))?;
+        let len = paths
+            .checked_mul(num_points)
+            .ok_or_else(|| Error::Invalid("paths * num_points overflows".to_string()))?;
+        let out = buffer(out, len)?;
+        let acc_args = params.acc_args()?;
+        for (i, path) in out.chunks_exact_mut(num_points.max(1)).enumerate() {
+            let returns = gen_returns(&params.gen_args(num_points, path_seed(seed, i)))?;
+            for (o, v) in path.iter_mut().zip(accumulate_iter(returns, &acc_args)) {
+                *o = v;
+            }
+        }
+        Ok(())
+    })())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{FINSIM_LEVERAGE_POINTWISE, FinsimParams, finsim_gen_paths, finsim_gen_returns, finsim_last_error};
+    use crate::model::path_seed;
+    use crate::returns::{AccumulateArgs, GenReturnsArgs, RngKind, TimeBase, accumulate, gen_returns};
+
+    const PARAMS: FinsimParams = FinsimParams {
+        yearly_mean: 1.07,
+        yearly_stddev: 1.15,
+        interval_seconds: 86400,
+        start_value: 100.0,
+        leverage_kind: FINSIM_LEVERAGE_POINTWISE,
+        leverage: 2.0,
+    };
+
+    #[test]
+    fn gen_paths_matches_library() {
+        let mut out = vec![0.0; 2 * 5];
+        assert_eq!(0, unsafe { finsim_gen_paths(&PARAMS, 7, 2, 5, out.as_mut_ptr()) });
+        let acc_args = AccumulateArgs {
+            accumulate: true,
+            start_value: 100.0,
+            continuous_leverage: None,
+            pointwise_leverage: Some(2.0),
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        for (i, path) in out.chunks(5).enumerate() {
+            let args = GenReturnsArgs {
+                total_seconds: None,
+                interval_seconds: Some(86400),
+                num_points: 5,
+                yearly_mean: 1.07,
+                yearly_stddev: 1.15,
+                seed: Some(path_seed(7, i)),
+                rng: RngKind::Std,
+                time_base: TimeBase::WallClock,
+                tail_df: None,
+            };
+            assert_eq!(accumulate(gen_returns(&args).unwrap(), &acc_args), path);
+        }
+
+        let mut returns = vec![0.0; 5];
+        assert_eq!(0, unsafe { finsim_gen_returns(&PARAMS, path_seed(7, 1), 5, returns.as_mut_ptr()) });
+        assert_approx_eq!(out[5] / 100.0, 1.0 + (returns[0] - 1.0) * 2.0);
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let params = FinsimParams { yearly_stddev: 0.5, ..PARAMS };
+        let mut out = vec![0.0; 5];
+        assert_eq!(-1, unsafe { finsim_gen_returns(&params, 1, 5, out.as_mut_ptr()) });
+        let message = unsafe { CStr::from_ptr(finsim_last_error()) }.to_str().unwrap();
+        assert!(message.starts_with("the yearly stddev must be"), "{}", message);
+
+        let params = FinsimParams { leverage_kind: 9, ..PARAMS };
+        assert_eq!(-1, unsafe { finsim_gen_paths(&params, 1, 1, 5, out.as_mut_ptr()) });
+        assert_eq!(-1, unsafe { finsim_gen_paths(&PARAMS, 1, 1, 5, std::ptr::null_mut()) });
+    }
+}
@@ -0,0 +1,87 @@
+//! Interop with polars: simulated paths as a `DataFrame` and historical
+//! prices from one
+
+use polars::prelude::*;
+
+use crate::calibrate::{Calibration, calibrate};
+use crate::error::{Error, Result};
+use crate::returns::TimeBase;
+use crate::simulation::Simulation;
+
+fn invalid(e: PolarsError) -> Error {
+    Error::Invalid(e.to_string())
+}
+
+/// `paths` runs of the simulation in long format, one row per tick with the
+/// columns `path`, `tick`, `t_seconds`, `return` and `value`. Path `i` is
+/// seeded like in `Simulation::monte_carlo`.
+pub fn paths_frame(sim: &Simulation, paths: usize) -> Result<DataFrame> {
+    let rows = paths * sim.gen_args().num_points;
+    let mut path = Vec::with_capacity(rows);
+    let mut tick = Vec::with_capacity(rows);
+    let mut t_seconds = Vec::with_capacity(rows);
+    let mut tick_return = Vec::with_capacity(rows);
+    let mut value = Vec::with_capacity(rows);
+    for i in 0..paths {
+        for (j, p) in sim.path(i)?.enumerate() {
+            path.push(i as u64);
+            tick.push(j as u64);
+            t_seconds.push(p.t_seconds);
+            tick_return.push(p.tick_return);
+            value.push(p.value);
+        }
+    }
+    df!(
+        "path" => path,
+        "tick" => tick,
+        "t_seconds" => t_seconds,
+        "return" => tick_return,
+        "value" => value,
+    )
+    .map_err(invalid)
+}
+
+/// The numbers in `column` of `df`, skipping nulls, e.g. closing prices
+pub fn column_values(df: &DataFrame, column: &str) -> Result<Vec<f64>> {
+    let column = df.column(column).and_then(|c| c.cast(&DataType::Float64)).map_err(invalid)?;
+    Ok(column.f64().map_err(invalid)?.iter().flatten().collect())
+}
+
+/// Like `calibrate`, for the prices in `price_column` of `df`, sampled every
+/// `interval_seconds` of `time_base` time
+pub fn calibrate_frame(df: &DataFrame, price_column: &str, interval_seconds: f64, time_base: TimeBase) -> Result<Calibration> {
+    let prices = column_values(df, price_column)?;
+    if prices.len() < 3 {
+        return Err(Error::Invalid(format!("at least 3 prices are needed for calibration, got {}", prices.len())));
+    }
+    Ok(calibrate(&prices, interval_seconds, time_base))
+}
+
+#[cfg(test)]
+mod tests {
+    use polars::prelude::*;
+
+    use super::{calibrate_frame, column_values, paths_frame};
+    use crate::returns::TimeBase;
+    use crate::simulation::SimulationBuilder;
+
+    #[test]
+    fn paths_frame_test() {
+        let sim = SimulationBuilder::new().num_points(4).interval(60).seed(3).accumulate(100.0).build().unwrap();
+        let df = paths_frame(&sim, 2).unwrap();
+        assert_eq!((8, 5), df.shape());
+        let values = column_values(&df, "value").unwrap();
+        assert_eq!(sim.run().unwrap(), values[..4]);
+        let last = sim.monte_carlo(2).unwrap()[1].last;
+        assert_eq!(last, values[7]);
+        assert_eq!(vec![60.0, 120.0, 180.0, 240.0], column_values(&df, "t_seconds").unwrap()[4..]);
+    }
+
+    #[test]
+    fn calibrate_frame_test() {
+        let df = df!("close" => [Some(1.0), None, Some(2.0), Some(1.0)]).unwrap();
+        let res = calibrate_frame(&df, "close", 31556952.0, TimeBase::WallClock).unwrap();
+        assert_eq!(2, res.num_returns);
+        assert!(calibrate_frame(&df, "open", 1.0, TimeBase::WallClock).is_err());
+    }
+}
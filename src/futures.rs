@@ -0,0 +1,76 @@
+//! Exposure through futures, rolled into the next contract at regular roll
+//! dates. Between rolls a future follows the underlying. At a roll the
+//! expiring contract is sold and the next one bought at a different price:
+//! in contango the next one is dearer, so the same money buys fewer
+//! contracts and the position loses the roll yield, as in commodity and VIX
+//! futures funds. In backwardation it's cheaper and the roll yield is a
+//! gain.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// When a futures position rolls and what each roll costs or earns
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct FuturesArgs {
+    /// Ticks between rolls, the first roll at the end of tick `roll_ticks`
+    pub roll_ticks: usize,
+    /// Return of each roll as a factor, e.g. 0.97 for a contango drag of 3%
+    /// per roll and 1.01 for a backwardation yield of 1%
+    pub roll_yield: f64,
+}
+
+impl FuturesArgs {
+    pub fn validate(&self) -> Result<()> {
+        if self.roll_ticks == 0 {
+            return Err(Error::Invalid("--roll-every must be at least one interval".to_string()));
+        }
+        if !self.roll_yield.is_finite() || self.roll_yield <= 0.0 {
+            return Err(Error::Invalid(format!("--roll-yield must be above -100%, got {}%", (self.roll_yield - 1.0) * 100.0)));
+        }
+        Ok(())
+    }
+
+    /// The returns of the futures position on an underlying with `returns`
+    pub fn roll<I: Iterator<Item = f64>>(&self, returns: I) -> Rolled<I> {
+        Rolled { returns, args: *self, tick: 0 }
+    }
+}
+
+/// Returns with the roll yield applied at every roll date, see
+/// `FuturesArgs::roll`
+pub struct Rolled<I> {
+    returns: I,
+    args: FuturesArgs,
+    tick: usize,
+}
+
+impl<I: Iterator<Item = f64>> Iterator for Rolled<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let r = self.returns.next()?;
+        self.tick += 1;
+        Some(if self.tick.is_multiple_of(self.args.roll_ticks) { r * self.args.roll_yield } else { r })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.returns.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FuturesArgs;
+
+    #[test]
+    fn roll_yield_at_roll_dates() {
+        let contango = FuturesArgs { roll_ticks: 2, roll_yield: 0.5 };
+        let returns: Vec<f64> = contango.roll(std::iter::repeat_n(2.0, 5)).collect();
+        assert_eq!(vec![2.0, 1.0, 2.0, 1.0, 2.0], returns);
+        let every_tick = FuturesArgs { roll_ticks: 1, roll_yield: 1.25 };
+        assert_eq!(vec![1.25, 2.5], every_tick.roll([1.0, 2.0].into_iter()).collect::<Vec<f64>>());
+        assert!(FuturesArgs { roll_ticks: 0, ..contango }.validate().is_err());
+        assert!(FuturesArgs { roll_yield: 0.0, ..contango }.validate().is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! Savings goals: how likely a plan is to reach a target by a date, and the
+//! contribution it takes to reach it with a given probability. The savings
+//! grow like the accumulation phase of a `RetireArgs` plan, and the target is
+//! in today's money.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::model::ReturnModel;
+use crate::retire::RetireArgs;
+use crate::returns::GenReturnsArgs;
+use crate::stats::percent;
+
+/// Bisection steps of `GoalArgs::solve_contribution`, enough to pin the
+/// contribution down to a billionth of the first bracket
+const BISECTION_STEPS: usize = 30;
+
+/// Doublings of the upper bound of the bisection before giving up on a
+/// target that no contribution reaches
+const MAX_DOUBLINGS: usize = 64;
+
+/// A target for the savings of a plan at the end of its accumulation phase
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct GoalArgs {
+    /// The savings, contributions and horizon, with no retirement phase
+    pub plan: RetireArgs,
+    /// Savings to have at the horizon, in today's money
+    pub target: f64,
+}
+
+/// The share of paths reaching the target
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct GoalReport {
+    pub paths: usize,
+    /// Paths on which the savings reached the target
+    pub successes: usize,
+}
+
+impl GoalArgs {
+    pub fn validate(&self) -> Result<()> {
+        if !self.target.is_finite() || self.target < 0.0 {
+            return Err(Error::Invalid(format!("--target must be a non-negative number, got {}", self.target)));
+        }
+        self.plan.validate()
+    }
+
+    /// How many of `paths` paths of `model` reach the target, with paths
+    /// seeded like `RetireArgs::plan_paths`
+    pub fn probability(
+        &self,
+        model: &dyn ReturnModel,
+        gen_args: &GenReturnsArgs,
+        paths: usize,
+        seed: Option<u64>,
+    ) -> Result<GoalReport> {
+        let outcomes = self.plan.plan_paths(model, gen_args, paths, seed)?;
+        Ok(GoalReport { paths, successes: outcomes.iter().filter(|o| o.at_retirement >= self.target).count() })
+    }
+
+    /// The smallest contribution per tick with which at least a share
+    /// `probability` of the paths reach the target, by bisection over the
+    /// same paths for every contribution, or `None` if no contribution does,
+    /// e.g. without any ticks to save in. A random seed is drawn once without
+    /// `seed`, the paths have to stay the same for the bisection to converge.
+    pub fn solve_contribution(
+        &self,
+        model: &dyn ReturnModel,
+        gen_args: &GenReturnsArgs,
+        paths: usize,
+        seed: Option<u64>,
+        probability: f64,
+    ) -> Result<Option<f64>> {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(Error::Invalid(format!("--probability must be between 0% and 100%, got {}%", probability * 100.0)));
+        }
+        let seed = Some(seed.unwrap_or_else(rand::random));
+        let reaches = |contribution: f64| -> Result<bool> {
+            let goal = GoalArgs { plan: RetireArgs { contribution, salary: None, ..self.plan.clone() }, target: self.target };
+            let report = goal.probability(model, gen_args, paths, seed)?;
+            Ok(report.successes as f64 >= probability * paths as f64)
+        };
+        if reaches(0.0)? {
+            return Ok(Some(0.0));
+        }
+        // A first guess saving the target without any growth
+        let mut high = self.target.max(1.0) / self.plan.accumulation_ticks.max(1) as f64;
+        let mut doublings = 0;
+        while !reaches(high)? {
+            doublings += 1;
+            if doublings > MAX_DOUBLINGS {
+                return Ok(None);
+            }
+            high *= 2.0;
+        }
+        let mut low = 0.0;
+        for _ in 0..BISECTION_STEPS {
+            let mid = 0.5 * (low + high);
+            if reaches(mid)? {
+                high = mid;
+            } else {
+                low = mid;
+            }
+        }
+        Ok(Some(high))
+    }
+}
+
+impl GoalReport {
+    /// The share of paths reaching the target in percent
+    pub fn percent(&self) -> f64 {
+        percent(self.successes, self.paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GoalArgs;
+    use crate::inflation::InflationArgs;
+    use crate::model::LogNormal;
+    use crate::retire::RetireArgs;
+    use crate::returns::{GenReturnsArgs, RngKind, TimeBase};
+
+    fn goal(contribution: f64, target: f64) -> GoalArgs {
+        let plan = RetireArgs {
+            balance: 0.0,
+            contribution,
+            salary: None,
+            withdrawal: 0.0,
+            accumulation_ticks: 10,
+            retirement_ticks: 0,
+            inflation: InflationArgs::constant(1.0),
+            annuity: None,
+        };
+        GoalArgs { plan, target }
+    }
+
+    fn gen_args(yearly_stddev: f64) -> GenReturnsArgs {
+        GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(31556952),
+            num_points: 0,
+            yearly_mean: 1.05,
+            yearly_stddev,
+            seed: Some(1),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        }
+    }
+
+    #[test]
+    fn probability_of_a_target() {
+        let gen_args = gen_args(1.2);
+        let model = LogNormal::from(&gen_args);
+        let report = goal(10.0, 130.0).probability(&model, &gen_args, 200, Some(1)).unwrap();
+        assert_eq!(200, report.paths);
+        assert!(report.successes > 0 && report.successes < 200);
+        assert_eq!(200, goal(10.0, 0.0).probability(&model, &gen_args, 200, Some(1)).unwrap().successes);
+        assert!(goal(10.0, f64::NAN).validate().is_err());
+    }
+
+    #[test]
+    fn contribution_for_a_probability() {
+        // Without volatility every path saves 10 contributions growing at 5%
+        let gen_args = gen_args(1.0);
+        let model = LogNormal::from(&gen_args);
+        let future_value = (1.05f64.powi(10) - 1.0) / 0.05;
+        let contribution = goal(0.0, 1000.0).solve_contribution(&model, &gen_args, 10, Some(1), 0.9).unwrap().unwrap();
+        assert!((contribution * future_value - 1000.0).abs() < 1e-3);
+
+        // More certainty takes more saving
+        let gen_args = self::gen_args(1.2);
+        let model = LogNormal::from(&gen_args);
+        let solve = |p| goal(0.0, 1000.0).solve_contribution(&model, &gen_args, 200, Some(1), p).unwrap().unwrap();
+        let (median, likely) = (solve(0.5), solve(0.9));
+        assert!(likely > median);
+        let report = goal(likely, 1000.0).probability(&model, &gen_args, 200, Some(1)).unwrap();
+        assert!(report.percent() >= 90.0);
+        assert_eq!(Some(0.0), goal(0.0, 0.0).solve_contribution(&model, &gen_args, 200, Some(1), 0.9).unwrap());
+        assert!(goal(0.0, 1000.0).solve_contribution(&model, &gen_args, 200, Some(1), 1.5).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! A stochastic price level to deflate values and index cash flows with. The
+//! yearly inflation rate follows an AR(1) process: every tick it moves part
+//! of the way back to its target and takes a normal shock, and the price
+//! level grows at the rate of the tick. Dividing by the price level turns
+//! nominal values into real ones, in the money of t=0.
+
+use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::model::path_seed;
+use crate::portable;
+use crate::returns::{DataPoint, RngKind, SeededRng};
+
+/// The inflation process, with rates as yearly factors, e.g. 1.02 for 2%
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct InflationArgs {
+    /// The rate the process reverts to
+    pub target: f64,
+    /// The rate at t=0
+    pub start: f64,
+    /// Share of a deviation from the target left after a year, at least 0
+    /// and below 1
+    pub persistence: f64,
+    /// Long-run standard deviation of the yearly log rate, e.g. 0.01 for
+    /// about 1 percentage point. 0 for a deterministic path from `start` to
+    /// `target`.
+    pub volatility: f64,
+}
+
+impl InflationArgs {
+    /// A constant rate
+    pub fn constant(yearly: f64) -> InflationArgs {
+        InflationArgs { target: yearly, start: yearly, persistence: 0.0, volatility: 0.0 }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (flag, rate) in [("--inflation", self.target), ("--inflation-start", self.start)] {
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(Error::Invalid(format!("{} must be above -100%, got {}%", flag, (rate - 1.0) * 100.0)));
+            }
+        }
+        if !(0.0..1.0).contains(&self.persistence) {
+            return Err(Error::Invalid(format!("--inflation-persistence must be at least 0 and below 1, got {}", self.persistence)));
+        }
+        if !self.volatility.is_finite() || self.volatility < 0.0 {
+            return Err(Error::Invalid(format!("--inflation-vol must be a non-negative number, got {}", self.volatility)));
+        }
+        Ok(())
+    }
+
+    /// The price level at each of `num_points` points, relative to 1 at t=0,
+    /// with `ticks_per_year` ticks a year. The shocks are drawn from a
+    /// generator of kind `rng` of their own, seeded with
+    /// `path_seed(seed, usize::MAX)` so that the returns generated with
+    /// `seed` stay the same.
+    pub fn levels(&self, ticks_per_year: f64, num_points: usize, rng: RngKind, seed: Option<u64>) -> Inflation {
+        let phi = self.persistence.powf(1.0 / ticks_per_year);
+        Inflation {
+            target: self.target.ln(),
+            rate: self.start.ln(),
+            phi,
+            sigma: self.volatility * (1.0 - phi * phi).sqrt(),
+            years_per_tick: 1.0 / ticks_per_year,
+            level: 1.0,
+            rng: SeededRng::new(rng, seed.map(|s| path_seed(s, usize::MAX))),
+            portable: (rng == RngKind::Portable).then(|| portable::Normal::new(0.0, 1.0)),
+            remaining: num_points,
+        }
+    }
+}
+
+/// The inflation to deflate simulated paths by, drawn alongside their returns
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RealArgs {
+    pub inflation: InflationArgs,
+    pub ticks_per_year: f64,
+    /// The generator of the shocks, that of the returns
+    pub rng: RngKind,
+}
+
+impl RealArgs {
+    /// The price levels of a path whose returns are seeded with `seed`
+    pub fn levels(&self, num_points: usize, seed: Option<u64>) -> Inflation {
+        self.inflation.levels(self.ticks_per_year, num_points, self.rng, seed)
+    }
+}
+
+/// `value` at a point with price level `level` in the money of t=0: an
+/// accumulated value divided by the level, a return by its change since the
+/// previous point at `prev_level`. The log of a value, with `log_values`,
+/// has the log of the divisor subtracted instead.
+fn real(value: f64, accumulated: bool, log_values: bool, prev_level: f64, level: f64) -> f64 {
+    let divisor = if accumulated { level } else { level / prev_level };
+    if log_values { value - divisor.ln() } else { value / divisor }
+}
+
+/// The values, accumulated or returns and their logs with `log_values`, in
+/// the money of t=0 with the price level at each from `levels`. Ends with the
+/// shorter of both.
+pub fn deflate<I: Iterator<Item = f64>, L: Iterator<Item = f64>>(
+    values: I,
+    levels: L,
+    accumulated: bool,
+    log_values: bool,
+) -> Deflated<I, L> {
+    Deflated { values, levels, accumulated, log_values, prev_level: 1.0 }
+}
+
+/// Iterator over real values, see `deflate`
+pub struct Deflated<I, L> {
+    values: I,
+    levels: L,
+    accumulated: bool,
+    log_values: bool,
+    prev_level: f64,
+}
+
+impl<I: Iterator<Item = f64>, L: Iterator<Item = f64>> Iterator for Deflated<I, L> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let (value, level) = (self.values.next()?, self.levels.next()?);
+        let value = real(value, self.accumulated, self.log_values, self.prev_level, level);
+        self.prev_level = level;
+        Some(value)
+    }
+}
+
+/// The data points with the price level at each from `levels`, and with
+/// `real` their returns and values in the money of t=0 like `deflate`. Ends
+/// with the shorter of both, and passes the data points through unchanged
+/// without levels.
+pub fn with_levels<I: Iterator<Item = DataPoint>, L: Iterator<Item = f64>>(
+    rows: I,
+    levels: Option<L>,
+    real: bool,
+    accumulated: bool,
+    log_values: bool,
+) -> WithLevels<I, L> {
+    WithLevels { rows, levels, real, accumulated, log_values, prev_level: 1.0 }
+}
+
+/// Iterator over data points with price levels, see `with_levels`
+pub struct WithLevels<I, L> {
+    rows: I,
+    levels: Option<L>,
+    real: bool,
+    accumulated: bool,
+    log_values: bool,
+    prev_level: f64,
+}
+
+impl<I: Iterator<Item = DataPoint>, L: Iterator<Item = f64>> Iterator for WithLevels<I, L> {
+    type Item = DataPoint;
+
+    fn next(&mut self) -> Option<DataPoint> {
+        let Some(levels) = &mut self.levels else {
+            return self.rows.next();
+        };
+        let (p, level) = (self.rows.next()?, levels.next()?);
+        let p = match self.real {
+            true => DataPoint {
+                tick_return: real(p.tick_return, false, false, self.prev_level, level),
+                value: real(p.value, self.accumulated, self.log_values, self.prev_level, level),
+                ..p
+            },
+            false => p,
+        };
+        self.prev_level = level;
+        Some(DataPoint { price_level: Some(level), ..p })
+    }
+}
+
+/// Iterator over price levels, see `InflationArgs::levels`
+pub struct Inflation {
+    /// Log of the target rate
+    target: f64,
+    /// Log of the current rate
+    rate: f64,
+    /// Persistence over one tick
+    phi: f64,
+    /// Standard deviation of the shock of one tick
+    sigma: f64,
+    years_per_tick: f64,
+    level: f64,
+    rng: SeededRng,
+    /// The sampling of `RngKind::Portable`, `None` for the other generators
+    portable: Option<portable::Normal>,
+    remaining: usize,
+}
+
+impl Iterator for Inflation {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.level *= (self.rate * self.years_per_tick).exp();
+        let z: f64 = match &mut self.portable {
+            Some(normal) => normal.sample(&mut self.rng),
+            None => StandardNormal.sample(&mut self.rng),
+        };
+        self.rate = self.target + self.phi * (self.rate - self.target) + self.sigma * z;
+        Some(self.level)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{InflationArgs, deflate, with_levels};
+    use crate::returns::{AccumulateArgs, RngKind, data_points};
+
+    #[test]
+    fn deterministic_rates() {
+        let levels: Vec<f64> = InflationArgs::constant(1.02).levels(4.0, 8, RngKind::Std, Some(1)).collect();
+        assert_eq!(8, levels.len());
+        assert_approx_eq!(1.02, levels[3], 1e-12);
+        assert_approx_eq!(1.0404, levels[7], 1e-12);
+
+        // From 10% back to 2%, halving the deviation of the log rate every
+        // year
+        let args = InflationArgs { start: 1.1, persistence: 0.5, ..InflationArgs::constant(1.02) };
+        let levels: Vec<f64> = args.levels(1.0, 2, RngKind::Std, None).collect();
+        assert_approx_eq!(1.1, levels[0], 1e-12);
+        let second = (1.02f64.ln() + 0.5 * (1.1f64.ln() - 1.02f64.ln())).exp();
+        assert_approx_eq!(1.1 * second, levels[1], 1e-12);
+    }
+
+    #[test]
+    fn shocks_revert_to_the_target() {
+        let args = InflationArgs { target: 1.03, start: 1.03, persistence: 0.6, volatility: 0.02 };
+        let levels: Vec<f64> = args.levels(12.0, 12 * 2000, RngKind::Std, Some(3)).collect();
+        let yearly: Vec<f64> = levels.chunks(12).scan(1.0, |prev, year| {
+            let rate = (year[11] / *prev).ln();
+            *prev = year[11];
+            Some(rate)
+        }).collect();
+        let mean = yearly.iter().sum::<f64>() / yearly.len() as f64;
+        assert_approx_eq!(1.03f64.ln(), mean, 2e-3);
+        assert!(yearly.iter().any(|&r| (r - mean).abs() > 0.01));
+        let same: Vec<f64> = args.levels(12.0, 12, RngKind::Std, Some(3)).collect();
+        assert_eq!(levels[..12], same[..]);
+        assert!(InflationArgs { persistence: 1.0, ..args }.validate().is_err());
+        assert!(InflationArgs { volatility: -0.1, ..args }.validate().is_err());
+    }
+
+    #[test]
+    fn real_values_and_returns() {
+        let levels = [1.25, 1.5];
+        let values: Vec<f64> = deflate([2.5, 6.0].into_iter(), levels.into_iter(), true, false).collect();
+        assert_eq!(vec![2.0, 4.0], values);
+        let returns: Vec<f64> = deflate([2.5, 2.4].into_iter(), levels.into_iter(), false, false).collect();
+        assert_eq!(vec![2.0, 2.0], returns);
+
+        let acc_args = AccumulateArgs {
+            accumulate: true,
+            start_value: 1.0,
+            continuous_leverage: None,
+            pointwise_leverage: None,
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        let rows = || data_points([2.5, 2.4].into_iter(), 1.0, &acc_args);
+        let nominal: Vec<_> = with_levels(rows(), Some(levels.into_iter()), false, true, false).collect();
+        assert_eq!((2.5, 6.0, Some(1.5)), (nominal[0].tick_return, nominal[1].value, nominal[1].price_level));
+        let real: Vec<_> = with_levels(rows(), Some(levels.into_iter()), true, true, false).collect();
+        assert_eq!((2.0, 4.0, Some(1.5)), (real[1].tick_return, real[1].value, real[1].price_level));
+        assert_eq!(rows().collect::<Vec<_>>(), with_levels(rows(), None::<std::iter::Empty<f64>>, true, true, false).collect::<Vec<_>>());
+
+        // Logs of values have the log of the level subtracted
+        let logs: Vec<f64> = deflate([2.5f64.ln(), 6.0f64.ln()].into_iter(), levels.into_iter(), true, true).collect();
+        assert_approx_eq!(2.0f64.ln(), logs[0], 1e-12);
+        assert_approx_eq!(4.0f64.ln(), logs[1], 1e-12);
+        let logs: Vec<f64> = deflate([2.5f64.ln(), 2.4f64.ln()].into_iter(), levels.into_iter(), false, true).collect();
+        assert_approx_eq!(2.0f64.ln(), logs[1], 1e-12);
+        let log_args = AccumulateArgs { log_values: true, ..acc_args.clone() };
+        let log_rows = || data_points([2.5, 2.4].into_iter(), 1.0, &log_args);
+        let real: Vec<_> = with_levels(log_rows(), Some(levels.into_iter()), true, true, true).collect();
+        assert_approx_eq!(2.0, real[1].tick_return, 1e-12);
+        assert_approx_eq!(4.0f64.ln(), real[1].value, 1e-12);
+    }
+}
@@ -0,0 +1,354 @@
+use std::io::{self, BufRead, Read};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::output::{STREAM_MAGIC, StreamHeader, StreamKind};
+use crate::returns::DataPoint;
+
+/// Whether a series holds prices or returns
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SeriesKind {
+    /// Guess from the data
+    Auto,
+    /// Gross returns, e.g. 1.01 for +1%
+    Returns,
+    /// Price levels
+    Prices,
+}
+
+impl SeriesKind {
+    /// Resolves `Auto` by guessing from the series, falling back to `default`
+    /// when the series is too short to tell
+    pub fn resolve(self, series: &[f64], default: SeriesKind) -> SeriesKind {
+        match self {
+            SeriesKind::Auto => guess_kind(series).unwrap_or(default),
+            kind => kind,
+        }
+    }
+}
+
+/// Guesses whether a series holds prices or returns. Returns hover around 1
+/// independently of each other, so they are typically closer to 1 than to
+/// the previous return. Prices drift away from any fixed level but stay close
+/// to the previous price. `None` if there are fewer than 3 values.
+pub fn guess_kind(series: &[f64]) -> Option<SeriesKind> {
+    if series.len() < 3 {
+        return None;
+    }
+    let n = series.len() as f64;
+    let dist_from_one = series.iter().map(|v| (v - 1.0).abs()).sum::<f64>() / n;
+    let dist_from_prev = series.windows(2).map(|w| (w[1] / w[0] - 1.0).abs()).sum::<f64>() / (n - 1.0);
+    if dist_from_one < dist_from_prev {
+        Some(SeriesKind::Returns)
+    } else {
+        Some(SeriesKind::Prices)
+    }
+}
+
+/// Returns between consecutive prices, one fewer than the prices
+pub fn prices_to_returns(prices: &[f64]) -> Vec<f64> {
+    prices.windows(2).map(|w| w[1] / w[0]).collect()
+}
+
+/// Prices starting at `start` and compounding the returns, one more than the
+/// returns
+pub fn returns_to_prices(returns: &[f64], start: f64) -> Vec<f64> {
+    let mut price = start;
+    std::iter::once(start)
+        .chain(returns.iter().map(|r| {
+            price *= r;
+            price
+        }))
+        .collect()
+}
+
+/// Series read by `read_input`
+pub enum Input {
+    /// One value per line
+    Plain(Vec<f64>),
+    /// The series of the stream format, one per header
+    Stream(Vec<StreamSeries>),
+}
+
+impl Input {
+    /// The values, those of all series of a stream one after the other
+    pub fn into_values(self) -> Vec<f64> {
+        match self {
+            Input::Plain(series) => series,
+            Input::Stream(series) => series.iter().flat_map(|s| s.points.iter().map(|p| p.value)).collect(),
+        }
+    }
+}
+
+/// A series of the stream format, see `output::StreamHeader`
+pub struct StreamSeries {
+    pub header: StreamHeader,
+    /// Returns are NaN without a return column
+    pub points: Vec<DataPoint>,
+}
+
+impl StreamSeries {
+    pub fn values(&self) -> Vec<f64> {
+        self.points.iter().map(|p| p.value).collect()
+    }
+
+    /// The returns behind the values, recovered from consecutive values
+    /// (and the start value) of accumulated series
+    pub fn returns(&self) -> Vec<f64> {
+        let values = self.points.iter().map(|p| p.value);
+        match self.header.kind {
+            StreamKind::Returns => values.collect(),
+            StreamKind::LogReturns => values.map(f64::exp).collect(),
+            StreamKind::Values => prices_to_returns(&std::iter::once(self.header.start_value).chain(values).collect::<Vec<_>>()),
+            StreamKind::LogValues => {
+                let logs: Vec<f64> = std::iter::once(self.header.start_value.ln()).chain(values).collect();
+                logs.windows(2).map(|w| (w[1] - w[0]).exp()).collect()
+            }
+        }
+    }
+}
+
+/// Reads one value per line like `read_series`, or the stream format when
+/// the input starts with its header
+pub fn read_input(mut r: impl BufRead) -> Result<Input> {
+    let mut first = String::new();
+    r.read_line(&mut first)?;
+    let stream = first.starts_with(STREAM_MAGIC);
+    let r = io::Cursor::new(first).chain(r);
+    if stream {
+        read_stream(r).map(Input::Stream)
+    } else {
+        read_plain(r).map(Input::Plain)
+    }
+}
+
+/// Reads a series with one value per line. Blank lines and lines starting
+/// with `#` are skipped. The values of the stream format are read as well.
+pub fn read_series(r: impl BufRead) -> Result<Vec<f64>> {
+    read_input(r).map(Input::into_values)
+}
+
+fn read_stream(r: impl BufRead) -> Result<Vec<StreamSeries>> {
+    let mut series: Vec<StreamSeries> = Vec::new();
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        if line.starts_with(STREAM_MAGIC) {
+            series.push(StreamSeries { header: line.trim_end().parse()?, points: Vec::new() });
+            continue;
+        }
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some(s) = series.last_mut() else { continue };
+        let invalid = || Error::Parse(format!("line {}: invalid stream line '{}'", i + 1, line));
+        let mut fields = line.split_whitespace().map(|f| f.parse::<f64>());
+        let mut next = || fields.next().and_then(|f| f.ok()).ok_or_else(invalid);
+        let (t_seconds, value) = (next()?, next()?);
+        let tick_return = if s.header.with_returns { next()? } else { f64::NAN };
+        let price_level = if s.header.with_inflation { Some(next()?).filter(|l| !l.is_nan()) } else { None };
+        s.points.push(DataPoint { t_seconds, tick_return, value, price_level });
+    }
+    Ok(series)
+}
+
+fn read_plain(r: impl BufRead) -> Result<Vec<f64>> {
+    let mut series = Vec::new();
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let v = line
+            .parse::<f64>()
+            .map_err(|_| Error::Parse(format!("line {}: invalid number '{}'", i + 1, line)))?;
+        series.push(v);
+    }
+    Ok(series)
+}
+
+/// Prices read from a CSV file, with the time of each price if the file has
+/// a date column
+#[derive(Deserialize, Serialize)]
+pub struct PriceSeries {
+    pub prices: Vec<f64>,
+    /// Unix time in seconds of each price
+    pub timestamps: Option<Vec<i64>>,
+}
+
+/// Reads prices from a CSV file such as those exported by Yahoo Finance or
+/// Stooq. Columns are given by header name or 0-based index; the first line
+/// is treated as a header when the price column there isn't a number. Rows
+/// with a missing price (empty or `null`) are skipped. Dates must start with
+/// `YYYY-MM-DD`; a missing date column just means no timestamps.
+pub fn read_price_csv(r: impl BufRead, price_column: &str, date_column: &str) -> Result<PriceSeries> {
+    let invalid = Error::Parse;
+    let mut price_idx = price_column.parse::<usize>().ok();
+    let mut date_idx = date_column.parse::<usize>().ok();
+    let mut prices = Vec::new();
+    let mut timestamps = Vec::new();
+    for (i, line) in r.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(|f| f.trim().trim_matches('"')).collect();
+        if i == 0 && price_idx.is_none_or(|p| fields.get(p).is_none_or(|f| f.parse::<f64>().is_err())) {
+            let find = |name: &str| fields.iter().position(|f| f.eq_ignore_ascii_case(name));
+            price_idx = price_idx.or_else(|| find(price_column));
+            date_idx = date_idx.or_else(|| find(date_column));
+            if price_idx.is_none() {
+                return Err(invalid(format!("no column named '{}' in header", price_column)));
+            }
+            continue;
+        }
+        let price_idx = price_idx.ok_or_else(|| invalid(format!("no column named '{}'", price_column)))?;
+        let price = match fields.get(price_idx) {
+            Some(&"") | Some(&"null") => continue,
+            Some(f) => f.parse::<f64>().map_err(|_| invalid(format!("line {}: invalid price '{}'", i + 1, f)))?,
+            None => return Err(invalid(format!("line {}: missing column {}", i + 1, price_idx))),
+        };
+        prices.push(price);
+        if let Some(date_idx) = date_idx {
+            let date = fields.get(date_idx).copied().unwrap_or_default();
+            let t = parse_date(date).ok_or_else(|| invalid(format!("line {}: invalid date '{}'", i + 1, date)))?;
+            timestamps.push(t);
+        }
+    }
+    Ok(PriceSeries {
+        prices,
+        timestamps: date_idx.map(|_| timestamps),
+    })
+}
+
+/// Parses the `YYYY-MM-DD` prefix of `s` into a unix time in seconds (at
+/// midnight UTC)
+pub(crate) fn parse_date(s: &str) -> Option<i64> {
+    let date = s.get(..10)?;
+    let mut parts = date.split('-');
+    let y: i64 = parts.next()?.parse().ok()?;
+    let m: i64 = parts.next()?.parse().ok()?;
+    let d: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    // Days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some((era * 146097 + doe - 719468) * 86400)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{Input, SeriesKind, guess_kind, parse_date, prices_to_returns, read_input, read_price_csv, read_series, returns_to_prices};
+    use crate::returns::{GenReturnsArgs, RngKind, TimeBase, gen_returns};
+
+    #[test]
+    fn read_series_test() {
+        let input = "# daily returns\n1.01\n\n 0.99 \n1e0\n";
+        assert_eq!(vec![1.01, 0.99, 1.0], read_series(input.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn read_stream_input() {
+        let input = concat!(
+            "# finsim kind=values interval_seconds=60 start_value=2 scenario=a columns=t_seconds,value\n",
+            "60\t3\n120\t1.5\n",
+            "# finsim kind=log-values start_value=1 columns=t_seconds,value,return\n",
+            "60\t0.5\tNaN\n",
+        );
+        let Input::Stream(series) = read_input(input.as_bytes()).unwrap() else { panic!("not a stream") };
+        assert_eq!(2, series.len());
+        assert_eq!((Some("a"), 60.0), (series[0].header.scenario.as_deref(), series[0].header.interval_seconds));
+        assert_eq!(vec![120.0], series[0].points.iter().skip(1).map(|p| p.t_seconds).collect::<Vec<_>>());
+        // The returns are recovered from the start value
+        assert_eq!(vec![1.5, 0.5], series[0].returns());
+        assert_approx_eq!(0.5f64.exp(), series[1].returns()[0], 1e-12);
+        assert!(series[1].points[0].tick_return.is_nan());
+        // Readers of plain values get the values of every series
+        assert_eq!(vec![3.0, 1.5, 0.5], read_series(input.as_bytes()).unwrap());
+        assert!(read_input("# finsim columns=t_seconds,value\n60\n".as_bytes()).is_err());
+        assert!(matches!(read_input("1.01\n".as_bytes()).unwrap(), Input::Plain(s) if s == [1.01]));
+    }
+
+    #[test]
+    fn read_series_invalid_line() {
+        let err = read_series("1.01\nabc\n".as_bytes()).unwrap_err();
+        assert_eq!("line 2: invalid number 'abc'", err.to_string());
+    }
+
+    #[test]
+    fn guess_kind_test() {
+        let mut args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(86400),
+            num_points: 250,
+            yearly_mean: 1.07,
+            yearly_stddev: 1.2,
+            seed: Some(1),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        for interval_seconds in [60, 86400, 31556952] {
+            args.interval_seconds = Some(interval_seconds);
+            let returns: Vec<f64> = gen_returns(&args).unwrap().collect();
+            assert_eq!(Some(SeriesKind::Returns), guess_kind(&returns));
+            assert_eq!(Some(SeriesKind::Prices), guess_kind(&returns_to_prices(&returns, 100.0)));
+            // Prices close to 1, like exchange rates
+            assert_eq!(Some(SeriesKind::Prices), guess_kind(&returns_to_prices(&returns, 1.1)));
+        }
+        assert_eq!(None, guess_kind(&[1.0, 1.1]));
+        assert_eq!(SeriesKind::Prices, SeriesKind::Auto.resolve(&[1.0], SeriesKind::Prices));
+        assert_eq!(SeriesKind::Returns, SeriesKind::Returns.resolve(&[100.0, 101.0, 102.0], SeriesKind::Prices));
+    }
+
+    #[test]
+    fn convert_prices_and_returns() {
+        assert_eq!(vec![100.0, 150.0, 75.0], returns_to_prices(&[1.5, 0.5], 100.0));
+        assert_eq!(vec![1.5, 0.5], prices_to_returns(&[100.0, 150.0, 75.0]));
+        assert!(prices_to_returns(&[100.0]).is_empty());
+    }
+
+    #[test]
+    fn parse_date_test() {
+        assert_eq!(Some(0), parse_date("1970-01-01"));
+        assert_eq!(Some(951782400), parse_date("2000-02-29"));
+        assert_eq!(Some(1700006400), parse_date("2023-11-15 00:00:00"));
+        assert_eq!(None, parse_date("2023-13-01"));
+        assert_eq!(None, parse_date("15/11/2023"));
+    }
+
+    #[test]
+    fn read_price_csv_with_header() {
+        let csv = "Date,Open,High,Low,Close,Adj Close,Volume\n\
+                   2023-01-03,1,1,1,100.0,99.0,10\n\
+                   2023-01-04,1,1,1,null,null,10\n\
+                   2023-01-05,1,1,1,102.5,101.0,10\n";
+        let series = read_price_csv(csv.as_bytes(), "Close", "Date").unwrap();
+        assert_eq!(vec![100.0, 102.5], series.prices);
+        assert_eq!(Some(vec![1672704000, 1672876800]), series.timestamps);
+
+        let series = read_price_csv(csv.as_bytes(), "adj close", "none").unwrap();
+        assert_eq!(vec![99.0, 101.0], series.prices);
+        assert_eq!(None, series.timestamps);
+
+        assert!(read_price_csv(csv.as_bytes(), "Price", "Date").is_err());
+    }
+
+    #[test]
+    fn read_price_csv_without_header() {
+        let csv = "2023-01-03,100\n2023-01-04,101\n";
+        let series = read_price_csv(csv.as_bytes(), "1", "0").unwrap();
+        assert_eq!(vec![100.0, 101.0], series.prices);
+        assert_eq!(Some(vec![1672704000, 1672790400]), series.timestamps);
+    }
+}
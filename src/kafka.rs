@@ -0,0 +1,518 @@
+//! A minimal Kafka producer for the text output, so that streaming pipelines
+//! can be load-tested with synthetic market data. Every line of output is
+//! published as a record to one partition of a topic, in batches, with Produce
+//! requests (version 3, the oldest Kafka 4 accepts) holding an uncompressed
+//! record batch. The first broker that accepts a connection is asked for the
+//! partition's leader with a Metadata request (version 4), and the records go
+//! to that leader. The record's timestamp is the time it was sent.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::info;
+
+use crate::error::{Error, Result};
+
+/// Records sent per Produce request unless the output is unbuffered
+pub const BATCH_SIZE: usize = 1000;
+
+/// Where to publish: `kafka://<broker>[,<broker>...]/<topic>[?key=<key>&partition=<n>]`
+#[derive(Clone, Debug, PartialEq)]
+pub struct KafkaTarget {
+    /// Brokers tried in order, as `host:port`
+    pub brokers: Vec<String>,
+    pub topic: String,
+    /// Key of every record, none by default
+    pub key: Option<String>,
+    pub partition: i32,
+}
+
+impl FromStr for KafkaTarget {
+    type Err = Error;
+
+    /// Parses the part after `kafka://`
+    fn from_str(s: &str) -> Result<Self> {
+        let (location, query) = s.split_once('?').unwrap_or((s, ""));
+        let (brokers, topic) = location
+            .split_once('/')
+            .filter(|(brokers, topic)| !brokers.is_empty() && !topic.is_empty())
+            .ok_or_else(|| Error::Parse(format!("expected kafka://<broker>[,<broker>...]/<topic>, got 'kafka://{}'", s)))?;
+        let mut target = KafkaTarget {
+            brokers: brokers.split(',').map(str::to_string).collect(),
+            topic: topic.to_string(),
+            key: None,
+            partition: 0,
+        };
+        for param in query.split('&').filter(|p| !p.is_empty()) {
+            match param.split_once('=') {
+                Some(("key", key)) => target.key = Some(key.to_string()),
+                Some(("partition", n)) => {
+                    target.partition = n.parse().ok().filter(|&n| n >= 0).ok_or_else(|| {
+                        Error::Parse(format!("partition must be a non-negative integer, got '{}'", n))
+                    })?
+                }
+                _ => return Err(Error::Parse(format!("unsupported Kafka parameter '{}', expected key or partition", param))),
+            }
+        }
+        Ok(target)
+    }
+}
+
+impl fmt::Display for KafkaTarget {
+    /// The part after `kafka://`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}?", self.brokers.join(","), self.topic)?;
+        if let Some(key) = &self.key {
+            write!(f, "key={}&", key)?;
+        }
+        write!(f, "partition={}", self.partition)
+    }
+}
+
+/// A connection to the partition's leader, publishing the output line by line
+pub struct Producer {
+    stream: TcpStream,
+    target: KafkaTarget,
+    batch_size: usize,
+    /// Complete lines not yet sent
+    records: Vec<Vec<u8>>,
+    /// Output after the last complete line
+    line: Vec<u8>,
+    correlation_id: i32,
+}
+
+impl Producer {
+    /// Looks up the leader of the partition of `target` on the first broker
+    /// that accepts, and connects to it to send `batch_size` records at a time
+    pub fn connect(target: &KafkaTarget, batch_size: usize) -> Result<Producer> {
+        let mut last_error = None;
+        for broker in &target.brokers {
+            let mut stream = match TcpStream::connect(broker) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    last_error = Some(format!("{}: {}", broker, e));
+                    continue;
+                }
+            };
+            let request = metadata_request(0, &target.topic);
+            let (host, port) = roundtrip(&mut stream, &request)
+                .and_then(|response| partition_leader(&response, &target.topic, target.partition))
+                .map_err(|e| Error::Invalid(format!("no Kafka partition leader from {}, {}", broker, e)))?;
+            let leader = format!("{}:{}", host, port);
+            let stream = TcpStream::connect((host.as_str(), port))
+                .map_err(|e| Error::Invalid(format!("Kafka partition leader {} unreachable, {}", leader, e)))?;
+            info!(broker, leader, "connected to Kafka");
+            return Ok(Producer {
+                stream,
+                target: target.clone(),
+                batch_size: batch_size.max(1),
+                records: Vec::new(),
+                line: Vec::new(),
+                correlation_id: 0,
+            });
+        }
+        Err(Error::Invalid(format!("no Kafka broker reachable, {}", last_error.unwrap_or_default())))
+    }
+
+    /// Sends the records waiting, if any, and checks that the broker took them
+    fn send(&mut self) -> io::Result<()> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as i64);
+        let key = self.target.key.as_deref().map(str::as_bytes);
+        let batch = record_batch(key, &self.records, timestamp);
+        self.correlation_id = self.correlation_id.wrapping_add(1);
+        let request = produce_request(self.correlation_id, &self.target.topic, self.target.partition, &batch);
+        let response = roundtrip(&mut self.stream, &request)?;
+        if let Some(code) = produce_error(&response)? {
+            return Err(kafka_error(code, &self.target.topic, self.target.partition));
+        }
+        self.records.clear();
+        Ok(())
+    }
+}
+
+impl Write for Producer {
+    /// Buffers the output and queues every complete line, without its line
+    /// break, as a record
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        while let Some(end) = self.line.iter().position(|&b| b == b'\n') {
+            self.records.push(self.line[..end].to_vec());
+            self.line.drain(..=end);
+            if self.records.len() >= self.batch_size {
+                self.send()?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
+
+/// Writes a request and reads the response, without its size
+fn roundtrip(stream: &mut TcpStream, request: &[u8]) -> io::Result<Vec<u8>> {
+    stream.write_all(request)?;
+    let mut size = [0; 4];
+    stream.read_exact(&mut size)?;
+    let mut response = vec![0; i32::from_be_bytes(size).max(0) as usize];
+    stream.read_exact(&mut response)?;
+    Ok(response)
+}
+
+/// A broker's error code as an error about `partition` of `topic`
+fn kafka_error(code: i16, topic: &str, partition: i32) -> io::Error {
+    io::Error::other(match code {
+        3 => format!("topic '{}' or partition {} doesn't exist", topic, partition),
+        5 => format!("partition {} of '{}' has no leader right now", partition, topic),
+        6 => format!("the broker doesn't lead partition {} of '{}' anymore, reconnect", partition, topic),
+        10 => "the batch is larger than the broker accepts".to_string(),
+        code => format!("Kafka error code {}", code),
+    })
+}
+
+/// The request header (version 1) of `api_key` at `version`
+fn request_header(api_key: i16, version: i16, correlation_id: i32) -> Vec<u8> {
+    let mut r = Vec::new();
+    r.extend_from_slice(&api_key.to_be_bytes());
+    r.extend_from_slice(&version.to_be_bytes());
+    r.extend_from_slice(&correlation_id.to_be_bytes());
+    put_string(&mut r, "finsim");
+    r
+}
+
+/// Prefixes a request with its size
+fn sized(r: Vec<u8>) -> Vec<u8> {
+    let mut request = (r.len() as i32).to_be_bytes().to_vec();
+    request.extend(r);
+    request
+}
+
+/// A Metadata request (version 4) of one topic, without creating it
+fn metadata_request(correlation_id: i32, topic: &str) -> Vec<u8> {
+    let mut r = request_header(3, 4, correlation_id);
+    r.extend_from_slice(&1i32.to_be_bytes());
+    put_string(&mut r, topic);
+    r.push(0); // Don't create the topic
+    sized(r)
+}
+
+/// The host and port of the leader of `partition` of `topic` in a Metadata
+/// response (version 4), without its size
+fn partition_leader(response: &[u8], topic: &str, partition: i32) -> io::Result<(String, u16)> {
+    let mut rest = response;
+    take(&mut rest, 8)?; // Correlation id and throttle time
+    let mut brokers = Vec::new();
+    for _ in 0..take_i32(&mut rest)? {
+        let node_id = take_i32(&mut rest)?;
+        let host = take_string(&mut rest)?;
+        let port = take_i32(&mut rest)?;
+        take_string(&mut rest)?; // Rack
+        brokers.push((node_id, host, port));
+    }
+    take_string(&mut rest)?; // Cluster id
+    take(&mut rest, 4)?; // Controller id
+    for _ in 0..take_i32(&mut rest)? {
+        let error = take_i16(&mut rest)?;
+        let name = take_string(&mut rest)?;
+        if name == topic && error != 0 {
+            return Err(kafka_error(error, topic, partition));
+        }
+        take(&mut rest, 1)?; // Internal
+        for _ in 0..take_i32(&mut rest)? {
+            let error = take_i16(&mut rest)?;
+            let index = take_i32(&mut rest)?;
+            let leader = take_i32(&mut rest)?;
+            for _ in 0..2 {
+                // Replicas and in-sync replicas
+                let len = take_i32(&mut rest)?;
+                take(&mut rest, 4 * len.max(0) as usize)?;
+            }
+            if name != topic || index != partition {
+                continue;
+            }
+            if error != 0 {
+                return Err(kafka_error(error, topic, partition));
+            }
+            return brokers
+                .iter()
+                .find(|(node_id, _, _)| *node_id == leader)
+                .map(|(_, host, port)| (host.clone(), *port as u16))
+                .ok_or_else(|| kafka_error(5, topic, partition));
+        }
+    }
+    Err(kafka_error(3, topic, partition))
+}
+
+/// A Produce request (version 3) of one record batch to one partition,
+/// awaiting the leader's acknowledgement
+fn produce_request(correlation_id: i32, topic: &str, partition: i32, batch: &[u8]) -> Vec<u8> {
+    let mut r = request_header(0, 3, correlation_id);
+    r.extend_from_slice(&(-1i16).to_be_bytes()); // No transaction
+    r.extend_from_slice(&1i16.to_be_bytes()); // acks
+    r.extend_from_slice(&30_000i32.to_be_bytes()); // Timeout in ms
+    r.extend_from_slice(&1i32.to_be_bytes());
+    put_string(&mut r, topic);
+    r.extend_from_slice(&1i32.to_be_bytes());
+    r.extend_from_slice(&partition.to_be_bytes());
+    r.extend_from_slice(&(batch.len() as i32).to_be_bytes());
+    r.extend_from_slice(batch);
+    sized(r)
+}
+
+/// The first error code of a Produce response (version 3), without its size
+fn produce_error(response: &[u8]) -> io::Result<Option<i16>> {
+    let mut rest = response;
+    take(&mut rest, 4)?; // Correlation id
+    for _ in 0..take_i32(&mut rest)? {
+        let name_len = take_i16(&mut rest)?;
+        take(&mut rest, name_len.max(0) as usize)?;
+        for _ in 0..take_i32(&mut rest)? {
+            take(&mut rest, 4)?; // Partition
+            let error = take_i16(&mut rest)?;
+            take(&mut rest, 16)?; // Base offset and log append time
+            if error != 0 {
+                return Ok(Some(error));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn take<'a>(rest: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if rest.len() < n {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated Kafka response"));
+    }
+    let (bytes, tail) = rest.split_at(n);
+    *rest = tail;
+    Ok(bytes)
+}
+
+fn take_i16(rest: &mut &[u8]) -> io::Result<i16> {
+    take(rest, 2).map(|b| i16::from_be_bytes([b[0], b[1]]))
+}
+
+fn take_i32(rest: &mut &[u8]) -> io::Result<i32> {
+    take(rest, 4).map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// A string, empty if null
+fn take_string(rest: &mut &[u8]) -> io::Result<String> {
+    let len = take_i16(rest)?;
+    take(rest, len.max(0) as usize).map(|b| String::from_utf8_lossy(b).into_owned())
+}
+
+/// A record batch (magic 2) of `values` with the same `key`, all stamped
+/// `timestamp` (ms since the epoch)
+fn record_batch(key: Option<&[u8]>, values: &[Vec<u8>], timestamp: i64) -> Vec<u8> {
+    // From the attributes on, covered by the CRC
+    let mut body = Vec::new();
+    body.extend_from_slice(&0i16.to_be_bytes()); // Uncompressed, CreateTime
+    body.extend_from_slice(&(values.len() as i32 - 1).to_be_bytes());
+    body.extend_from_slice(&timestamp.to_be_bytes());
+    body.extend_from_slice(&timestamp.to_be_bytes());
+    body.extend_from_slice(&(-1i64).to_be_bytes()); // No idempotence
+    body.extend_from_slice(&(-1i16).to_be_bytes());
+    body.extend_from_slice(&(-1i32).to_be_bytes());
+    body.extend_from_slice(&(values.len() as i32).to_be_bytes());
+    for (offset, value) in values.iter().enumerate() {
+        let mut record = vec![0]; // Attributes
+        put_varint(&mut record, 0); // Timestamp delta
+        put_varint(&mut record, offset as i64);
+        match key {
+            Some(key) => {
+                put_varint(&mut record, key.len() as i64);
+                record.extend_from_slice(key);
+            }
+            None => put_varint(&mut record, -1),
+        }
+        put_varint(&mut record, value.len() as i64);
+        record.extend_from_slice(value);
+        put_varint(&mut record, 0); // Headers
+        put_varint(&mut body, record.len() as i64);
+        body.extend(record);
+    }
+    let mut batch = 0i64.to_be_bytes().to_vec(); // Base offset
+    // Length after this field: epoch, magic, CRC and body
+    batch.extend_from_slice(&(4 + 1 + 4 + body.len() as i32).to_be_bytes());
+    batch.extend_from_slice(&(-1i32).to_be_bytes()); // Partition leader epoch
+    batch.push(2);
+    batch.extend_from_slice(&crc32c(&body).to_be_bytes());
+    batch.extend(body);
+    batch
+}
+
+fn put_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as i16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// A zigzag-encoded variable-length integer
+fn put_varint(buf: &mut Vec<u8>, n: i64) {
+    let mut n = ((n << 1) ^ (n >> 63)) as u64;
+    while n >= 0x80 {
+        buf.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// CRC-32C (Castagnoli), the checksum of record batches
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0x82F63B78 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::{KafkaTarget, Producer, crc32c, partition_leader, put_string, put_varint, record_batch};
+
+    #[test]
+    fn parse_target() {
+        let target: KafkaTarget = "a:9092,b:9092/ticks?key=btc&partition=2".parse().unwrap();
+        assert_eq!(vec!["a:9092", "b:9092"], target.brokers);
+        assert_eq!(("ticks", Some("btc"), 2), (target.topic.as_str(), target.key.as_deref(), target.partition));
+        assert_eq!(target, target.to_string().parse().unwrap());
+        assert_eq!(None, "a:9092/ticks".parse::<KafkaTarget>().unwrap().key);
+        assert!("a:9092".parse::<KafkaTarget>().is_err());
+        assert!("a:9092/ticks?partition=-1".parse::<KafkaTarget>().is_err());
+        assert!("a:9092/ticks?acks=all".parse::<KafkaTarget>().is_err());
+    }
+
+    #[test]
+    fn record_batch_encoding() {
+        assert_eq!(0xE3069283, crc32c(b"123456789"));
+        let mut buf = Vec::new();
+        for n in [0, -1, 1, 64, -65] {
+            put_varint(&mut buf, n);
+        }
+        assert_eq!(vec![0, 1, 2, 0x80, 1, 0x81, 1], buf);
+
+        let batch = record_batch(Some(b"k"), &[b"1.5".to_vec(), b"3".to_vec()], 1000);
+        assert_eq!(batch.len() - 12, i32::from_be_bytes(batch[8..12].try_into().unwrap()) as usize);
+        assert_eq!(2, batch[16]);
+        assert_eq!(crc32c(&batch[21..]), u32::from_be_bytes(batch[17..21].try_into().unwrap()));
+        assert_eq!(2, i32::from_be_bytes(batch[57..61].try_into().unwrap()));
+        assert!(batch.ends_with(&[2, b'3', 0]));
+    }
+
+    /// A Metadata response (version 4) for 'ticks', whose partition 0 is led
+    /// by the broker at `port` and partition 1 has no leader
+    fn metadata_response(port: u16) -> Vec<u8> {
+        let mut r = vec![0; 8];
+        r.extend_from_slice(&1i32.to_be_bytes());
+        r.extend_from_slice(&2i32.to_be_bytes());
+        put_string(&mut r, "127.0.0.1");
+        r.extend_from_slice(&(port as i32).to_be_bytes());
+        r.extend_from_slice(&(-1i16).to_be_bytes());
+        r.extend_from_slice(&(-1i16).to_be_bytes());
+        r.extend_from_slice(&2i32.to_be_bytes());
+        r.extend_from_slice(&1i32.to_be_bytes());
+        r.extend_from_slice(&0i16.to_be_bytes());
+        put_string(&mut r, "ticks");
+        r.push(0);
+        r.extend_from_slice(&2i32.to_be_bytes());
+        for (index, leader) in [(0i32, 2i32), (1, -1)] {
+            r.extend_from_slice(&[0, if leader < 0 { 5 } else { 0 }]);
+            r.extend_from_slice(&index.to_be_bytes());
+            r.extend_from_slice(&leader.to_be_bytes());
+            r.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2]);
+        }
+        r
+    }
+
+    #[test]
+    fn find_partition_leader() {
+        let response = metadata_response(9093);
+        assert_eq!(("127.0.0.1".to_string(), 9093), partition_leader(&response, "ticks", 0).unwrap());
+        let error = partition_leader(&response, "ticks", 1).unwrap_err();
+        assert!(error.to_string().contains("has no leader"), "{}", error);
+        let error = partition_leader(&response, "ticks", 2).unwrap_err();
+        assert!(error.to_string().contains("doesn't exist"), "{}", error);
+        assert!(partition_leader(&response[..20], "ticks", 0).is_err());
+    }
+
+    /// Answers a Metadata request with the leader at `port`, and returns the
+    /// request
+    fn bootstrap_broker(listener: TcpListener, port: u16) -> thread::JoinHandle<Vec<u8>> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut size = [0; 4];
+            stream.read_exact(&mut size).unwrap();
+            let mut request = vec![0; i32::from_be_bytes(size) as usize];
+            stream.read_exact(&mut request).unwrap();
+            let response = metadata_response(port);
+            stream.write_all(&(response.len() as i32).to_be_bytes()).unwrap();
+            stream.write_all(&response).unwrap();
+            request
+        })
+    }
+
+    /// Answers two Produce requests, the first without and the second with
+    /// an error, and returns the requests
+    fn fake_broker(listener: TcpListener) -> thread::JoinHandle<Vec<Vec<u8>>> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            [0i16, 6]
+                .iter()
+                .map(|error| {
+                    let mut size = [0; 4];
+                    stream.read_exact(&mut size).unwrap();
+                    let mut request = vec![0; i32::from_be_bytes(size) as usize];
+                    stream.read_exact(&mut request).unwrap();
+                    let mut response = request[4..8].to_vec();
+                    response.extend_from_slice(&[0, 0, 0, 1, 0, 5]);
+                    response.extend_from_slice(b"ticks");
+                    response.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0]);
+                    response.extend_from_slice(&error.to_be_bytes());
+                    response.extend_from_slice(&[0; 20]);
+                    stream.write_all(&(response.len() as i32).to_be_bytes()).unwrap();
+                    stream.write_all(&response).unwrap();
+                    request
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn producer_sends_batches() {
+        let leader = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = leader.local_addr().unwrap().port();
+        let handle = fake_broker(leader);
+        let bootstrap = TcpListener::bind("127.0.0.1:0").unwrap();
+        let broker = bootstrap.local_addr().unwrap().to_string();
+        let metadata = bootstrap_broker(bootstrap, port);
+        // The first broker refuses the connection, the second names the leader
+        let brokers = vec!["127.0.0.1:1".to_string(), broker];
+        let target = KafkaTarget { brokers, topic: "ticks".to_string(), key: None, partition: 0 };
+        let mut producer = Producer::connect(&target, 2).unwrap();
+        // A batch once two lines are complete, the rest on flush
+        write!(producer, "1.5\n3\n4.").unwrap();
+        writeln!(producer, "5").unwrap();
+        let error = producer.flush().unwrap_err();
+        assert!(error.to_string().contains("doesn't lead partition 0"), "{}", error);
+        let metadata = metadata.join().unwrap();
+        assert_eq!([0, 3, 0, 4], metadata[..4]);
+        assert!(metadata.ends_with(&[0, 5, b't', b'i', b'c', b'k', b's', 0]));
+        let requests = handle.join().unwrap();
+        assert_eq!([0, 0, 0, 3], requests[0][..4]);
+        assert!(requests[0].ends_with(&[2, b'3', 0]));
+        assert!(requests[1].ends_with(&[6, b'4', b'.', b'5', 0]));
+    }
+}
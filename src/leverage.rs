@@ -0,0 +1,160 @@
+//! Two ways to lever the same underlying path. A daily-reset leveraged ETF
+//! releverages at every tick and charges a yearly fee, and loses to
+//! volatility decay when the underlying swings back and forth. A margin loan
+//! borrows once and is never releveraged, and pays interest on the loan
+//! instead. Negative leverage is an inverse ETF and a short sale, whose
+//! losses grow without bound as the price rises while its gains are capped.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::returns::AccumulateArgs;
+
+/// The leverage and costs of both ways
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LeverageCompareArgs {
+    pub leverage: f64,
+    /// The value of the underlying and both levered positions at t=0
+    pub start_value: f64,
+    /// Yearly fee of the ETF as a factor, e.g. 1.0095 for 0.95%
+    pub yearly_fee: f64,
+    /// Yearly interest on the margin loan as a factor, e.g. 1.06 for 6%.
+    /// With a leverage below 1 the cash, including the proceeds of a short,
+    /// earns it instead, the short rebate.
+    pub yearly_margin_rate: f64,
+    /// Yearly fee for borrowing the shares of a short as a factor of their
+    /// value, e.g. 1.005 for 0.5%
+    pub yearly_borrow_fee: f64,
+}
+
+/// The values of the underlying and both levered positions at every point
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LeveragedValues {
+    pub underlying: Vec<f64>,
+    pub daily_reset: Vec<f64>,
+    pub margin: Vec<f64>,
+}
+
+impl LeverageCompareArgs {
+    pub fn validate(&self) -> Result<()> {
+        if !self.leverage.is_finite() {
+            return Err(Error::Invalid(format!("--leverage must be a finite number, got {}", self.leverage)));
+        }
+        if !self.start_value.is_finite() || self.start_value <= 0.0 {
+            return Err(Error::Invalid(format!("--start-value must be a positive number, got {}", self.start_value)));
+        }
+        let rates = [("--fee", self.yearly_fee), ("--margin-rate", self.yearly_margin_rate), ("--borrow-fee", self.yearly_borrow_fee)];
+        for (flag, rate) in rates {
+            if !rate.is_finite() || rate <= 0.0 {
+                return Err(Error::Invalid(format!("{} must be above -100%, got {}%", flag, (rate - 1.0) * 100.0)));
+            }
+        }
+        Ok(())
+    }
+
+    /// The accumulation that each way comes closest to without its costs,
+    /// recorded with the series by file targets
+    pub fn acc_args(&self) -> [AccumulateArgs; 3] {
+        let args = AccumulateArgs {
+            accumulate: true,
+            start_value: self.start_value,
+            continuous_leverage: None,
+            pointwise_leverage: None,
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        [
+            args.clone(),
+            AccumulateArgs { pointwise_leverage: Some(self.leverage), ..args.clone() },
+            AccumulateArgs { initial_leverage: Some(self.leverage), ..args },
+        ]
+    }
+
+    /// Both ways over the same returns, with `ticks_per_year` ticks a year.
+    /// The fees and interest accrue every tick. Neither position goes below
+    /// 0, and a margin position stays wiped out once it owes more than it
+    /// holds: a loan above its shares, or shorted shares above its cash.
+    pub fn compare(&self, returns: &[f64], ticks_per_year: f64) -> LeveragedValues {
+        let tick_fee = self.yearly_fee.powf(1.0 / ticks_per_year);
+        let tick_rate = self.yearly_margin_rate.powf(1.0 / ticks_per_year);
+        let tick_borrow_fee = self.yearly_borrow_fee.powf(1.0 / ticks_per_year);
+        let (mut underlying, mut daily_reset) = (self.start_value, self.start_value);
+        let (mut assets, mut debt) = (self.start_value * self.leverage, self.start_value * (self.leverage - 1.0));
+        let mut values = LeveragedValues {
+            underlying: Vec::with_capacity(returns.len()),
+            daily_reset: Vec::with_capacity(returns.len()),
+            margin: Vec::with_capacity(returns.len()),
+        };
+        for &r in returns {
+            underlying *= r;
+            daily_reset *= (1.0 + self.leverage * (r - 1.0)).max(0.0) / tick_fee;
+            // Shorted shares are owed, so the borrow fee adds to them
+            assets *= if assets < 0.0 { r * tick_borrow_fee } else { r };
+            debt *= tick_rate;
+            if assets <= debt {
+                (assets, debt) = (0.0, 0.0);
+            }
+            values.underlying.push(underlying);
+            values.daily_reset.push(daily_reset);
+            values.margin.push(assets - debt);
+        }
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::LeverageCompareArgs;
+
+    fn args(leverage: f64) -> LeverageCompareArgs {
+        LeverageCompareArgs { leverage, start_value: 100.0, yearly_fee: 1.0, yearly_margin_rate: 1.0, yearly_borrow_fee: 1.0 }
+    }
+
+    #[test]
+    fn volatility_decay_against_financing() {
+        // Up 10% and back down: the underlying ends where it started, the
+        // ETF loses to decay and the margin position doesn't
+        let values = args(2.0).compare(&[1.1, 1.0 / 1.1], 1.0);
+        assert_approx_eq!(100.0, values.underlying[1], 1e-9);
+        assert_approx_eq!(120.0 * (1.0 - 2.0 / 11.0), values.daily_reset[1], 1e-9);
+        assert_approx_eq!(100.0, values.margin[1], 1e-9);
+
+        // A steady trend compounds for the ETF, interest costs the margin
+        // position
+        let costs = LeverageCompareArgs { yearly_fee: 1.01, yearly_margin_rate: 1.05, ..args(2.0) };
+        let values = costs.compare(&[1.1, 1.1], 1.0);
+        assert_approx_eq!(120.0 * 1.2 / 1.01 / 1.01, values.daily_reset[1], 1e-9);
+        assert_approx_eq!(242.0 - 100.0 * 1.05 * 1.05, values.margin[1], 1e-9);
+        assert!(values.daily_reset[1] > values.margin[1]);
+    }
+
+    #[test]
+    fn positions_never_go_negative() {
+        let values = args(3.0).compare(&[0.6, 2.0], 1.0);
+        assert_eq!(vec![0.0, 0.0], values.daily_reset);
+        assert_eq!(vec![0.0, 0.0], values.margin);
+        assert_eq!(vec![60.0, 120.0], values.underlying);
+        assert!(args(f64::NAN).validate().is_err());
+        assert!(LeverageCompareArgs { yearly_margin_rate: 0.0, ..args(2.0) }.validate().is_err());
+    }
+
+    #[test]
+    fn shorts() {
+        // Short 100 with 200 cash: up 50% halves the position, up 100% wipes
+        // it out, while the inverse ETF compounds its daily losses
+        let values = args(-1.0).compare(&[1.5, 4.0 / 3.0, 0.5], 1.0);
+        assert_eq!(vec![150.0, 200.0, 100.0], values.underlying);
+        assert_approx_eq!(50.0, values.margin[0], 1e-9);
+        assert_eq!(vec![0.0, 0.0], values.margin[1..]);
+        assert_approx_eq!(50.0 * (2.0 / 3.0) * 1.5, values.daily_reset[2], 1e-9);
+
+        // The rebate on the cash against the fee on the borrowed shares
+        let costs = LeverageCompareArgs { yearly_margin_rate: 1.05, yearly_borrow_fee: 1.01, ..args(-1.0) };
+        let values = costs.compare(&[1.0], 1.0);
+        assert_approx_eq!(210.0 - 101.0, values.margin[0], 1e-9);
+    }
+}
@@ -1 +1,81 @@
+//! Simulation of (leveraged) investment returns: log-normal return series,
+//! accumulation with leverage, Monte Carlo over many paths and statistics of
+//! the results.
+//!
+//! `finsim::prelude` is the stable API, see its docs. The most used types are
+//! also re-exported here.
+//!
+//! ```
+//! use finsim::prelude::*;
+//!
+//! let sim = SimulationBuilder::new()
+//!     .preset(Preset::UsEquities)
+//!     .horizon(10 * 31556952)
+//!     .interval(86400)
+//!     .seed(1)
+//!     .accumulate(100.0)
+//!     .leverage(Leverage::Pointwise(2.0))
+//!     .build()?;
+//! let paths: Vec<Summary> = sim.monte_carlo(100)?;
+//! assert_eq!(100, paths.len());
+//! # Ok::<(), finsim::Error>(())
+//! ```
+
+pub mod calendar;
+pub mod calibrate;
+pub mod checks;
+#[doc(hidden)]
+pub mod config;
+pub mod dca;
+pub mod decimal;
+pub mod drawdown;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "fetch")]
+pub mod fetch;
+#[cfg(feature = "polars")]
+pub mod frame;
+pub mod futures;
+pub mod goal;
+pub mod inflation;
+pub mod input;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod leverage;
+pub mod metrics;
+pub mod model;
+pub mod mortgage;
+pub mod output;
+pub mod portable;
+pub mod portfolio;
+pub mod prelude;
+pub mod preset;
+pub mod pricing;
+#[doc(hidden)]
+pub mod repl;
+pub mod retire;
 pub mod returns;
+pub mod selftest;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod sequence;
+pub mod simulation;
+pub mod stats;
+pub mod strategy;
+pub mod tail;
+pub mod tax;
+#[cfg(feature = "stream")]
+pub mod stream;
+pub mod transform;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[doc(hidden)]
+pub mod watch;
+pub mod websocket;
+
+pub use error::{Error, Result};
+pub use model::ReturnModel;
+pub use output::Summary;
+pub use returns::DataPoint;
+pub use simulation::{Leverage, Simulation, SimulationBuilder};
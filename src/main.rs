@@ -1,27 +1,1829 @@
+mod calendar;
+mod calibrate;
+mod checks;
+mod cli;
+mod config;
+mod dca;
+mod decimal;
+mod drawdown;
+mod error;
+#[cfg(feature = "fetch")]
+mod fetch;
+mod futures;
+mod goal;
+mod inflation;
+mod input;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod leverage;
+mod metrics;
+mod model;
+mod mortgage;
+mod output;
+mod portable;
+mod portfolio;
+mod preset;
+mod pricing;
+mod repl;
+mod retire;
 mod returns;
+mod selftest;
+mod sequence;
+#[cfg(feature = "serve")]
+mod serve;
+mod stats;
+mod tail;
+mod tax;
+mod watch;
+mod websocket;
 
-use std::io::{self, Write};
+use std::fs::File;
+use std::ffi::OsString;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 
-use clap::Parser;
-use returns::{AccumulateArgs, GenReturnsArgs, accumulate, gen_returns};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use indicatif::{ProgressBar, ProgressIterator, ProgressStyle};
+use tracing::level_filters::LevelFilter;
+use tracing::info;
+use cli::{
+    AccumulateFlags, CalibrateFlags, CheckFlags, DcaFlags, FuturesFlags, GenReturnsFlags, GoalFlags, HarvestFlags, InflationFlags, LeverageCompareFlags, MortgageFlags, OptionFlags, OutputFlags, PresetArg,
+    RetireFlags, SelftestFlags, SeriesKindArg, TailFlags, TimeBaseFlags,
+};
+use calendar::BusinessCalendar;
+use checks::CheckArgs;
+use dca::{DcaArgs, DcaReport};
+use drawdown::{Drawdown, DrawdownRisk, Drawdowns};
+use error::Error;
+use inflation::RealArgs;
+use input::{Input, SeriesKind};
+use leverage::LeverageCompareArgs;
+use metrics::Metrics;
+use model::LogNormal;
+use mortgage::MortgageReport;
+use output::{OutputArgs, OutputFormat, OutputTarget, Run, StreamHeader};
+use portfolio::RebalancingReport;
+use pricing::OptionArgs;
+use retire::RetireReport;
+use returns::{AccumulateArgs, DataPoint, GenReturnsArgs, Interval, RngKind, TimeBase, accumulate, accumulate_iter, data_points, for_each_chunk, gen_returns, resume_returns};
+use selftest::SelftestArgs;
+use stats::BenchmarkStats;
+use tail::TailArgs;
+use tax::{HarvestArgs, HarvestReport};
 
+/// Simulate, accumulate and analyse financial return series
 #[derive(Parser)]
+#[command(name = "finsim", arg_required_else_help = true, args_override_self = true)]
 pub struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// TOML file with values for any of the flags of the subcommand, e.g. `num_points = 1000`.
+    /// Flags given on the command line take precedence
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<PathBuf>,
+
+    /// Start from the yearly mean and stddev of a built-in preset. Explicit flags, environment
+    /// variables and config files take precedence
+    #[arg(long, value_enum, global = true)]
+    #[allow(dead_code)] // Read by preset::expand_preset_args before parsing
+    preset: Option<PresetArg>,
+
+    /// Don't show progress bars on stderr. With mc, don't print the paths or the results of the
+    /// checks either, leaving just the exit code
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Log what is being done to stderr: -v for the timing of each run, -vv also for the derived
+    /// model parameters and per-path timings
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Re-run whenever the config file or the file read by the subcommand changes. Runs without a
+    /// seed of their own use the same random seed every time
+    #[arg(long, global = true)]
+    watch: bool,
+
+    /// Seed for runs without one of their own, passed to the re-runs by --watch
+    #[arg(long, global = true, hide = true)]
+    watch_seed: Option<u64>,
+}
+
+/// Generating at least this many points shows a progress bar
+const PROGRESS_MIN_POINTS: usize = 1_000_000;
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a return series, optionally accumulated
+    #[command(alias = "generate")]
+    Gen(GenArgs),
+
+    /// Accumulate an existing return series instead of generating one
+    #[command(alias = "accumulate")]
+    Acc(AccArgs),
+
+    /// Summary statistics (count, min, max, mean, last) of an existing series
+    Stats(StatsArgs),
+
+    /// Run many independent simulations, printing a CSV table of summary statistics per path
+    Mc(McArgs),
+
+    /// Price a European, Asian or barrier option by Monte Carlo: the mean discounted payoff of
+    /// paths drifting at the risk-free rate, with its standard error
+    OptionPrice(OptionPriceArgs),
+
+    /// Compare paying a fixed-rate loan off early with investing the same cash in the simulated
+    /// asset, over the horizon of the series
+    Mortgage(MortgageCmdArgs),
+
+    /// Generate the returns of a futures position on the simulated underlying, rolled into the next
+    /// contract every --roll-every at a roll yield, e.g. the contango drag of commodity and VIX
+    /// futures funds
+    Futures(FuturesArgs),
+
+    /// Lever the same path as a daily-reset leveraged ETF with a fee and with a margin loan
+    /// paying interest, writing both next to the underlying to compare volatility decay with
+    /// financing costs
+    LeverageCompare(LeverageCompareCmdArgs),
+
+    /// Compare investing a lump sum at once with spreading it over --periods intervals, on the
+    /// same paths, reporting how often each ends ahead and the spread of their outcomes
+    Dca(DcaCmdArgs),
+
+    /// Plan a retirement: save until a retirement date, then withdraw, and report how often the
+    /// savings last
+    Retire(RetireCmdArgs),
+
+    /// Report how often the savings reach a target by a horizon, or solve for the contribution
+    /// that reaches it with a given probability
+    Goal(GoalCmdArgs),
+
+    /// Simulate harvesting tax losses at the end of every year and reinvesting the tax saved, and
+    /// report its after-tax benefit over holding
+    Harvest(HarvestCmdArgs),
+
+    /// Run a retirement plan over the same returns in many orders, reporting how much of the
+    /// outcome comes from the order of the returns alone
+    Sequence(SequenceCmdArgs),
+
+    /// Fit a generalized Pareto distribution to the worst ticks of simulated or given returns,
+    /// reporting the tail index and the tick losses exceeded once in --return-periods years
+    Tail(TailCmdArgs),
+
+    /// Estimate --yearly-mean and --yearly-stddev from a historical price CSV
+    Calibrate(CalibrateCmdArgs),
+
+    /// Download daily historical prices from Stooq as CSV
+    #[cfg(feature = "fetch")]
+    Fetch(cli::FetchFlags),
+
+    /// Serve an HTTP/JSON API that simulates the flags posted to it, for web frontends and
+    /// notebooks
+    #[cfg(feature = "serve")]
+    Serve(ServeArgs),
+
+    /// Run several named simulations from a scenario file, tagging the output with their names
+    Scenarios(ScenariosArgs),
+
+    /// Run a simulation for every combination of the values in a sweep file, printing a CSV
+    /// table of summary statistics per combination
+    Sweep(SweepArgs),
+
+    /// Simulate a portfolio of correlated assets described by a portfolio spec file
+    Portfolio(PortfolioArgs),
+
+    /// Compare a portfolio rebalanced at every interval with buying and holding its assets on the
+    /// same paths, reporting the yearly rebalancing bonus (or penalty) across paths
+    Rebalancing(RebalancingArgs),
+
+    /// Tweak the flags interactively, re-running the simulation and charting it after every change
+    Repl(ReplArgs),
+
+    /// Check that large generated series converge to the requested yearly mean and stddev
+    Selftest(SelftestFlags),
+
+    /// Print a shell completion script
+    Completions(CompletionsArgs),
+
+    /// Print the man page, or write one for every subcommand to a directory
+    Man(ManArgs),
+}
+
+#[derive(clap::Args)]
+struct GenArgs {
     #[command(flatten)]
-    gen_returns: GenReturnsArgs,
+    gen_returns: GenReturnsFlags,
+
+    /// Save the random number generator state after the last point to FILE, for continuing the
+    /// series later with --resume-state
+    #[arg(long, value_name = "FILE")]
+    save_state: Option<PathBuf>,
+
+    /// Continue a series from the generator state saved by --save-state instead of starting from
+    /// --seed. To continue an accumulated series, pass its last value as --start-value
+    #[arg(long, value_name = "FILE", conflicts_with("seed"))]
+    resume_state: Option<PathBuf>,
+
+    /// Print the parameters derived from the flags (per-tick log mean and stddev, ticks per year,
+    /// terminal value distribution) instead of generating anything
+    #[arg(long)]
+    describe: bool,
+
+    /// Generate path i of `mc` with the same flags and --seed instead of the series with that seed
+    #[arg(long)]
+    path: Option<usize>,
+
+    #[command(flatten)]
+    inflation: InflationFlags,
+
+    /// Output the price level of the inflation, relative to 1 at t=0, after each value. Only on
+    /// stdout
+    #[arg(long, requires("inflation"))]
+    with_inflation: bool,
+
+    #[command(flatten)]
+    accumulate: AccumulateFlags,
+
+    #[command(flatten)]
+    output: OutputFlags,
+}
+
+#[derive(clap::Args)]
+struct AccArgs {
+    /// File with one return per line (e.g. 1.01 for +1%) or one price per line, or the output of
+    /// --format stream. Reads stdin if omitted
+    input: Option<PathBuf>,
+
+    /// Whether the input holds returns or prices. Stream input says so itself
+    #[arg(long, value_enum, default_value_t = SeriesKindArg::Auto)]
+    input_kind: SeriesKindArg,
+
+    /// Time between the input returns in seconds (or with a unit like 1d), used for timestamps in
+    /// the output. Defaults to the interval of stream input, or 1d
+    #[arg(long, visible_alias = "interval", value_parser = returns::parse_duration)]
+    interval_seconds: Option<f64>,
+
+    #[command(flatten)]
+    accumulate: AccumulateFlags,
+
+    #[command(flatten)]
+    output: OutputFlags,
+}
+
+#[derive(clap::Args)]
+struct StatsArgs {
+    /// File with one value per line, or the output of --format stream for a row per series. Reads
+    /// stdin if omitted
+    input: Option<PathBuf>,
+
+    /// Whether a file of one value per line holds prices (or accumulated values) or returns, for
+    /// the statistics of the returns. The stream format says so itself
+    #[arg(long, value_enum, default_value_t = SeriesKindArg::Auto)]
+    input_kind: SeriesKindArg,
+
+    /// Add a column of the Hurst exponent of the returns, by detrended fluctuation analysis: 0.5
+    /// for independent returns, above for persistent and below for mean-reverting ones
+    #[arg(long)]
+    hurst: bool,
+
+    /// Add columns of the Ulcer index (the root mean square drawdown) and the pain index (the mean
+    /// drawdown), and of the Martin and pain ratios: the yearly return above --risk-free over each
+    #[arg(long)]
+    ulcer: bool,
+
+    /// Add a column of the Omega ratio: the gains of the returns above --omega-threshold over their
+    /// shortfalls below it
+    #[arg(long)]
+    omega: bool,
+
+    /// Yearly threshold return of the Omega ratio in percent, compounded down to a tick
+    #[arg(long, value_name = "PERCENT", value_parser = returns::parse_percent, default_value = "0%", allow_hyphen_values(true))]
+    omega_threshold: f64,
+
+    /// Add columns of the beta, correlation, yearly tracking error and information ratio of the
+    /// returns against those of the series in FILE, tick by tick, read like the input. The two
+    /// must have as many returns.
+    #[arg(long, value_name = "FILE", conflicts_with("benchmark_scenario"))]
+    benchmark: Option<PathBuf>,
+
+    /// Like --benchmark, against the series of stream input with this scenario name, e.g. a second
+    /// generated series
+    #[arg(long, value_name = "NAME")]
+    benchmark_scenario: Option<String>,
+
+    /// Yearly risk-free return in percent, which the ratios count the return above
+    #[arg(long, value_name = "PERCENT", value_parser = returns::parse_percent, default_value = "0%", allow_hyphen_values(true))]
+    risk_free: f64,
+
+    /// Time between the values in seconds (or with a unit like 1d), to annualize the returns.
+    /// Defaults to the interval of stream input, or 1d
+    #[arg(long, visible_alias = "interval", value_parser = returns::parse_duration)]
+    interval_seconds: Option<f64>,
+
+    #[command(flatten)]
+    time_base: TimeBaseFlags,
+}
+
+#[derive(clap::Args)]
+struct McArgs {
+    #[command(flatten)]
+    gen_returns: GenReturnsFlags,
+
+    /// Number of paths to simulate. With --seed, every path gets its own seed derived from it,
+    /// so that `gen --seed <seed> --path <i>` regenerates path i
+    #[arg(long, default_value_t = 1000)]
+    paths: usize,
+
+    /// Number of threads to simulate the paths on, 0 for one per core. The results don't depend
+    /// on it
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+
+    #[command(flatten)]
+    inflation: InflationFlags,
+
+    #[command(flatten)]
+    accumulate: AccumulateFlags,
+
+    #[command(flatten)]
+    checks: CheckFlags,
+
+    /// Serve Prometheus metrics (paths completed, ticks per second, errors) on ADDR, e.g.
+    /// 127.0.0.1:9100, at /metrics while the paths are simulated
+    #[arg(long, value_name = "ADDR")]
+    metrics_addr: Option<String>,
+
+    /// Print every drawdown of every path instead of the summaries: the ticks of its peak, trough
+    /// and recovery, its depth and its length in ticks. Needs --accumulate
+    #[arg(long)]
+    drawdowns: bool,
+}
+
+#[derive(clap::Args)]
+struct OptionPriceArgs {
+    #[command(flatten)]
+    gen_returns: GenReturnsFlags,
+
+    #[command(flatten)]
+    option: OptionFlags,
+
+    /// Number of paths to simulate, at least 2 for a standard error
+    #[arg(long, default_value_t = 10_000)]
+    paths: usize,
+
+    /// Number of threads to simulate the paths on, 0 for one per core. The results don't depend
+    /// on it
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+}
+
+#[derive(clap::Args)]
+struct MortgageCmdArgs {
+    #[command(flatten)]
+    gen_returns: GenReturnsFlags,
+
+    #[command(flatten)]
+    mortgage: MortgageFlags,
+
+    /// Number of paths to simulate
+    #[arg(long, default_value_t = 1000)]
+    paths: usize,
+}
+
+#[derive(clap::Args)]
+struct FuturesArgs {
+    #[command(flatten)]
+    gen_returns: GenReturnsFlags,
+
+    #[command(flatten)]
+    futures: FuturesFlags,
+
+    #[command(flatten)]
+    accumulate: AccumulateFlags,
+
+    #[command(flatten)]
+    output: OutputFlags,
+}
+
+#[derive(clap::Args)]
+struct LeverageCompareCmdArgs {
+    #[command(flatten)]
+    gen_returns: GenReturnsFlags,
+
+    #[command(flatten)]
+    leverage: LeverageCompareFlags,
+
+    #[command(flatten)]
+    output: OutputFlags,
+}
+
+#[derive(clap::Args)]
+struct DcaCmdArgs {
+    #[command(flatten)]
+    gen_returns: GenReturnsFlags,
+
+    #[command(flatten)]
+    dca: DcaFlags,
 
+    /// Number of paths to simulate
+    #[arg(long, default_value_t = 1000)]
+    paths: usize,
+}
+
+#[derive(clap::Args)]
+struct RetireCmdArgs {
+    #[command(flatten)]
+    retire: RetireFlags,
+
+    /// Number of paths to simulate
+    #[arg(long, default_value_t = 1000)]
+    paths: usize,
+}
+
+#[derive(clap::Args)]
+struct GoalCmdArgs {
+    #[command(flatten)]
+    goal: GoalFlags,
+
+    /// Number of paths to simulate, for every contribution tried when solving
+    #[arg(long, default_value_t = 1000)]
+    paths: usize,
+}
+
+#[derive(clap::Args)]
+struct HarvestCmdArgs {
+    #[command(flatten)]
+    gen_returns: GenReturnsFlags,
+
+    #[command(flatten)]
+    harvest: HarvestFlags,
+
+    /// Number of paths to simulate
+    #[arg(long, default_value_t = 1000)]
+    paths: usize,
+}
+
+#[derive(clap::Args)]
+struct SequenceCmdArgs {
+    #[command(flatten)]
+    retire: RetireFlags,
+
+    /// File with one return per interval (e.g. 1.01 for +1%) or one price per line, at least as
+    /// many as the plan has intervals. Generates one path from --yearly-return and --yearly-vol if
+    /// omitted
+    #[arg(long, value_name = "FILE")]
+    returns: Option<PathBuf>,
+
+    /// Whether --returns holds returns or prices
+    #[arg(long, value_enum, default_value_t = SeriesKindArg::Auto, requires("returns"))]
+    input_kind: SeriesKindArg,
+
+    /// Number of random orders of the returns
+    #[arg(long, default_value_t = 1000)]
+    permutations: usize,
+}
+
+#[derive(clap::Args)]
+#[command(mut_arg("num_points", |a| a.required(false).required_unless_present("returns").default_value("0").hide_default_value(true)))]
+struct TailCmdArgs {
+    #[command(flatten)]
+    gen_returns: GenReturnsFlags,
+
+    #[command(flatten)]
+    tail: TailFlags,
+
+    /// File with one return per interval (e.g. 1.01 for +1%) or one price per line, spaced
+    /// --interval-seconds apart, to fit instead of simulated paths
+    #[arg(long, value_name = "FILE")]
+    returns: Option<PathBuf>,
+
+    /// Whether --returns holds returns or prices
+    #[arg(long, value_enum, default_value_t = SeriesKindArg::Auto, requires("returns"))]
+    input_kind: SeriesKindArg,
+
+    /// Number of paths to simulate, fitted together
+    #[arg(long, default_value_t = 100, conflicts_with("returns"))]
+    paths: usize,
+}
+
+#[derive(clap::Args)]
+struct CalibrateCmdArgs {
+    /// CSV file with historical prices (or returns). Reads stdin if omitted
+    input: Option<PathBuf>,
+
+    /// Whether the input column holds prices or returns
+    #[arg(long, value_enum, default_value_t = SeriesKindArg::Auto)]
+    input_kind: SeriesKindArg,
+
+    #[command(flatten)]
+    calibrate: CalibrateFlags,
+}
+
+#[derive(clap::Args)]
+struct ScenariosArgs {
+    /// TOML file with flags shared by all scenarios at the top level and a
+    /// `[scenarios.<name>]` table of flags for each scenario
+    file: PathBuf,
+
+    #[command(flatten)]
+    output: OutputFlags,
+}
+
+#[derive(clap::Args)]
+struct SweepArgs {
+    /// TOML file with flags shared by all runs at the top level and a `[sweep]` table with
+    /// an array of values or a `{ from, to, step }` range for each swept flag
+    file: PathBuf,
+}
+
+#[derive(clap::Args)]
+struct PortfolioArgs {
+    /// TOML file with an `[[assets]]` table (name, weight and optionally yearly_mean,
+    /// yearly_stddev and foreign) per asset, an optional `correlation` matrix, an optional `[fx]`
+    /// exchange rate for the foreign assets and flags at the top level
+    file: PathBuf,
+
+    #[command(flatten)]
+    output: OutputFlags,
+}
+
+#[derive(clap::Args)]
+struct RebalancingArgs {
+    /// Portfolio spec file, in the format of `finsim portfolio`
+    file: PathBuf,
+
+    /// Number of paths to simulate
+    #[arg(long, default_value_t = 1000)]
+    paths: usize,
+}
+
+#[cfg(feature = "serve")]
+#[derive(clap::Args)]
+struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8000")]
+    addr: String,
+
+    /// Most points a request may simulate over all its paths, answering 400 above
+    #[arg(long, default_value_t = 10_000_000, value_parser = returns::parse_count)]
+    max_points: usize,
+}
+
+#[derive(clap::Args)]
+struct ReplArgs {
+    /// TOML file with the flags to start from, in the format of --config
+    file: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct CompletionsArgs {
+    /// Shell to complete in
+    shell: clap_complete::Shell,
+}
+
+#[derive(clap::Args)]
+struct ManArgs {
+    /// Write finsim.1 and a finsim-<subcommand>.1 page per subcommand to DIR instead of printing
+    /// the top level page
+    #[arg(long, value_name = "DIR")]
+    dir: Option<PathBuf>,
+}
+
+/// The flags a scenario in a scenario file can set
+#[derive(Parser)]
+#[command(name = "scenario", no_binary_name = true, args_override_self = true)]
+struct ScenarioArgs {
     #[command(flatten)]
+    gen_returns: GenReturnsFlags,
+
+    #[command(flatten)]
+    accumulate: AccumulateFlags,
+}
+
+/// The settings of an entry in a scenario, sweep or portfolio file
+struct Entry {
+    gen_returns: GenReturnsArgs,
     accumulate: AccumulateArgs,
 }
 
+impl ScenarioArgs {
+    /// Converts and validates the flags
+    fn into_entry(self) -> error::Result<Entry> {
+        let gen_returns = GenReturnsArgs::from(self.gen_returns);
+        let ticks_per_year = gen_returns.time_base.ticks_per_year(gen_returns.tick_interval());
+        let entry = Entry {
+            gen_returns,
+            accumulate: self.accumulate.into_args(ticks_per_year),
+        };
+        entry.gen_returns.validate()?;
+        entry.accumulate.validate()?;
+        Ok(entry)
+    }
+
+    /// Parses and validates the flags of an entry in a scenario, sweep or
+    /// portfolio file, exiting with an error naming `entry` if they are invalid.
+    /// `seed` is used if the entry doesn't set one.
+    fn parse_entry(args: &[String], entry: &str, seed: Option<u64>) -> Entry {
+        let mut args = args.to_vec();
+        if let Err(e) = preset::expand_preset(&mut args, 0) {
+            eprintln!("error: in {}: {}", entry, e);
+            std::process::exit(1);
+        }
+        let res = ScenarioArgs::try_parse_from(args).and_then(|s| {
+            s.into_entry().map_err(|e| ScenarioArgs::command().error(ErrorKind::ValueValidation, e))
+        });
+        let mut s = res.unwrap_or_else(|e| {
+            eprintln!("In {}:", entry);
+            e.exit();
+        });
+        s.gen_returns.seed = s.gen_returns.seed.or(seed);
+        s
+    }
+}
+
 fn main() {
-    let args = Args::parse();
-    let returns = gen_returns(&args.gen_returns);
-    let acc = accumulate(returns, &args.accumulate);
-    let stdout = io::stdout();
-    let mut handle = io::BufWriter::new(stdout);
-    for r in acc.iter() {
-        writeln!(handle, "{}", r).unwrap();
-    }
-    handle.flush().unwrap();
+    let argv = config::expand_config_args(std::env::args_os().collect())
+        .and_then(preset::expand_preset_args)
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        });
+    let matches = cli::with_env(Args::command()).get_matches_from(argv);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let quiet = args.quiet;
+    let level = match args.verbose {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_max_level(level)
+        .init();
+    if args.watch && args.watch_seed.is_none() {
+        watch(&args);
+    }
+    let watch_seed = args.watch_seed;
+    match args.command {
+        Command::Gen(args) => {
+            let mut gen = GenReturnsArgs::from(args.gen_returns);
+            gen.seed = gen.seed.or(watch_seed);
+            if let Some(i) = args.path {
+                let Some(seed) = gen.seed else {
+                    Args::command().error(ErrorKind::MissingRequiredArgument, "--path requires --seed").exit();
+                };
+                gen.seed = Some(model::path_seed(seed, i));
+            }
+            let gen_args = &gen;
+            let acc_args = args.accumulate.into_args(gen_args.time_base.ticks_per_year(gen_args.tick_interval()));
+            let output_args = OutputArgs { with_inflation: args.with_inflation, ..output_args(args.output) };
+            validate(Some(gen_args), &acc_args);
+            let inflation = args.inflation.args();
+            if let Err(e) = output_args.validate().and(inflation.map_or(Ok(()), |i| i.validate())) {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            if gen_args.rng != RngKind::Std && (args.save_state.is_some() || args.resume_state.is_some()) {
+                let message = "--save-state and --resume-state only work with --rng std";
+                Args::command().error(ErrorKind::ArgumentConflict, message).exit();
+            }
+            if args.describe && gen_args.tail_df.is_some_and(f64::is_finite) {
+                let message = "--describe derives lognormal terminal values, which --tail-df doesn't have";
+                Args::command().error(ErrorKind::ArgumentConflict, message).exit();
+            }
+            if args.describe {
+                let derived = gen_args.derived();
+                let start_value = if acc_args.accumulate { acc_args.start_value } else { 1.0 };
+                let mut out = io::BufWriter::new(io::stdout().lock());
+                writeln!(out, "interval seconds: {}", derived.interval_seconds).unwrap();
+                writeln!(out, "total seconds: {}", derived.total_seconds).unwrap();
+                writeln!(out, "ticks per year: {}", derived.ticks_per_year).unwrap();
+                writeln!(out, "tick log mean: {}", derived.tick_mu).unwrap();
+                writeln!(out, "tick log stddev: {}", derived.tick_sigma).unwrap();
+                writeln!(out, "terminal value (unlevered):").unwrap();
+                writeln!(out, "  mean: {}", start_value * derived.terminal_mean()).unwrap();
+                // z-scores of the 5th and 95th percentile
+                writeln!(out, "  5th percentile: {}", start_value * derived.terminal_quantile(-1.6448536269514722)).unwrap();
+                writeln!(out, "  median: {}", start_value * derived.terminal_quantile(0.0)).unwrap();
+                writeln!(out, "  95th percentile: {}", start_value * derived.terminal_quantile(1.6448536269514722)).unwrap();
+                out.flush().unwrap();
+                return;
+            }
+            let mut generated = match &args.resume_state {
+                Some(path) => {
+                    let state = std::fs::read_to_string(path)
+                        .map_err(Error::from)
+                        .and_then(|s| s.parse())
+                        .unwrap_or_else(|e| {
+                            eprintln!("error: failed to read generator state from {}: {}", path.display(), e);
+                            std::process::exit(1);
+                        });
+                    or_exit(resume_returns(gen_args, &state))
+                }
+                None => or_exit(gen_returns(gen_args)),
+            };
+            // Lines on a terminal show the progress of streamed output
+            let stream = streams(&output_args);
+            let on_terminal = output_args.output == OutputTarget::Stdout && io::stdout().is_terminal();
+            let bar = match gen_args.num_points {
+                n if n >= PROGRESS_MIN_POINTS => progress_bar(n, "points", quiet || on_terminal),
+                _ => ProgressBar::hidden(),
+            };
+            info!(num_points = gen_args.num_points, "generating");
+            let start = Instant::now();
+            let ticks_per_year = gen_args.time_base.ticks_per_year(gen_args.tick_interval());
+            let results = if stream {
+                let interval = gen_args.tick_interval();
+                let rows = data_points((&mut generated).progress_with(bar.clone()), interval, &acc_args);
+                let levels = inflation.map(|i| i.levels(ticks_per_year, gen_args.num_points, gen_args.rng, gen_args.seed));
+                let rows = inflation::with_levels(rows, levels, args.inflation.real, acc_args.accumulate, acc_args.log_values);
+                let run = Run {
+                    scenario: None,
+                    values: &[],
+                    returns: None,
+                    interval,
+                    gen_args: Some(gen_args),
+                    acc_args: &acc_args,
+                };
+                write_stream([(run, rows)], gen_args.num_points, &output_args);
+                None
+            } else {
+                let (mut returns, mut acc) = simulate((&mut generated).progress_with(bar.clone()), &acc_args, output_args.with_returns);
+                if let Some(inflation) = inflation.filter(|_| args.inflation.real) {
+                    let levels: Vec<f64> = inflation.levels(ticks_per_year, gen_args.num_points, gen_args.rng, gen_args.seed).collect();
+                    acc = inflation::deflate(acc.into_iter(), levels.iter().copied(), acc_args.accumulate, acc_args.log_values).collect();
+                    returns = returns.map(|r| inflation::deflate(r.into_iter(), levels.iter().copied(), false, false).collect());
+                }
+                Some((returns, acc))
+            };
+            bar.finish_and_clear();
+            info!(elapsed = ?start.elapsed(), "generated");
+            if let (Some(path), Some(state)) = (&args.save_state, generated.rng_state()) {
+                let res = std::fs::write(path, format!("{}\n", state)).map_err(Error::from);
+                or_exit(res.map_err(|e| e.context(format!("failed to save generator state to {}", path.display()))));
+            }
+            if let Some((returns, acc)) = results {
+                let run = Run {
+                    scenario: None,
+                    values: &acc,
+                    returns: returns.as_deref(),
+                    interval: gen_args.tick_interval(),
+                    gen_args: Some(gen_args),
+                    acc_args: &acc_args,
+                };
+                write_output(&[run], &output_args);
+            }
+        }
+        Command::Acc(cmd) => {
+            let mut output_args = output_args(cmd.output);
+            let inputs: Vec<(Option<StreamHeader>, Vec<f64>)> = match or_exit(open_input(cmd.input.as_deref()).and_then(input::read_input)) {
+                Input::Plain(series) => {
+                    let returns = match SeriesKind::from(cmd.input_kind).resolve(&series, SeriesKind::Returns) {
+                        SeriesKind::Prices => input::prices_to_returns(&series),
+                        _ => series,
+                    };
+                    vec![(None, returns)]
+                }
+                Input::Stream(series) => series.into_iter().map(|s| (Some(s.header.clone()), s.returns())).collect(),
+            };
+            // The start time and tags of stream input pass through unless given
+            if let Some(header) = inputs.iter().find_map(|(header, _)| header.as_ref()) {
+                output_args.start_timestamp = output_args.start_timestamp.or(header.start_timestamp);
+                for (k, v) in &header.tags {
+                    if !output_args.tags.iter().any(|(key, _)| key == k) {
+                        output_args.tags.push((k.clone(), v.clone()));
+                    }
+                }
+            }
+            let interval = |header: &Option<StreamHeader>| -> Interval {
+                cmd.interval_seconds.or(header.as_ref().map(|h| h.interval_seconds)).unwrap_or(86400.0).into()
+            };
+            // Financing accrues at the interval of the first series
+            let ticks_per_year = TimeBase::WallClock.ticks_per_year(inputs.first().map_or(interval(&None), |(h, _)| interval(h)));
+            let mut acc_args = cmd.accumulate.into_args(ticks_per_year);
+            validate(None, &acc_args);
+            acc_args.accumulate = true;
+            let accs: Vec<Vec<f64>> = inputs.iter().map(|(_, returns)| accumulate(returns.iter().copied(), &acc_args)).collect();
+            let runs: Vec<Run> = inputs
+                .iter()
+                .zip(&accs)
+                .map(|((header, returns), acc)| Run {
+                    scenario: header.as_ref().and_then(|h| h.scenario.as_deref()),
+                    values: acc,
+                    returns: output_args.with_returns.then_some(returns.as_slice()),
+                    interval: interval(header),
+                    gen_args: None,
+                    acc_args: &acc_args,
+                })
+                .collect();
+            write_output(&runs, &output_args);
+        }
+        Command::Stats(cmd) => {
+            let time_base = TimeBase::from(cmd.time_base);
+            if let Err(e) = time_base.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            // (scenario, values, returns, interval) of every series
+            let series: Vec<_> = match or_exit(open_input(cmd.input.as_deref()).and_then(input::read_input)) {
+                Input::Stream(series) => series
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| {
+                        let scenario = s.header.scenario.clone().unwrap_or_else(|| i.to_string());
+                        (scenario, s.values(), s.returns(), Some(s.header.interval_seconds))
+                    })
+                    .collect(),
+                Input::Plain(values) => {
+                    let returns = match SeriesKind::from(cmd.input_kind).resolve(&values, SeriesKind::Prices) {
+                        SeriesKind::Returns => values.clone(),
+                        _ => input::prices_to_returns(&values),
+                    };
+                    vec![(String::new(), values, returns, None)]
+                }
+            };
+            let mut columns = Vec::new();
+            if cmd.hurst {
+                columns.push("hurst");
+            }
+            if cmd.ulcer {
+                columns.extend(["ulcer_index", "pain_index", "martin_ratio", "pain_ratio"]);
+            }
+            if cmd.omega {
+                columns.push("omega");
+            }
+            let benchmark = match (&cmd.benchmark, &cmd.benchmark_scenario) {
+                (Some(path), _) => Some(match or_exit(open_input(Some(path)).and_then(input::read_input)) {
+                    Input::Stream(series) => series.first().map(|s| s.returns()).unwrap_or_default(),
+                    Input::Plain(values) => match SeriesKind::from(cmd.input_kind).resolve(&values, SeriesKind::Prices) {
+                        SeriesKind::Returns => values,
+                        _ => input::prices_to_returns(&values),
+                    },
+                }),
+                (None, Some(name)) => match series.iter().find(|s| &s.0 == name) {
+                    Some(s) => Some(s.2.clone()),
+                    None => {
+                        let msg = format!("no series of the input has the scenario name '{}'", name);
+                        Args::command().error(ErrorKind::InvalidValue, msg).exit();
+                    }
+                },
+                (None, None) => None,
+            };
+            if benchmark.is_some() {
+                columns.extend(["beta", "correlation", "tracking_error", "information_ratio"]);
+            }
+            let rows: Vec<_> = series
+                .iter()
+                .map(|(scenario, values, returns, interval_seconds)| {
+                    let interval_seconds = cmd.interval_seconds.or(*interval_seconds).unwrap_or(86400.0);
+                    let ticks_per_year = time_base.ticks_per_year(interval_seconds.into());
+                    let excess_return = stats::yearly_return(returns, ticks_per_year) - cmd.risk_free / 100.0;
+                    let mut extra = Vec::new();
+                    if cmd.hurst {
+                        extra.push(stats::hurst(returns));
+                    }
+                    if cmd.ulcer {
+                        let risk = DrawdownRisk::of(1.0, input::returns_to_prices(returns, 1.0).into_iter().skip(1));
+                        extra.extend([
+                            risk.ulcer_index,
+                            risk.pain_index,
+                            risk.martin_ratio(excess_return),
+                            risk.pain_ratio(excess_return),
+                        ]);
+                    }
+                    if cmd.omega {
+                        let threshold = (1.0 + cmd.omega_threshold / 100.0).powf(1.0 / ticks_per_year);
+                        extra.push(stats::omega(returns, threshold));
+                    }
+                    if let Some(benchmark) = &benchmark {
+                        let relative = or_exit(BenchmarkStats::of(returns, benchmark, ticks_per_year));
+                        extra.extend([relative.beta, relative.correlation, relative.tracking_error, relative.information_ratio]);
+                    }
+                    let keys = if series.len() > 1 { vec![scenario.clone()] } else { Vec::new() };
+                    (keys, output::Summary::of(values), extra)
+                })
+                .collect();
+            let keys = if rows.len() > 1 { vec!["scenario".to_string()] } else { Vec::new() };
+            let mut stdout = io::stdout().lock();
+            output::write_stats_csv(&keys, &columns, &rows, &mut stdout).unwrap();
+        }
+        Command::Mc(cmd) => {
+            let gen_args = GenReturnsArgs::from(cmd.gen_returns);
+            let acc_args = cmd.accumulate.into_args(gen_args.time_base.ticks_per_year(gen_args.tick_interval()));
+            let check_args = CheckArgs::from(cmd.checks);
+            validate(Some(&gen_args), &acc_args);
+            if cmd.drawdowns && !acc_args.accumulate {
+                Args::command().error(ErrorKind::ArgumentConflict, "--drawdowns needs --accumulate").exit();
+            }
+            let inflation = cmd.inflation.args();
+            if let Err(e) = check_args.validate().and(inflation.map_or(Ok(()), |i| i.validate())) {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let ticks_per_year = gen_args.time_base.ticks_per_year(gen_args.tick_interval());
+            let real = inflation.filter(|_| cmd.inflation.real).map(|inflation| RealArgs { inflation, ticks_per_year, rng: gen_args.rng });
+            let seed = gen_args.seed.or(watch_seed);
+            let metrics = Arc::new(Metrics::new());
+            if let Some(addr) = &cmd.metrics_addr {
+                let listener = or_exit(std::net::TcpListener::bind(addr).map_err(Error::from));
+                metrics::serve_metrics(listener, metrics.clone());
+            }
+            let bar = progress_bar(cmd.paths, "paths", quiet);
+            info!(paths = cmd.paths, num_points = gen_args.num_points, "simulating");
+            let start = Instant::now();
+            let model = LogNormal::from(&gen_args);
+            let interval_seconds = gen_args.tick_interval_seconds();
+            let (with_drawdowns, start_value, log_values) = (cmd.drawdowns, acc_args.start_value, acc_args.log_values);
+            let of = |values: &mut dyn Iterator<Item = f64>| {
+                let mut drawdowns = Drawdowns::new(start_value);
+                let summary = output::Summary::of_iter(values.inspect(|&value| {
+                    if with_drawdowns {
+                        drawdowns.push(if log_values { value.exp() } else { value });
+                    }
+                }));
+                (summary, drawdowns.finish())
+            };
+            let paths: Vec<(output::Summary, Vec<Drawdown>)> = if cmd.threads == 1 {
+                let num_points = gen_args.num_points;
+                or_exit(
+                    model::monte_carlo_with(&model, interval_seconds, num_points, &acc_args, real.as_ref(), cmd.paths, seed, of)
+                        .progress_with(bar.clone())
+                        .inspect(|summary| match summary {
+                            Ok(_) => metrics.path_completed(gen_args.num_points),
+                            Err(_) => metrics.error(),
+                        })
+                        .collect(),
+                )
+            } else {
+                let pool = rayon::ThreadPoolBuilder::new().num_threads(cmd.threads).build();
+                let pool = or_exit(pool.map_err(|e| Error::Invalid(e.to_string())));
+                or_exit(pool.install(|| {
+                    let num_points = gen_args.num_points;
+                    let real = real.as_ref();
+                    model::par_monte_carlo_with(&model, interval_seconds, num_points, &acc_args, real, cmd.paths, seed, of, || {
+                        bar.inc(1);
+                        metrics.path_completed(num_points);
+                    })
+                }))
+            };
+            let (summaries, drawdowns): (Vec<output::Summary>, Vec<Vec<Drawdown>>) = paths.into_iter().unzip();
+            bar.finish_and_clear();
+            info!(elapsed = ?start.elapsed(), "simulated");
+            let checks = check_args.run(&summaries, acc_args.log_values);
+            if !quiet {
+                let rows: Vec<(Vec<String>, output::Summary)> =
+                    summaries.into_iter().enumerate().map(|(i, s)| (vec![i.to_string()], s)).collect();
+                let mut stdout = io::BufWriter::new(io::stdout().lock());
+                let keys = ["path".to_string()];
+                if cmd.drawdowns {
+                    let rows: Vec<_> = drawdowns.into_iter().enumerate().map(|(i, d)| (vec![i.to_string()], d)).collect();
+                    output::write_drawdowns_csv(&keys, &rows, &mut stdout).unwrap();
+                } else if real.is_some() && acc_args.accumulate && !acc_args.log_values {
+                    let years = gen_args.num_points as f64 / ticks_per_year;
+                    output::write_cagr_summary_csv(&keys, &rows, acc_args.start_value, years, &mut stdout).unwrap();
+                } else {
+                    output::write_summary_csv(&keys, &rows, &mut stdout).unwrap();
+                }
+                stdout.flush().unwrap();
+                for check in &checks {
+                    eprintln!("{}", check);
+                }
+            }
+            std::process::exit(checks::exit_code(&checks));
+        }
+        Command::OptionPrice(cmd) => {
+            let option = OptionArgs::from(cmd.option);
+            let gen_args = option
+                .risk_neutral(&GenReturnsArgs::from(cmd.gen_returns))
+                .unwrap_or_else(|e| Args::command().error(ErrorKind::ArgumentConflict, e).exit());
+            let acc_args = option.acc_args();
+            validate(Some(&gen_args), &acc_args);
+            if let Err(e) = option.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            if cmd.paths < 2 {
+                Args::command().error(ErrorKind::ValueValidation, "--paths must be at least 2").exit();
+            }
+            let seed = gen_args.seed.or(watch_seed);
+            let bar = progress_bar(cmd.paths, "paths", quiet);
+            info!(paths = cmd.paths, num_points = gen_args.num_points, "pricing");
+            let model = LogNormal::from(&gen_args);
+            let interval_seconds = gen_args.tick_interval_seconds();
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(cmd.threads).build();
+            let pool = or_exit(pool.map_err(|e| Error::Invalid(e.to_string())));
+            let paths = or_exit(pool.install(|| {
+                let num_points = gen_args.num_points;
+                model::par_monte_carlo(&model, interval_seconds, num_points, &acc_args, None, cmd.paths, seed, || bar.inc(1))
+            }));
+            bar.finish_and_clear();
+            let estimate = option.price(&gen_args, &paths);
+            println!("price: {}", estimate.price);
+            println!("standard error: {}", estimate.std_error);
+            if let Some(price) = option.black_scholes(&gen_args) {
+                println!("black-scholes: {}", price);
+            }
+        }
+        Command::Mortgage(cmd) => {
+            let gen_args = GenReturnsArgs::from(cmd.gen_returns);
+            if let Err(e) = gen_args.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let mortgage = cmd.mortgage.into_args(gen_args.tick_interval_seconds());
+            if let Err(e) = mortgage.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            if cmd.paths == 0 {
+                Args::command().error(ErrorKind::ValueValidation, "--paths must be at least 1").exit();
+            }
+            let seed = gen_args.seed.or(watch_seed);
+            info!(paths = cmd.paths, num_points = gen_args.num_points, "comparing");
+            let model = LogNormal::from(&gen_args);
+            let comparisons = or_exit(mortgage.compare_paths(&model, &gen_args, cmd.paths, seed));
+            let report = MortgageReport::of(&comparisons);
+            println!("prepay net worth (median): {}", report.prepay_median);
+            println!("invest net worth (median): {}", report.invest_median);
+            println!(
+                "invest beats prepay: {} of {} paths ({}%)",
+                report.invest_wins,
+                report.paths,
+                report.invest_wins_percent()
+            );
+        }
+        Command::Futures(cmd) => {
+            let output_args = output_args(cmd.output);
+            let mut gen_args = GenReturnsArgs::from(cmd.gen_returns);
+            gen_args.seed = gen_args.seed.or(watch_seed);
+            let acc_args = cmd.accumulate.into_args(gen_args.time_base.ticks_per_year(gen_args.tick_interval()));
+            validate(Some(&gen_args), &acc_args);
+            let futures = cmd.futures.into_args(gen_args.tick_interval_seconds());
+            if let Err(e) = futures.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let generated = futures.roll(or_exit(gen_returns(&gen_args)));
+            let (returns, acc) = simulate(generated, &acc_args, output_args.with_returns);
+            let run = Run {
+                scenario: None,
+                values: &acc,
+                returns: returns.as_deref(),
+                interval: gen_args.tick_interval(),
+                gen_args: Some(&gen_args),
+                acc_args: &acc_args,
+            };
+            write_output(&[run], &output_args);
+        }
+        Command::LeverageCompare(cmd) => {
+            let output_args = output_args(cmd.output);
+            let gen_args = GenReturnsArgs::from(cmd.gen_returns);
+            if let Err(e) = gen_args.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let leverage = LeverageCompareArgs::from(cmd.leverage);
+            if let Err(e) = leverage.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let returns: Vec<f64> = or_exit(gen_returns(&gen_args)).collect();
+            let interval = gen_args.tick_interval();
+            let values = leverage.compare(&returns, gen_args.time_base.ticks_per_year(interval));
+            let acc_args = leverage.acc_args();
+            let series = [("underlying", &values.underlying), ("daily-reset", &values.daily_reset), ("margin", &values.margin)];
+            let runs: Vec<Run> = series
+                .into_iter()
+                .zip(&acc_args)
+                .map(|((name, values), acc_args)| Run {
+                    scenario: Some(name),
+                    values,
+                    returns: output_args.with_returns.then_some(&returns[..]),
+                    interval,
+                    gen_args: Some(&gen_args),
+                    acc_args,
+                })
+                .collect();
+            write_output(&runs, &output_args);
+        }
+        Command::Dca(cmd) => {
+            let gen_args = GenReturnsArgs::from(cmd.gen_returns);
+            if let Err(e) = gen_args.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let dca = DcaArgs::from(cmd.dca);
+            if let Err(e) = dca.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            if cmd.paths == 0 {
+                Args::command().error(ErrorKind::ValueValidation, "--paths must be at least 1").exit();
+            }
+            let seed = gen_args.seed.or(watch_seed);
+            info!(paths = cmd.paths, num_points = gen_args.num_points, "comparing");
+            let model = LogNormal::from(&gen_args);
+            let outcomes = or_exit(dca.compare_paths(&model, &gen_args, cmd.paths, seed));
+            let report = DcaReport::of(&outcomes);
+            println!("lump sum (median): {}", report.lump_sum_median);
+            println!("lump sum (10th percentile): {}", report.lump_sum_p10);
+            println!("lump sum (90th percentile): {}", report.lump_sum_p90);
+            println!("dca (median): {}", report.dca_median);
+            println!("dca (10th percentile): {}", report.dca_p10);
+            println!("dca (90th percentile): {}", report.dca_p90);
+            println!(
+                "lump sum beats dca: {} of {} paths ({}%)",
+                report.lump_sum_wins,
+                report.paths,
+                report.lump_sum_wins_percent()
+            );
+            println!("dca beats lump sum: {} of {} paths ({}%)", report.dca_wins, report.paths, report.dca_wins_percent());
+        }
+        Command::Retire(cmd) => {
+            let gen_args = cmd.retire.gen_args();
+            if let Err(e) = gen_args.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let retire = cmd.retire.into_args();
+            if let Err(e) = retire.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            if cmd.paths == 0 {
+                Args::command().error(ErrorKind::ValueValidation, "--paths must be at least 1").exit();
+            }
+            let seed = gen_args.seed.or(watch_seed);
+            info!(paths = cmd.paths, num_points = retire.ticks(), "planning");
+            let model = LogNormal::from(&gen_args);
+            let outcomes = or_exit(retire.plan_paths(&model, &gen_args, cmd.paths, seed));
+            let report = RetireReport::of(&outcomes);
+            println!(
+                "savings last: {} of {} paths ({}%)",
+                report.successes,
+                report.paths,
+                report.success_percent()
+            );
+            println!("savings at retirement (median, today's money): {}", report.at_retirement_median);
+            println!("savings at the end (median, today's money): {}", report.at_end_median);
+            println!("savings at the end (10th percentile, today's money): {}", report.at_end_p10);
+            if retire.annuity.is_some() {
+                let ticks_per_year = gen_args.time_base.ticks_per_year(gen_args.tick_interval());
+                println!("annuity payout a year (median, today's money): {}", report.annuity_payout_median * ticks_per_year);
+            }
+        }
+        Command::Goal(cmd) => {
+            let gen_args = cmd.goal.gen_args();
+            if let Err(e) = gen_args.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let (probability, years_per_tick) = (cmd.goal.probability, cmd.goal.years_per_tick());
+            let goal = cmd.goal.into_args();
+            if let Err(e) = goal.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            if cmd.paths == 0 {
+                Args::command().error(ErrorKind::ValueValidation, "--paths must be at least 1").exit();
+            }
+            let seed = gen_args.seed.or(watch_seed);
+            info!(paths = cmd.paths, num_points = goal.plan.ticks(), "planning");
+            let model = LogNormal::from(&gen_args);
+            match probability {
+                Some(p) => match or_exit(goal.solve_contribution(&model, &gen_args, cmd.paths, seed, p / 100.0)) {
+                    Some(contribution) => {
+                        println!("contribution a year for {}% (today's money): {}", p, contribution / years_per_tick)
+                    }
+                    None => println!("no contribution reaches the target on {}% of the paths", p),
+                },
+                None => {
+                    let report = or_exit(goal.probability(&model, &gen_args, cmd.paths, seed));
+                    println!("target reached: {} of {} paths ({}%)", report.successes, report.paths, report.percent());
+                }
+            }
+        }
+        Command::Harvest(cmd) => {
+            let gen_args = GenReturnsArgs::from(cmd.gen_returns);
+            if let Err(e) = gen_args.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let harvest = HarvestArgs::from(cmd.harvest);
+            if let Err(e) = harvest.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            if cmd.paths == 0 {
+                Args::command().error(ErrorKind::ValueValidation, "--paths must be at least 1").exit();
+            }
+            let seed = gen_args.seed.or(watch_seed);
+            info!(paths = cmd.paths, num_points = gen_args.num_points, "harvesting");
+            let model = LogNormal::from(&gen_args);
+            let outcomes = or_exit(harvest.simulate_paths(&model, &gen_args, cmd.paths, seed));
+            let report = HarvestReport::of(&outcomes);
+            println!("after-tax value harvesting (median): {}", report.harvested_median);
+            println!("after-tax value holding (median): {}", report.held_median);
+            println!("harvesting benefit (mean): {}%", report.benefit_mean * 100.0);
+            println!("harvesting benefit (median): {}%", report.benefit_median * 100.0);
+            println!("harvesting benefit (10th percentile): {}%", report.benefit_p10 * 100.0);
+            println!("harvesting benefit (90th percentile): {}%", report.benefit_p90 * 100.0);
+            println!("losses harvested (median): {}", report.harvests_median);
+            println!("harvesting ahead: {} of {} paths ({}%)", report.helped, report.paths, report.helped_percent());
+        }
+        Command::Sequence(cmd) => {
+            let gen_args = cmd.retire.gen_args();
+            if let Err(e) = gen_args.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let retire = cmd.retire.into_args();
+            if let Err(e) = retire.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            if cmd.permutations == 0 {
+                Args::command().error(ErrorKind::ValueValidation, "--permutations must be at least 1").exit();
+            }
+            let seed = gen_args.seed.or(watch_seed);
+            let returns: Vec<f64> = match &cmd.returns {
+                Some(path) => {
+                    let series = or_exit(open_input(Some(path)).and_then(input::read_series));
+                    match SeriesKind::from(cmd.input_kind).resolve(&series, SeriesKind::Returns) {
+                        SeriesKind::Prices => input::prices_to_returns(&series),
+                        _ => series,
+                    }
+                }
+                None => {
+                    let seed = seed.map(|s| model::path_seed(s, 0));
+                    or_exit(gen_returns(&GenReturnsArgs { seed, ..gen_args })).collect()
+                }
+            };
+            info!(permutations = cmd.permutations, num_points = retire.ticks(), "reordering");
+            let ticks_per_year = gen_args.time_base.ticks_per_year(gen_args.tick_interval());
+            let report =
+                or_exit(sequence::sequence_risk(&retire, &returns, ticks_per_year, cmd.permutations, gen_args.rng, seed));
+            println!(
+                "savings last: {} of {} orders ({}%)",
+                report.successes,
+                report.permutations,
+                report.success_percent()
+            );
+            println!("savings at the end (in order, today's money): {}", report.original.at_end);
+            println!("savings at the end (reversed, today's money): {}", report.reversed.at_end);
+            println!("savings at the end (min, today's money): {}", report.at_end_min);
+            println!("savings at the end (10th percentile, today's money): {}", report.at_end_p10);
+            println!("savings at the end (median, today's money): {}", report.at_end_median);
+            println!("savings at the end (90th percentile, today's money): {}", report.at_end_p90);
+            println!("savings at the end (max, today's money): {}", report.at_end_max);
+            println!("savings at the end (stddev over orders, today's money): {}", report.at_end_stddev);
+        }
+        Command::Tail(cmd) => {
+            let gen_args = GenReturnsArgs::from(cmd.gen_returns);
+            if let Err(e) = gen_args.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let tail = TailArgs::from(cmd.tail);
+            if let Err(e) = tail.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            if cmd.paths == 0 {
+                Args::command().error(ErrorKind::ValueValidation, "--paths must be at least 1").exit();
+            }
+            let report = match &cmd.returns {
+                Some(path) => {
+                    let series = or_exit(open_input(Some(path)).and_then(input::read_series));
+                    let returns = match SeriesKind::from(cmd.input_kind).resolve(&series, SeriesKind::Returns) {
+                        SeriesKind::Prices => input::prices_to_returns(&series),
+                        _ => series,
+                    };
+                    or_exit(tail.report(&returns, gen_args.time_base.ticks_per_year(gen_args.tick_interval())))
+                }
+                None => {
+                    info!(paths = cmd.paths, num_points = gen_args.num_points, "fitting the tail");
+                    let model = LogNormal::from(&gen_args);
+                    or_exit(tail.simulate_paths(&model, &gen_args, cmd.paths, gen_args.seed.or(watch_seed)))
+                }
+            };
+            println!("ticks: {}", report.observations);
+            println!("ticks beyond the threshold: {} ({}%)", report.exceedances, tail.tail * 100.0);
+            println!("threshold tick loss: {}%", report.threshold * 100.0);
+            println!("tail index (GPD shape): {}", report.shape);
+            println!("GPD scale: {}%", report.scale * 100.0);
+            for level in &report.return_levels {
+                println!("1-in-{}-year tick loss: {}%", level.years, level.loss * 100.0);
+            }
+        }
+        Command::Calibrate(cmd) => {
+            let mut series = or_exit(input::read_price_csv(
+                or_exit(open_input(cmd.input.as_deref())),
+                &cmd.calibrate.price_column,
+                &cmd.calibrate.date_column,
+            ));
+            if SeriesKind::from(cmd.input_kind).resolve(&series.prices, SeriesKind::Prices) == SeriesKind::Returns {
+                series.prices = input::returns_to_prices(&series.prices, 1.0);
+                // The first return ends one interval after the first price
+                if let Some(timestamps) = &mut series.timestamps {
+                    if let (Some(&first), Some(&second)) = (timestamps.first(), timestamps.get(1)) {
+                        timestamps.insert(0, 2 * first - second);
+                    }
+                }
+            }
+            if series.prices.len() < 3 {
+                Args::command()
+                    .error(ErrorKind::InvalidValue, "at least 3 prices are needed for calibration")
+                    .exit();
+            }
+            let time_base = TimeBase::from(cmd.calibrate.time_base);
+            if let Err(e) = time_base.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let interval_seconds = cmd
+                .calibrate
+                .interval_seconds
+                .or_else(|| calibrate::mean_interval_seconds(series.timestamps.as_deref()?, time_base));
+            let Some(interval_seconds) = interval_seconds else {
+                Args::command()
+                    .error(ErrorKind::MissingRequiredArgument, "--interval-seconds is required when the input has no dates")
+                    .exit();
+            };
+            let calibration = calibrate::calibrate(&series.prices, interval_seconds, time_base);
+            println!("{}", calibration.to_flags());
+            if cmd.calibrate.moments {
+                eprintln!("returns: {}", calibration.num_returns);
+                eprintln!("skewness: {}", calibration.skewness);
+                eprintln!("excess kurtosis: {}", calibration.excess_kurtosis);
+            }
+        }
+        #[cfg(feature = "fetch")]
+        Command::Fetch(flags) => match fetch::fetch_csv(&flags.into()) {
+            Ok(csv) => print!("{}", csv),
+            Err(e) => {
+                eprintln!("error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        Command::Scenarios(cmd) => {
+            let output_args = output_args(cmd.output);
+            let config = std::fs::read_to_string(&cmd.file)
+                .map_err(Error::from)
+                .and_then(|c| config::scenarios_to_args(&c))
+                .unwrap_or_else(|e| {
+                    eprintln!("error: invalid scenario file {}: {}", cmd.file.display(), e);
+                    std::process::exit(1);
+                });
+            let scenarios: Vec<(String, Entry)> = config
+                .into_iter()
+                .map(|(name, args)| {
+                    let scenario_args = ScenarioArgs::parse_entry(&args, &format!("scenario '{}'", name), watch_seed);
+                    (name, scenario_args)
+                })
+                .collect();
+            if streams(&output_args) {
+                let runs = scenarios.iter().map(|(name, s)| {
+                    let run = Run {
+                        scenario: Some(name),
+                        values: &[],
+                        returns: None,
+                        interval: s.gen_returns.tick_interval(),
+                        gen_args: Some(&s.gen_returns),
+                        acc_args: &s.accumulate,
+                    };
+                    let generated = or_exit(gen_returns(&s.gen_returns));
+                    (run, data_points(generated, run.interval, &s.accumulate))
+                });
+                write_stream(runs, scenarios.iter().map(|(_, s)| s.gen_returns.num_points).sum(), &output_args);
+                return;
+            }
+            let results: Vec<(Option<Vec<f64>>, Vec<f64>)> = scenarios
+                .iter()
+                .map(|(_, s)| simulate(or_exit(gen_returns(&s.gen_returns)), &s.accumulate, output_args.with_returns))
+                .collect();
+            let runs: Vec<Run> = scenarios
+                .iter()
+                .zip(&results)
+                .map(|((name, s), (returns, acc))| Run {
+                    scenario: Some(name),
+                    values: acc,
+                    returns: returns.as_deref(),
+                    interval: s.gen_returns.tick_interval(),
+                    gen_args: Some(&s.gen_returns),
+                    acc_args: &s.accumulate,
+                })
+                .collect();
+            write_output(&runs, &output_args);
+        }
+        Command::Sweep(cmd) => {
+            let points = std::fs::read_to_string(&cmd.file)
+                .map_err(Error::from)
+                .and_then(|c| config::sweep_to_args(&c))
+                .unwrap_or_else(|e| {
+                    eprintln!("error: invalid sweep file {}: {}", cmd.file.display(), e);
+                    std::process::exit(1);
+                });
+            let keys: Vec<String> = points[0].0.iter().map(|(k, _)| k.clone()).collect();
+            let rows: Vec<(Vec<String>, output::Summary)> = points
+                .into_iter()
+                .map(|(assignment, args)| {
+                    let point: Vec<String> = assignment.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                    let s = ScenarioArgs::parse_entry(&args, &format!("sweep point {}", point.join(", ")), watch_seed);
+                    let values = accumulate_iter(or_exit(gen_returns(&s.gen_returns)), &s.accumulate);
+                    (assignment.into_iter().map(|(_, v)| v).collect(), output::Summary::of_iter(values))
+                })
+                .collect();
+            let mut stdout = io::BufWriter::new(io::stdout().lock());
+            output::write_summary_csv(&keys, &rows, &mut stdout).unwrap();
+            stdout.flush().unwrap();
+        }
+        Command::Portfolio(cmd) => {
+            let output_args = output_args(cmd.output);
+            let spec = read_portfolio(&cmd.file);
+            let entry = format!("portfolio file {}", cmd.file.display());
+            let args = ScenarioArgs::parse_entry(&spec.args, &entry, watch_seed);
+            let generated = or_exit(portfolio::portfolio_returns(&spec, &args.gen_returns));
+            if streams(&output_args) {
+                let run = Run {
+                    scenario: None,
+                    values: &[],
+                    returns: None,
+                    interval: args.gen_returns.tick_interval(),
+                    gen_args: None,
+                    acc_args: &args.accumulate,
+                };
+                let rows = data_points(generated, run.interval, &args.accumulate);
+                write_stream([(run, rows)], args.gen_returns.num_points, &output_args);
+                return;
+            }
+            let (returns, acc) = simulate(generated, &args.accumulate, output_args.with_returns);
+            let run = Run {
+                scenario: None,
+                values: &acc,
+                returns: returns.as_deref(),
+                interval: args.gen_returns.tick_interval(),
+                gen_args: None,
+                acc_args: &args.accumulate,
+            };
+            write_output(&[run], &output_args);
+        }
+        Command::Rebalancing(cmd) => {
+            let spec = read_portfolio(&cmd.file);
+            let entry = format!("portfolio file {}", cmd.file.display());
+            let gen_args = ScenarioArgs::parse_entry(&spec.args, &entry, watch_seed).gen_returns;
+            if gen_args.num_points == 0 {
+                Args::command().error(ErrorKind::ValueValidation, "--num-points must be at least 1").exit();
+            }
+            if cmd.paths == 0 {
+                Args::command().error(ErrorKind::ValueValidation, "--paths must be at least 1").exit();
+            }
+            info!(paths = cmd.paths, num_points = gen_args.num_points, "comparing");
+            let outcomes = or_exit(portfolio::compare_rebalancing(&spec, &gen_args, cmd.paths));
+            let years = gen_args.num_points as f64 / gen_args.time_base.ticks_per_year(gen_args.tick_interval());
+            let report = RebalancingReport::of(&outcomes, years);
+            println!("rebalanced value (median): {}", report.rebalanced_median);
+            println!("buy-and-hold value (median): {}", report.buy_and_hold_median);
+            println!("rebalancing bonus a year (mean): {}%", report.bonus_mean * 100.0);
+            println!("rebalancing bonus a year (median): {}%", report.bonus_median * 100.0);
+            println!("rebalancing bonus a year (10th percentile): {}%", report.bonus_p10 * 100.0);
+            println!("rebalancing bonus a year (90th percentile): {}%", report.bonus_p90 * 100.0);
+            println!(
+                "rebalanced beats buy-and-hold: {} of {} paths ({}%)",
+                report.rebalanced_wins,
+                report.paths,
+                report.rebalanced_wins_percent()
+            );
+        }
+        #[cfg(feature = "serve")]
+        Command::Serve(cmd) => {
+            let listener = or_exit(std::net::TcpListener::bind(&cmd.addr).map_err(Error::from));
+            eprintln!("listening on http://{}", cmd.addr);
+            let res = serve::serve(listener, cmd.max_points, |args, paths| {
+                let mut args = args.to_vec();
+                preset::expand_preset(&mut args, 0)?;
+                let s = ScenarioArgs::try_parse_from(args).map_err(|e| Error::Parse(e.render().to_string()))?.into_entry()?;
+                // Seeded per path like mc
+                (0..paths)
+                    .map(|i| {
+                        let gen_args = GenReturnsArgs { seed: s.gen_returns.seed.map(|seed| model::path_seed(seed, i)), ..s.gen_returns };
+                        Ok(simulate(gen_returns(&gen_args)?, &s.accumulate, false).1)
+                    })
+                    .collect()
+            });
+            or_exit(res.map_err(Error::from));
+        }
+        Command::Repl(cmd) => {
+            let mut session = repl::Session::default();
+            if let Some(path) = &cmd.file {
+                let res = std::fs::read_to_string(path).map_err(Error::from).and_then(|c| session.load(&c));
+                if let Err(e) = res {
+                    eprintln!("error: invalid flags file {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+            let stdin = io::stdin().lock();
+            repl::repl(&mut session, stdin, &mut io::stdout().lock(), |args, mut out| {
+                let mut args = args.to_vec();
+                preset::expand_preset(&mut args, 0)?;
+                let s = ScenarioArgs::try_parse_from(args).map_err(|e| Error::Parse(e.render().to_string()))?.into_entry()?;
+                let (_, acc) = simulate(gen_returns(&s.gen_returns)?, &s.accumulate, false);
+                let summary = output::Summary::of(&acc);
+                output::write_summary_csv(&[], &[(Vec::new(), summary)], &mut out)?;
+                Ok(output::write_ascii_chart(&acc, 72, 16, &mut out)?)
+            })
+            .unwrap();
+        }
+        Command::Selftest(cmd) => {
+            let cmd = SelftestArgs::from(cmd);
+            if let Err(e) = cmd.validate() {
+                Args::command().error(ErrorKind::ValueValidation, e).exit();
+            }
+            let start = Instant::now();
+            let checks = or_exit(selftest::selftest(&cmd));
+            info!(elapsed = ?start.elapsed(), "ran self-test");
+            let mut out = io::BufWriter::new(io::stdout().lock());
+            for check in checks.iter().filter(|c| !quiet || !c.passed()) {
+                writeln!(out, "{}", check).unwrap();
+            }
+            out.flush().unwrap();
+            if checks.iter().any(|c| !c.passed()) {
+                std::process::exit(1);
+            }
+        }
+        Command::Man(cmd) => {
+            let command = cli::with_env(Args::command());
+            let res = match &cmd.dir {
+                Some(dir) => clap_mangen::generate_to(command, dir),
+                None => clap_mangen::Man::new(command).render(&mut io::stdout()),
+            };
+            if let Err(e) = res {
+                eprintln!("error: failed to write man page: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Completions(cmd) => {
+            clap_complete::generate(cmd.shell, &mut cli::with_env(Args::command()), "finsim", &mut io::stdout());
+        }
+    }
+}
+
+/// Runs the command line again without --watch every time the config file or
+/// the file read by the subcommand changes. Each run is a child process, so
+/// that errors (which exit) keep the watch going.
+fn watch(args: &Args) -> ! {
+    let input = match &args.command {
+        Command::Acc(cmd) => cmd.input.clone(),
+        Command::Stats(cmd) => cmd.input.clone(),
+        Command::Calibrate(cmd) => cmd.input.clone(),
+        Command::Scenarios(cmd) => Some(cmd.file.clone()),
+        Command::Sweep(cmd) => Some(cmd.file.clone()),
+        Command::Portfolio(cmd) => Some(cmd.file.clone()),
+        Command::Rebalancing(cmd) => Some(cmd.file.clone()),
+        Command::Sequence(cmd) => cmd.returns.clone(),
+        Command::Tail(cmd) => cmd.returns.clone(),
+        _ => None,
+    };
+    let paths: Vec<PathBuf> = args.config.iter().cloned().chain(input).collect();
+    if paths.is_empty() {
+        let msg = "--watch needs a --config file or a subcommand reading a file";
+        Args::command().error(ErrorKind::MissingRequiredArgument, msg).exit();
+    }
+    let exe = std::env::current_exe().unwrap_or_else(|e| {
+        eprintln!("error: failed to locate the finsim executable: {}", e);
+        std::process::exit(1);
+    });
+    let argv: Vec<OsString> = std::env::args_os().skip(1).filter(|a| a != "--watch").collect();
+    let seed = rand::random::<u64>().to_string();
+    watch::watch(&paths, |changed| {
+        if let Some(path) = changed {
+            eprintln!("{} changed, re-running", path.display());
+        }
+        let status = std::process::Command::new(&exe).args(&argv).args(["--watch-seed", &seed]).status();
+        if let Err(e) = status {
+            eprintln!("error: failed to run {}: {}", exe.display(), e);
+            std::process::exit(1);
+        }
+    })
+}
+
+/// Exits with a usage error if the generation or accumulation flags are out of
+/// range
+fn validate(gen_args: Option<&GenReturnsArgs>, acc_args: &AccumulateArgs) {
+    let res = gen_args.map_or(Ok(()), |a| a.validate()).and_then(|_| acc_args.validate());
+    if let Err(e) = res {
+        Args::command().error(ErrorKind::ValueValidation, e).exit();
+    }
+}
+
+/// The output flags, or exits with a usage error if they don't go together
+fn output_args(mut flags: OutputFlags) -> OutputArgs {
+    let holidays = flags.holidays.take();
+    let mut args = OutputArgs::from(flags);
+    if let Some(path) = holidays {
+        let calendar = std::fs::read_to_string(&path).map_err(Error::from).and_then(|s| BusinessCalendar::parse(&s));
+        args.calendar = Some(calendar.unwrap_or_else(|e| {
+            eprintln!("error: invalid holidays file {}: {}", path.display(), e);
+            std::process::exit(1);
+        }));
+    }
+    if let Err(e) = args.validate() {
+        Args::command().error(ErrorKind::ValueValidation, e).exit();
+    }
+    args
+}
+
+/// The value of a library call, or exits with its error. Calls on validated
+/// flags only fail for reasons outside finsim.
+fn or_exit<T>(res: error::Result<T>) -> T {
+    res.unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    })
+}
+
+/// Accumulates generated returns, also collecting the returns themselves if
+/// `keep_returns` is set
+fn simulate(generated: impl Iterator<Item = f64>, acc_args: &AccumulateArgs, keep_returns: bool) -> (Option<Vec<f64>>, Vec<f64>) {
+    if keep_returns {
+        let returns: Vec<f64> = generated.collect();
+        let acc = accumulate(returns.iter().copied(), acc_args);
+        (Some(returns), acc)
+    } else {
+        (None, accumulate(generated, acc_args))
+    }
+}
+
+/// A progress bar counting up to `len`, drawn on stderr unless it isn't a
+/// terminal, --quiet is given or stdout is piped to another program (whose
+/// own output would mix with the bar)
+fn progress_bar(len: usize, unit: &str, quiet: bool) -> ProgressBar {
+    if quiet || stdout_is_pipe() {
+        return ProgressBar::hidden();
+    }
+    let template = format!("{{wide_bar}} {{human_pos}}/{{human_len}} {} ({{eta}} left)", unit);
+    ProgressBar::new(len as u64).with_style(ProgressStyle::with_template(&template).unwrap())
+}
+
+#[cfg(unix)]
+fn stdout_is_pipe() -> bool {
+    use std::os::fd::AsFd;
+    use std::os::unix::fs::FileTypeExt;
+
+    let metadata = io::stdout().as_fd().try_clone_to_owned().and_then(|fd| File::from(fd).metadata());
+    metadata.is_ok_and(|m| m.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn stdout_is_pipe() -> bool {
+    false
+}
+
+/// Reads and parses a portfolio spec file, exiting if it's invalid
+fn read_portfolio(file: &Path) -> portfolio::PortfolioSpec {
+    std::fs::read_to_string(file).map_err(Error::from).and_then(|s| portfolio::parse_portfolio(&s)).unwrap_or_else(|e| {
+        eprintln!("error: invalid portfolio file {}: {}", file.display(), e);
+        std::process::exit(1);
+    })
+}
+
+/// Opens the file at `path`, or stdin without one
+fn open_input(path: Option<&Path>) -> error::Result<Box<dyn BufRead>> {
+    Ok(match path {
+        Some(path) => Box::new(BufReader::new(File::open(path).map_err(|e| Error::from(e).context(path.display()))?)),
+        None => Box::new(io::stdin().lock()),
+    })
+}
+
+fn write_output(runs: &[Run], args: &OutputArgs) {
+    match &args.output {
+        OutputTarget::Stdout if args.realtime.is_none() => {
+            let mut handle = stdout_writer(args);
+            for run in runs {
+                output::write_text(run, args, &mut handle).unwrap();
+            }
+            handle.flush().unwrap();
+        }
+        #[cfg(feature = "sqlite")]
+        OutputTarget::Sqlite(path) => {
+            let res = runs.iter().try_for_each(|run| output::write_sqlite(path, run).map(|_| ()));
+            or_exit(res.map_err(|e| e.context(format!("failed to write {}", path.display()))));
+        }
+        #[cfg(feature = "xlsx")]
+        OutputTarget::Xlsx(path) => {
+            or_exit(output::write_xlsx(path, runs).map_err(|e| e.context(format!("failed to write {}", path.display()))));
+        }
+        #[cfg(feature = "mmap")]
+        OutputTarget::Binary(_) => {
+            let len = runs.iter().map(|run| run.values.len()).sum();
+            write_stream(runs.iter().map(|run| (*run, run.rows())), len, args);
+        }
+        OutputTarget::Stdout | OutputTarget::WebSocket(_) => write_stream(runs.iter().map(|run| (*run, run.rows())), 0, args),
+        #[cfg(feature = "kafka")]
+        OutputTarget::Kafka(_) => write_stream(runs.iter().map(|run| (*run, run.rows())), 0, args),
+    }
+}
+
+/// Whether the target is written as the series are generated, so that memory
+/// use doesn't grow with them. SQLite is with --chunk-size.
+fn streams(args: &OutputArgs) -> bool {
+    match args.output {
+        OutputTarget::Stdout | OutputTarget::WebSocket(_) => true,
+        #[cfg(feature = "kafka")]
+        OutputTarget::Kafka(_) => true,
+        #[cfg(feature = "mmap")]
+        OutputTarget::Binary(_) => true,
+        #[cfg(feature = "sqlite")]
+        OutputTarget::Sqlite(_) => args.chunk_size.is_some(),
+        #[allow(unreachable_patterns)]
+        _ => false,
+    }
+}
+
+/// Writes the data points of series to a target that `streams` as they come,
+/// or --chunk-size at a time. The values of the runs are ignored. `len` is the
+/// total number of points, which binary files are sized for up front.
+#[cfg_attr(not(feature = "mmap"), allow(unused_variables))]
+fn write_stream<'a, I: Iterator<Item = DataPoint>>(
+    runs: impl IntoIterator<Item = (Run<'a>, I)>,
+    len: usize,
+    args: &OutputArgs,
+) {
+    match &args.output {
+        #[cfg(feature = "mmap")]
+        OutputTarget::Binary(path) => {
+            let res = output::BinaryWriter::create(path, len).and_then(|mut w| {
+                for (_, rows) in runs {
+                    chunked(rows, args.chunk_size, |chunk| {
+                        for p in chunk {
+                            w.push(&p)?;
+                        }
+                        Ok::<(), Error>(())
+                    })?;
+                }
+                w.finish()
+            });
+            if let Err(e) = res {
+                eprintln!("error: failed to write {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "sqlite")]
+        OutputTarget::Sqlite(path) => {
+            let res = runs.into_iter().try_for_each(|(run, rows)| {
+                let mut w = output::SqliteWriter::create(path, &run, args.with_returns)?;
+                chunked(rows, args.chunk_size, |chunk| w.append(chunk))?;
+                w.finish().map(|_| ())
+            });
+            if let Err(e) = res {
+                eprintln!("error: failed to write {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+        _ => {
+            // Every chunk counts from the same t=0
+            let args = &OutputArgs {
+                start_timestamp: Some(output::start_timestamp(args)),
+                ..args.clone()
+            };
+            let mut w = text_writer(args);
+            let res = runs.into_iter().try_for_each(|(run, rows)| {
+                if args.format == OutputFormat::Stream {
+                    writeln!(w, "{}", StreamHeader::of(&run, args))?;
+                }
+                let mut write = |chunk: &mut dyn Iterator<Item = DataPoint>| output::write_text_stream(run.scenario, chunk, args, &mut w);
+                match args.realtime {
+                    Some(speedup) => chunked(output::paced(rows, speedup), args.chunk_size, &mut write),
+                    None => chunked(rows, args.chunk_size, &mut write),
+                }
+            });
+            if let Err(e) = res.and_then(|_| w.flush()) {
+                eprintln!("error: failed to write {}: {}", args.output, e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Hands `rows` to `write` at once, or up to `chunk_size` at a time
+fn chunked<E>(
+    mut rows: impl Iterator<Item = DataPoint>,
+    chunk_size: Option<usize>,
+    mut write: impl FnMut(&mut dyn Iterator<Item = DataPoint>) -> std::result::Result<(), E>,
+) -> std::result::Result<(), E> {
+    match chunk_size {
+        Some(chunk_size) => for_each_chunk(rows, chunk_size, |chunk| write(&mut chunk.iter().copied())),
+        None => write(&mut rows),
+    }
+}
+
+/// The writer of the text targets: stdout, the first client of a WebSocket or
+/// a Kafka producer, which sends a record at a time when unbuffered
+fn text_writer(args: &OutputArgs) -> Box<dyn Write> {
+    match &args.output {
+        OutputTarget::WebSocket(addr) => {
+            eprintln!("waiting for a WebSocket client on ws://{}", addr);
+            Box::new(or_exit(websocket::accept(addr)))
+        }
+        #[cfg(feature = "kafka")]
+        OutputTarget::Kafka(target) => {
+            let batch_size = if args.unbuffered || args.realtime.is_some() { 1 } else { kafka::BATCH_SIZE };
+            Box::new(or_exit(kafka::Producer::connect(target, batch_size)))
+        }
+        _ => stdout_writer(args),
+    }
+}
+
+/// Stdout, buffered unless --unbuffered or --realtime is given
+fn stdout_writer(args: &OutputArgs) -> Box<dyn Write> {
+    let stdout = io::stdout().lock();
+    // Stdout itself is line buffered
+    if args.unbuffered || args.realtime.is_some() {
+        Box::new(stdout)
+    } else {
+        Box::new(io::BufWriter::with_capacity(args.buffer_size, stdout))
+    }
 }
@@ -0,0 +1,196 @@
+//! Prometheus metrics of a long-running simulation, for operational
+//! monitoring: the paths and ticks simulated, their rate and the errors. `serve`
+//! exposes them on its own `/metrics` endpoint, and `serve_metrics` on a
+//! listener of their own for batch commands such as `mc`.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+/// Longest wait for a client to send its request or take the response
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest request line and headers accepted, in bytes
+pub const MAX_HEAD: u64 = 8 << 10;
+
+/// Content type of the Prometheus text exposition format
+pub const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Counters shared by the threads simulating and the one serving them
+#[derive(Debug)]
+pub struct Metrics {
+    start: Instant,
+    paths: AtomicU64,
+    ticks: AtomicU64,
+    errors: AtomicU64,
+    requests: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    /// Counters at zero, with the rates measured from now
+    pub fn new() -> Metrics {
+        Metrics {
+            start: Instant::now(),
+            paths: AtomicU64::new(0),
+            ticks: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts a path of `ticks` ticks as completed
+    pub fn path_completed(&self, ticks: usize) {
+        self.paths.fetch_add(1, Ordering::Relaxed);
+        self.ticks.fetch_add(ticks as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a failed simulation or request
+    pub fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a request served
+    pub fn request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let uptime = self.start.elapsed().as_secs_f64();
+        let ticks = self.ticks.load(Ordering::Relaxed);
+        let ticks_per_second = if uptime > 0.0 { ticks as f64 / uptime } else { 0.0 };
+        let metrics: [(&str, &str, &str, String); 6] = [
+            ("paths_completed_total", "counter", "Paths simulated", self.paths.load(Ordering::Relaxed).to_string()),
+            ("ticks_total", "counter", "Ticks simulated, over all paths", ticks.to_string()),
+            ("ticks_per_second", "gauge", "Ticks simulated per second since the start", ticks_per_second.to_string()),
+            ("errors_total", "counter", "Failed simulations and requests", self.errors.load(Ordering::Relaxed).to_string()),
+            ("requests_total", "counter", "Requests served", self.requests.load(Ordering::Relaxed).to_string()),
+            ("uptime_seconds", "gauge", "Seconds since the start", uptime.to_string()),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = write!(text, "# HELP finsim_{0} {1}.\n# TYPE finsim_{0} {2}\nfinsim_{0} {3}\n", name, help, kind, value);
+        }
+        text
+    }
+}
+
+/// Serves `GET /metrics` on `listener` from a thread of its own, until the
+/// process exits. A client silent for `TIMEOUT` is dropped rather than
+/// holding up the scrapes after it.
+pub fn serve_metrics(listener: TcpListener, metrics: Arc<Metrics>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.and_then(|mut stream| {
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                let head = read_head(&mut BufReader::new(&stream))?;
+                metrics.request();
+                let line = head.first().map_or("", String::as_str);
+                let (status, body) = match line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+                    ["GET", path] if path.split('?').next() == Some("/metrics") => (200, metrics.render()),
+                    _ => (404, "only GET /metrics\n".to_string()),
+                };
+                write_response(&mut stream, status, TEXT_CONTENT_TYPE, &body)
+            }) {
+                warn!(error = %e, "failed to serve metrics");
+            }
+        }
+    })
+}
+
+/// Reads the request line and headers of an HTTP/1.1 request, up to the
+/// empty line ending them, without their line breaks. Fails with
+/// `InvalidData` once they're longer than `MAX_HEAD` bytes, rather than
+/// buffering a line without end.
+pub fn read_head(r: &mut impl BufRead) -> io::Result<Vec<String>> {
+    let mut r = r.take(MAX_HEAD);
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line)? == 0 {
+            break;
+        }
+        if !line.ends_with('\n') && r.limit() == 0 {
+            let message = format!("the request line and headers must be at most {} bytes", MAX_HEAD);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line.to_string());
+    }
+    Ok(lines)
+}
+
+/// Writes an HTTP/1.1 response of `status` with a body of `content_type`,
+/// closing the connection after it
+pub fn write_response(w: &mut impl Write, status: u16, content_type: &str, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "",
+    };
+    write!(
+        w,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        body
+    )?;
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+
+    use super::{Metrics, serve_metrics};
+
+    #[test]
+    fn render_and_serve() {
+        let metrics = Arc::new(Metrics::new());
+        metrics.path_completed(100);
+        metrics.path_completed(50);
+        metrics.error();
+        let text = metrics.render();
+        assert!(text.contains("# TYPE finsim_paths_completed_total counter\nfinsim_paths_completed_total 2\n"), "{}", text);
+        assert!(text.contains("\nfinsim_ticks_total 150\n"), "{}", text);
+        assert!(text.contains("\nfinsim_errors_total 1\n"), "{}", text);
+        assert!(text.contains("# TYPE finsim_ticks_per_second gauge\n"), "{}", text);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        serve_metrics(listener, metrics.clone());
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("finsim_requests_total 1\n"), "{}", response);
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,295 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use tracing::{Level, debug};
+
+use crate::error::Result;
+use crate::inflation::{RealArgs, deflate};
+use crate::output::Summary;
+use crate::returns::{AccumulateArgs, GenReturnsArgs, RngKind, TimeBase, accumulate_iter, gen_returns};
+
+/// A generator of return series, one factor per tick (e.g. 1.01 for +1%).
+/// Implement it to feed your own returns into `accumulate`, `monte_carlo` or
+/// a `SimulationBuilder`.
+pub trait ReturnModel {
+    /// `num_points` returns spaced `interval_seconds` apart. The same seed
+    /// should give the same series, `None` means a random one.
+    fn returns(&self, interval_seconds: f64, num_points: usize, seed: Option<u64>)
+        -> Result<Box<dyn Iterator<Item = f64> + '_>>;
+}
+
+/// Log-normally distributed returns with a yearly (geometric) mean and
+/// standard deviation, as generated by `gen_returns`, or with `tail_df` the
+/// heavier tails of Student's t log returns
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LogNormal {
+    pub yearly_mean: f64,
+    pub yearly_stddev: f64,
+    #[serde(default)]
+    pub rng: RngKind,
+    #[serde(default)]
+    pub time_base: TimeBase,
+    #[serde(default)]
+    pub tail_df: Option<f64>,
+}
+
+impl From<&GenReturnsArgs> for LogNormal {
+    fn from(args: &GenReturnsArgs) -> LogNormal {
+        LogNormal {
+            yearly_mean: args.yearly_mean,
+            yearly_stddev: args.yearly_stddev,
+            rng: args.rng,
+            time_base: args.time_base,
+            tail_df: args.tail_df,
+        }
+    }
+}
+
+impl ReturnModel for LogNormal {
+    fn returns(&self, interval_seconds: f64, num_points: usize, seed: Option<u64>)
+        -> Result<Box<dyn Iterator<Item = f64> + '_>> {
+        // Whole seconds as in `GenReturnsArgs`, unless that would round to 0
+        let (total_seconds, interval_seconds) = if interval_seconds.fract() == 0.0 {
+            (None, Some(interval_seconds as usize))
+        } else {
+            (Some((interval_seconds * num_points as f64).round() as usize), None)
+        };
+        let returns = gen_returns(&GenReturnsArgs {
+            total_seconds,
+            interval_seconds,
+            num_points,
+            yearly_mean: self.yearly_mean,
+            yearly_stddev: self.yearly_stddev,
+            seed,
+            rng: self.rng,
+            time_base: self.time_base,
+            tail_df: self.tail_df,
+        })?;
+        Ok(Box::new(returns))
+    }
+}
+
+/// Seed of path `i` of a run with several paths and seed `seed`: output
+/// `i + 1` of a SplitMix64 generator seeded with `seed`, i.e. the SplitMix64
+/// finalizer of `seed + (i + 1) * 0x9e3779b97f4a7c15`. Any path can be
+/// regenerated on its own from it, and unlike counting up from the seed,
+/// runs with nearby seeds don't share paths.
+pub fn path_seed(seed: u64, i: usize) -> u64 {
+    let mut z = seed.wrapping_add((i as u64).wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Summaries of `paths` independent accumulated paths of `model`, path `i`
+/// seeded with `path_seed(seed, i)`, with `real` of the real values of each
+/// path, deflated by an inflation seeded with the seed of the path. Stops at
+/// the first path the model fails to generate, after yielding its error.
+#[allow(dead_code)] // The binary's mc uses monte_carlo_with to find drawdowns too
+pub fn monte_carlo<'a>(
+    model: &'a dyn ReturnModel,
+    interval_seconds: f64,
+    num_points: usize,
+    acc_args: &'a AccumulateArgs,
+    real: Option<&'a RealArgs>,
+    paths: usize,
+    seed: Option<u64>,
+) -> impl Iterator<Item = Result<Summary>> + 'a {
+    monte_carlo_with(model, interval_seconds, num_points, acc_args, real, paths, seed, |values| Summary::of_iter(values))
+}
+
+/// Like `monte_carlo`, but reducing the values of every path with `of`
+/// instead of summarizing them, e.g. into its drawdowns
+#[allow(clippy::too_many_arguments)]
+pub fn monte_carlo_with<'a, T>(
+    model: &'a dyn ReturnModel,
+    interval_seconds: f64,
+    num_points: usize,
+    acc_args: &'a AccumulateArgs,
+    real: Option<&'a RealArgs>,
+    paths: usize,
+    seed: Option<u64>,
+    of: impl Fn(&mut dyn Iterator<Item = f64>) -> T + 'a,
+) -> impl Iterator<Item = Result<T>> + 'a {
+    let mut failed = false;
+    (0..paths).map_while(move |i| {
+        if failed {
+            return None;
+        }
+        let path = simulate_path(model, interval_seconds, num_points, acc_args, real, seed, i, &of);
+        failed = path.is_err();
+        Some(path)
+    })
+}
+
+/// Like `monte_carlo`, but simulating the paths in parallel on the current
+/// rayon thread pool. The seeds don't depend on which thread simulates a
+/// path, so with a seed the summaries are the same for any number of
+/// threads. `on_path` is called as each path is done, in no particular
+/// order, e.g. to show progress.
+#[cfg(feature = "parallel")]
+#[allow(clippy::too_many_arguments)]
+pub fn par_monte_carlo(
+    model: &(dyn ReturnModel + Sync),
+    interval_seconds: f64,
+    num_points: usize,
+    acc_args: &AccumulateArgs,
+    real: Option<&RealArgs>,
+    paths: usize,
+    seed: Option<u64>,
+    on_path: impl Fn() + Sync,
+) -> Result<Vec<Summary>> {
+    let of = |values: &mut dyn Iterator<Item = f64>| Summary::of_iter(values);
+    par_monte_carlo_with(model, interval_seconds, num_points, acc_args, real, paths, seed, of, on_path)
+}
+
+/// Like `par_monte_carlo`, but reducing the values of every path with `of`
+/// like `monte_carlo_with`
+#[cfg(feature = "parallel")]
+#[allow(clippy::too_many_arguments)]
+pub fn par_monte_carlo_with<T: Send>(
+    model: &(dyn ReturnModel + Sync),
+    interval_seconds: f64,
+    num_points: usize,
+    acc_args: &AccumulateArgs,
+    real: Option<&RealArgs>,
+    paths: usize,
+    seed: Option<u64>,
+    of: impl Fn(&mut dyn Iterator<Item = f64>) -> T + Sync,
+    on_path: impl Fn() + Sync,
+) -> Result<Vec<T>> {
+    use rayon::prelude::*;
+
+    (0..paths)
+        .into_par_iter()
+        .map(|i| {
+            let path = simulate_path(model, interval_seconds, num_points, acc_args, real, seed, i, &of);
+            on_path();
+            path
+        })
+        .collect()
+}
+
+/// Path `i` of `monte_carlo_with`, reduced with `of`
+#[allow(clippy::too_many_arguments)]
+fn simulate_path<T>(
+    model: &dyn ReturnModel,
+    interval_seconds: f64,
+    num_points: usize,
+    acc_args: &AccumulateArgs,
+    real: Option<&RealArgs>,
+    seed: Option<u64>,
+    i: usize,
+    of: &dyn Fn(&mut dyn Iterator<Item = f64>) -> T,
+) -> Result<T> {
+    // Only timed when logged, there's no clock in the browser
+    let start = tracing::enabled!(Level::DEBUG).then(Instant::now);
+    let seed = seed.map(|s| path_seed(s, i));
+    let path = model.returns(interval_seconds, num_points, seed).map(|returns| {
+        let mut values = accumulate_iter(returns, acc_args);
+        match real {
+            Some(real) => of(&mut deflate(values, real.levels(num_points, seed), acc_args.accumulate, acc_args.log_values)),
+            None => of(&mut values),
+        }
+    });
+    if let Some(start) = start {
+        debug!(path = i, elapsed = ?start.elapsed(), "simulated path");
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogNormal, ReturnModel, monte_carlo};
+    use crate::error::Result;
+    use crate::inflation::{InflationArgs, RealArgs};
+    use crate::returns::{AccumulateArgs, GenReturnsArgs, RngKind, TimeBase, gen_returns};
+
+    /// The same return every tick
+    struct Constant(f64);
+
+    impl ReturnModel for Constant {
+        fn returns(&self, _: f64, num_points: usize, _: Option<u64>) -> Result<Box<dyn Iterator<Item = f64> + '_>> {
+            Ok(Box::new(std::iter::repeat_n(self.0, num_points)))
+        }
+    }
+
+    #[test]
+    fn lognormal_matches_gen_returns() {
+        let args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(60),
+            num_points: 5,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(3),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let model = LogNormal::from(&args);
+        let returns: Vec<f64> = model.returns(60.0, 5, Some(3)).unwrap().collect();
+        assert_eq!(gen_returns(&args).unwrap().collect::<Vec<f64>>(), returns);
+        assert_eq!(5, model.returns(0.5, 5, None).unwrap().count());
+    }
+
+    #[test]
+    fn path_seed_is_splitmix64() {
+        // The first outputs of SplitMix64 seeded with 0
+        assert_eq!(0xe220a8397b1dcdaf, super::path_seed(0, 0));
+        assert_eq!(0x6e789e6aa1b965f4, super::path_seed(0, 1));
+        assert_ne!(super::path_seed(1, 0), super::path_seed(0, 1));
+    }
+
+    #[test]
+    fn monte_carlo_with_custom_model() {
+        let acc_args = AccumulateArgs {
+            accumulate: true,
+            start_value: 100.0,
+            continuous_leverage: None,
+            pointwise_leverage: Some(2.0),
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        let summaries: Vec<_> = monte_carlo(&Constant(1.1), 1.0, 2, &acc_args, None, 3, Some(1)).collect::<Result<_>>().unwrap();
+        assert_eq!(3, summaries.len());
+        assert!(summaries.iter().all(|s| (s.last - 144.0).abs() < 1e-9));
+
+        // In the money of t=0 after two years of 10% inflation
+        let real = RealArgs { inflation: InflationArgs::constant(1.1), ticks_per_year: 1.0, rng: RngKind::Std };
+        let summaries: Vec<_> = monte_carlo(&Constant(1.1), 1.0, 2, &acc_args, Some(&real), 3, Some(1)).collect::<Result<_>>().unwrap();
+        assert!(summaries.iter().all(|s| (s.last - 144.0 / 1.21).abs() < 1e-9));
+
+        let invalid = LogNormal { yearly_mean: 1.1, yearly_stddev: 0.5, rng: RngKind::Std, time_base: TimeBase::WallClock, tail_df: None };
+        let results: Vec<_> = monte_carlo(&invalid, 1.0, 2, &acc_args, None, 3, Some(1)).collect();
+        assert_eq!(1, results.len());
+        assert!(results[0].is_err());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn par_monte_carlo_is_independent_of_threads() {
+        let acc_args = AccumulateArgs {
+            accumulate: true,
+            start_value: 100.0,
+            continuous_leverage: None,
+            pointwise_leverage: None,
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        let model = LogNormal { yearly_mean: 1.07, yearly_stddev: 1.2, rng: RngKind::Pcg, time_base: TimeBase::WallClock, tail_df: None };
+        let sequential: Vec<_> = monte_carlo(&model, 86400.0, 50, &acc_args, None, 20, Some(5)).collect::<Result<_>>().unwrap();
+        for threads in [1, 4] {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let parallel = pool.install(|| super::par_monte_carlo(&model, 86400.0, 50, &acc_args, None, 20, Some(5), || ()));
+            assert_eq!(sequential, parallel.unwrap());
+        }
+
+        let invalid = LogNormal { yearly_mean: 1.1, yearly_stddev: 0.5, rng: RngKind::Std, time_base: TimeBase::WallClock, tail_df: None };
+        assert!(super::par_monte_carlo(&invalid, 1.0, 2, &acc_args, None, 3, Some(1), || ()).is_err());
+    }
+}
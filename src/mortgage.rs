@@ -0,0 +1,193 @@
+//! Paying a fixed-rate loan off early versus investing the same cash. Both
+//! strategies spend the regular payment plus an extra payment every tick:
+//! prepaying puts the extra towards the loan, investing puts it into the
+//! simulated asset. Whatever either doesn't owe, e.g. once the loan is paid
+//! off, is invested too, so they only differ in when the loan is repaid.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::model::{ReturnModel, path_seed};
+use crate::returns::GenReturnsArgs;
+use crate::stats::{percent, quantile};
+
+/// A fixed-rate loan repaid in equal payments every tick, and the cash
+/// available on top of them
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MortgageArgs {
+    /// Outstanding balance at t=0
+    pub balance: f64,
+    /// Yearly interest as a factor, e.g. 1.05 for 5%
+    pub yearly_rate: f64,
+    /// Ticks until the regular payments repay the loan
+    pub term_ticks: usize,
+    /// Cash on top of the regular payment every tick
+    pub extra_payment: f64,
+}
+
+/// Net worth, investments minus the loan balance, of both strategies at the
+/// end of a path
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Comparison {
+    pub prepay: f64,
+    pub invest: f64,
+}
+
+/// Net worth of the strategies over many paths
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct MortgageReport {
+    pub paths: usize,
+    pub prepay_median: f64,
+    pub invest_median: f64,
+    /// Paths on which investing ends with a higher net worth
+    pub invest_wins: usize,
+}
+
+impl MortgageArgs {
+    pub fn validate(&self) -> Result<()> {
+        if !self.balance.is_finite() || self.balance < 0.0 {
+            return Err(Error::Invalid(format!("--loan-balance must be a non-negative number, got {}", self.balance)));
+        }
+        if !self.yearly_rate.is_finite() || self.yearly_rate <= 0.0 {
+            return Err(Error::Invalid(format!("--loan-rate must be above -100%, got {}%", (self.yearly_rate - 1.0) * 100.0)));
+        }
+        if self.term_ticks == 0 {
+            return Err(Error::Invalid("--loan-term must be at least one interval".to_string()));
+        }
+        if !self.extra_payment.is_finite() || self.extra_payment < 0.0 {
+            return Err(Error::Invalid(format!("--extra-payment must be a non-negative number, got {}", self.extra_payment)));
+        }
+        Ok(())
+    }
+
+    /// The regular payment of an annuity repaying the balance in
+    /// `term_ticks` ticks of interest `tick_rate`, e.g. 1.004 for 0.4%
+    pub fn payment(&self, tick_rate: f64) -> f64 {
+        let i = tick_rate - 1.0;
+        if i == 0.0 {
+            return self.balance / self.term_ticks as f64;
+        }
+        self.balance * i / (1.0 - tick_rate.powf(-(self.term_ticks as f64)))
+    }
+
+    /// Both strategies over one path of returns of the asset, with
+    /// `ticks_per_year` ticks a year. The returns apply to what's invested
+    /// over a tick, the payments are made at its end.
+    pub fn compare(&self, returns: impl Iterator<Item = f64>, ticks_per_year: f64) -> Comparison {
+        let tick_rate = self.yearly_rate.powf(1.0 / ticks_per_year);
+        let payment = self.payment(tick_rate);
+        let cash = payment + self.extra_payment;
+        // Investments and loan balance of prepaying and investing
+        let (mut prepay, mut invest) = ((0.0, self.balance), (0.0, self.balance));
+        for r in returns {
+            for (strategy, due) in [(&mut prepay, cash), (&mut invest, payment)] {
+                let (assets, debt) = strategy;
+                *debt *= tick_rate;
+                let paid = due.min(*debt);
+                *debt -= paid;
+                *assets = *assets * r + cash - paid;
+            }
+        }
+        Comparison {
+            prepay: prepay.0 - prepay.1,
+            invest: invest.0 - invest.1,
+        }
+    }
+
+    /// `compare` over `paths` paths of `model`, with the interval, number of
+    /// points and time base of `gen_args` and path `i` seeded with
+    /// `path_seed(seed, i)`
+    pub fn compare_paths(
+        &self,
+        model: &dyn ReturnModel,
+        gen_args: &GenReturnsArgs,
+        paths: usize,
+        seed: Option<u64>,
+    ) -> Result<Vec<Comparison>> {
+        let interval_seconds = gen_args.tick_interval_seconds();
+        let ticks_per_year = gen_args.time_base.ticks_per_year(gen_args.tick_interval());
+        (0..paths)
+            .map(|i| {
+                let returns = model.returns(interval_seconds, gen_args.num_points, seed.map(|s| path_seed(s, i)))?;
+                Ok(self.compare(returns, ticks_per_year))
+            })
+            .collect()
+    }
+}
+
+impl MortgageReport {
+    pub fn of(comparisons: &[Comparison]) -> MortgageReport {
+        let median = |f: fn(&Comparison) -> f64| quantile(&comparisons.iter().map(f).collect::<Vec<_>>(), 0.5);
+        MortgageReport {
+            paths: comparisons.len(),
+            prepay_median: median(|c| c.prepay),
+            invest_median: median(|c| c.invest),
+            invest_wins: comparisons.iter().filter(|c| c.invest > c.prepay).count(),
+        }
+    }
+
+    /// The share of paths on which investing wins in percent, the
+    /// probability that it beats prepaying
+    pub fn invest_wins_percent(&self) -> f64 {
+        percent(self.invest_wins, self.paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{MortgageArgs, MortgageReport};
+    use crate::model::LogNormal;
+    use crate::returns::{GenReturnsArgs, RngKind, TimeBase};
+
+    fn loan(extra_payment: f64) -> MortgageArgs {
+        MortgageArgs { balance: 1000.0, yearly_rate: 1.06, term_ticks: 10, extra_payment }
+    }
+
+    #[test]
+    fn regular_payments_repay_the_loan() {
+        let args = loan(0.0);
+        // 1000 at 6% a year over 10 yearly payments
+        assert_approx_eq!(135.8680, args.payment(1.06), 1e-4);
+        assert_eq!(100.0, MortgageArgs { yearly_rate: 1.0, ..args.clone() }.payment(1.0));
+        let c = args.compare(std::iter::repeat_n(1.0, 10), 1.0);
+        assert_approx_eq!(0.0, c.prepay, 1e-9);
+        assert_approx_eq!(0.0, c.invest, 1e-9);
+    }
+
+    #[test]
+    fn the_better_rate_wins() {
+        // Investing at the loan rate is a draw, above it investing wins and
+        // below it prepaying does
+        let args = loan(50.0);
+        let draw = args.compare(std::iter::repeat_n(1.06, 15), 1.0);
+        assert_approx_eq!(draw.prepay, draw.invest, 1e-9);
+        let above = args.compare(std::iter::repeat_n(1.08, 15), 1.0);
+        assert!(above.invest > above.prepay);
+        let below = args.compare(std::iter::repeat_n(1.02, 15), 1.0);
+        assert!(below.invest < below.prepay);
+    }
+
+    #[test]
+    fn report_over_paths() {
+        let gen_args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(31556952),
+            num_points: 15,
+            yearly_mean: 1.12,
+            yearly_stddev: 1.2,
+            seed: Some(1),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let model = LogNormal::from(&gen_args);
+        let comparisons = loan(50.0).compare_paths(&model, &gen_args, 101, Some(1)).unwrap();
+        let report = MortgageReport::of(&comparisons);
+        assert_eq!(101, report.paths);
+        assert!(report.invest_wins > 0 && report.invest_wins < 101);
+        assert_eq!(100.0 * report.invest_wins as f64 / 101.0, report.invest_wins_percent());
+        assert!(report.invest_median > report.prepay_median);
+    }
+}
@@ -0,0 +1,1512 @@
+use std::fmt;
+use std::io::{self, Write};
+#[cfg(any(feature = "sqlite", feature = "xlsx", feature = "mmap"))]
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::calendar::BusinessCalendar;
+use crate::drawdown::Drawdown;
+use crate::error::{Error, Result};
+#[cfg(feature = "kafka")]
+use crate::kafka::KafkaTarget;
+use crate::returns::{AccumulateArgs, DataPoint, Float, GenReturnsArgs, Interval};
+use crate::stats::KahanSum;
+
+/// Where and how to write the results
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OutputArgs {
+    /// Where to write the results
+    pub output: OutputTarget,
+
+    /// Format of the text written to stdout
+    pub format: OutputFormat,
+
+    /// Measurement name used by the influx format
+    pub measurement: String,
+
+    /// Tags (key, value) added to every influx line
+    pub tags: Vec<(String, String)>,
+
+    /// Unix time in seconds of t=0 for the influx format, now if `None`
+    pub start_timestamp: Option<i64>,
+
+    /// Timestamps the influx points on consecutive business days after
+    /// `start_timestamp`, one point per day, instead of `t_seconds` after it
+    #[serde(default)]
+    pub calendar: Option<BusinessCalendar>,
+
+    /// Flush stdout after every line
+    pub unbuffered: bool,
+
+    /// Size in bytes of the stdout write buffer
+    pub buffer_size: usize,
+
+    /// Output the per-tick return alongside each accumulated value
+    pub with_returns: bool,
+
+    /// Output the price level of the data points after their value, for
+    /// series with an inflation simulated alongside. Only on stdout.
+    #[serde(default)]
+    pub with_inflation: bool,
+
+    /// Generate, accumulate and write this many points at a time, also for
+    /// targets that otherwise collect the whole series first
+    #[serde(default)]
+    pub chunk_size: Option<usize>,
+
+    /// Write each data point once its `t_seconds`, divided by this speedup,
+    /// has passed, like a live feed. Only for text output.
+    #[serde(default)]
+    pub realtime: Option<f64>,
+}
+
+impl OutputArgs {
+    /// Checks that the chunk size is positive and the target can be written
+    /// in chunks
+    pub fn validate(&self) -> Result<()> {
+        if self.calendar.is_some() && self.start_timestamp.is_none() {
+            return Err(Error::Invalid("--business-days requires --start-timestamp".to_string()));
+        }
+        if self.with_inflation && !self.output.is_text() {
+            return Err(Error::Invalid("--with-inflation only works with text output: stdout, a WebSocket or Kafka".to_string()));
+        }
+        if let Some(speedup) = self.realtime {
+            if !speedup.is_finite() || speedup <= 0.0 {
+                return Err(Error::Invalid(format!("--realtime must be a positive speedup, got {}", speedup)));
+            }
+            if !self.output.is_text() {
+                return Err(Error::Invalid("--realtime only works with text output: stdout, a WebSocket or Kafka".to_string()));
+            }
+        }
+        match (self.chunk_size, &self.output) {
+            (Some(0), _) => Err(Error::Invalid("--chunk-size must be positive".to_string())),
+            #[cfg(feature = "xlsx")]
+            (Some(_), OutputTarget::Xlsx(_)) => {
+                Err(Error::Invalid("--chunk-size doesn't work with Excel output, which is built in memory".to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// One value per line
+    Plain,
+    /// InfluxDB line protocol with nanosecond timestamps
+    Influx,
+    /// Tab-separated `t_seconds` and value under a `StreamHeader`, for
+    /// chaining commands with pipes
+    Stream,
+}
+
+/// What the values of a series in the stream format are
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StreamKind {
+    Returns,
+    LogReturns,
+    /// Accumulated values
+    Values,
+    LogValues,
+}
+
+impl StreamKind {
+    const NAMES: [(StreamKind, &'static str); 4] = [
+        (StreamKind::Returns, "returns"),
+        (StreamKind::LogReturns, "log-returns"),
+        (StreamKind::Values, "values"),
+        (StreamKind::LogValues, "log-values"),
+    ];
+
+    fn of(args: &AccumulateArgs) -> StreamKind {
+        match (args.accumulate, args.log_values) {
+            (false, false) => StreamKind::Returns,
+            (false, true) => StreamKind::LogReturns,
+            (true, false) => StreamKind::Values,
+            (true, true) => StreamKind::LogValues,
+        }
+    }
+}
+
+/// The line starting every series in the stream format: `# finsim` and
+/// space-separated `key=value` pairs, e.g.
+/// `# finsim kind=values interval_seconds=60 start_value=1 columns=t_seconds,value tag.host=a`.
+/// Spaces, `=` and `%` in the values are percent-encoded, and unknown keys
+/// are ignored when reading. Being a comment, it's skipped by readers of one
+/// value per line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamHeader {
+    pub kind: StreamKind,
+    pub interval_seconds: f64,
+    /// The value at t=0 the values were accumulated from
+    pub start_value: f64,
+    /// Unix time in seconds of t=0
+    pub start_timestamp: Option<i64>,
+    pub scenario: Option<String>,
+    pub tags: Vec<(String, String)>,
+    /// Whether the lines have a return column after the value
+    pub with_returns: bool,
+    /// Whether the lines end with a price level column
+    pub with_inflation: bool,
+}
+
+/// Starts the header line of a series in the stream format
+pub const STREAM_MAGIC: &str = "# finsim ";
+
+impl StreamHeader {
+    /// The header of a run written with `args`
+    pub fn of(run: &Run, args: &OutputArgs) -> StreamHeader {
+        StreamHeader {
+            kind: StreamKind::of(run.acc_args),
+            interval_seconds: run.interval.seconds(),
+            start_value: run.acc_args.start_value,
+            start_timestamp: Some(start_timestamp(args)),
+            scenario: run.scenario.map(str::to_string),
+            tags: args.tags.clone(),
+            with_returns: args.with_returns,
+            with_inflation: args.with_inflation,
+        }
+    }
+}
+
+impl fmt::Display for StreamHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = StreamKind::NAMES.iter().find(|(k, _)| *k == self.kind).map_or("", |(_, name)| name);
+        write!(f, "{}kind={} interval_seconds={} start_value={}", STREAM_MAGIC, kind, self.interval_seconds, self.start_value)?;
+        if let Some(t) = self.start_timestamp {
+            write!(f, " start_timestamp={}", t)?;
+        }
+        if let Some(scenario) = &self.scenario {
+            write!(f, " scenario={}", percent_encode(scenario))?;
+        }
+        write!(f, " columns=t_seconds,value")?;
+        if self.with_returns {
+            write!(f, ",return")?;
+        }
+        if self.with_inflation {
+            write!(f, ",inflation")?;
+        }
+        for (k, v) in &self.tags {
+            write!(f, " tag.{}={}", percent_encode(k), percent_encode(v))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for StreamHeader {
+    type Err = Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let pairs = line
+            .strip_prefix(STREAM_MAGIC)
+            .ok_or_else(|| Error::Parse(format!("a stream header starts with '{}'", STREAM_MAGIC.trim_end())))?;
+        let mut header = StreamHeader {
+            kind: StreamKind::Values,
+            interval_seconds: 86400.0,
+            start_value: 1.0,
+            start_timestamp: None,
+            scenario: None,
+            tags: Vec::new(),
+            with_returns: false,
+            with_inflation: false,
+        };
+        let invalid = |key: &str, value: &str| Error::Parse(format!("invalid {} '{}' in the stream header", key, value));
+        for pair in pairs.split_whitespace() {
+            let (key, value) = pair.split_once('=').ok_or_else(|| invalid("pair", pair))?;
+            match key {
+                "kind" => {
+                    header.kind = StreamKind::NAMES.iter().find(|(_, name)| *name == value).ok_or_else(|| invalid(key, value))?.0
+                }
+                "interval_seconds" => header.interval_seconds = value.parse().map_err(|_| invalid(key, value))?,
+                "start_value" => header.start_value = value.parse().map_err(|_| invalid(key, value))?,
+                "start_timestamp" => header.start_timestamp = Some(value.parse().map_err(|_| invalid(key, value))?),
+                "scenario" => header.scenario = Some(percent_decode(value)),
+                "columns" => {
+                    let extra = value.strip_prefix("t_seconds,value").ok_or_else(|| invalid(key, value))?;
+                    (header.with_returns, header.with_inflation) = match extra {
+                        "" => (false, false),
+                        ",return" => (true, false),
+                        ",inflation" => (false, true),
+                        ",return,inflation" => (true, true),
+                        _ => return Err(invalid(key, value)),
+                    };
+                }
+                _ => {
+                    if let Some(tag) = key.strip_prefix("tag.") {
+                        header.tags.push((percent_decode(tag), percent_decode(value)));
+                    }
+                }
+            }
+        }
+        Ok(header)
+    }
+}
+
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '%' | '=' | ' ' | '\t' | '\n' | '\r' => encoded.push_str(&format!("%{:02X}", c as u32)),
+            c => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum OutputTarget {
+    Stdout,
+    /// A WebSocket server on this address, waiting for one client
+    WebSocket(String),
+    #[cfg(feature = "kafka")]
+    Kafka(KafkaTarget),
+    #[cfg(feature = "sqlite")]
+    Sqlite(PathBuf),
+    #[cfg(feature = "xlsx")]
+    Xlsx(PathBuf),
+    #[cfg(feature = "mmap")]
+    Binary(PathBuf),
+}
+
+impl FromStr for OutputTarget {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once("://") {
+            None if s == "-" => Ok(OutputTarget::Stdout),
+            Some(("ws", addr)) if !addr.is_empty() => Ok(OutputTarget::WebSocket(addr.to_string())),
+            #[cfg(feature = "kafka")]
+            Some(("kafka", target)) => Ok(OutputTarget::Kafka(target.parse()?)),
+            #[cfg(not(feature = "kafka"))]
+            Some(("kafka", _)) => Err(Error::Parse("Kafka output requires finsim to be built with the `kafka` feature".to_string())),
+            #[cfg(feature = "sqlite")]
+            Some(("sqlite", path)) => Ok(OutputTarget::Sqlite(PathBuf::from(path))),
+            #[cfg(not(feature = "sqlite"))]
+            Some(("sqlite", _)) => Err(Error::Parse("SQLite output requires finsim to be built with the `sqlite` feature".to_string())),
+            #[cfg(feature = "xlsx")]
+            Some(("xlsx", path)) => Ok(OutputTarget::Xlsx(PathBuf::from(path))),
+            #[cfg(not(feature = "xlsx"))]
+            Some(("xlsx", _)) => Err(Error::Parse("Excel output requires finsim to be built with the `xlsx` feature".to_string())),
+            #[cfg(feature = "mmap")]
+            Some(("bin", path)) => Ok(OutputTarget::Binary(PathBuf::from(path))),
+            #[cfg(not(feature = "mmap"))]
+            Some(("bin", _)) => Err(Error::Parse("Binary output requires finsim to be built with the `mmap` feature".to_string())),
+            _ => Err(Error::Parse(format!("unsupported output target '{}'", s))),
+        }
+    }
+}
+
+impl fmt::Display for OutputTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputTarget::Stdout => write!(f, "-"),
+            OutputTarget::WebSocket(addr) => write!(f, "ws://{}", addr),
+            #[cfg(feature = "kafka")]
+            OutputTarget::Kafka(target) => write!(f, "kafka://{}", target),
+            #[cfg(feature = "sqlite")]
+            OutputTarget::Sqlite(path) => write!(f, "sqlite://{}", path.display()),
+            #[cfg(feature = "xlsx")]
+            OutputTarget::Xlsx(path) => write!(f, "xlsx://{}", path.display()),
+            #[cfg(feature = "mmap")]
+            OutputTarget::Binary(path) => write!(f, "bin://{}", path.display()),
+        }
+    }
+}
+
+impl OutputTarget {
+    /// Whether the target takes the text output, line by line
+    pub fn is_text(&self) -> bool {
+        match self {
+            OutputTarget::Stdout | OutputTarget::WebSocket(_) => true,
+            #[cfg(feature = "kafka")]
+            OutputTarget::Kafka(_) => true,
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+}
+
+impl From<OutputTarget> for String {
+    fn from(target: OutputTarget) -> String {
+        target.to_string()
+    }
+}
+
+impl TryFrom<String> for OutputTarget {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// Parses a `key=value` influx tag
+pub fn parse_tag(s: &str) -> Result<(String, String)> {
+    match s.split_once('=') {
+        Some((k, v)) if !k.is_empty() && !v.is_empty() => Ok((k.to_string(), v.to_string())),
+        _ => Err(Error::Parse(format!("expected key=value, got '{}'", s))),
+    }
+}
+
+/// A series ready to be written, together with what produced it
+#[derive(Clone, Copy)]
+pub struct Run<'a> {
+    /// Name of the scenario, for runs from a scenario file
+    pub scenario: Option<&'a str>,
+    pub values: &'a [f64],
+    /// The returns the values were accumulated from, if they should be output too
+    pub returns: Option<&'a [f64]>,
+    pub interval: Interval,
+    /// `None` for series that were not generated by finsim
+    #[cfg_attr(not(any(feature = "sqlite", feature = "xlsx")), allow(dead_code))] // Only recorded by file targets
+    pub gen_args: Option<&'a GenReturnsArgs>,
+    #[cfg_attr(not(any(feature = "sqlite", feature = "xlsx")), allow(dead_code))]
+    pub acc_args: &'a AccumulateArgs,
+}
+
+impl Run<'_> {
+    /// The values as data points. Their return is NaN for runs that don't
+    /// keep the returns of accumulated values.
+    pub fn rows(&self) -> impl Iterator<Item = DataPoint> + '_ {
+        self.values.iter().enumerate().map(|(i, &value)| DataPoint {
+            t_seconds: self.interval.at(i + 1),
+            tick_return: match self.returns {
+                Some(returns) => returns[i],
+                None if !self.acc_args.accumulate => value,
+                None => f64::NAN,
+            },
+            value,
+            price_level: None,
+        })
+    }
+}
+
+/// Writes a run to stdout-like outputs in the requested format
+pub fn write_text(run: &Run, args: &OutputArgs, w: &mut impl Write) -> io::Result<()> {
+    match args.format {
+        OutputFormat::Plain => write_plain(run, w),
+        OutputFormat::Influx => {
+            write_influx(run, &args.measurement, &args.tags, start_timestamp(args), args.calendar.as_ref(), w)
+        }
+        OutputFormat::Stream => {
+            writeln!(w, "{}", StreamHeader::of(run, args))?;
+            let columns = Columns { returns: args.with_returns, inflation: args.with_inflation };
+            stream_rows(run.rows(), columns, w)
+        }
+    }
+}
+
+/// Like `write_text`, but writing the data points as they come, so that the
+/// series never has to be held in memory. Their returns are written with
+/// `--with-returns` and their price levels with `--with-inflation`. The
+/// stream format's header is left to the caller, to be written once per run
+/// however many times this is called.
+pub fn write_text_stream(
+    scenario: Option<&str>,
+    rows: impl Iterator<Item = DataPoint>,
+    args: &OutputArgs,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let columns = Columns { returns: args.with_returns, inflation: args.with_inflation };
+    match args.format {
+        OutputFormat::Plain => plain_rows(scenario, rows, columns, w),
+        OutputFormat::Influx => {
+            let start_timestamp = start_timestamp(args);
+            let rows = on_calendar(rows, start_timestamp, args.calendar.as_ref());
+            influx_rows(scenario, rows, columns, &args.measurement, &args.tags, start_timestamp, w)
+        }
+        OutputFormat::Stream => stream_rows(rows, columns, w),
+    }
+}
+
+/// `--start-timestamp`, or now
+pub fn start_timestamp(args: &OutputArgs) -> i64 {
+    args.start_timestamp.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as i64)
+    })
+}
+
+/// Data points released in real time, see `paced`
+pub struct Paced<I> {
+    rows: I,
+    start: Instant,
+    speedup: f64,
+}
+
+/// Holds back each data point until its `t_seconds` divided by `speedup` has
+/// passed since the call, e.g. a daily series at a speedup of 86400 yields a
+/// point a second
+pub fn paced<I: Iterator<Item = DataPoint>>(rows: I, speedup: f64) -> Paced<I> {
+    Paced { rows, start: Instant::now(), speedup }
+}
+
+impl<I: Iterator<Item = DataPoint>> Iterator for Paced<I> {
+    type Item = DataPoint;
+
+    fn next(&mut self) -> Option<DataPoint> {
+        let point = self.rows.next()?;
+        let due = Duration::from_secs_f64((point.t_seconds / self.speedup).max(0.0));
+        if let Some(wait) = due.checked_sub(self.start.elapsed()) {
+            thread::sleep(wait);
+        }
+        Some(point)
+    }
+}
+
+/// Writes one value per line, preceded by the scenario name and the return
+/// (space separated) when the run has them
+pub fn write_plain(run: &Run, w: &mut impl Write) -> io::Result<()> {
+    plain_rows(run.scenario, run.rows(), Columns { returns: run.returns.is_some(), inflation: false }, w)
+}
+
+/// Which optional fields of the data points are written
+#[derive(Clone, Copy)]
+struct Columns {
+    returns: bool,
+    inflation: bool,
+}
+
+fn plain_rows(
+    scenario: Option<&str>,
+    rows: impl Iterator<Item = DataPoint>,
+    columns: Columns,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    for p in rows {
+        if let Some(scenario) = scenario {
+            write!(w, "{} ", scenario)?;
+        }
+        if columns.returns {
+            write!(w, "{} ", p.tick_return)?;
+        }
+        match p.price_level.filter(|_| columns.inflation) {
+            Some(level) => writeln!(w, "{} {}", p.value, level)?,
+            None => writeln!(w, "{}", p.value)?,
+        }
+    }
+    Ok(())
+}
+
+/// Writes the tab-separated lines of the stream format, with NaN for price
+/// levels that weren't simulated
+fn stream_rows(rows: impl Iterator<Item = DataPoint>, columns: Columns, w: &mut impl Write) -> io::Result<()> {
+    for p in rows {
+        write!(w, "{}\t{}", p.t_seconds, p.value)?;
+        if columns.returns {
+            write!(w, "\t{}", p.tick_return)?;
+        }
+        if columns.inflation {
+            write!(w, "\t{}", p.price_level.unwrap_or(f64::NAN))?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Writes one InfluxDB line protocol point per value, with the value in the
+/// `value` field and the return, if any, in the `return` field. The scenario
+/// name, if any, is added as a `scenario` tag. Points are timestamped their
+/// `t_seconds` after `start_timestamp`, or with a calendar on the business
+/// days after it.
+pub fn write_influx(
+    run: &Run,
+    measurement: &str,
+    tags: &[(String, String)],
+    start_timestamp: i64,
+    calendar: Option<&BusinessCalendar>,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let rows = on_calendar(run.rows(), start_timestamp, calendar);
+    let columns = Columns { returns: run.returns.is_some(), inflation: false };
+    influx_rows(run.scenario, rows, columns, measurement, tags, start_timestamp, w)
+}
+
+/// The rows with their `t_seconds` moved to the business days after
+/// `start_timestamp`, one per day, if there is a calendar
+fn on_calendar<'a>(
+    rows: impl Iterator<Item = DataPoint> + 'a,
+    start_timestamp: i64,
+    calendar: Option<&'a BusinessCalendar>,
+) -> impl Iterator<Item = DataPoint> + 'a {
+    let mut days = calendar.map(|c| c.timestamps(start_timestamp));
+    rows.map(move |p| match &mut days {
+        // There is always a next business day
+        Some(days) => DataPoint { t_seconds: (days.next().unwrap() - start_timestamp) as f64, ..p },
+        None => p,
+    })
+}
+
+fn influx_rows(
+    scenario: Option<&str>,
+    rows: impl Iterator<Item = DataPoint>,
+    columns: Columns,
+    measurement: &str,
+    tags: &[(String, String)],
+    start_timestamp: i64,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let mut series_key = escape_influx(measurement, &[',', ' ']);
+    let scenario_tag = scenario.map(|s| ("scenario", s));
+    for (k, v) in tags.iter().map(|(k, v)| (k.as_str(), v.as_str())).chain(scenario_tag) {
+        series_key.push(',');
+        series_key.push_str(&escape_influx(k, &[',', '=', ' ']));
+        series_key.push('=');
+        series_key.push_str(&escape_influx(v, &[',', '=', ' ']));
+    }
+    let start_ns = start_timestamp as i128 * 1_000_000_000;
+    for p in rows {
+        let t_ns = start_ns + (p.t_seconds * 1e9).round() as i128;
+        write!(w, "{} ", series_key)?;
+        if columns.returns {
+            write!(w, "return={},", p.tick_return)?;
+        }
+        write!(w, "value={}", p.value)?;
+        if let Some(level) = p.price_level.filter(|_| columns.inflation) {
+            write!(w, ",inflation={}", level)?;
+        }
+        writeln!(w, " {}", t_ns)?;
+    }
+    Ok(())
+}
+
+fn escape_influx(s: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Aggregates of a series, the same as the `summary` view of the SQLite target
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub last: f64,
+}
+
+impl Summary {
+    /// Summarizes the values, with NaN aggregates for an empty series
+    pub fn of<F: Float>(values: &[F]) -> Summary {
+        Summary::of_iter(values.iter().copied())
+    }
+
+    /// Like `of`, but taking the values as they come, in constant memory
+    pub fn of_iter<F: Float>(values: impl IntoIterator<Item = F>) -> Summary {
+        let (mut count, mut min, mut max, mut last) = (0, f64::INFINITY, f64::NEG_INFINITY, f64::NAN);
+        let mut sum = KahanSum::default();
+        for v in values {
+            let v = v.as_f64();
+            count += 1;
+            min = min.min(v);
+            max = max.max(v);
+            sum.add(v);
+            last = v;
+        }
+        if count == 0 {
+            (min, max) = (f64::NAN, f64::NAN);
+        }
+        Summary {
+            count,
+            min,
+            max,
+            mean: sum.value() / count as f64,
+            last,
+        }
+    }
+
+    /// The compound annual growth rate from `start_value` to the last value
+    /// over `years`, e.g. 0.05 for 5% a year
+    pub fn cagr(&self, start_value: f64, years: f64) -> f64 {
+        (self.last / start_value).powf(1.0 / years) - 1.0
+    }
+}
+
+/// Writes a CSV table with one row per series, e.g. per sweep point: the
+/// values identifying the series, in the order of `keys`, followed by its
+/// summary. The aggregates of an empty series are left blank.
+pub fn write_summary_csv(keys: &[String], rows: &[(Vec<String>, Summary)], w: &mut impl Write) -> io::Result<()> {
+    summary_csv(keys, &[], rows.iter().map(|(values, summary)| (values, summary, Vec::new())), w)
+}
+
+/// Like `write_summary_csv`, with a last column of the `Summary::cagr` of
+/// every series from `start_value` over `years`
+pub fn write_cagr_summary_csv(
+    keys: &[String],
+    rows: &[(Vec<String>, Summary)],
+    start_value: f64,
+    years: f64,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let rows = rows.iter().map(|(values, summary)| (values, summary, vec![summary.cagr(start_value, years)]));
+    summary_csv(keys, &["cagr"], rows, w)
+}
+
+/// Like `write_summary_csv`, followed by a column per name in `columns` of
+/// further statistics of every series, e.g. its Hurst exponent, given in the
+/// same order. NaN statistics are left blank.
+pub fn write_stats_csv(
+    keys: &[String],
+    columns: &[&str],
+    rows: &[(Vec<String>, Summary, Vec<f64>)],
+    w: &mut impl Write,
+) -> io::Result<()> {
+    summary_csv(keys, columns, rows.iter().map(|(values, summary, stats)| (values, summary, stats.clone())), w)
+}
+
+fn summary_csv<'a>(
+    keys: &[String],
+    columns: &[&str],
+    rows: impl Iterator<Item = (&'a Vec<String>, &'a Summary, Vec<f64>)>,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    for key in keys {
+        write!(w, "{},", key)?;
+    }
+    write!(w, "count,min,max,mean,last")?;
+    for column in columns {
+        write!(w, ",{}", column)?;
+    }
+    writeln!(w)?;
+    for (values, summary, stats) in rows {
+        for value in values {
+            write!(w, "{},", value)?;
+        }
+        if summary.count == 0 {
+            // No aggregates rather than NaN, like the NULLs of the SQLite view
+            write!(w, "0,,,,")?;
+        } else {
+            write!(
+                w,
+                "{},{},{},{},{}",
+                summary.count,
+                summary.min,
+                summary.max,
+                summary.mean,
+                summary.last
+            )?;
+        }
+        for stat in stats {
+            if stat.is_nan() { write!(w, ",")? } else { write!(w, ",{}", stat)? }
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Writes a CSV table with a row per drawdown of every series: the values
+/// identifying the series, in the order of `keys`, followed by the ticks of
+/// the drawdown's start, trough and recovery, its depth and its length in
+/// ticks. The recovery of a drawdown a series ends in is left blank.
+pub fn write_drawdowns_csv(keys: &[String], rows: &[(Vec<String>, Vec<Drawdown>)], w: &mut impl Write) -> io::Result<()> {
+    for key in keys {
+        write!(w, "{},", key)?;
+    }
+    writeln!(w, "start,trough,recovery,depth,length")?;
+    for (values, drawdowns) in rows {
+        for d in drawdowns {
+            for value in values {
+                write!(w, "{},", value)?;
+            }
+            let recovery = d.recovery.map(|tick| tick.to_string()).unwrap_or_default();
+            writeln!(w, "{},{},{},{},{}", d.start, d.trough, recovery, d.depth, d.length)?;
+        }
+    }
+    Ok(())
+}
+
+/// Draws the values as a chart `width` characters wide and `height` lines
+/// high, with the max and min value labelling the top and bottom line. Each
+/// column shows the last value of the stretch of the series it covers.
+pub fn write_ascii_chart(values: &[f64], width: usize, height: usize, w: &mut impl Write) -> io::Result<()> {
+    let Summary { min, max, .. } = Summary::of(values);
+    if values.is_empty() || width == 0 || height == 0 {
+        return Ok(());
+    }
+    let cols = width.min(values.len());
+    let rows: Vec<usize> = (0..cols)
+        .map(|c| {
+            let v = values[(c + 1) * values.len() / cols - 1];
+            if max > min {
+                ((v - min) / (max - min) * (height - 1) as f64).round() as usize
+            } else {
+                0
+            }
+        })
+        .collect();
+    for line in (0..height).rev() {
+        let label = match line {
+            l if l == height - 1 => format!("{:>12.6}", max),
+            0 => format!("{:>12.6}", min),
+            _ => " ".repeat(12),
+        };
+        let chart: String = rows.iter().map(|&r| if r == line { '*' } else { ' ' }).collect();
+        writeln!(w, "{} |{}", label, chart.trim_end())?;
+    }
+    Ok(())
+}
+
+/// Appends the run to the database at `path`, creating the schema if needed.
+/// Every run gets a row in `runs` with its parameters, its values (and
+/// returns, if any) go into `points` and the `summary` view aggregates them
+/// per run.
+#[cfg(feature = "sqlite")]
+pub fn write_sqlite(path: &Path, run: &Run) -> Result<i64> {
+    let mut w = SqliteWriter::create(path, run, run.returns.is_some())?;
+    w.append(run.rows())?;
+    w.finish()
+}
+
+/// Writes a run to an SQLite database like `write_sqlite`, but a chunk of
+/// points at a time, each in its own transaction
+#[cfg(feature = "sqlite")]
+pub struct SqliteWriter {
+    conn: rusqlite::Connection,
+    run_id: i64,
+    tick: i64,
+    with_returns: bool,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteWriter {
+    /// Adds a row for `run` to `runs`, creating the schema if needed. The
+    /// values of `run` are ignored; its points are appended with `append`.
+    pub fn create(path: &Path, run: &Run, with_returns: bool) -> Result<SqliteWriter> {
+        let Run { interval, gen_args, acc_args, .. } = *run;
+        let conn = rusqlite::Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                scenario TEXT,
+                num_points INTEGER NOT NULL,
+                interval_seconds REAL NOT NULL,
+                yearly_mean REAL,
+                yearly_stddev REAL,
+                seed INTEGER,
+                accumulated INTEGER NOT NULL,
+                start_value REAL,
+                continuous_leverage REAL,
+                pointwise_leverage REAL,
+                initial_leverage REAL
+            );
+            CREATE TABLE IF NOT EXISTS points (
+                run_id INTEGER NOT NULL REFERENCES runs(id),
+                tick INTEGER NOT NULL,
+                t_seconds REAL NOT NULL,
+                value REAL NOT NULL,
+                tick_return REAL,
+                PRIMARY KEY (run_id, tick)
+            );
+            CREATE VIEW IF NOT EXISTS summary AS
+                SELECT
+                    run_id,
+                    COUNT(*) AS count,
+                    MIN(value) AS min,
+                    MAX(value) AS max,
+                    AVG(value) AS mean,
+                    (SELECT value FROM points l WHERE l.run_id = p.run_id ORDER BY tick DESC LIMIT 1) AS last
+                FROM points p
+                GROUP BY run_id;",
+        )?;
+
+        conn.execute(
+            "INSERT INTO runs (
+                scenario, num_points, interval_seconds, yearly_mean, yearly_stddev, seed,
+                accumulated, start_value, continuous_leverage, pointwise_leverage, initial_leverage
+            ) VALUES (?1, 0, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            rusqlite::params![
+                run.scenario,
+                interval.seconds(),
+                gen_args.map(|a| a.yearly_mean),
+                gen_args.map(|a| a.yearly_stddev),
+                // SQLite integers are signed, keep the bit pattern of the seed
+                gen_args.and_then(|a| a.seed).map(|s| s as i64),
+                acc_args.accumulate,
+                acc_args.accumulate.then_some(acc_args.start_value),
+                acc_args.continuous_leverage,
+                acc_args.pointwise_leverage,
+                acc_args.initial_leverage,
+            ],
+        )?;
+        let run_id = conn.last_insert_rowid();
+        Ok(SqliteWriter {
+            conn,
+            run_id,
+            tick: 0,
+            with_returns,
+        })
+    }
+
+    /// Appends the points to the run in one transaction
+    pub fn append(&mut self, rows: impl Iterator<Item = DataPoint>) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO points (run_id, tick, t_seconds, value, tick_return) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for p in rows {
+                let r = self.with_returns.then_some(p.tick_return);
+                stmt.execute(rusqlite::params![self.run_id, self.tick, p.t_seconds, p.value, r])?;
+                self.tick += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Records the number of points appended, returning the id of the run
+    pub fn finish(self) -> Result<i64> {
+        self.conn.execute("UPDATE runs SET num_points = ?1 WHERE id = ?2", [self.tick, self.run_id])?;
+        Ok(self.run_id)
+    }
+}
+
+/// Size in bytes of a record written by `BinaryWriter`
+#[cfg(feature = "mmap")]
+pub const BINARY_RECORD_SIZE: usize = 24;
+
+/// Writes data points to a file as fixed-width records through a memory map,
+/// so that series far larger than memory can be written without buffering
+/// them. Every record is the `t_seconds`, `tick_return` and `value` of a
+/// point as little-endian `f64`s, with no header; the records of several runs
+/// follow each other in order.
+#[cfg(feature = "mmap")]
+pub struct BinaryWriter {
+    file: std::fs::File,
+    map: Option<memmap2::MmapMut>,
+    /// Number of records the file currently has room for
+    capacity: usize,
+    len: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl BinaryWriter {
+    /// Creates the file at `path`, overwriting any existing file, with room
+    /// for `capacity` records. Writing more grows the file.
+    pub fn create(path: &Path, capacity: usize) -> Result<BinaryWriter> {
+        let file = std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        let mut writer = BinaryWriter {
+            file,
+            map: None,
+            capacity: 0,
+            len: 0,
+        };
+        writer.reserve(capacity)?;
+        Ok(writer)
+    }
+
+    /// Resizes the file and its map to hold `capacity` records
+    fn reserve(&mut self, capacity: usize) -> Result<()> {
+        if let Some(map) = self.map.take() {
+            map.flush()?;
+        }
+        self.file.set_len((capacity * BINARY_RECORD_SIZE) as u64)?;
+        self.capacity = capacity;
+        if capacity > 0 {
+            // Safe as long as no other process truncates the file while it is mapped
+            self.map = Some(unsafe { memmap2::MmapMut::map_mut(&self.file)? });
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, p: &DataPoint) -> Result<()> {
+        if self.len == self.capacity {
+            self.reserve((2 * self.capacity).max(1024))?;
+        }
+        let start = self.len * BINARY_RECORD_SIZE;
+        let map = self.map.as_mut().expect("mapped while there is room");
+        let record = &mut map[start..start + BINARY_RECORD_SIZE];
+        for (field, v) in record.chunks_exact_mut(8).zip([p.t_seconds, p.tick_return, p.value]) {
+            field.copy_from_slice(&v.to_le_bytes());
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Flushes the records to the file and truncates it to them, returning
+    /// their number
+    pub fn finish(mut self) -> Result<usize> {
+        if let Some(map) = self.map.take() {
+            map.flush()?;
+        }
+        self.file.set_len((self.len * BINARY_RECORD_SIZE) as u64)?;
+        Ok(self.len)
+    }
+}
+
+/// Writes the runs to a new Excel workbook at `path`, overwriting any existing
+/// file. Every run gets a sheet, named after its scenario, with one row per
+/// tick (and the return in a fourth column if any). The `Summary` sheet has a
+/// column per run with its parameters plus formulas aggregating its series.
+#[cfg(feature = "xlsx")]
+pub fn write_xlsx(path: &Path, runs: &[Run]) -> Result<()> {
+    use rust_xlsxwriter::{Format, Formula, Workbook};
+
+    let mut workbook = Workbook::new();
+    let bold = Format::new().set_bold();
+    let sheet_names: Vec<String> = runs
+        .iter()
+        .enumerate()
+        .map(|(i, run)| match run.scenario {
+            Some(scenario) => scenario.to_string(),
+            None if runs.len() == 1 => "Series".to_string(),
+            None => format!("Series {}", i + 1),
+        })
+        .collect();
+
+    for (run, name) in runs.iter().zip(&sheet_names) {
+        let series = workbook.add_worksheet().set_name(name)?;
+        series.write_row_with_format(0, 0, ["tick", "t_seconds", "value"], &bold)?;
+        if run.returns.is_some() {
+            series.write_string_with_format(0, 3, "return", &bold)?;
+        }
+        for (i, p) in run.rows().enumerate() {
+            let row = i as u32 + 1;
+            series.write_number(row, 0, i as f64)?;
+            series.write_number(row, 1, p.t_seconds)?;
+            series.write_number(row, 2, p.value)?;
+            if run.returns.is_some() {
+                series.write_number(row, 3, p.tick_return)?;
+            }
+        }
+    }
+
+    let summary = workbook.add_worksheet().set_name("Summary")?;
+    type Parameter = (&'static str, fn(&Run) -> Option<f64>);
+    let parameters: [Parameter; 9] = [
+        ("num_points", |r| Some(r.values.len() as f64)),
+        ("interval_seconds", |r| Some(r.interval.seconds())),
+        ("yearly_mean", |r| r.gen_args.map(|a| a.yearly_mean)),
+        ("yearly_stddev", |r| r.gen_args.map(|a| a.yearly_stddev)),
+        ("seed", |r| r.gen_args.and_then(|a| a.seed).map(|s| s as f64)),
+        ("start_value", |r| r.acc_args.accumulate.then_some(r.acc_args.start_value)),
+        ("continuous_leverage", |r| r.acc_args.continuous_leverage),
+        ("pointwise_leverage", |r| r.acc_args.pointwise_leverage),
+        ("initial_leverage", |r| r.acc_args.initial_leverage),
+    ];
+    let aggregates = ["count", "min", "max", "mean", "last"];
+    for (row, name) in parameters.iter().map(|(name, _)| name).chain(&aggregates).enumerate() {
+        summary.write_string_with_format(row as u32 + 1, 0, *name, &bold)?;
+    }
+    for (i, (run, name)) in runs.iter().zip(&sheet_names).enumerate() {
+        let col = i as u16 + 1;
+        summary.write_string_with_format(0, col, name, &bold)?;
+        for (row, (_, get)) in parameters.iter().enumerate() {
+            if let Some(v) = get(run) {
+                summary.write_number(row as u32 + 1, col, v)?;
+            }
+        }
+        if run.values.is_empty() {
+            continue;
+        }
+        let sheet = format!("'{}'", name.replace('\'', "''"));
+        let last_row = run.values.len() + 1;
+        let range = format!("{}!C2:C{}", sheet, last_row);
+        let formulas = [
+            format!("=COUNT({})", range),
+            format!("=MIN({})", range),
+            format!("=MAX({})", range),
+            format!("=AVERAGE({})", range),
+            format!("={}!C{}", sheet, last_row),
+        ];
+        for (j, formula) in formulas.into_iter().enumerate() {
+            summary.write_formula((parameters.len() + j) as u32 + 1, col, Formula::new(formula))?;
+        }
+    }
+    summary.autofit();
+
+    workbook.save(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{OutputArgs, OutputFormat, OutputTarget, Run, StreamHeader, StreamKind, Summary};
+    use crate::calendar::BusinessCalendar;
+    use crate::returns::{AccumulateArgs, DataPoint, data_points};
+
+    fn acc_args(accumulate: bool) -> AccumulateArgs {
+        AccumulateArgs {
+            accumulate,
+            start_value: 1.0,
+            continuous_leverage: None,
+            pointwise_leverage: None,
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        }
+    }
+
+    fn new_run<'a>(values: &'a [f64], acc_args: &'a AccumulateArgs) -> Run<'a> {
+        Run {
+            scenario: None,
+            values,
+            returns: None,
+            interval: 1.0.into(),
+            gen_args: None,
+            acc_args,
+        }
+    }
+
+    #[test]
+    fn parse_output_target() {
+        assert_eq!(OutputTarget::Stdout, "-".parse().unwrap());
+        assert!("ftp://example.com/out.txt".parse::<OutputTarget>().is_err());
+        assert!("out.txt".parse::<OutputTarget>().is_err());
+        assert_eq!("-", OutputTarget::Stdout.to_string());
+        let ws = OutputTarget::WebSocket("127.0.0.1:9000".to_string());
+        assert_eq!(ws, "ws://127.0.0.1:9000".parse().unwrap());
+        assert_eq!("ws://127.0.0.1:9000", ws.to_string());
+        assert!("ws://".parse::<OutputTarget>().is_err());
+    }
+
+    #[test]
+    fn paced_data_points() {
+        // A point every 20ms at 50 times the speed
+        let start = Instant::now();
+        let rows = data_points([1.5, 2.0, 1.0].into_iter(), 1.0, &acc_args(true));
+        let values: Vec<f64> = super::paced(rows, 50.0).map(|p| p.value).collect();
+        assert_eq!(vec![1.5, 3.0, 3.0], values);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn output_args_round_trip_through_toml() {
+        let args = OutputArgs {
+            output: OutputTarget::Stdout,
+            format: OutputFormat::Influx,
+            measurement: "finsim".to_string(),
+            tags: vec![("host".to_string(), "a".to_string())],
+            start_timestamp: None,
+            calendar: None,
+            unbuffered: false,
+            buffer_size: 1024,
+            with_returns: false,
+            with_inflation: false,
+            chunk_size: None,
+            realtime: None,
+        };
+        let toml = toml::to_string(&args).unwrap();
+        assert!(toml.starts_with("output = \"-\"\nformat = \"influx\"\n"), "{}", toml);
+        let parsed: OutputArgs = toml::from_str(&toml).unwrap();
+        assert_eq!(OutputTarget::Stdout, parsed.output);
+        assert_eq!(OutputFormat::Influx, parsed.format);
+        assert_eq!(args.tags, parsed.tags);
+    }
+
+    #[test]
+    fn stream_format() {
+        let acc_args = acc_args(true);
+        let values = [1.5, 3.0];
+        let run = Run { scenario: Some("bull run"), returns: Some(&[1.5, 2.0]), ..new_run(&values, &acc_args) };
+        let args = OutputArgs {
+            output: OutputTarget::Stdout,
+            format: OutputFormat::Stream,
+            measurement: "finsim".to_string(),
+            tags: vec![("host".to_string(), "a=b".to_string())],
+            start_timestamp: Some(100),
+            calendar: None,
+            unbuffered: false,
+            buffer_size: 1024,
+            with_returns: true,
+            with_inflation: false,
+            chunk_size: None,
+            realtime: None,
+        };
+        let mut buf = Vec::new();
+        super::write_text(&run, &args, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            concat!(
+                "# finsim kind=values interval_seconds=1 start_value=1 start_timestamp=100 scenario=bull%20run ",
+                "columns=t_seconds,value,return tag.host=a%3Db\n",
+                "1\t1.5\t1.5\n2\t3\t2\n",
+            ),
+            text
+        );
+        let header: StreamHeader = text.lines().next().unwrap().parse().unwrap();
+        assert_eq!(StreamHeader::of(&run, &args), header);
+        assert_eq!(Some("bull run"), header.scenario.as_deref());
+        assert_eq!(StreamKind::Values, header.kind);
+
+        // Unknown keys are skipped, missing ones default
+        let header: StreamHeader = "# finsim kind=log-returns later=1".parse().unwrap();
+        assert_eq!((StreamKind::LogReturns, 86400.0, false), (header.kind, header.interval_seconds, header.with_returns));
+        assert!("# finsim kind=prices".parse::<StreamHeader>().is_err());
+        assert!("# finsim columns=value".parse::<StreamHeader>().is_err());
+    }
+
+    #[test]
+    fn write_summary_csv_test() {
+        assert_eq!(Summary { count: 3, min: 0.5, max: 2.0, mean: 1.0, last: 0.5 }, Summary::of(&[0.5, 2.0, 0.5]));
+        assert!(Summary::of::<f64>(&[]).last.is_nan());
+
+        let keys = ["pointwise_leverage".to_string(), "seed".to_string()];
+        let rows = [
+            (vec!["1".to_string(), "7".to_string()], Summary::of(&[1.0, 2.0])),
+            (vec!["2".to_string(), "7".to_string()], Summary::of(&[1.0, 4.0])),
+        ];
+        let mut buf = Vec::new();
+        super::write_summary_csv(&keys, &rows, &mut buf).unwrap();
+        assert_eq!(
+            "pointwise_leverage,seed,count,min,max,mean,last\n1,7,2,1,2,1.5,2\n2,7,2,1,4,2.5,4\n",
+            String::from_utf8(buf).unwrap()
+        );
+
+        let mut buf = Vec::new();
+        super::write_summary_csv(&[], &[(Vec::new(), Summary::of(&[3.0]))], &mut buf).unwrap();
+        assert_eq!("count,min,max,mean,last\n1,3,3,3,3\n", String::from_utf8(buf).unwrap());
+
+        let mut buf = Vec::new();
+        super::write_summary_csv(&[], &[(Vec::new(), Summary::of::<f64>(&[]))], &mut buf).unwrap();
+        assert_eq!("count,min,max,mean,last\n0,,,,\n", String::from_utf8(buf).unwrap());
+
+        // Doubling in 2 years
+        let rows = [(Vec::new(), Summary::of(&[1.0, 2.0])), (Vec::new(), Summary::of::<f64>(&[]))];
+        let mut buf = Vec::new();
+        super::write_cagr_summary_csv(&[], &rows, 1.0, 2.0, &mut buf).unwrap();
+        let csv = format!("count,min,max,mean,last,cagr\n2,1,2,1.5,2,{}\n0,,,,,\n", 2f64.sqrt() - 1.0);
+        assert_eq!(csv, String::from_utf8(buf).unwrap());
+
+        let mut drawdowns = crate::drawdown::Drawdowns::new(1.0);
+        [0.5, 1.0, 0.8].into_iter().for_each(|value| drawdowns.push(value));
+        let mut buf = Vec::new();
+        super::write_drawdowns_csv(&["path".to_string()], &[(vec!["3".to_string()], drawdowns.finish())], &mut buf).unwrap();
+        assert_eq!("path,start,trough,recovery,depth,length\n3,0,1,2,0.5,2\n3,2,3,,0.19999999999999996,1\n", String::from_utf8(buf).unwrap());
+
+        let rows = [(Vec::new(), Summary::of(&[1.0, 2.0]), vec![0.5, f64::NAN])];
+        let mut buf = Vec::new();
+        super::write_stats_csv(&[], &["hurst", "x"], &rows, &mut buf).unwrap();
+        assert_eq!("count,min,max,mean,last,hurst,x\n2,1,2,1.5,2,0.5,\n", String::from_utf8(buf).unwrap());
+    }
+
+    #[test]
+    fn write_ascii_chart_test() {
+        let mut buf = Vec::new();
+        // Two values per column
+        super::write_ascii_chart(&[1.0, 0.0, 3.0, 4.0, 1.0, 2.0], 3, 3, &mut buf).unwrap();
+        assert_eq!(
+            concat!(
+                "    4.000000 | *\n",
+                "             |  *\n",
+                "    0.000000 |*\n",
+            ),
+            String::from_utf8(buf).unwrap()
+        );
+
+        let mut buf = Vec::new();
+        super::write_ascii_chart(&[1.0, 3.0], 10, 2, &mut buf).unwrap();
+        assert_eq!("    3.000000 | *\n    1.000000 |*\n", String::from_utf8(buf).unwrap());
+    }
+
+    #[test]
+    fn write_text_stream_test() {
+        let args = OutputArgs {
+            output: OutputTarget::Stdout,
+            format: OutputFormat::Influx,
+            measurement: "finsim".to_string(),
+            tags: Vec::new(),
+            start_timestamp: Some(0),
+            calendar: None,
+            unbuffered: false,
+            buffer_size: 1024,
+            with_returns: true,
+            with_inflation: false,
+            chunk_size: None,
+            realtime: None,
+        };
+        let rows = data_points([1.5, 2.0].into_iter(), 2.0, &acc_args(true));
+        let mut buf = Vec::new();
+        super::write_text_stream(Some("a"), rows, &args, &mut buf).unwrap();
+        assert_eq!(
+            "finsim,scenario=a return=1.5,value=1.5 2000000000\nfinsim,scenario=a return=2,value=3 4000000000\n",
+            String::from_utf8(buf).unwrap()
+        );
+
+        // From Thursday 2023-12-21 on business days, Friday and Monday
+        let args = OutputArgs {
+            start_timestamp: Some(1703116800),
+            calendar: Some(BusinessCalendar::default()),
+            with_returns: false,
+            ..args
+        };
+        let rows = data_points([1.5, 2.0].into_iter(), 2.0, &acc_args(true));
+        let mut buf = Vec::new();
+        super::write_text_stream(None, rows, &args, &mut buf).unwrap();
+        assert_eq!(
+            "finsim value=1.5 1703203200000000000\nfinsim value=3 1703462400000000000\n",
+            String::from_utf8(buf).unwrap()
+        );
+        assert!(OutputArgs { start_timestamp: None, ..args.clone() }.validate().is_err());
+
+        // The price level after the value
+        let with_levels = || {
+            let rows = data_points([1.5, 2.0].into_iter(), 2.0, &acc_args(true));
+            rows.zip([1.25, 1.5]).map(|(p, level)| DataPoint { price_level: Some(level), ..p })
+        };
+        let args = OutputArgs { start_timestamp: Some(0), calendar: None, with_inflation: true, ..args };
+        let mut buf = Vec::new();
+        super::write_text_stream(None, with_levels(), &args, &mut buf).unwrap();
+        assert_eq!(
+            "finsim value=1.5,inflation=1.25 2000000000\nfinsim value=3,inflation=1.5 4000000000\n",
+            String::from_utf8(buf).unwrap()
+        );
+        let args = OutputArgs { format: OutputFormat::Plain, ..args };
+        let mut buf = Vec::new();
+        super::write_text_stream(None, with_levels(), &args, &mut buf).unwrap();
+        assert_eq!("1.5 1.25\n3 1.5\n", String::from_utf8(buf).unwrap());
+    }
+
+    #[test]
+    fn write_plain_test() {
+        let acc_args = acc_args(true);
+        let mut buf = Vec::new();
+        super::write_plain(&new_run(&[1.5, 2.0, 0.25], &acc_args), &mut buf).unwrap();
+        assert_eq!("1.5\n2\n0.25\n", String::from_utf8(buf).unwrap());
+
+        let mut buf = Vec::new();
+        let run = Run {
+            returns: Some(&[1.5, 2.0]),
+            ..new_run(&[1.5, 3.0], &acc_args)
+        };
+        super::write_plain(&run, &mut buf).unwrap();
+        assert_eq!("1.5 1.5\n2 3\n", String::from_utf8(buf).unwrap());
+
+        let mut buf = Vec::new();
+        let run = Run {
+            scenario: Some("levered"),
+            ..run
+        };
+        super::write_plain(&run, &mut buf).unwrap();
+        assert_eq!("levered 1.5 1.5\nlevered 2 3\n", String::from_utf8(buf).unwrap());
+    }
+
+    #[test]
+    fn write_influx_test() {
+        let acc_args = acc_args(true);
+        let mut buf = Vec::new();
+        let tags = vec![
+            ("asset".to_string(), "s&p 500".to_string()),
+            ("path".to_string(), "0".to_string()),
+        ];
+        let run = Run {
+            interval: 0.5.into(),
+            ..new_run(&[1.5, 2.0], &acc_args)
+        };
+        super::write_influx(&run, "my prices", &tags, 1_600_000_000, None, &mut buf).unwrap();
+        assert_eq!(
+            "my\\ prices,asset=s&p\\ 500,path=0 value=1.5 1600000000500000000\n\
+             my\\ prices,asset=s&p\\ 500,path=0 value=2 1600000001000000000\n",
+            String::from_utf8(buf).unwrap()
+        );
+
+        let mut buf = Vec::new();
+        let run = Run {
+            scenario: Some("base"),
+            returns: Some(&[1.5]),
+            ..new_run(&[1.5], &acc_args)
+        };
+        super::write_influx(&run, "finsim", &[], 0, None, &mut buf).unwrap();
+        assert_eq!("finsim,scenario=base return=1.5,value=1.5 1000000000\n", String::from_utf8(buf).unwrap());
+    }
+
+    #[test]
+    fn parse_tag_test() {
+        assert_eq!(("a".to_string(), "b=c".to_string()), super::parse_tag("a=b=c").unwrap());
+        assert!(super::parse_tag("a").is_err());
+        assert!(super::parse_tag("=b").is_err());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn write_sqlite_test() {
+        use crate::returns::{GenReturnsArgs, RngKind, TimeBase};
+
+        assert_eq!(
+            OutputTarget::Sqlite("/tmp/out.db".into()),
+            "sqlite:///tmp/out.db".parse().unwrap()
+        );
+
+        let path = std::env::temp_dir().join(format!("finsim-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let gen_args = GenReturnsArgs {
+            total_seconds: Some(30),
+            interval_seconds: None,
+            num_points: 3,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(u64::MAX),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let acc_args = acc_args(false);
+        let first_run = Run {
+            interval: gen_args.tick_interval(),
+            gen_args: Some(&gen_args),
+            ..new_run(&[1.0, 3.0, 2.0], &acc_args)
+        };
+        let second_run = Run {
+            scenario: Some("base"),
+            returns: Some(&[4.0]),
+            ..new_run(&[4.0], &acc_args)
+        };
+        let first = super::write_sqlite(&path, &first_run).unwrap();
+        let second = super::write_sqlite(&path, &second_run).unwrap();
+        assert_ne!(first, second);
+
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        let (count, min, max, mean, last): (i64, f64, f64, f64, f64) = conn
+            .query_row(
+                "SELECT count, min, max, mean, last FROM summary WHERE run_id = ?1",
+                [first],
+                |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
+            )
+            .unwrap();
+        assert_eq!((3, 1.0, 3.0, 2.0, 2.0), (count, min, max, mean, last));
+        let t_last: f64 = conn
+            .query_row("SELECT t_seconds FROM points WHERE run_id = ?1 AND tick = 2", [first], |r| r.get(0))
+            .unwrap();
+        assert_eq!(30.0, t_last);
+        let r: Option<f64> = conn
+            .query_row("SELECT tick_return FROM points WHERE run_id = ?1", [second], |r| r.get(0))
+            .unwrap();
+        assert_eq!(Some(4.0), r);
+        let seed: i64 = conn.query_row("SELECT seed FROM runs WHERE id = ?1", [first], |r| r.get(0)).unwrap();
+        assert_eq!(u64::MAX, seed as u64);
+        let scenario: String = conn.query_row("SELECT scenario FROM runs WHERE id = ?1", [second], |r| r.get(0)).unwrap();
+        assert_eq!("base", scenario);
+
+        let mut w = super::SqliteWriter::create(&path, &first_run, false).unwrap();
+        w.append(first_run.rows().take(2)).unwrap();
+        w.append(first_run.rows().skip(2)).unwrap();
+        let chunked = w.finish().unwrap();
+        let summary = |id: i64| -> (i64, i64, f64) {
+            conn.query_row("SELECT r.num_points, s.count, s.last FROM runs r JOIN summary s ON s.run_id = r.id WHERE r.id = ?1", [id], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap()
+        };
+        assert_eq!(summary(first), summary(chunked));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn binary_writer_test() {
+        use super::{BINARY_RECORD_SIZE, BinaryWriter};
+        use crate::returns::DataPoint;
+
+        assert_eq!(
+            OutputTarget::Binary("/tmp/out.bin".into()),
+            "bin:///tmp/out.bin".parse().unwrap()
+        );
+
+        let path = std::env::temp_dir().join(format!("finsim-test-{}.bin", std::process::id()));
+        let points: Vec<DataPoint> = (0..3000)
+            .map(|i| DataPoint {
+                t_seconds: i as f64,
+                tick_return: 1.0 + i as f64 / 1e4,
+                value: -(i as f64),
+                price_level: None,
+            })
+            .collect();
+        // Starting too small makes it grow the file
+        let mut w = BinaryWriter::create(&path, 2).unwrap();
+        for p in &points {
+            w.push(p).unwrap();
+        }
+        assert_eq!(3000, w.finish().unwrap());
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(3000 * BINARY_RECORD_SIZE, bytes.len());
+        let read: Vec<f64> = bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect();
+        for (p, record) in points.iter().zip(read.chunks_exact(3)) {
+            assert_eq!([p.t_seconds, p.tick_return, p.value], record);
+        }
+
+        assert_eq!(0, BinaryWriter::create(&path, 0).unwrap().finish().unwrap());
+        assert_eq!(0, std::fs::metadata(&path).unwrap().len());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "xlsx")]
+    #[test]
+    fn write_xlsx_test() {
+        use crate::returns::{GenReturnsArgs, RngKind, TimeBase};
+
+        let path = std::env::temp_dir().join(format!("finsim-test-{}.xlsx", std::process::id()));
+        let gen_args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(60),
+            num_points: 3,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: None,
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let acc_args = AccumulateArgs {
+            start_value: 100.0,
+            pointwise_leverage: Some(2.0),
+            ..acc_args(true)
+        };
+        let runs = [
+            Run {
+                scenario: Some("levered"),
+                returns: Some(&[1.01, 0.98, 1.03]),
+                interval: 60.0.into(),
+                gen_args: Some(&gen_args),
+                ..new_run(&[101.0, 99.0, 102.0], &acc_args)
+            },
+            Run {
+                scenario: Some("it's empty"),
+                ..new_run(&[], &acc_args)
+            },
+        ];
+        super::write_xlsx(&path, &runs).unwrap();
+        // xlsx files are zip archives
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(b"PK", &bytes[..2]);
+        std::fs::remove_file(&path).unwrap();
+
+        let duplicate = [new_run(&[1.0], &acc_args), new_run(&[1.0], &acc_args)];
+        super::write_xlsx(&path, &duplicate).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+//! A generator and normal sampler defined entirely in this crate, for
+//! `RngKind::Portable`. The other generators come from `rand_*` crates and
+//! are sampled by `rand_distr`, whose algorithms may change between
+//! versions. Here the bits are fixed: PCG32 for the uniforms, Box–Muller for
+//! the normals and `libm`, a pure Rust libm, for the logarithms and
+//! trigonometry, so a seed gives the same series on every platform and
+//! with every version of finsim.
+
+use rand::RngCore;
+
+const MULTIPLIER: u64 = 6364136223846793005;
+/// The default stream of the reference implementation, `PCG32_INITIALIZER`
+const STREAM: u64 = 0xda3e39cb94b95bdb;
+
+/// PCG XSH RR 64/32, as `pcg32_random_r` of the PCG reference implementation
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    /// Seeded like `pcg32_srandom_r(seed, STREAM)`
+    pub fn new(seed: u64) -> Pcg32 {
+        let mut rng = Pcg32 { state: 0, increment: (STREAM << 1) | 1 };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        rng
+    }
+
+    fn step(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+}
+
+impl RngCore for Pcg32 {
+    fn next_u32(&mut self) -> u32 {
+        self.step()
+    }
+
+    /// The first draw in the high half
+    fn next_u64(&mut self) -> u64 {
+        let high = u64::from(self.step());
+        (high << 32) | u64::from(self.step())
+    }
+
+    /// Little-endian draws, the last one truncated
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            chunk.copy_from_slice(&self.step().to_le_bytes()[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// A uniform draw in (0, 1] from the top 53 bits of `next_u64`
+fn uniform<R: RngCore>(rng: &mut R) -> f64 {
+    ((rng.next_u64() >> 11) + 1) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+/// Normal distribution sampled with the Box–Muller transform. Each pair of
+/// uniforms gives two draws, the second kept for the next sample.
+#[derive(Clone, Debug)]
+pub struct Normal {
+    mean: f64,
+    std_dev: f64,
+    spare: Option<f64>,
+}
+
+impl Normal {
+    pub fn new(mean: f64, std_dev: f64) -> Normal {
+        Normal { mean, std_dev, spare: None }
+    }
+
+    pub fn sample<R: RngCore>(&mut self, rng: &mut R) -> f64 {
+        let z = match self.spare.take() {
+            Some(z) => z,
+            None => {
+                let r = (-2.0 * libm::log(uniform(rng))).sqrt();
+                let theta = 2.0 * std::f64::consts::PI * uniform(rng);
+                self.spare = Some(r * libm::sin(theta));
+                r * libm::cos(theta)
+            }
+        };
+        self.mean + self.std_dev * z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+
+    use super::{Normal, Pcg32};
+
+    #[test]
+    fn pcg32_matches_the_reference() {
+        // pcg32-demo of the reference implementation, seeded with (42, 54)
+        let mut rng = Pcg32 { state: 0, increment: (54 << 1) | 1 };
+        rng.step();
+        rng.state = rng.state.wrapping_add(42);
+        rng.step();
+        let draws: Vec<u32> = (0..6).map(|_| rng.next_u32()).collect();
+        assert_eq!(vec![0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e], draws);
+    }
+
+    #[test]
+    fn normal_draws_are_pinned() {
+        let mut rng = Pcg32::new(1);
+        let mut normal = Normal::new(0.0, 1.0);
+        let draws: Vec<f64> = (0..4).map(|_| normal.sample(&mut rng)).collect();
+        // Also what the same algorithm gives in Python with glibc's log, sin and cos
+        assert_eq!(vec![0.3483449519622474, -0.06696870370909154, 1.061355738780286, 1.1181818951728395], draws);
+    }
+}
@@ -0,0 +1,653 @@
+use rand_distr::{ChiSquared, Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::model::path_seed;
+use crate::portable;
+use crate::returns::{tick_log_params, GenReturnsArgs, RngKind, SeededRng};
+use crate::stats::{Moments, percent, quantile};
+
+/// One asset of a portfolio spec
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Asset {
+    pub name: String,
+    /// Share of the portfolio, relative to the other weights
+    pub weight: f64,
+    /// Falls back to the top level `yearly_mean` if `None`
+    pub yearly_mean: Option<f64>,
+    /// Falls back to the top level `yearly_stddev` if `None`
+    pub yearly_stddev: Option<f64>,
+    /// Priced in the foreign currency of the `FxLeg`, converted to the home
+    /// currency at its exchange rate unless hedged
+    #[serde(default)]
+    pub foreign: bool,
+}
+
+/// The exchange rate of a portfolio's foreign currency, in units of the home
+/// currency, as another lognormal process correlated with the assets
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct FxLeg {
+    pub yearly_mean: f64,
+    pub yearly_stddev: f64,
+    /// The correlation of the exchange rate with every asset, in their order
+    pub correlation: Vec<f64>,
+    /// Whether the currency risk of the foreign assets is hedged, so that
+    /// they earn their returns in the foreign currency
+    pub hedged: bool,
+}
+
+/// A portfolio spec file: the assets, the correlation between their log
+/// returns and the flags for the rest of the simulation
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct PortfolioSpec {
+    pub assets: Vec<Asset>,
+    /// Row `i` holds the correlations of asset `i` with every asset
+    pub correlation: Vec<Vec<f64>>,
+    pub fx: Option<FxLeg>,
+    /// Command line flags from the top level keys, in the format of
+    /// `config::config_to_args`
+    pub args: Vec<String>,
+}
+
+/// Parses a portfolio spec file. Each `[[assets]]` table has a `name`, a
+/// `weight` and optionally its own `yearly_mean` and `yearly_stddev`.
+/// `correlation` is a matrix with one row per asset, in the same order, and
+/// defaults to uncorrelated assets. Assets with `foreign = true` are
+/// converted at the exchange rate of the `[fx]` table, which has a
+/// `yearly_stddev`, optionally a `yearly_mean` (default 1, no drift), a
+/// `correlation` with every asset (default 0) and `hedged` (default false).
+/// All other top level keys are flags.
+pub fn parse_portfolio(spec: &str) -> Result<PortfolioSpec> {
+    let mut table = spec.parse::<toml::Table>().map_err(|e| Error::Parse(e.to_string()))?;
+    let assets = match table.remove("assets") {
+        Some(toml::Value::Array(assets)) if !assets.is_empty() => assets
+            .into_iter()
+            .enumerate()
+            .map(|(i, a)| parse_asset(a).map_err(|e| e.context(format!("assets[{}]", i))))
+            .collect::<Result<Vec<_>>>()?,
+        _ => return Err(Error::Parse("expected at least one [[assets]] table".to_string())),
+    };
+    let correlation = match table.remove("correlation") {
+        Some(toml::Value::Array(rows)) => rows
+            .into_iter()
+            .map(|row| match row {
+                toml::Value::Array(row) => row.into_iter().map(|v| to_f64(&v)).collect::<Option<Vec<_>>>(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::Parse("correlation must be an array of arrays of numbers".to_string()))?,
+        Some(_) => return Err(Error::Parse("correlation must be an array of arrays of numbers".to_string())),
+        None => identity(assets.len()),
+    };
+    validate_correlation(&correlation, assets.len())?;
+    let fx = match table.remove("fx") {
+        Some(fx) => Some(parse_fx(fx, assets.len()).map_err(|e| e.context("fx"))?),
+        None => None,
+    };
+    match &fx {
+        Some(fx) => cholesky(&with_fx(&correlation, fx)).map_err(|e| e.context("fx"))?,
+        None if assets.iter().any(|a| a.foreign) => {
+            return Err(Error::Parse("foreign assets need an [fx] table".to_string()));
+        }
+        None => Vec::new(),
+    };
+    if assets.iter().any(|a| a.weight < 0.0) || assets.iter().map(|a| a.weight).sum::<f64>() <= 0.0 {
+        return Err(Error::Parse("weights must be non-negative and not all zero".to_string()));
+    }
+    let args = config::table_to_args(table)?;
+    Ok(PortfolioSpec { assets, correlation, fx, args })
+}
+
+fn parse_asset(value: toml::Value) -> Result<Asset> {
+    let toml::Value::Table(mut table) = value else {
+        return Err(Error::Parse("must be a table".to_string()));
+    };
+    let mut number = |key: &str| match table.remove(key) {
+        Some(v) => to_f64(&v).map(Some).ok_or_else(|| Error::Parse(format!("{} must be a number", key))),
+        None => Ok(None),
+    };
+    let weight = number("weight")?.ok_or_else(|| Error::Parse("missing weight".to_string()))?;
+    let yearly_mean = number("yearly_mean")?;
+    let yearly_stddev = number("yearly_stddev")?;
+    let foreign = match table.remove("foreign") {
+        Some(toml::Value::Boolean(foreign)) => foreign,
+        Some(_) => return Err(Error::Parse("foreign must be a boolean".to_string())),
+        None => false,
+    };
+    let name = match table.remove("name") {
+        Some(toml::Value::String(name)) => name,
+        _ => return Err(Error::Parse("missing name".to_string())),
+    };
+    if let Some(key) = table.keys().next() {
+        return Err(Error::Parse(format!("unknown key '{}'", key)));
+    }
+    if yearly_mean.is_some_and(|m| !m.is_finite() || m <= 0.0) {
+        return Err(Error::Parse("yearly_mean must be a positive factor".to_string()));
+    }
+    if yearly_stddev.is_some_and(|s| !s.is_finite() || s < 1.0) {
+        return Err(Error::Parse("yearly_stddev must be a factor of at least 1".to_string()));
+    }
+    Ok(Asset { name, weight, yearly_mean, yearly_stddev, foreign })
+}
+
+fn parse_fx(value: toml::Value, n: usize) -> Result<FxLeg> {
+    let toml::Value::Table(mut table) = value else {
+        return Err(Error::Parse("must be a table".to_string()));
+    };
+    let mut number = |key: &str| match table.remove(key) {
+        Some(v) => to_f64(&v).map(Some).ok_or_else(|| Error::Parse(format!("{} must be a number", key))),
+        None => Ok(None),
+    };
+    let yearly_mean = number("yearly_mean")?.unwrap_or(1.0);
+    let yearly_stddev = number("yearly_stddev")?.ok_or_else(|| Error::Parse("missing yearly_stddev".to_string()))?;
+    let correlation = match table.remove("correlation") {
+        Some(toml::Value::Array(row)) => row
+            .iter()
+            .map(to_f64)
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::Parse("correlation must be an array of numbers".to_string()))?,
+        Some(_) => return Err(Error::Parse("correlation must be an array of numbers".to_string())),
+        None => vec![0.0; n],
+    };
+    let hedged = match table.remove("hedged") {
+        Some(toml::Value::Boolean(hedged)) => hedged,
+        Some(_) => return Err(Error::Parse("hedged must be a boolean".to_string())),
+        None => false,
+    };
+    if let Some(key) = table.keys().next() {
+        return Err(Error::Parse(format!("unknown key '{}'", key)));
+    }
+    if !yearly_mean.is_finite() || yearly_mean <= 0.0 {
+        return Err(Error::Parse("yearly_mean must be a positive factor".to_string()));
+    }
+    if !yearly_stddev.is_finite() || yearly_stddev < 1.0 {
+        return Err(Error::Parse("yearly_stddev must be a factor of at least 1".to_string()));
+    }
+    if correlation.len() != n {
+        return Err(Error::Invalid(format!("correlation must have {} entries, one per asset", n)));
+    }
+    if correlation.iter().any(|c| !(-1.0..=1.0).contains(c)) {
+        return Err(Error::Invalid("correlations must be between -1 and 1".to_string()));
+    }
+    Ok(FxLeg { yearly_mean, yearly_stddev, correlation, hedged })
+}
+
+/// The correlation matrix of the assets and the exchange rate, which comes
+/// last
+fn with_fx(correlation: &[Vec<f64>], fx: &FxLeg) -> Vec<Vec<f64>> {
+    let mut m: Vec<Vec<f64>> =
+        correlation.iter().zip(&fx.correlation).map(|(row, &c)| [&row[..], &[c]].concat()).collect();
+    m.push([&fx.correlation[..], &[1.0]].concat());
+    m
+}
+
+fn to_f64(value: &toml::Value) -> Option<f64> {
+    match value {
+        toml::Value::Float(f) => Some(*f),
+        toml::Value::Integer(i) => Some(*i as f64),
+        _ => None,
+    }
+}
+
+fn identity(n: usize) -> Vec<Vec<f64>> {
+    (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect()
+}
+
+fn validate_correlation(correlation: &[Vec<f64>], n: usize) -> Result<()> {
+    if correlation.len() != n || correlation.iter().any(|row| row.len() != n) {
+        return Err(Error::Invalid(format!("correlation must be a {}x{} matrix, one row per asset", n, n)));
+    }
+    for (i, row) in correlation.iter().enumerate() {
+        if row[i] != 1.0 {
+            return Err(Error::Invalid("correlation must have 1 on the diagonal".to_string()));
+        }
+        for (j, &c) in row.iter().enumerate().take(i) {
+            if c != correlation[j][i] {
+                return Err(Error::Invalid("correlation must be symmetric".to_string()));
+            }
+            if !(-1.0..=1.0).contains(&c) {
+                return Err(Error::Invalid("correlations must be between -1 and 1".to_string()));
+            }
+        }
+    }
+    cholesky(correlation).map(|_| ())
+}
+
+/// Lower triangular `L` with `L * L^T` equal to the (positive semidefinite)
+/// matrix. Perfectly correlated rows get zero pivots, which the remaining
+/// entries of their column then don't depend on, as long as they're
+/// consistent with the perfect correlation.
+fn cholesky(m: &[Vec<f64>]) -> Result<Vec<Vec<f64>>> {
+    let n = m.len();
+    let mut l = vec![vec![0.0; n]; n];
+    for j in 0..n {
+        let d = m[j][j] - (0..j).map(|k| l[j][k] * l[j][k]).sum::<f64>();
+        if d < -1e-9 {
+            return Err(Error::Invalid("correlation matrix is not positive semidefinite".to_string()));
+        }
+        l[j][j] = d.max(0.0).sqrt();
+        for i in j + 1..n {
+            let s = m[i][j] - (0..j).map(|k| l[i][k] * l[j][k]).sum::<f64>();
+            l[i][j] = if l[j][j] > 1e-12 {
+                s / l[j][j]
+            } else if s.abs() < 1e-9 {
+                0.0
+            } else {
+                return Err(Error::Invalid("correlation matrix is not positive semidefinite".to_string()));
+            };
+        }
+    }
+    Ok(l)
+}
+
+/// Iterator over the returns of a portfolio rebalanced to its weights at
+/// every tick, see `portfolio_returns`
+pub struct PortfolioReturns {
+    /// Per asset mean and standard deviation of the log return over one tick
+    params: Vec<(f64, f64)>,
+    weights: Vec<f64>,
+    /// Mean and standard deviation of the log return of the exchange rate
+    /// over one tick, drawn last, and whether it applies to the foreign
+    /// assets or they're hedged
+    fx: Option<((f64, f64), bool)>,
+    foreign: Vec<bool>,
+    cholesky: Vec<Vec<f64>>,
+    /// Degrees of freedom of Student's t shocks and the chi-square draw
+    /// shared by every asset in a tick, `None` for normal shocks
+    tail: Option<(f64, ChiSquared<f64>)>,
+    rng: SeededRng,
+    /// The sampling of `RngKind::Portable`, `None` for the other generators
+    portable: Option<portable::Normal>,
+    remaining: usize,
+}
+
+impl PortfolioReturns {
+    /// The returns of every asset over the next tick, in the home currency,
+    /// from the same draws as the portfolio's return
+    pub fn next_assets(&mut self) -> Option<Vec<f64>> {
+        let (returns, fx) = self.draw()?;
+        Some(returns.into_iter().zip(&self.foreign).map(|(ret, &foreign)| if foreign { ret * fx } else { ret }).collect())
+    }
+
+    /// The weights the portfolio is rebalanced to, summing to 1
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// The returns of every asset over the next tick in its own currency, and
+    /// the return of the exchange rate, 1 without one or when hedged
+    fn draw(&mut self) -> Option<(Vec<f64>, f64)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let draws = self.params.len() + usize::from(self.fx.is_some());
+        let z: Vec<f64> = (0..draws)
+            .map(|_| match &mut self.portable {
+                Some(normal) => normal.sample(&mut self.rng),
+                None => StandardNormal.sample(&mut self.rng),
+            })
+            .collect();
+        // A multivariate t keeps the correlation of the normal draws it's
+        // made of, scaled to unit variance as in `returns_from`
+        let scale = match &self.tail {
+            Some((df, chi_squared)) => ((df - 2.0) / chi_squared.sample(&mut self.rng)).sqrt(),
+            None => 1.0,
+        };
+        let z: Vec<f64> = z.into_iter().map(|z| scale * z).collect();
+        let exp: fn(f64) -> f64 = if self.portable.is_some() { libm::exp } else { f64::exp };
+        let fx = match self.fx {
+            Some(((mu, sigma), false)) => {
+                let x: f64 = self.cholesky[self.params.len()].iter().zip(&z).map(|(l, z)| l * z).sum();
+                exp(mu + sigma * x)
+            }
+            _ => 1.0,
+        };
+        let returns = self
+            .params
+            .iter()
+            .zip(&self.cholesky)
+            .map(|(&(mu, sigma), l)| {
+                let x: f64 = l.iter().zip(&z).map(|(l, z)| l * z).sum();
+                exp(mu + sigma * x)
+            })
+            .collect();
+        Some((returns, fx))
+    }
+}
+
+impl Iterator for PortfolioReturns {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let (returns, fx) = self.draw()?;
+        let ret = returns
+            .into_iter()
+            .zip(&self.weights)
+            .zip(&self.foreign)
+            .map(|((ret, w), &foreign)| {
+                let ret = w * ret;
+                if foreign { ret * fx } else { ret }
+            })
+            .sum();
+        Some(ret)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+/// Generates returns of a portfolio of correlated lognormal assets, with the
+/// number of points, interval, seed and generator from `args`. Assets
+/// without their own yearly mean or standard deviation use the ones in
+/// `args`. With an `FxLeg`, the returns are in the home currency. With a
+/// `tail_df`, the shocks of every asset are Student's t with the same draw
+/// of their scale, so that heavy tails hit correlated assets together.
+pub fn portfolio_returns(spec: &PortfolioSpec, args: &GenReturnsArgs) -> Result<PortfolioReturns> {
+    args.validate()?;
+    let tail = match args.tail_df.filter(|df| df.is_finite()) {
+        Some(df) => {
+            debug!(df, "student's t shocks");
+            Some((df, ChiSquared::new(df).map_err(|e| Error::Invalid(e.to_string()))?))
+        }
+        None => None,
+    };
+    let cholesky = match &spec.fx {
+        Some(fx) => cholesky(&with_fx(&spec.correlation, fx))?,
+        None => cholesky(&spec.correlation)?,
+    };
+    let rng = SeededRng::new(args.rng, args.seed);
+    let ticks_per_year = args.time_base.ticks_per_year(args.tick_interval());
+    let total_weight: f64 = spec.assets.iter().map(|a| a.weight).sum();
+    Ok(PortfolioReturns {
+        params: spec
+            .assets
+            .iter()
+            .map(|a| {
+                let yearly_mean = a.yearly_mean.unwrap_or(args.yearly_mean);
+                let yearly_stddev = a.yearly_stddev.unwrap_or(args.yearly_stddev);
+                let (tick_mu, tick_sigma) = tick_log_params(args.rng, yearly_mean, yearly_stddev, ticks_per_year);
+                debug!(asset = a.name, weight = a.weight / total_weight, tick_mu, tick_sigma, "portfolio asset");
+                (tick_mu, tick_sigma)
+            })
+            .collect(),
+        weights: spec.assets.iter().map(|a| a.weight / total_weight).collect(),
+        fx: spec.fx.as_ref().map(|fx| {
+            let (tick_mu, tick_sigma) = tick_log_params(args.rng, fx.yearly_mean, fx.yearly_stddev, ticks_per_year);
+            debug!(tick_mu, tick_sigma, hedged = fx.hedged, "exchange rate");
+            ((tick_mu, tick_sigma), fx.hedged)
+        }),
+        foreign: spec.assets.iter().map(|a| a.foreign).collect(),
+        cholesky,
+        tail,
+        rng,
+        portable: (args.rng == RngKind::Portable).then(|| portable::Normal::new(0.0, 1.0)),
+        remaining: args.num_points,
+    })
+}
+
+/// A portfolio rebalanced to its weights at every tick and the same assets
+/// bought at the weights and held, on the same path, both starting from 1
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct RebalancingOutcome {
+    pub rebalanced: f64,
+    pub buy_and_hold: f64,
+}
+
+/// The rebalancing bonus over many paths, as the yearly return of the
+/// rebalanced portfolio above that of buy-and-hold, e.g. 0.002 for 0.2% a
+/// year. Negative bonuses are a rebalancing penalty.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct RebalancingReport {
+    pub paths: usize,
+    pub rebalanced_median: f64,
+    pub buy_and_hold_median: f64,
+    pub bonus_mean: f64,
+    pub bonus_median: f64,
+    pub bonus_p10: f64,
+    pub bonus_p90: f64,
+    /// Paths on which the rebalanced portfolio ended higher
+    pub rebalanced_wins: usize,
+}
+
+/// Simulates `paths` paths of the portfolio both rebalanced and bought and
+/// held, with path `i` seeded with `path_seed(seed, i)` of the seed in `args`
+pub fn compare_rebalancing(spec: &PortfolioSpec, args: &GenReturnsArgs, paths: usize) -> Result<Vec<RebalancingOutcome>> {
+    (0..paths)
+        .map(|i| {
+            let args = GenReturnsArgs { seed: args.seed.map(|s| path_seed(s, i)), ..*args };
+            let mut returns = portfolio_returns(spec, &args)?;
+            let weights = returns.weights().to_vec();
+            let mut holdings = weights.clone();
+            let mut rebalanced = 1.0;
+            while let Some(assets) = returns.next_assets() {
+                rebalanced *= weights.iter().zip(&assets).map(|(w, r)| w * r).sum::<f64>();
+                holdings.iter_mut().zip(&assets).for_each(|(h, r)| *h *= r);
+            }
+            Ok(RebalancingOutcome { rebalanced, buy_and_hold: holdings.iter().sum() })
+        })
+        .collect()
+}
+
+impl RebalancingReport {
+    /// The report of paths `years` long
+    pub fn of(outcomes: &[RebalancingOutcome], years: f64) -> RebalancingReport {
+        let bonuses: Vec<f64> = outcomes.iter().map(|o| (o.rebalanced / o.buy_and_hold).powf(1.0 / years) - 1.0).collect();
+        let values = |f: fn(&RebalancingOutcome) -> f64| outcomes.iter().map(f).collect::<Vec<_>>();
+        RebalancingReport {
+            paths: outcomes.len(),
+            rebalanced_median: quantile(&values(|o| o.rebalanced), 0.5),
+            buy_and_hold_median: quantile(&values(|o| o.buy_and_hold), 0.5),
+            bonus_mean: bonuses.iter().copied().collect::<Moments>().mean(),
+            bonus_median: quantile(&bonuses, 0.5),
+            bonus_p10: quantile(&bonuses, 0.1),
+            bonus_p90: quantile(&bonuses, 0.9),
+            rebalanced_wins: outcomes.iter().filter(|o| o.rebalanced > o.buy_and_hold).count(),
+        }
+    }
+
+    /// The share of paths on which the rebalanced portfolio ended higher in
+    /// percent
+    pub fn rebalanced_wins_percent(&self) -> f64 {
+        percent(self.rebalanced_wins, self.paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{cholesky, compare_rebalancing, parse_portfolio, portfolio_returns, Asset, FxLeg, RebalancingReport};
+    use crate::returns::{gen_returns, GenReturnsArgs, RngKind, TimeBase};
+
+    fn gen_args(seed: u64) -> GenReturnsArgs {
+        GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(86400),
+            num_points: 20,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(seed),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        }
+    }
+
+    #[test]
+    fn parse_portfolio_test() {
+        let spec = parse_portfolio(
+            r#"
+            num_points = 1000
+            accumulate = true
+            correlation = [[1.0, 0.6], [0.6, 1]]
+
+            [[assets]]
+            name = "stocks"
+            weight = 60
+            yearly_mean = 1.07
+
+            [[assets]]
+            name = "bonds"
+            weight = 40
+            yearly_stddev = 1.05
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Asset { name: "stocks".to_string(), weight: 60.0, yearly_mean: Some(1.07), yearly_stddev: None, foreign: false },
+                Asset { name: "bonds".to_string(), weight: 40.0, yearly_mean: None, yearly_stddev: Some(1.05), foreign: false },
+            ],
+            spec.assets
+        );
+        assert_eq!(vec![vec![1.0, 0.6], vec![0.6, 1.0]], spec.correlation);
+        assert_eq!(vec!["--num-points", "1000", "--accumulate"], spec.args);
+
+        let spec = parse_portfolio("[[assets]]\nname = \"a\"\nweight = 1\n[[assets]]\nname = \"b\"\nweight = 1").unwrap();
+        assert_eq!(vec![vec![1.0, 0.0], vec![0.0, 1.0]], spec.correlation);
+    }
+
+    #[test]
+    fn parse_portfolio_rejects_invalid_specs() {
+        let two_assets = "[[assets]]\nname = \"a\"\nweight = 1\n[[assets]]\nname = \"b\"\nweight = 1\n";
+        assert!(parse_portfolio("num_points = 10").is_err());
+        assert!(parse_portfolio("[[assets]]\nweight = 1").is_err());
+        assert!(parse_portfolio("[[assets]]\nname = \"a\"").is_err());
+        assert!(parse_portfolio("[[assets]]\nname = \"a\"\nweight = 1\nmean = 1.1").is_err());
+        assert!(parse_portfolio("[[assets]]\nname = \"a\"\nweight = 0").is_err());
+        assert!(parse_portfolio("[[assets]]\nname = \"a\"\nweight = 1\nyearly_stddev = 0.5").is_err());
+        assert!(parse_portfolio("[[assets]]\nname = \"a\"\nweight = 1\nyearly_mean = -1").is_err());
+        assert!(parse_portfolio(&format!("correlation = [[1.0]]\n{}", two_assets)).is_err());
+        assert!(parse_portfolio(&format!("correlation = [[1, 0.5], [0.4, 1]]\n{}", two_assets)).is_err());
+        assert!(parse_portfolio(&format!("correlation = [[1, 2], [2, 1]]\n{}", two_assets)).is_err());
+        assert!(parse_portfolio(&format!("correlation = [[2, 0], [0, 1]]\n{}", two_assets)).is_err());
+    }
+
+    #[test]
+    fn parse_fx_test() {
+        let foreign = "[[assets]]\nname = \"a\"\nweight = 1\nforeign = true\n";
+        let spec = parse_portfolio(&format!("{}[fx]\nyearly_stddev = 1.1\ncorrelation = [-0.3]", foreign)).unwrap();
+        assert!(spec.assets[0].foreign);
+        assert_eq!(Some(FxLeg { yearly_mean: 1.0, yearly_stddev: 1.1, correlation: vec![-0.3], hedged: false }), spec.fx);
+        assert!(parse_portfolio(foreign).is_err());
+        assert!(parse_portfolio(&format!("{}[fx]\nyearly_mean = 1.1", foreign)).is_err());
+        assert!(parse_portfolio(&format!("{}[fx]\nyearly_stddev = 1.1\ncorrelation = [0.1, 0.2]", foreign)).is_err());
+        assert!(parse_portfolio(&format!("{}[fx]\nyearly_stddev = 1.1\nhedged = 1", foreign)).is_err());
+        // Perfectly correlated with each other but not with the exchange rate
+        let two = "correlation = [[1, 1], [1, 1]]\n[[assets]]\nname = \"a\"\nweight = 1\n[[assets]]\nname = \"b\"\nweight = 1\n";
+        assert!(parse_portfolio(&format!("{}[fx]\nyearly_stddev = 1.1\ncorrelation = [0.5, -0.5]", two)).is_err());
+    }
+
+    #[test]
+    fn fx_converts_foreign_returns() {
+        let spec = |fx: &str| parse_portfolio(&format!("[[assets]]\nname = \"a\"\nweight = 1\nforeign = true\n[fx]\n{}", fx)).unwrap();
+        let returns = |fx: &str| portfolio_returns(&spec(fx), &gen_args(3)).unwrap().collect::<Vec<f64>>();
+        // An exchange rate without volatility or drift changes nothing
+        let hedged = returns("yearly_stddev = 1.5\nhedged = true");
+        for (h, f) in hedged.iter().zip(returns("yearly_stddev = 1")) {
+            assert_approx_eq!(h, f, 1e-12);
+        }
+        // A currency that moves exactly against the asset cancels its volatility
+        let cancelled = returns("yearly_stddev = 1.5\ncorrelation = [-1]");
+        assert_eq!(20, cancelled.len());
+        assert!(cancelled.iter().all(|r| (r - cancelled[0]).abs() < 1e-12));
+        assert!(hedged.iter().any(|r| (r - hedged[0]).abs() > 1e-3));
+    }
+
+    #[test]
+    fn cholesky_test() {
+        let l = cholesky(&[vec![1.0, 0.6], vec![0.6, 1.0]]).unwrap();
+        assert_approx_eq!(1.0, l[0][0]);
+        assert_approx_eq!(0.6, l[1][0]);
+        assert_approx_eq!(0.8, l[1][1]);
+        assert_eq!(0.0, l[0][1]);
+
+        // Perfectly correlated assets are fine, impossible correlations are not
+        let m = [vec![1.0, 1.0, 0.5], vec![1.0, 1.0, 0.5], vec![0.5, 0.5, 1.0]];
+        assert!(cholesky(&m).is_ok());
+        let m = [vec![1.0, 0.9, -0.9], vec![0.9, 1.0, 0.9], vec![-0.9, 0.9, 1.0]];
+        assert!(cholesky(&m).is_err());
+    }
+
+    #[test]
+    fn single_asset_portfolio_matches_gen_returns() {
+        let spec = parse_portfolio("[[assets]]\nname = \"a\"\nweight = 2").unwrap();
+        for rng in [RngKind::Std, RngKind::Portable] {
+            let args = GenReturnsArgs { rng, ..gen_args(7) };
+            let portfolio: Vec<f64> = portfolio_returns(&spec, &args).unwrap().collect();
+            let single: Vec<f64> = gen_returns(&args).unwrap().collect();
+            assert_eq!(20, portfolio.len());
+            for (p, s) in portfolio.iter().zip(single) {
+                assert_approx_eq!(s, p, 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn tail_df_gives_heavy_tailed_correlated_shocks() {
+        let two = "correlation = [[1, 1], [1, 1]]\n[[assets]]\nname = \"a\"\nweight = 1\n[[assets]]\nname = \"b\"\nweight = 1\n";
+        let spec = parse_portfolio(two).unwrap();
+        let args = GenReturnsArgs { num_points: 20000, ..gen_args(11) };
+        let heavy = GenReturnsArgs { tail_df: Some(3.0), ..args };
+        let kurtosis = |args: &GenReturnsArgs| {
+            let logs: Vec<f64> = portfolio_returns(&spec, args).unwrap().map(f64::ln).collect();
+            let n = logs.len() as f64;
+            let mean = logs.iter().sum::<f64>() / n;
+            let m2 = logs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+            let m4 = logs.iter().map(|x| (x - mean).powi(4)).sum::<f64>() / n;
+            (m2.sqrt(), m4 / (m2 * m2))
+        };
+        let (normal_sigma, normal_kurtosis) = kurtosis(&args);
+        let (heavy_sigma, heavy_kurtosis) = kurtosis(&heavy);
+        assert!((normal_kurtosis - 3.0).abs() < 0.3);
+        assert!(heavy_kurtosis > 6.0);
+        assert_approx_eq!(normal_sigma, heavy_sigma, normal_sigma * 0.1);
+
+        // Perfectly correlated assets still move together
+        let mut returns = portfolio_returns(&spec, &heavy).unwrap();
+        while let Some(assets) = returns.next_assets() {
+            assert_approx_eq!(assets[0], assets[1], 1e-12);
+        }
+        let portable = GenReturnsArgs { rng: RngKind::Portable, ..heavy };
+        assert!(portfolio_returns(&spec, &portable).is_err());
+    }
+
+    #[test]
+    fn rebalancing_bonus_of_uncorrelated_assets() {
+        let two = |correlation: f64| {
+            parse_portfolio(&format!(
+                "correlation = [[1, {0}], [{0}, 1]]\n[[assets]]\nname = \"a\"\nweight = 1\n[[assets]]\nname = \"b\"\nweight = 1",
+                correlation
+            ))
+            .unwrap()
+        };
+        let args = GenReturnsArgs { interval_seconds: Some(31556952), num_points: 30, ..gen_args(5) };
+        let outcomes = compare_rebalancing(&two(0.0), &args, 200).unwrap();
+        assert_eq!(200, outcomes.len());
+        // Rebalancing between equally good, uncorrelated and volatile assets
+        // harvests their volatility on most paths
+        let report = RebalancingReport::of(&outcomes, 30.0);
+        assert!(report.bonus_median > 0.01);
+        assert!(report.rebalanced_wins_percent() > 50.0);
+        assert!(report.bonus_p10 < report.bonus_median && report.bonus_median < report.bonus_p90);
+
+        // Assets moving together never drift from their weights
+        for o in compare_rebalancing(&two(1.0), &args, 20).unwrap() {
+            assert_approx_eq!(o.rebalanced, o.buy_and_hold, 1e-9);
+        }
+        let rebalanced: Vec<f64> = compare_rebalancing(&two(0.0), &args, 3).unwrap().iter().map(|o| o.rebalanced).collect();
+        let portfolio: Vec<f64> = (0..3)
+            .map(|i| {
+                let args = GenReturnsArgs { seed: Some(crate::model::path_seed(5, i)), ..args };
+                portfolio_returns(&two(0.0), &args).unwrap().product()
+            })
+            .collect();
+        for (r, p) in rebalanced.iter().zip(portfolio) {
+            assert_approx_eq!(r, p, 1e-9);
+        }
+    }
+}
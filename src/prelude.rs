@@ -0,0 +1,24 @@
+//! The stable API in one import: `use finsim::prelude::*;`
+//!
+//! Everything here follows semver. Items outside the prelude are public too,
+//! but only those that are documented are covered; the modules behind the
+//! command line (`config`, `repl`, `watch`) are hidden and may change in any
+//! release.
+
+pub use crate::calibrate::{Calibration, calibrate};
+pub use crate::checks::{Check, CheckArgs};
+pub use crate::error::{Error, Result};
+#[cfg(feature = "parallel")]
+pub use crate::model::par_monte_carlo;
+pub use crate::model::{LogNormal, ReturnModel, monte_carlo, path_seed};
+pub use crate::output::Summary;
+pub use crate::preset::Preset;
+pub use crate::pricing::{Barrier, BarrierKind, Estimate, OptionArgs, OptionKind, OptionStyle};
+pub use crate::returns::{
+    AccumulateArgs, Accumulator, DataPoint, Financing, Float, GenReturnsArgs, Interval, RngKind, RngState, SeededRng, TimeBase,
+    accumulate, accumulate_iter, data_points, gen_log_returns, gen_returns, gen_returns_as, gen_returns_with,
+    resume_returns,
+};
+pub use crate::simulation::{Leverage, Simulation, SimulationBuilder};
+pub use crate::strategy::{Allocation, ConstantLeverage, Strategy, TickState, accumulate_with};
+pub use crate::transform::ReturnsExt;
@@ -0,0 +1,191 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, env_var_name};
+use crate::error::{Error, Result};
+
+/// Built-in yearly mean and stddev of common asset classes, rough long run
+/// nominal figures. The crypto presets beyond bitcoin also draw heavier
+/// tailed returns and count wall-clock years, since crypto trades around the
+/// clock.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// US stock market: mean 1.08, stddev 1.17
+    UsEquities,
+    /// World stock market: mean 1.07, stddev 1.16
+    GlobalEquities,
+    /// Investment grade bonds: mean 1.035, stddev 1.06
+    GlobalBonds,
+    /// Gold: mean 1.05, stddev 1.16
+    Gold,
+    /// Bitcoin: mean 1.3, stddev 1.8
+    Bitcoin,
+    /// Ether: mean 1.3, stddev 2.0, tails of Student's t with 4 degrees of
+    /// freedom
+    Ethereum,
+    /// Smaller cryptocurrencies: mean 1.1, stddev 3.0, tails of Student's t
+    /// with 3 degrees of freedom
+    Altcoins,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 7] = [
+        Preset::UsEquities,
+        Preset::GlobalEquities,
+        Preset::GlobalBonds,
+        Preset::Gold,
+        Preset::Bitcoin,
+        Preset::Ethereum,
+        Preset::Altcoins,
+    ];
+
+    /// The name of the preset, as given to `--preset`
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::UsEquities => "us-equities",
+            Preset::GlobalEquities => "global-equities",
+            Preset::GlobalBonds => "global-bonds",
+            Preset::Gold => "gold",
+            Preset::Bitcoin => "bitcoin",
+            Preset::Ethereum => "ethereum",
+            Preset::Altcoins => "altcoins",
+        }
+    }
+
+    /// The yearly (geometric) mean and stddev of the preset
+    pub fn params(self) -> (f64, f64) {
+        match self {
+            Preset::UsEquities => (1.08, 1.17),
+            Preset::GlobalEquities => (1.07, 1.16),
+            Preset::GlobalBonds => (1.035, 1.06),
+            Preset::Gold => (1.05, 1.16),
+            Preset::Bitcoin => (1.3, 1.8),
+            Preset::Ethereum => (1.3, 2.0),
+            Preset::Altcoins => (1.1, 3.0),
+        }
+    }
+
+    /// Degrees of freedom of the Student's t log returns of the preset, see
+    /// `GenReturnsArgs::tail_df`, `None` for normal ones
+    pub fn tail_df(self) -> Option<f64> {
+        match self {
+            Preset::Ethereum => Some(4.0),
+            Preset::Altcoins => Some(3.0),
+            _ => None,
+        }
+    }
+
+    /// The preset as command line flags. Flags whose environment variable is
+    /// set are left out, since the environment takes precedence over presets.
+    pub fn to_args(self) -> Vec<String> {
+        let (mean, stddev) = self.params();
+        let mut flags = vec![("yearly-mean", mean.to_string()), ("yearly-stddev", stddev.to_string())];
+        if let Some(df) = self.tail_df() {
+            flags.extend([("tail-df", df.to_string()), ("time-base", "wall-clock".to_string())]);
+        }
+        flags
+            .into_iter()
+            .filter(|(flag, _)| env::var_os(env_var_name(flag)).is_none())
+            .flat_map(|(flag, value)| [format!("--{}", flag), value])
+            .collect()
+    }
+}
+
+impl FromStr for Preset {
+    type Err = Error;
+
+    /// Parses the name of a preset, ignoring case
+    fn from_str(s: &str) -> Result<Self> {
+        Preset::ALL.into_iter().find(|p| p.name().eq_ignore_ascii_case(s)).ok_or_else(|| {
+            let names: Vec<&str> = Preset::ALL.iter().map(|p| p.name()).collect();
+            Error::Parse(format!("unknown preset '{}', expected one of {}", s, names.join(", ")))
+        })
+    }
+}
+
+/// Replaces `--preset <name>` (or `--preset=<name>`) in `args[at..]` with the
+/// flags of the preset, inserted at `at` so that flags given explicitly win
+/// with `args_override_self`. The last preset wins if there are several.
+/// Returns whether there was a preset.
+#[doc(hidden)] // Command line plumbing, not part of the stable API
+pub fn expand_preset<T: AsRef<OsStr> + From<String>>(args: &mut Vec<T>, at: usize) -> Result<bool> {
+    let mut preset = None;
+    let mut i = at;
+    while i < args.len() {
+        let arg = args[i].as_ref().to_str();
+        let (len, name) = match arg {
+            Some("--preset") if i + 1 < args.len() => (2, args[i + 1].as_ref().to_string_lossy().to_string()),
+            Some(a) if a.starts_with("--preset=") => (1, a["--preset=".len()..].to_string()),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        preset = Some(name.parse::<Preset>()?);
+        args.drain(i..i + len);
+    }
+    let Some(preset) = preset else {
+        return Ok(false);
+    };
+    args.splice(at..at, preset.to_args().into_iter().map(T::from));
+    Ok(true)
+}
+
+/// Expands `--preset <name>` on the command line, or the `FINSIM_PRESET`
+/// environment variable, into the flags of the preset right after the
+/// subcommand. Run after `config::expand_config_args`, so that a config file
+/// overrides the preset and can name one itself.
+#[doc(hidden)] // Command line plumbing, not part of the stable API
+pub fn expand_preset_args(mut args: Vec<OsString>) -> Result<Vec<OsString>> {
+    let at = (config::subcommand_index(&args) + 1).min(args.len());
+    if expand_preset(&mut args, at)? {
+        return Ok(args);
+    }
+    if let Some(name) = env::var_os(env_var_name("preset")) {
+        let preset = name.to_string_lossy().parse::<Preset>()?;
+        args.splice(at..at, preset.to_args().into_iter().map(OsString::from));
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsString;
+
+    use super::{Preset, expand_preset, expand_preset_args};
+
+    #[test]
+    fn expand_preset_test() {
+        let mut args = vec!["--seed".to_string(), "1".to_string(), "--preset".to_string(), "gold".to_string()];
+        assert!(expand_preset(&mut args, 0).unwrap());
+        assert_eq!(vec!["--yearly-mean", "1.05", "--yearly-stddev", "1.16", "--seed", "1"], args);
+        assert!(!expand_preset(&mut args, 0).unwrap());
+
+        let mut args: Vec<String> =
+            ["--preset=gold", "-n", "1", "--preset=bitcoin"].into_iter().map(String::from).collect();
+        assert!(expand_preset(&mut args, 0).unwrap());
+        assert_eq!(vec!["--yearly-mean", "1.3", "--yearly-stddev", "1.8", "-n", "1"], args);
+
+        let mut args = vec!["--preset=nope".to_string()];
+        assert!(expand_preset(&mut args, 0).unwrap_err().to_string().contains("us-equities, global-equities"));
+        assert_eq!((1.3, 1.8), Preset::Bitcoin.params());
+        assert_eq!(Preset::GlobalBonds, "Global-Bonds".parse().unwrap());
+        let expected = ["--yearly-mean", "1.1", "--yearly-stddev", "3", "--tail-df", "3", "--time-base", "wall-clock"];
+        assert_eq!(expected.map(String::from).to_vec(), Preset::Altcoins.to_args());
+    }
+
+    #[test]
+    fn expand_preset_args_test() {
+        let args: Vec<OsString> = ["finsim", "-q", "mc", "--yearly-mean", "1.1", "--preset", "bitcoin"]
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+        let expanded = expand_preset_args(args).unwrap();
+        let expected = ["finsim", "-q", "mc", "--yearly-mean", "1.3", "--yearly-stddev", "1.8", "--yearly-mean", "1.1"];
+        assert_eq!(expected.map(OsString::from).to_vec(), expanded);
+    }
+}
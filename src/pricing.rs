@@ -0,0 +1,297 @@
+//! Monte Carlo pricing of options on a simulated underlying. The paths drift
+//! at the risk-free rate, the risk-neutral measure under which an option is
+//! worth its discounted expected payoff, so the price is the mean of the
+//! discounted payoffs of many paths. The payoffs only need a path's
+//! `Summary`: its last, mean, lowest and highest price.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::output::Summary;
+use crate::returns::{AccumulateArgs, GenReturnsArgs, geometric_mean};
+use crate::stats::Moments;
+
+/// Call or put
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OptionKind {
+    /// The right to buy at the strike
+    Call,
+    /// The right to sell at the strike
+    Put,
+}
+
+/// Which price of the underlying the strike is compared to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OptionStyle {
+    /// The price at expiry
+    #[default]
+    European,
+    /// The arithmetic average of the prices at every point of the path
+    Asian,
+}
+
+/// Whether a barrier is crossed from below or above, and whether crossing
+/// it activates or cancels the option
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BarrierKind {
+    UpAndOut,
+    UpAndIn,
+    DownAndOut,
+    DownAndIn,
+}
+
+/// A barrier, monitored at t=0 and at every point of the path
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Barrier {
+    pub kind: BarrierKind,
+    pub level: f64,
+}
+
+/// An option on the underlying of a `GenReturnsArgs`, expiring at the end of
+/// its series
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OptionArgs {
+    pub kind: OptionKind,
+    #[serde(default)]
+    pub style: OptionStyle,
+    #[serde(default)]
+    pub barrier: Option<Barrier>,
+    pub strike: f64,
+    /// The price of the underlying at t=0
+    pub spot: f64,
+    /// The risk-free yearly return as a factor, e.g. 1.04 for 4%
+    pub yearly_rate: f64,
+}
+
+/// A Monte Carlo price and its standard error
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Estimate {
+    pub price: f64,
+    pub std_error: f64,
+    pub paths: usize,
+}
+
+impl OptionArgs {
+    pub fn validate(&self) -> Result<()> {
+        if !self.strike.is_finite() || self.strike < 0.0 {
+            return Err(Error::Invalid(format!("--strike must be a non-negative number, got {}", self.strike)));
+        }
+        if !self.spot.is_finite() || self.spot <= 0.0 {
+            return Err(Error::Invalid(format!("--spot must be a positive number, got {}", self.spot)));
+        }
+        if !self.yearly_rate.is_finite() || self.yearly_rate <= 0.0 {
+            return Err(Error::Invalid(format!("--rate must be above -100%, got {}%", (self.yearly_rate - 1.0) * 100.0)));
+        }
+        if let Some(level) = self.barrier.map(|b| b.level).filter(|l| !l.is_finite() || *l <= 0.0) {
+            return Err(Error::Invalid(format!("--barrier must be a positive number, got {}", level)));
+        }
+        Ok(())
+    }
+
+    /// `gen_args` with the yearly mean replaced by the risk-neutral one: the
+    /// geometric mean of an expected yearly return of `yearly_rate`. Only
+    /// lognormal prices have one, the exponential of Student's t log returns
+    /// has no expectation, so a finite `tail_df` is an error.
+    pub fn risk_neutral(&self, gen_args: &GenReturnsArgs) -> Result<GenReturnsArgs> {
+        if gen_args.tail_df.is_some_and(f64::is_finite) {
+            return Err(Error::Invalid("--tail-df has no risk-neutral drift, so options can't be priced with it".to_string()));
+        }
+        Ok(GenReturnsArgs {
+            yearly_mean: geometric_mean(self.yearly_rate, gen_args.yearly_stddev),
+            ..gen_args.clone()
+        })
+    }
+
+    /// Accumulation of the returns into prices of the underlying
+    pub fn acc_args(&self) -> AccumulateArgs {
+        AccumulateArgs {
+            accumulate: true,
+            start_value: self.spot,
+            continuous_leverage: None,
+            pointwise_leverage: None,
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        }
+    }
+
+    /// The payoff at expiry of a path of prices of the underlying. A path
+    /// without points stays at the spot.
+    pub fn payoff(&self, path: &Summary) -> f64 {
+        if path.count == 0 {
+            return self.payoff(&Summary::of(&[self.spot]));
+        }
+        let price = match self.style {
+            OptionStyle::European => path.last,
+            OptionStyle::Asian => path.mean,
+        };
+        let payoff = match self.kind {
+            OptionKind::Call => (price - self.strike).max(0.0),
+            OptionKind::Put => (self.strike - price).max(0.0),
+        };
+        let Some(barrier) = self.barrier else {
+            return payoff;
+        };
+        let (crossed, knocks_in) = match barrier.kind {
+            BarrierKind::UpAndOut => (path.max.max(self.spot) >= barrier.level, false),
+            BarrierKind::UpAndIn => (path.max.max(self.spot) >= barrier.level, true),
+            BarrierKind::DownAndOut => (path.min.min(self.spot) <= barrier.level, false),
+            BarrierKind::DownAndIn => (path.min.min(self.spot) <= barrier.level, true),
+        };
+        if crossed == knocks_in { payoff } else { 0.0 }
+    }
+
+    /// The mean discounted payoff of the paths, which should be simulated
+    /// with `risk_neutral` and `acc_args`
+    pub fn price(&self, gen_args: &GenReturnsArgs, paths: &[Summary]) -> Estimate {
+        let discount = self.yearly_rate.powf(-years(gen_args));
+        let payoffs: Moments = paths.iter().map(|p| discount * self.payoff(p)).collect();
+        Estimate {
+            price: payoffs.mean(),
+            std_error: (payoffs.variance() / payoffs.count() as f64).sqrt(),
+            paths: payoffs.count(),
+        }
+    }
+
+    /// The Black-Scholes price, which `price` converges to, for European
+    /// options without a barrier
+    pub fn black_scholes(&self, gen_args: &GenReturnsArgs) -> Option<f64> {
+        if self.style != OptionStyle::European || self.barrier.is_some() {
+            return None;
+        }
+        let years = years(gen_args);
+        let rate = self.yearly_rate.ln();
+        let sigma = gen_args.yearly_stddev.ln() * years.sqrt();
+        let strike = self.strike * (-rate * years).exp();
+        let (call, put) = if sigma == 0.0 || strike == 0.0 {
+            ((self.spot - strike).max(0.0), (strike - self.spot).max(0.0))
+        } else {
+            let d1 = (self.spot / strike).ln() / sigma + sigma / 2.0;
+            let d2 = d1 - sigma;
+            (
+                self.spot * normal_cdf(d1) - strike * normal_cdf(d2),
+                strike * normal_cdf(-d2) - self.spot * normal_cdf(-d1),
+            )
+        };
+        Some(match self.kind {
+            OptionKind::Call => call,
+            OptionKind::Put => put,
+        })
+    }
+}
+
+/// Time to expiry in years of the time base of `gen_args`
+pub fn years(gen_args: &GenReturnsArgs) -> f64 {
+    gen_args.derived().total_seconds / gen_args.time_base.seconds_per_year()
+}
+
+fn normal_cdf(x: f64) -> f64 {
+    libm::erfc(-x / std::f64::consts::SQRT_2) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{Barrier, BarrierKind, OptionArgs, OptionKind, OptionStyle};
+    use crate::model::{LogNormal, monte_carlo};
+    use crate::output::Summary;
+    use crate::returns::{GenReturnsArgs, RngKind, TimeBase};
+
+    fn european(kind: OptionKind, strike: f64, yearly_rate: f64) -> OptionArgs {
+        OptionArgs { kind, style: OptionStyle::European, barrier: None, strike, spot: 100.0, yearly_rate }
+    }
+
+    fn simulate(option: &OptionArgs, paths: usize) -> (GenReturnsArgs, Vec<Summary>) {
+        let gen_args = option.risk_neutral(&gen_args()).unwrap();
+        let model = LogNormal::from(&gen_args);
+        let acc_args = option.acc_args();
+        let paths = monte_carlo(&model, gen_args.tick_interval_seconds(), 12, &acc_args, None, paths, Some(1));
+        (gen_args, paths.collect::<Result<_, _>>().unwrap())
+    }
+
+    fn gen_args() -> GenReturnsArgs {
+        GenReturnsArgs {
+            total_seconds: Some(365 * 86400),
+            interval_seconds: None,
+            num_points: 12,
+            yearly_mean: 1.5,
+            yearly_stddev: 1.2,
+            seed: Some(1),
+            rng: RngKind::Std,
+            time_base: TimeBase::Days365,
+            tail_df: None,
+        }
+    }
+
+    #[test]
+    fn black_scholes_reference() {
+        // S = 100, K = 100, r = 5% continuous, sigma = 20%, T = 1: 10.4506 and 5.5735
+        let call = european(OptionKind::Call, 100.0, 0.05f64.exp());
+        let gen_args = GenReturnsArgs { yearly_stddev: 0.2f64.exp(), ..gen_args() };
+        assert_approx_eq!(10.4506, call.black_scholes(&gen_args).unwrap(), 1e-4);
+        let put = OptionArgs { kind: OptionKind::Put, ..call };
+        assert_approx_eq!(5.5735, put.black_scholes(&gen_args).unwrap(), 1e-4);
+        assert_eq!(None, OptionArgs { style: OptionStyle::Asian, ..put }.black_scholes(&gen_args));
+    }
+
+    #[test]
+    fn monte_carlo_converges_to_black_scholes() {
+        for kind in [OptionKind::Call, OptionKind::Put] {
+            let option = european(kind, 105.0, 1.03);
+            let (gen_args, paths) = simulate(&option, 20_000);
+            let estimate = option.price(&gen_args, &paths);
+            assert_eq!(20_000, estimate.paths);
+            assert!(estimate.std_error > 0.0 && estimate.std_error < 0.2);
+            assert!((estimate.price - option.black_scholes(&gen_args).unwrap()).abs() < 4.0 * estimate.std_error);
+        }
+    }
+
+    #[test]
+    fn expired_option_is_worth_its_intrinsic_value() {
+        let option = european(OptionKind::Put, 105.0, 1.03);
+        let gen_args = GenReturnsArgs { num_points: 0, ..gen_args() };
+        assert_eq!(Some(5.0), option.black_scholes(&gen_args));
+        let empty = Summary::of::<f64>(&[]);
+        assert_eq!(5.0, option.price(&gen_args, &[empty, empty]).price);
+        assert!(OptionArgs { spot: 0.0, ..option }.validate().is_err());
+    }
+
+    #[test]
+    fn heavy_tails_have_no_risk_neutral_drift() {
+        let option = european(OptionKind::Call, 100.0, 1.03);
+        assert!(option.risk_neutral(&GenReturnsArgs { tail_df: Some(4.0), ..gen_args() }).is_err());
+        let normal = option.risk_neutral(&GenReturnsArgs { tail_df: Some(f64::INFINITY), ..gen_args() }).unwrap();
+        assert_eq!(option.risk_neutral(&gen_args()).unwrap().yearly_mean, normal.yearly_mean);
+    }
+
+    #[test]
+    fn path_dependent_payoffs() {
+        let call = european(OptionKind::Call, 100.0, 1.03);
+        let (gen_args, paths) = simulate(&call, 2_000);
+        let price = |option: &OptionArgs| option.price(&gen_args, &paths).price;
+        let barrier = |kind, level| OptionArgs { barrier: Some(Barrier { kind, level }), ..call.clone() };
+
+        // Averaging dampens the volatility the option is worth
+        let asian = OptionArgs { style: OptionStyle::Asian, ..call.clone() };
+        assert!(price(&asian) > 0.0 && price(&asian) < price(&call));
+
+        // A knock-in and a knock-out at the same level add up to the option
+        for (out, into) in [(BarrierKind::UpAndOut, BarrierKind::UpAndIn), (BarrierKind::DownAndOut, BarrierKind::DownAndIn)] {
+            for level in [80.0, 100.0, 120.0] {
+                for (path, vanilla) in paths.iter().zip(paths.iter().map(|p| call.payoff(p))) {
+                    assert_eq!(vanilla, barrier(out, level).payoff(path) + barrier(into, level).payoff(path));
+                }
+            }
+        }
+        assert_eq!(price(&call), price(&barrier(BarrierKind::UpAndOut, 1e9)));
+        assert!(price(&barrier(BarrierKind::UpAndOut, 120.0)) < price(&call));
+        // Starting at or beyond the barrier crosses it at t=0
+        assert_eq!(0.0, price(&barrier(BarrierKind::DownAndOut, 100.0)));
+        assert!(barrier(BarrierKind::UpAndIn, -1.0).validate().is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{self, BufRead, Write};
+
+use crate::config;
+use crate::error::{Error, Result};
+
+/// Flags a session starts with
+const DEFAULT_FLAGS: [(&str, &str); 3] = [("num_points", "252"), ("interval_seconds", "1d"), ("accumulate", "true")];
+
+const HELP: &str = "\
+<flag> <value>  set a flag (also <flag>=<value>), e.g. `yearly_mean 1.1`, and re-run
+unset <flag>    remove a flag and re-run
+show            print the current flags
+run             re-run (an empty line does too)
+help            print this help
+quit            leave (also exit or end of input)";
+
+/// The flags of an interactive session, in the format of a config file
+pub struct Session {
+    flags: toml::Table,
+}
+
+impl Default for Session {
+    fn default() -> Session {
+        let mut session = Session::new(toml::Table::new());
+        for (key, value) in DEFAULT_FLAGS {
+            session.set(key, value);
+        }
+        session
+    }
+}
+
+impl Session {
+    pub fn new(flags: toml::Table) -> Session {
+        Session { flags }
+    }
+
+    /// Adds (or overrides) the flags from a config file
+    pub fn load(&mut self, config: &str) -> Result<()> {
+        let table = config.parse::<toml::Table>().map_err(|e| Error::Parse(e.to_string()))?;
+        self.flags.extend(table.into_iter().map(|(k, v)| (k.replace('-', "_"), v)));
+        Ok(())
+    }
+
+    /// The flags as command line arguments
+    pub fn args(&self) -> Result<Vec<String>> {
+        config::table_to_args(self.flags.clone())
+    }
+
+    /// Sets a flag, interpreting the value like a config file would and as
+    /// a string if it isn't valid TOML (so `1d` needs no quotes)
+    pub fn set(&mut self, key: &str, value: &str) {
+        let value = format!("value = {}", value)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut t| t.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+        self.flags.insert(key.replace('-', "_"), value);
+    }
+
+    pub fn unset(&mut self, key: &str) -> bool {
+        self.flags.remove(&key.replace('-', "_")).is_some()
+    }
+}
+
+/// Reads commands from `input` until `quit` or the end of input, calling
+/// `run` with the session's flags after every change. Errors from `run` are
+/// printed and leave the session running.
+pub fn repl(
+    session: &mut Session,
+    input: impl BufRead,
+    out: &mut impl Write,
+    mut run: impl FnMut(&[String], &mut dyn Write) -> Result<()>,
+) -> io::Result<()> {
+    let mut rerun = |session: &Session, out: &mut dyn Write| -> io::Result<()> {
+        if let Err(e) = session.args().and_then(|args| run(&args, out)) {
+            writeln!(out, "error: {}", e.to_string().trim_end())?;
+        }
+        Ok(())
+    };
+    rerun(session, out)?;
+    let mut lines = input.lines();
+    loop {
+        write!(out, "finsim> ")?;
+        out.flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            writeln!(out)?;
+            return Ok(());
+        };
+        let line = line.trim();
+        let (command, rest) = line.split_once(['=', ' ']).map_or((line, ""), |(c, r)| (c.trim(), r.trim()));
+        match (command, rest) {
+            ("quit" | "exit", _) => return Ok(()),
+            ("help", _) => writeln!(out, "{}", HELP)?,
+            ("show", _) => {
+                for (key, value) in &session.flags {
+                    writeln!(out, "{} = {}", key, value)?;
+                }
+            }
+            ("" | "run", _) => rerun(session, out)?,
+            ("unset", key) if !key.is_empty() => {
+                if session.unset(key) {
+                    rerun(session, out)?;
+                } else {
+                    writeln!(out, "error: '{}' is not set", key)?;
+                }
+            }
+            (key, value) if !value.is_empty() => {
+                session.set(key, value);
+                rerun(session, out)?;
+            }
+            _ => writeln!(out, "error: unknown command '{}', try help", line)?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{repl, Session};
+
+    #[test]
+    fn session_set_and_unset() {
+        let mut session = Session::new(toml::Table::new());
+        session.set("num-points", "10");
+        session.set("interval_seconds", "1d");
+        session.set("accumulate", "true");
+        session.set("yearly_mean", "1.1");
+        assert_eq!(
+            vec!["--num-points", "10", "--interval-seconds", "1d", "--accumulate", "--yearly-mean", "1.1"],
+            session.args().unwrap()
+        );
+        session.set("accumulate", "false");
+        assert!(session.unset("yearly_mean"));
+        assert!(!session.unset("yearly_mean"));
+        assert_eq!(vec!["--num-points", "10", "--interval-seconds", "1d"], session.args().unwrap());
+    }
+
+    #[test]
+    fn session_defaults_and_load() {
+        let mut session = Session::default();
+        session.load("num-points = 10\nseed = 1").unwrap();
+        assert_eq!(
+            vec!["--num-points", "10", "--interval-seconds", "1d", "--accumulate", "--seed", "1"],
+            session.args().unwrap()
+        );
+        assert!(session.load("seed = ").is_err());
+    }
+
+    #[test]
+    fn repl_reruns_after_changes() {
+        let mut session = Session::new(toml::Table::new());
+        let input = "seed 1\nbogus\nunset seed\nunset seed\n\nshow\nquit\nseed 2\n";
+        let mut out = Vec::new();
+        let mut runs = Vec::new();
+        repl(&mut session, input.as_bytes(), &mut out, |args, w| {
+            runs.push(args.to_vec());
+            writeln!(w, "ran").unwrap();
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(vec![vec![], vec!["--seed".to_string(), "1".to_string()], vec![], vec![]], runs);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("error: unknown command 'bogus'"));
+        assert!(out.contains("error: 'seed' is not set"));
+        assert_eq!(4, out.matches("ran\n").count());
+    }
+}
@@ -0,0 +1,379 @@
+//! A retirement plan: saving until a retirement date, then living off the
+//! savings. Contributions and withdrawals are in today's money and grow with
+//! the simulated inflation, and a path succeeds if the savings last until
+//! the end of the retirement. The contributions are either a fixed amount or
+//! a share of a salary that grows faster than prices, by a fixed or random
+//! real raise. At retirement part of the savings can buy an annuity, whose
+//! fixed payout covers part of the withdrawals for the rest of the plan.
+
+use rand_distr::{Distribution, StandardNormal};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::inflation::InflationArgs;
+use crate::model::{ReturnModel, path_seed};
+use crate::portable;
+use crate::returns::{GenReturnsArgs, RngKind, SeededRng};
+use crate::stats::{percent, quantile};
+
+/// The phases of a retirement plan, in ticks of the simulated series
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RetireArgs {
+    /// Savings at t=0
+    pub balance: f64,
+    /// Saved at the end of every tick before retirement, in today's money,
+    /// unless saving a share of `salary`
+    pub contribution: f64,
+    #[serde(default)]
+    pub salary: Option<SalaryArgs>,
+    /// Withdrawn at the end of every tick after retirement, in today's money
+    pub withdrawal: f64,
+    /// Ticks until retirement
+    pub accumulation_ticks: usize,
+    /// Ticks the savings have to last from retirement
+    pub retirement_ticks: usize,
+    /// The inflation the contributions and withdrawals grow with
+    pub inflation: InflationArgs,
+    #[serde(default)]
+    pub annuity: Option<AnnuityArgs>,
+}
+
+/// An annuity bought with part of the savings at retirement
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AnnuityArgs {
+    /// Share of the savings at retirement spent on the annuity, e.g. 0.3 for
+    /// 30%
+    pub share: f64,
+    /// Paid at the end of every tick in retirement as a share of the price
+    /// of the annuity. The payout is fixed and loses value with inflation.
+    pub payout_rate: f64,
+}
+
+/// A salary until retirement, a share of which is saved instead of a fixed
+/// contribution
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SalaryArgs {
+    /// Paid at the end of the first tick, in today's money
+    pub start: f64,
+    /// Share of the salary saved, e.g. 0.15 for 15%
+    pub savings_rate: f64,
+    /// Yearly real raise as a factor, e.g. 1.01 for 1% above inflation
+    pub yearly_growth: f64,
+    /// Standard deviation of the yearly log raise, 0 for a fixed raise
+    pub yearly_vol: f64,
+}
+
+/// One path of a plan, with the savings in today's money
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Outcome {
+    pub at_retirement: f64,
+    /// 0 if the savings ran out
+    pub at_end: f64,
+    /// Ticks after retirement until the savings ran out, `None` if they
+    /// lasted
+    pub depleted_after: Option<usize>,
+    /// Payout of the annuity every tick in the money of t=0 at retirement, 0
+    /// without one
+    pub annuity_payout: f64,
+}
+
+/// The outcomes of a plan over many paths
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct RetireReport {
+    pub paths: usize,
+    /// Paths on which the savings lasted
+    pub successes: usize,
+    pub at_retirement_median: f64,
+    pub at_end_median: f64,
+    pub at_end_p10: f64,
+    pub annuity_payout_median: f64,
+}
+
+impl RetireArgs {
+    pub fn validate(&self) -> Result<()> {
+        for (flag, amount) in [("--balance", self.balance), ("--contribution", self.contribution), ("--withdrawal", self.withdrawal)] {
+            if !amount.is_finite() || amount < 0.0 {
+                return Err(Error::Invalid(format!("{} must be a non-negative number, got {}", flag, amount)));
+            }
+        }
+        if let Some(salary) = &self.salary {
+            salary.validate()?;
+        }
+        if let Some(annuity) = &self.annuity {
+            if !(0.0..=1.0).contains(&annuity.share) {
+                return Err(Error::Invalid(format!("--annuitize must be between 0% and 100%, got {}%", annuity.share * 100.0)));
+            }
+            if !annuity.payout_rate.is_finite() || annuity.payout_rate < 0.0 {
+                return Err(Error::Invalid(format!("--annuity-rate must be a non-negative percentage, got {}", annuity.payout_rate)));
+            }
+        }
+        self.inflation.validate()
+    }
+
+    /// Ticks of both phases, the number of points to simulate
+    pub fn ticks(&self) -> usize {
+        self.accumulation_ticks + self.retirement_ticks
+    }
+
+    /// The plan over one path of returns, the price levels at the end of each
+    /// tick and the contributions in today's money until retirement. The
+    /// returns apply to the savings over a tick, the contribution or
+    /// withdrawal is made at its end. The annuity is bought at the start of
+    /// the first tick of retirement and its payout adds to the savings at the
+    /// end of every tick after.
+    pub fn plan(
+        &self,
+        returns: impl Iterator<Item = f64>,
+        price_levels: impl Iterator<Item = f64>,
+        contributions: impl Iterator<Item = f64>,
+    ) -> Outcome {
+        let (mut balance, mut prices) = (self.balance, 1.0);
+        let mut at_retirement = balance;
+        let mut depleted_after = None;
+        let (mut payout, mut annuity_payout) = (0.0, 0.0);
+        let mut contributions = contributions.take(self.accumulation_ticks);
+        for (tick, (r, level)) in returns.zip(price_levels).take(self.ticks()).enumerate() {
+            if let Some(annuity) = self.annuity.filter(|_| tick == self.accumulation_ticks) {
+                payout = balance * annuity.share * annuity.payout_rate;
+                annuity_payout = payout / prices;
+                balance -= balance * annuity.share;
+            }
+            prices = level;
+            if tick < self.accumulation_ticks {
+                balance = balance * r + contributions.next().unwrap_or(0.0) * prices;
+                at_retirement = balance / prices;
+            } else if depleted_after.is_none() {
+                balance = balance * r - self.withdrawal * prices + payout;
+                if balance <= 0.0 {
+                    balance = 0.0;
+                    depleted_after = Some(tick + 1 - self.accumulation_ticks);
+                }
+            }
+        }
+        Outcome { at_retirement, at_end: balance / prices, depleted_after, annuity_payout }
+    }
+
+    /// The contributions of every tick in today's money, the fixed
+    /// contribution or the saved share of the salary, see
+    /// `SalaryArgs::contributions`
+    pub fn contributions(&self, ticks_per_year: f64, rng: RngKind, seed: Option<u64>) -> Box<dyn Iterator<Item = f64>> {
+        match &self.salary {
+            Some(salary) => Box::new(salary.contributions(ticks_per_year, rng, seed)),
+            None => Box::new(std::iter::repeat(self.contribution)),
+        }
+    }
+
+    /// `plan` over `paths` paths of `model`, with the interval, time base and
+    /// generator of `gen_args` and path `i` seeded with `path_seed(seed, i)`.
+    /// The inflation and salary of a path are seeded with the seed of its
+    /// returns.
+    pub fn plan_paths(
+        &self,
+        model: &dyn ReturnModel,
+        gen_args: &GenReturnsArgs,
+        paths: usize,
+        seed: Option<u64>,
+    ) -> Result<Vec<Outcome>> {
+        let interval_seconds = gen_args.tick_interval_seconds();
+        let ticks_per_year = gen_args.time_base.ticks_per_year(gen_args.tick_interval());
+        (0..paths)
+            .map(|i| {
+                let path_seed = seed.map(|s| path_seed(s, i));
+                let returns = model.returns(interval_seconds, self.ticks(), path_seed)?;
+                let levels = self.inflation.levels(ticks_per_year, self.ticks(), gen_args.rng, path_seed);
+                Ok(self.plan(returns, levels, self.contributions(ticks_per_year, gen_args.rng, path_seed)))
+            })
+            .collect()
+    }
+}
+
+impl SalaryArgs {
+    pub fn validate(&self) -> Result<()> {
+        if !self.start.is_finite() || self.start < 0.0 {
+            return Err(Error::Invalid(format!("--salary must be a non-negative number, got {}", self.start)));
+        }
+        if !(0.0..=1.0).contains(&self.savings_rate) {
+            return Err(Error::Invalid(format!("--savings-rate must be between 0% and 100%, got {}%", self.savings_rate * 100.0)));
+        }
+        if !self.yearly_growth.is_finite() || self.yearly_growth <= 0.0 {
+            return Err(Error::Invalid(format!("--salary-growth must be above -100%, got {}%", (self.yearly_growth - 1.0) * 100.0)));
+        }
+        if !self.yearly_vol.is_finite() || self.yearly_vol < 0.0 {
+            return Err(Error::Invalid(format!("--salary-vol must be a non-negative number, got {}", self.yearly_vol)));
+        }
+        Ok(())
+    }
+
+    /// The saved share of the salary of every tick in today's money, with
+    /// `ticks_per_year` ticks a year. The raises are drawn from a generator
+    /// of kind `rng` of their own, seeded with `path_seed(seed, usize::MAX - 1)`
+    /// so that the returns and the inflation generated with `seed` stay the
+    /// same.
+    pub fn contributions(&self, ticks_per_year: f64, rng: RngKind, seed: Option<u64>) -> Contributions {
+        Contributions {
+            salary: self.start,
+            savings_rate: self.savings_rate,
+            mu: self.yearly_growth.ln() / ticks_per_year,
+            sigma: self.yearly_vol / ticks_per_year.sqrt(),
+            rng: SeededRng::new(rng, seed.map(|s| path_seed(s, usize::MAX - 1))),
+            portable: (rng == RngKind::Portable).then(|| portable::Normal::new(0.0, 1.0)),
+        }
+    }
+}
+
+/// Endless iterator over the contributions of a salary, see
+/// `SalaryArgs::contributions`
+pub struct Contributions {
+    salary: f64,
+    savings_rate: f64,
+    /// Mean and standard deviation of the log raise of one tick
+    mu: f64,
+    sigma: f64,
+    rng: SeededRng,
+    /// The sampling of `RngKind::Portable`, `None` for the other generators
+    portable: Option<portable::Normal>,
+}
+
+impl Iterator for Contributions {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let contribution = self.salary * self.savings_rate;
+        let z: f64 = match &mut self.portable {
+            Some(normal) => normal.sample(&mut self.rng),
+            None => StandardNormal.sample(&mut self.rng),
+        };
+        self.salary *= (self.mu + self.sigma * z).exp();
+        Some(contribution)
+    }
+}
+
+impl RetireReport {
+    pub fn of(outcomes: &[Outcome]) -> RetireReport {
+        let values = |f: fn(&Outcome) -> f64| outcomes.iter().map(f).collect::<Vec<_>>();
+        let at_end = values(|o| o.at_end);
+        RetireReport {
+            paths: outcomes.len(),
+            successes: outcomes.iter().filter(|o| o.depleted_after.is_none()).count(),
+            at_retirement_median: quantile(&values(|o| o.at_retirement), 0.5),
+            at_end_median: quantile(&at_end, 0.5),
+            at_end_p10: quantile(&at_end, 0.1),
+            annuity_payout_median: quantile(&values(|o| o.annuity_payout), 0.5),
+        }
+    }
+
+    /// The share of paths on which the savings lasted in percent
+    pub fn success_percent(&self) -> f64 {
+        percent(self.successes, self.paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{AnnuityArgs, RetireArgs, RetireReport, SalaryArgs};
+    use crate::inflation::InflationArgs;
+    use crate::model::LogNormal;
+    use crate::returns::{GenReturnsArgs, RngKind, TimeBase};
+
+    fn plan(withdrawal: f64) -> RetireArgs {
+        RetireArgs {
+            balance: 100.0,
+            contribution: 10.0,
+            salary: None,
+            withdrawal,
+            accumulation_ticks: 2,
+            retirement_ticks: 3,
+            inflation: InflationArgs::constant(1.0),
+            annuity: None,
+        }
+    }
+
+    #[test]
+    fn phases_without_growth() {
+        // 100 + 2 * 10 saved, then 3 * 30 withdrawn
+        let outcome = plan(30.0).plan(std::iter::repeat(1.0), std::iter::repeat(1.0), std::iter::repeat(10.0));
+        assert_eq!(120.0, outcome.at_retirement);
+        assert_eq!(30.0, outcome.at_end);
+        assert_eq!(None, outcome.depleted_after);
+        // 50 a year runs out in the third year of retirement
+        let outcome = plan(50.0).plan(std::iter::repeat(1.0), std::iter::repeat(1.0), std::iter::repeat(10.0));
+        assert_eq!(0.0, outcome.at_end);
+        assert_eq!(Some(3), outcome.depleted_after);
+    }
+
+    #[test]
+    fn amounts_grow_with_inflation() {
+        // Savings growing at inflation keep their real value, and so do the
+        // contributions and withdrawals
+        let args = RetireArgs { inflation: InflationArgs::constant(1.1), ..plan(30.0) };
+        let levels = args.inflation.levels(1.0, args.ticks(), RngKind::Std, None);
+        let outcome = args.plan(std::iter::repeat(1.1), levels, args.contributions(1.0, RngKind::Std, None));
+        assert_approx_eq!(120.0, outcome.at_retirement, 1e-9);
+        assert_approx_eq!(30.0, outcome.at_end, 1e-9);
+        assert!(RetireArgs { inflation: InflationArgs::constant(0.0), ..args }.validate().is_err());
+    }
+
+    #[test]
+    fn saving_a_share_of_a_salary() {
+        // 20% of 50, then of 55 after a 10% raise
+        let salary = SalaryArgs { start: 50.0, savings_rate: 0.2, yearly_growth: 1.1, yearly_vol: 0.0 };
+        let args = RetireArgs { salary: Some(salary), ..plan(30.0) };
+        let contributions: Vec<f64> = args.contributions(1.0, RngKind::Std, None).take(2).collect();
+        assert_approx_eq!(10.0, contributions[0], 1e-9);
+        assert_approx_eq!(11.0, contributions[1], 1e-9);
+        let outcome = args.plan(std::iter::repeat(1.0), std::iter::repeat(1.0), args.contributions(1.0, RngKind::Std, None));
+        assert_approx_eq!(121.0, outcome.at_retirement, 1e-9);
+
+        // Random raises, the same with the same seed
+        let uncertain = SalaryArgs { yearly_vol: 0.05, ..salary };
+        let raises = |seed| uncertain.contributions(12.0, RngKind::Std, Some(seed)).take(24).collect::<Vec<f64>>();
+        assert_eq!(raises(1), raises(1));
+        assert_ne!(raises(1), raises(2));
+        assert!(SalaryArgs { savings_rate: 1.5, ..salary }.validate().is_err());
+        assert!(SalaryArgs { yearly_growth: 0.0, ..salary }.validate().is_err());
+    }
+
+    #[test]
+    fn annuity_covers_part_of_the_withdrawals() {
+        // Half of the 120 at retirement buys 24 a year, so 30 a year only
+        // takes 6 of the other half
+        let annuity = AnnuityArgs { share: 0.5, payout_rate: 0.4 };
+        let args = RetireArgs { annuity: Some(annuity), ..plan(30.0) };
+        let outcome = args.plan(std::iter::repeat(1.0), std::iter::repeat(1.0), std::iter::repeat(10.0));
+        assert_eq!(120.0, outcome.at_retirement);
+        assert_eq!(24.0, outcome.annuity_payout);
+        assert_eq!(42.0, outcome.at_end);
+        assert_eq!(None, outcome.depleted_after);
+        assert!(RetireArgs { annuity: Some(AnnuityArgs { share: 2.0, ..annuity }), ..plan(30.0) }.validate().is_err());
+    }
+
+    #[test]
+    fn report_over_paths() {
+        let gen_args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(31556952),
+            num_points: 0,
+            yearly_mean: 1.05,
+            yearly_stddev: 1.2,
+            seed: Some(1),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let model = LogNormal::from(&gen_args);
+        let args = RetireArgs { accumulation_ticks: 20, retirement_ticks: 30, ..plan(12.0) };
+        let report = RetireReport::of(&args.plan_paths(&model, &gen_args, 200, Some(1)).unwrap());
+        assert_eq!(200, report.paths);
+        assert!(report.successes > 0 && report.successes < 200);
+        assert_eq!(100.0 * report.successes as f64 / 200.0, report.success_percent());
+        assert!(report.at_end_p10 <= report.at_end_median);
+
+        // The inflation of each path is drawn alongside its returns
+        let inflation = InflationArgs { persistence: 0.8, volatility: 0.03, ..InflationArgs::constant(1.0) };
+        let uncertain = RetireArgs { inflation, ..args };
+        let outcomes = uncertain.plan_paths(&model, &gen_args, 200, Some(1)).unwrap();
+        assert_ne!(report, RetireReport::of(&outcomes));
+    }
+}
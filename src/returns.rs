@@ -1,117 +1,987 @@
-use clap::Parser;
-use rand::SeedableRng;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Add, Mul, Sub};
+use std::str::FromStr;
+
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha12Rng;
 use rand_distr::Distribution;
+use rand_pcg::Pcg64Mcg;
+use rand_xoshiro::Xoshiro256PlusPlus;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::decimal::{self, Decimal};
+use crate::error::{Error, Result};
+use crate::portable::{self, Pcg32};
+
+pub const SECONDS_PER_YEAR: f64 = 31556952.0;
+
+/// Floating point type of returns and accumulated values. `f64` is the
+/// default everywhere; `f32` halves the memory of stored series at the cost of
+/// precision. Returns are always drawn as `f64` and rounded, so an `f32`
+/// series is the rounded `f64` series with the same seed.
+pub trait Float:
+    Copy + PartialOrd + fmt::Debug + fmt::Display + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+{
+    fn of_f64(x: f64) -> Self;
+    fn as_f64(self) -> f64;
+    fn ln(self) -> Self;
+    fn ln_1p(self) -> Self;
+    fn exp(self) -> Self;
+    fn max(self, other: Self) -> Self;
+}
+
+macro_rules! impl_float {
+    ($t:ty) => {
+        impl Float for $t {
+            fn of_f64(x: f64) -> $t {
+                x as $t
+            }
+
+            fn as_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn ln(self) -> $t {
+                <$t>::ln(self)
+            }
+
+            fn ln_1p(self) -> $t {
+                <$t>::ln_1p(self)
+            }
+
+            fn exp(self) -> $t {
+                <$t>::exp(self)
+            }
+
+            fn max(self, other: $t) -> $t {
+                <$t>::max(self, other)
+            }
+        }
+    };
+}
 
-const SECONDS_PER_YEAR: f64 = 31556952.0;
+impl_float!(f32);
+impl_float!(f64);
 
-#[derive(Parser)]
+/// Parses a duration in seconds, either a plain number or a number with one of
+/// the suffixes `s`, `m` (minutes), `h`, `d`, `w`, `mo` (1/12 year) or `y`,
+/// e.g. `30m` or `1.5d`. Years are 365.2425 days, as in `SECONDS_PER_YEAR`.
+pub fn parse_duration(s: &str) -> Result<f64> {
+    let s = s.trim();
+    let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let factor = match unit {
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        "w" => 7.0 * 86400.0,
+        "mo" => SECONDS_PER_YEAR / 12.0,
+        "y" => SECONDS_PER_YEAR,
+        _ => {
+            return Err(Error::Parse(format!(
+                "unknown duration unit '{}', expected one of s, m, h, d, w, mo, y",
+                unit
+            )))
+        }
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 0.0 => Ok(n * factor),
+        _ => Err(Error::Parse(format!("invalid duration '{}'", s))),
+    }
+}
+
+/// Like `parse_duration`, rounded to whole seconds
+pub fn parse_whole_seconds(s: &str) -> Result<usize> {
+    parse_duration(s).map(|d| d.round() as usize)
+}
+
+/// Parameters of a generated return series. Exactly one of `total_seconds`
+/// and `interval_seconds` should be set. A `total_seconds` that isn't a
+/// multiple of `num_points` is spread evenly over the points as the exact
+/// fraction `total_seconds / num_points`, see `Interval`, so the last point
+/// is at `total_seconds` and the annualization covers exactly that time.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct GenReturnsArgs {
-    /// Simulation time in seconds (from first data point to last). Incomatiable with interval_seconds
-    #[arg(short, long, conflicts_with("interval_seconds"), required_unless_present("interval_seconds"))]
+    /// Simulation time in seconds, from the first data point to the last
     pub total_seconds: Option<usize>,
 
-    /// Time between data points in seconds. Incomatiable with --total-seconds
-    #[arg(short, long, conflicts_with("total_seconds"), required_unless_present("total_seconds"))]
+    /// Time between data points in seconds
     pub interval_seconds: Option<usize>,
 
     /// How many data points to generate (equally spaced in time)
-    #[arg(short, long)]
     pub num_points: usize,
 
-    /// The yearly (geometric) mean return
-    #[arg(long, default_value_t = 1.0)]
+    /// The yearly (geometric) mean return as a factor, e.g. 1.07 for +7%
     pub yearly_mean: f64,
 
-    /// The yearly standard deviation (geometric)
-    #[arg(long, default_value_t = 1.5)]
+    /// The yearly (geometric) standard deviation as a factor, e.g. 1.15 for 15%
     pub yearly_stddev: f64,
 
-    /// The seed to use for random number generation (for reproducible results)
-    #[arg(long)]
+    /// The seed to use for random number generation, from entropy if `None`
     pub seed: Option<u64>,
+
+    /// The random number generator. The same seed gives a different series
+    /// with each generator, so pass the same `rng` to reproduce a run.
+    #[serde(default)]
+    pub rng: RngKind,
+
+    /// What a year of the yearly mean and standard deviation is made of
+    #[serde(default)]
+    pub time_base: TimeBase,
+
+    /// Degrees of freedom of Student's t log returns, scaled to the same
+    /// standard deviation, for heavier tails than the normal ones of `None`:
+    /// the smaller, the more extreme moves. Above 2, infinity is normal.
+    #[serde(default)]
+    pub tail_df: Option<f64>,
+}
+
+impl GenReturnsArgs {
+    /// Time between data points, derived from whichever of `total_seconds`
+    /// and `interval_seconds` was given
+    pub fn tick_interval(&self) -> Interval {
+        if let Some(s) = self.total_seconds {
+            Interval::new(s as f64, self.num_points)
+        } else if let Some(s) = self.interval_seconds {
+            Interval::from(s as f64)
+        } else {
+            Interval::from(0.0)
+        }
+    }
+
+    /// Time between data points in seconds, rounded to the nearest `f64`
+    pub fn tick_interval_seconds(&self) -> f64 {
+        self.tick_interval().seconds()
+    }
+
+    /// Checks that the parameters describe a valid distribution, which
+    /// `gen_returns` relies on
+    pub fn validate(&self) -> Result<()> {
+        if self.total_seconds == Some(0) || self.interval_seconds == Some(0) {
+            return Err(Error::Invalid("the simulated time must be longer than 0 seconds".to_string()));
+        }
+        self.time_base.validate()?;
+        let mean = self.yearly_mean;
+        if !mean.is_finite() || mean <= 0.0 {
+            return Err(Error::Invalid(format!(
+                "the yearly mean must be a positive factor (e.g. 1.07 for +7%), got {}",
+                mean
+            )));
+        }
+        let stddev = self.yearly_stddev;
+        if !stddev.is_finite() || stddev < 1.0 {
+            return Err(Error::Invalid(format!(
+                "the yearly stddev must be a geometric factor of at least 1 (e.g. 1.15 for 15%), got {}",
+                stddev
+            )));
+        }
+        if let Some(df) = self.tail_df {
+            if df.is_nan() || df <= 2.0 {
+                return Err(Error::Invalid(format!("--tail-df must be above 2 for a finite stddev, got {}", df)));
+            }
+            if self.rng == RngKind::Portable && df.is_finite() {
+                return Err(Error::Invalid("--tail-df doesn't work with --rng portable".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Parameters derived by the annualization, see `Derived`
+    pub fn derived(&self) -> Derived {
+        let interval = self.tick_interval();
+        let ticks_per_year = self.time_base.ticks_per_year(interval);
+        let (tick_mu, tick_sigma) = tick_log_params(self.rng, self.yearly_mean, self.yearly_stddev, ticks_per_year);
+        let n = self.num_points as f64;
+        Derived {
+            interval_seconds: interval.seconds(),
+            total_seconds: interval.at(self.num_points),
+            ticks_per_year,
+            tick_mu,
+            tick_sigma,
+            terminal_mu: tick_mu * n,
+            terminal_sigma: tick_sigma * n.sqrt(),
+        }
+    }
+}
+
+/// Parameters derived from `GenReturnsArgs` by the annualization, for
+/// checking them before generating anything. The terminal values are those
+/// of normal log returns, whatever the `tail_df`.
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Derived {
+    pub interval_seconds: f64,
+    pub total_seconds: f64,
+    pub ticks_per_year: f64,
+    /// Mean and standard deviation of the log return over one tick
+    pub tick_mu: f64,
+    pub tick_sigma: f64,
+    /// Mean and standard deviation of the log of the product of all returns,
+    /// i.e. of the terminal value of the unlevered series starting at 1
+    pub terminal_mu: f64,
+    pub terminal_sigma: f64,
+}
+
+impl Derived {
+    /// Terminal value of the unlevered series starting at 1 that is exceeded
+    /// with probability 1 - p, for the quantile z-score of p
+    pub fn terminal_quantile(&self, z: f64) -> f64 {
+        (self.terminal_mu + z * self.terminal_sigma).exp()
+    }
+
+    /// Expected terminal value of the unlevered series starting at 1
+    pub fn terminal_mean(&self) -> f64 {
+        (self.terminal_mu + self.terminal_sigma.powi(2) / 2.0).exp()
+    }
+}
+
+/// Parses a percentage, with or without the trailing `%`
+pub fn parse_percent(s: &str) -> Result<f64> {
+    s.trim()
+        .trim_end_matches('%')
+        .parse::<f64>()
+        .map_err(|_| Error::Parse(format!("invalid percentage '{}'", s)))
+}
+
+/// Parses a count, with `_` allowed between digits like in `1_000_000`
+pub fn parse_count(s: &str) -> Result<usize> {
+    let digits = s.trim();
+    if digits.starts_with('_') || digits.ends_with('_') {
+        return Err(Error::Parse(format!("invalid count '{}'", s)));
+    }
+    digits.replace('_', "").parse().map_err(|_| Error::Parse(format!("invalid count '{}'", s)))
+}
+
+/// Time between data points as the fraction `seconds / ticks`, e.g. 10
+/// seconds over 3 ticks. Times and annualization are computed from the
+/// fraction rather than from the rounded `f64` quotient, so the rounding of
+/// a fractional interval isn't multiplied by the tick number. Converting an
+/// `f64` gives an interval of that many seconds per tick.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interval {
+    seconds: f64,
+    ticks: usize,
+}
+
+impl Interval {
+    /// `seconds` spread evenly over `ticks` ticks. No ticks, as of an empty
+    /// series, count as one, so the annualization stays finite.
+    pub fn new(seconds: f64, ticks: usize) -> Interval {
+        Interval { seconds, ticks: ticks.max(1) }
+    }
+
+    /// Seconds per tick, rounded to the nearest `f64`
+    pub fn seconds(&self) -> f64 {
+        self.seconds / self.ticks as f64
+    }
+
+    /// Seconds from t=0 to the end of tick `tick`, exact up to one rounding
+    /// while `tick * seconds` is an integer below 2^53
+    pub fn at(&self, tick: usize) -> f64 {
+        tick as f64 * self.seconds / self.ticks as f64
+    }
+}
+
+impl From<f64> for Interval {
+    fn from(seconds: f64) -> Interval {
+        Interval::new(seconds, 1)
+    }
+}
+
+/// The annualization convention: what the seconds of a series count and how
+/// many of them make a year, for generating returns from yearly parameters
+/// as well as for estimating yearly parameters from returns. Wall-clock time
+/// runs around the clock, so daily bars of a market open 252 days a year are
+/// about 1.45 days apart. In trading time only the sessions count: a year is
+/// `days_per_year` sessions of `hours_per_day` hours, and daily bars are one
+/// session (e.g. `6.5h`) apart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimeBase {
+    /// Years of `SECONDS_PER_YEAR`, 365.2425 days
+    #[default]
+    WallClock,
+    /// Years of 365 days, the ACT/365 day count of money markets
+    #[serde(rename = "days-365")]
+    Days365,
+    TradingDays { days_per_year: f64, hours_per_day: f64 },
 }
 
-pub fn gen_returns(args: &GenReturnsArgs) -> impl Iterator<Item = f64> {
-    let mut interval_seconds: f64 = 0.0;
-    let mut total_seconds: f64 = 0.0;
-    let num_points_f = args.num_points as f64;
-    if let Some(s) = args.total_seconds {
-        total_seconds = s as f64;
-        interval_seconds = total_seconds / num_points_f;
-    } else if let Some(s) = args.interval_seconds {
-        interval_seconds = s as f64;
-        total_seconds = interval_seconds * num_points_f;
+impl TimeBase {
+    pub fn seconds_per_year(&self) -> f64 {
+        match *self {
+            TimeBase::WallClock => SECONDS_PER_YEAR,
+            TimeBase::Days365 => 365.0 * 86400.0,
+            TimeBase::TradingDays { days_per_year, hours_per_day } => days_per_year * hours_per_day * 3600.0,
+        }
     }
 
-    let yearly_mu = args.yearly_mean.ln();
-    let yearly_sigma = args.yearly_stddev.ln();
+    /// The annualization factor: ticks of `interval` per year. Log means
+    /// scale with it and log standard deviations with its square root.
+    pub fn ticks_per_year(&self, interval: Interval) -> f64 {
+        self.seconds_per_year() * interval.ticks as f64 / interval.seconds
+    }
+
+    /// The flags selecting this time base, empty for the default
+    pub fn to_flags(self) -> String {
+        match self {
+            TimeBase::WallClock => String::new(),
+            TimeBase::Days365 => "--time-base days-365".to_string(),
+            TimeBase::TradingDays { days_per_year, hours_per_day } => format!(
+                "--time-base trading-days --trading-days-per-year {} --trading-hours-per-day {}",
+                days_per_year, hours_per_day
+            ),
+        }
+    }
+
+    /// Checks that the trading days fit in a year and the sessions in a day
+    pub fn validate(&self) -> Result<()> {
+        if let TimeBase::TradingDays { days_per_year, hours_per_day } = *self {
+            if !(days_per_year > 0.0 && days_per_year <= 366.0) {
+                return Err(Error::Invalid(format!("trading days per year must be in (0, 366], got {}", days_per_year)));
+            }
+            if !(hours_per_day > 0.0 && hours_per_day <= 24.0) {
+                return Err(Error::Invalid(format!("trading hours per day must be in (0, 24], got {}", hours_per_day)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The geometric yearly mean, as a factor, of log-normal returns with the
+/// arithmetic yearly mean `arithmetic_mean`, i.e. an expected yearly return
+/// of `arithmetic_mean`. The expected return exceeds the geometric one, that
+/// of the median path, by half the log variance: `E[R] = exp(mu + sigma² / 2)`.
+pub fn geometric_mean(arithmetic_mean: f64, yearly_stddev: f64) -> f64 {
+    arithmetic_mean / (yearly_stddev.ln().powi(2) / 2.0).exp()
+}
+
+/// Mean and standard deviation of the log return over one tick, for yearly
+/// (geometric) mean and standard deviation and `ticks_per_year` ticks a year.
+/// With `RngKind::Portable` the logs are libm's, the same on every platform.
+pub fn tick_log_params(rng: RngKind, yearly_mean: f64, yearly_stddev: f64, ticks_per_year: f64) -> (f64, f64) {
+    let ln: fn(f64) -> f64 = if rng == RngKind::Portable { libm::log } else { f64::ln };
+    let yearly_mu = ln(yearly_mean);
+    let yearly_sigma = ln(yearly_stddev);
 
-    let ticks_per_year = SECONDS_PER_YEAR / interval_seconds;
     let tick_mu = yearly_mu / ticks_per_year;
     let tick_sigma = (yearly_sigma.powi(2) / ticks_per_year).sqrt();
+    (tick_mu, tick_sigma)
+}
+
+/// Position of the random number generator, for continuing a series where a
+/// previous run stopped. Formatted as `chacha12:<hex seed>:<word position>`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct RngState {
+    pub seed: [u8; 32],
+    pub word_pos: u128,
+}
+
+impl fmt::Display for RngState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chacha12:")?;
+        for b in self.seed {
+            write!(f, "{:02x}", b)?;
+        }
+        write!(f, ":{}", self.word_pos)
+    }
+}
+
+impl FromStr for RngState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::Parse(format!("invalid generator state '{}'", s));
+        let mut parts = s.trim().split(':');
+        if parts.next() != Some("chacha12") {
+            return Err(invalid());
+        }
+        let hex = parts.next().filter(|h| h.len() == 64).ok_or_else(invalid)?;
+        let mut seed = [0u8; 32];
+        for (i, b) in seed.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+        }
+        let word_pos = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(RngState { seed, word_pos })
+    }
+}
+
+impl From<RngState> for String {
+    fn from(state: RngState) -> String {
+        state.to_string()
+    }
+}
+
+impl TryFrom<String> for RngState {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+/// A random number generator for `gen_returns`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum RngKind {
+    /// ChaCha12, the generator of `rand::rngs::StdRng`. Unpredictable, and
+    /// the only one whose state can be saved with `Returns::rng_state`.
+    #[default]
+    #[serde(rename = "std")]
+    Std,
+    /// xoshiro256++, faster but predictable from its output
+    #[serde(rename = "xoshiro256++")]
+    Xoshiro256PlusPlus,
+    /// PCG XSL 128/64 (MCG), faster but predictable from its output
+    #[serde(rename = "pcg")]
+    Pcg,
+    /// PCG32 with Box–Muller sampling, both implemented in finsim, see
+    /// `portable`. A little slower, but a seed gives the same series on every
+    /// platform and with any version of the `rand` crates.
+    #[serde(rename = "portable")]
+    Portable,
+}
+
+/// A generator of any `RngKind`
+#[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)] // Boxing ChaCha would add an indirection to every draw
+pub enum SeededRng {
+    Std(ChaCha12Rng),
+    Xoshiro256PlusPlus(Xoshiro256PlusPlus),
+    Pcg(Pcg64Mcg),
+    Portable(Pcg32),
+}
+
+impl SeededRng {
+    /// A generator of `kind` seeded with `seed`, or from entropy if `None`
+    pub fn new(kind: RngKind, seed: Option<u64>) -> SeededRng {
+        fn seeded<R: SeedableRng>(seed: Option<u64>) -> R {
+            seed.map_or_else(R::from_entropy, R::seed_from_u64)
+        }
+        match kind {
+            RngKind::Std => SeededRng::Std(seeded(seed)),
+            RngKind::Xoshiro256PlusPlus => SeededRng::Xoshiro256PlusPlus(seeded(seed)),
+            RngKind::Pcg => SeededRng::Pcg(seeded(seed)),
+            // Not SeedableRng, whose seed_from_u64 is up to rand_core
+            RngKind::Portable => SeededRng::Portable(Pcg32::new(seed.unwrap_or_else(rand::random))),
+        }
+    }
+}
+
+macro_rules! dispatch {
+    ($rng:expr, $r:ident => $e:expr) => {
+        match $rng {
+            SeededRng::Std($r) => $e,
+            SeededRng::Xoshiro256PlusPlus($r) => $e,
+            SeededRng::Pcg($r) => $e,
+            SeededRng::Portable($r) => $e,
+        }
+    };
+}
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        dispatch!(self, r => r.next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        dispatch!(self, r => r.next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        dispatch!(self, r => r.fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> std::result::Result<(), rand::Error> {
+        dispatch!(self, r => r.try_fill_bytes(dest))
+    }
+}
+
+/// Iterator over generated returns, see `gen_returns` and `gen_returns_with`
+pub struct Returns<R = SeededRng, F = f64> {
+    /// Distribution of the log returns
+    distr: LogDistr,
+    rng: R,
+    remaining: usize,
+    /// Whether to yield the log returns instead of the returns
+    log_space: bool,
+    float: PhantomData<F>,
+}
+
+impl<F> Returns<SeededRng, F> {
+    /// State of the random number generator after the returns produced so
+    /// far. Passing it to `resume_returns` continues the series from here.
+    /// `None` unless the generator is `RngKind::Std`.
+    pub fn rng_state(&self) -> Option<RngState> {
+        match &self.rng {
+            SeededRng::Std(rng) => Some(RngState {
+                seed: rng.get_seed(),
+                word_pos: rng.get_word_pos(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The sampling of the log returns, by `rand_distr` or, for
+/// `RngKind::Portable`, by `portable`
+enum LogDistr {
+    RandDistr(rand_distr::Normal<f64>),
+    Portable(portable::Normal),
+    /// Student's t draws `t` scaled by `scale` around `mu`
+    StudentT { t: rand_distr::StudentT<f64>, mu: f64, scale: f64 },
+}
+
+impl<R: Rng, F: Float> Iterator for Returns<R, F> {
+    type Item = F;
+
+    fn next(&mut self) -> Option<F> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let x = match &mut self.distr {
+            LogDistr::RandDistr(distr) => distr.sample(&mut self.rng),
+            LogDistr::Portable(distr) => distr.sample(&mut self.rng),
+            LogDistr::StudentT { t, mu, scale } => *mu + *scale * t.sample(&mut self.rng),
+        };
+        if self.log_space {
+            return Some(F::of_f64(x));
+        }
+        Some(F::of_f64(match self.distr {
+            // The exp of rand_distr::LogNormal, which is libm's unless another
+            // crate enables num-traits/std, and which differs from std's in the
+            // last bit of some values
+            LogDistr::RandDistr(_) | LogDistr::StudentT { .. } => rand_distr::num_traits::Float::exp(x),
+            LogDistr::Portable(_) => libm::exp(x),
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+/// Generates the returns described by `args`, which must pass
+/// `GenReturnsArgs::validate`
+pub fn gen_returns(args: &GenReturnsArgs) -> Result<Returns> {
+    gen_returns_as(args)
+}
+
+/// Like `gen_returns`, but yielding the logs of the returns, skipping the
+/// `exp` per tick. The draws are the same, so with the same parameters its
+/// values are the logs of the `gen_returns` values.
+pub fn gen_log_returns(args: &GenReturnsArgs) -> Result<Returns> {
+    Ok(Returns {
+        log_space: true,
+        ..gen_returns(args)?
+    })
+}
+
+/// Like `gen_returns`, but yielding returns of type `F`, e.g.
+/// `gen_returns_as::<f32>`
+pub fn gen_returns_as<F: Float>(args: &GenReturnsArgs) -> Result<Returns<SeededRng, F>> {
+    gen_returns_with(args, SeededRng::new(args.rng, args.seed))
+}
+
+/// Like `gen_returns`, but drawing from `rng` instead of a generator seeded
+/// with `args.seed` and `args.rng`, which are ignored except that
+/// `RngKind::Portable` still samples the normals with `portable::Normal`.
+/// Pass `&mut rng` to keep using the generator afterwards or to share it
+/// between series.
+pub fn gen_returns_with<R: Rng, F: Float>(args: &GenReturnsArgs, rng: R) -> Result<Returns<R, F>> {
+    returns_from(args, rng)
+}
 
-    let tick_distr = rand_distr::LogNormal::new(tick_mu, tick_sigma).unwrap();
+/// Like `gen_returns`, but continuing from a generator state saved by
+/// `Returns::rng_state` instead of starting from `args.seed`. With the same
+/// parameters the result equals the continuation of the original series.
+/// The state is of a `RngKind::Std` generator, `args.rng` is ignored.
+pub fn resume_returns(args: &GenReturnsArgs, state: &RngState) -> Result<Returns> {
+    let mut rng = ChaCha12Rng::from_seed(state.seed);
+    rng.set_word_pos(state.word_pos);
+    returns_from(&GenReturnsArgs { rng: RngKind::Std, ..args.clone() }, SeededRng::Std(rng))
+}
 
-    let rng = if let Some(seed) = args.seed {
-        rand::rngs::StdRng::seed_from_u64(seed)
-    } else {
-        rand::rngs::StdRng::from_entropy()
+fn returns_from<R: Rng, F: Float>(args: &GenReturnsArgs, rng: R) -> Result<Returns<R, F>> {
+    args.validate()?;
+    let interval_seconds = args.tick_interval_seconds();
+    let ticks_per_year = args.time_base.ticks_per_year(args.tick_interval());
+    let (tick_mu, tick_sigma) = tick_log_params(args.rng, args.yearly_mean, args.yearly_stddev, ticks_per_year);
+    debug!(tick_mu, tick_sigma, interval_seconds, num_points = args.num_points, "lognormal returns");
+    let tick_distr = rand_distr::Normal::new(tick_mu, tick_sigma).map_err(|e| Error::Invalid(e.to_string()))?;
+    let tick_distr = match args.tail_df.filter(|df| df.is_finite()) {
+        Some(df) => {
+            debug!(df, "student's t log returns");
+            let t = rand_distr::StudentT::new(df).map_err(|e| Error::Invalid(e.to_string()))?;
+            // A t distribution has variance df / (df - 2)
+            LogDistr::StudentT { t, mu: tick_mu, scale: tick_sigma * ((df - 2.0) / df).sqrt() }
+        }
+        None if args.rng == RngKind::Portable => LogDistr::Portable(portable::Normal::new(tick_mu, tick_sigma)),
+        None => LogDistr::RandDistr(tick_distr),
     };
 
-    tick_distr.sample_iter(rng).take(args.num_points)
+    Ok(Returns {
+        distr: tick_distr,
+        rng,
+        remaining: args.num_points,
+        log_space: false,
+        float: PhantomData,
+    })
 }
 
-#[derive(Parser)]
+/// How to accumulate a return series. At most one of the leverages should be
+/// set. Negative leverages are inverse exposure, short the asset: continuous
+/// leverage `l` follows the price to the power `l`, pointwise leverage loses
+/// everything on a tick the price rises by `1/|l|` or more, and initial
+/// leverage shorts `|l|` times the start value, holding the proceeds as
+/// cash, and keeps losing below 0 as the price rises without bound.
+/// Borrowing and shorting are free unless `financing` is set.
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct AccumulateArgs {
     /// Whether to accumulate returns
-    #[arg(short, long, default_value_t = false)]
     pub accumulate: bool,
 
     /// The value to begin accumulating from at t=0
-    #[arg(long, default_value_t = 1.0)]
     pub start_value: f64,
 
     /// Leverage to be held constant over the entire series (releverages continuously between points)
-    #[arg(long, conflicts_with_all(["pointwise_leverage", "initial_leverage"]), allow_hyphen_values(true))]
     pub continuous_leverage: Option<f64>,
 
     /// Leverage to be held constant over the entire series (releverages discretely at every point)
-    #[arg(long, conflicts_with_all(["continuous_leverage", "initial_leverage"]), allow_hyphen_values(true))]
     pub pointwise_leverage: Option<f64>,
 
     /// Leverage at t=0, never releveraged
-    #[arg(long, conflicts_with_all(["continuous_leverage", "pointwise_leverage"]), allow_hyphen_values(true))]
     pub initial_leverage: Option<f64>,
+
+    /// Output the natural log of every value. Accumulated values are then
+    /// tracked in log space, so that they neither overflow nor underflow.
+    #[serde(default)]
+    pub log_values: bool,
+
+    /// Track accumulated values as exact decimals with this many places,
+    /// e.g. 2 for cents, rounding to them at every tick, see
+    /// `decimal::Decimal`. Not with continuous leverage or log values.
+    #[serde(default)]
+    pub decimal_places: Option<u32>,
+
+    /// The interest and fees of the leverage, see `Financing`
+    #[serde(default)]
+    pub financing: Option<Financing>,
+}
+
+/// What leverage costs and earns, accruing every tick: the margin rate is
+/// paid on borrowed money and earned on cash, including the proceeds of a
+/// short as the short rebate, and a short pays the borrow fee on the value of
+/// the shares it borrowed. As in `leverage::LeverageCompareArgs`, but for
+/// every leverage: continuous and pointwise leverage `l` hold `1 - l` times
+/// the value as cash, borrowed when negative, and initial leverage the cash
+/// or debt of t=0. Unlevered values are unaffected.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Financing {
+    /// Yearly interest as a factor, e.g. 1.06 for 6%
+    pub yearly_margin_rate: f64,
+    /// Yearly fee for borrowing the shares of a short as a factor of their
+    /// value, e.g. 1.005 for 0.5%
+    pub yearly_borrow_fee: f64,
+    /// Ticks a year of the returns, to accrue both per tick
+    pub ticks_per_year: f64,
+}
+
+impl Financing {
+    /// The margin rate and borrow fee over one tick, as factors
+    fn per_tick(&self) -> (f64, f64) {
+        let exponent = 1.0 / self.ticks_per_year;
+        (self.yearly_margin_rate.powf(exponent), self.yearly_borrow_fee.powf(exponent))
+    }
+}
+
+impl AccumulateArgs {
+    /// Checks that the start value and leverage are finite numbers
+    pub fn validate(&self) -> Result<()> {
+        let values = [
+            ("--start-value", Some(self.start_value)),
+            ("--continuous-leverage", self.continuous_leverage),
+            ("--pointwise-leverage", self.pointwise_leverage),
+            ("--initial-leverage", self.initial_leverage),
+        ];
+        for (flag, value) in values {
+            if let Some(v) = value.filter(|v| !v.is_finite()) {
+                return Err(Error::Invalid(format!("{} must be a finite number, got {}", flag, v)));
+            }
+        }
+        if let Some(places) = self.decimal_places {
+            if places > decimal::MAX_PLACES {
+                return Err(Error::Invalid(format!("--decimal-places must be at most {}, got {}", decimal::MAX_PLACES, places)));
+            }
+            if self.continuous_leverage.is_some() || self.log_values {
+                return Err(Error::Invalid(
+                    "--decimal-places can't be combined with --continuous-leverage or --log-values".to_string(),
+                ));
+            }
+        }
+        if let Some(financing) = self.financing {
+            let rates = [("--margin-rate", financing.yearly_margin_rate), ("--borrow-fee", financing.yearly_borrow_fee)];
+            for (flag, rate) in rates {
+                if !rate.is_finite() || rate <= 0.0 {
+                    return Err(Error::Invalid(format!("{} must be above -100%, got {}%", flag, (rate - 1.0) * 100.0)));
+                }
+            }
+            if !financing.ticks_per_year.is_finite() || financing.ticks_per_year <= 0.0 {
+                return Err(Error::Invalid(format!("the ticks per year must be positive, got {}", financing.ticks_per_year)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Running state of an accumulation, turning one return at a time into the
+/// next value
+#[derive(Clone, Debug)]
+pub struct Accumulator<F = f64> {
+    accumulate: bool,
+    continuous_leverage: Option<F>,
+    pointwise_leverage: Option<F>,
+    acc: F,
+    /// Borrowed part of the start value, for initial leverage
+    debt: F,
+    /// Sum of the leveraged log returns so far, for continuous leverage and
+    /// log values
+    log_growth: F,
+    log_values: bool,
+    /// The value and debt as exact decimals, for `AccumulateArgs::decimal_places`
+    decimal: Option<(Decimal, Decimal)>,
+    /// The margin rate and borrow fee over one tick, see `Financing`
+    financing: Option<(F, F)>,
+}
+
+impl<F: Float> Accumulator<F> {
+    pub fn new(args: &AccumulateArgs) -> Accumulator<F> {
+        // The leverages take precedence in this order
+        let initial_leverage = match (args.continuous_leverage, args.pointwise_leverage) {
+            (None, None) => args.initial_leverage,
+            _ => None,
+        };
+        Accumulator {
+            accumulate: args.accumulate,
+            continuous_leverage: args.continuous_leverage.map(F::of_f64),
+            pointwise_leverage: args.pointwise_leverage.filter(|_| args.continuous_leverage.is_none()).map(F::of_f64),
+            acc: F::of_f64(initial_leverage.map_or(args.start_value, |l| args.start_value * l)),
+            debt: F::of_f64(initial_leverage.map_or(0.0, |l| args.start_value * (l - 1.0))),
+            log_growth: F::of_f64(0.0),
+            log_values: args.log_values,
+            decimal: args.decimal_places.map(|places| {
+                let start = Decimal::from_f64(args.start_value, places);
+                let acc = initial_leverage.map_or(start, |l| start.mul_f64(l));
+                (acc, acc - start)
+            }),
+            financing: args.financing.map(|f| {
+                let (rate, fee) = f.per_tick();
+                (F::of_f64(rate), F::of_f64(fee))
+            }),
+        }
+    }
+
+    /// The value after the next return
+    pub fn push(&mut self, r: F) -> F {
+        let zero = F::of_f64(0.0);
+        if !self.accumulate {
+            return if self.log_values { r.ln() } else { r };
+        }
+        if let Some((acc, debt)) = self.decimal {
+            let acc = acc.mul_f64(self.leveraged(r, acc.to_f64() < 0.0).as_f64());
+            let debt = match self.financing {
+                Some((rate, _)) => debt.mul_f64(rate.as_f64()),
+                None => debt,
+            };
+            self.decimal = Some((acc, debt));
+            return F::of_f64((acc - debt).to_f64());
+        }
+        if let Some((rate, _)) = self.financing {
+            self.debt = self.debt * rate;
+        }
+        if self.log_values || self.continuous_leverage.is_some() {
+            // Summing logs instead of multiplying r^l every tick is cheaper
+            // and doesn't compound rounding errors over long series
+            let log_r = if let Some(l) = self.continuous_leverage {
+                match self.financing {
+                    Some((rate, fee)) => {
+                        let held = if l < zero { r * fee } else { r };
+                        l * held.ln() + (F::of_f64(1.0) - l) * rate.ln()
+                    }
+                    None => l * r.ln(),
+                }
+            } else {
+                self.leveraged(r, self.acc < zero).ln()
+            };
+            self.log_growth = self.log_growth + log_r;
+            if !self.log_values {
+                // There is no debt with continuous leverage
+                return self.acc * self.log_growth.exp();
+            }
+            if self.acc < zero {
+                // A short: ln(cash - shorted) without leaving log space
+                let log_shorted = (zero - self.acc).ln() + self.log_growth;
+                let log_cash = (zero - self.debt).ln();
+                return log_cash + (zero - (log_shorted - log_cash).exp()).ln_1p();
+            }
+            let log_acc = self.acc.ln() + self.log_growth;
+            // ln(acc - debt) without leaving log space
+            return if self.debt > zero {
+                log_acc + (zero - (self.debt.ln() - log_acc).exp()).ln_1p()
+            } else if self.debt < zero {
+                let log_cash = (zero - self.debt).ln();
+                let (hi, lo) = if log_acc > log_cash { (log_acc, log_cash) } else { (log_cash, log_acc) };
+                hi + (lo - hi).exp().ln_1p()
+            } else {
+                log_acc
+            };
+        }
+        self.acc = self.acc * self.leveraged(r, self.acc < zero);
+        self.acc - self.debt
+    }
+
+    /// The return with pointwise leverage and its financing applied, if
+    /// any, or that of a position held, owing the borrow fee if `short`
+    fn leveraged(&self, r: F, short: bool) -> F {
+        let one = F::of_f64(1.0);
+        match (self.pointwise_leverage, self.financing) {
+            (Some(l), None) => (one + ((r - one) * l)).max(F::of_f64(0.0)),
+            (Some(l), Some((rate, fee))) => {
+                let held = if l < F::of_f64(0.0) { r * fee } else { r };
+                (l * held + (one - l) * rate).max(F::of_f64(0.0))
+            }
+            (None, Some((_, fee))) if short => r * fee,
+            (None, _) => r,
+        }
+    }
+}
+
+/// Iterator over accumulated values, see `accumulate_iter`
+pub struct Accumulate<I, F = f64> {
+    returns: I,
+    acc: Accumulator<F>,
+}
+
+impl<I: Iterator<Item = F>, F: Float> Iterator for Accumulate<I, F> {
+    type Item = F;
+
+    fn next(&mut self) -> Option<F> {
+        self.returns.next().map(|r| self.acc.push(r))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.returns.size_hint()
+    }
+}
+
+/// Accumulates the returns lazily, one value per return, so that series of
+/// any length can be streamed in constant memory. The returns are passed
+/// through unchanged unless `args.accumulate` is set.
+pub fn accumulate_iter<I: Iterator<Item = F>, F: Float>(returns: I, args: &AccumulateArgs) -> Accumulate<I, F> {
+    if args.accumulate {
+        debug!(
+            start_value = args.start_value,
+            continuous_leverage = ?args.continuous_leverage,
+            pointwise_leverage = ?args.pointwise_leverage,
+            initial_leverage = ?args.initial_leverage,
+            "accumulating"
+        );
+    }
+    Accumulate {
+        returns,
+        acc: Accumulator::new(args),
+    }
 }
 
-pub fn accumulate(returns: impl Iterator<Item = f64>, args: &AccumulateArgs) -> Vec<f64> {
-    if !args.accumulate {
-        return returns.collect();
-    }
-    let mut acc = args.start_value;
-    if let Some(continuous_leverage) = args.continuous_leverage {
-        returns
-            .map(|r| r.powf(continuous_leverage))
-            .map(|r| {let v = acc * r; acc = v; v})
-            .collect()
-    } else if let Some(pointwise_leverage) = args.pointwise_leverage {
-        returns
-            .map(|r| (1.0 + ((r - 1.0) * pointwise_leverage)).max(0.0))
-            .map(|r| {let v = acc * r; acc = v; v})
-            .collect()
-    } else if let Some(initial_leverage) = args.initial_leverage {
-        acc = args.start_value * initial_leverage;
-        returns
-            .map(|r| {let v = acc * r; acc = v; v})
-            .map(|a| a - args.start_value * (initial_leverage - 1.0))
-            .collect()
-    } else {
-        returns
-            .map(|r| {let v = acc * r; acc = v; v})
-            .collect()
+/// Like `accumulate_iter`, collected
+pub fn accumulate<F: Float>(returns: impl Iterator<Item = F>, args: &AccumulateArgs) -> Vec<F> {
+    accumulate_iter(returns, args).collect()
+}
+
+/// A point of a series: when it is, the return since the previous point and
+/// the accumulated value (the return itself when not accumulating)
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DataPoint {
+    /// Seconds since t=0. The first point is one interval after t=0.
+    pub t_seconds: f64,
+    pub tick_return: f64,
+    pub value: f64,
+    /// The price level of an inflation simulated alongside, relative to 1 at
+    /// t=0, see `inflation::InflationArgs`
+    #[serde(default)]
+    pub price_level: Option<f64>,
+}
+
+/// Iterator over data points, see `data_points`
+pub struct DataPoints<I> {
+    returns: I,
+    acc: Accumulator,
+    interval: Interval,
+    tick: usize,
+}
+
+impl<I: Iterator<Item = f64>> Iterator for DataPoints<I> {
+    type Item = DataPoint;
+
+    fn next(&mut self) -> Option<DataPoint> {
+        let r = self.returns.next()?;
+        self.tick += 1;
+        Some(DataPoint {
+            t_seconds: self.interval.at(self.tick),
+            tick_return: r,
+            value: self.acc.push(r),
+            price_level: None,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.returns.size_hint()
     }
 }
 
+/// Like `accumulate_iter`, but with the time and return of each value, for
+/// returns `interval` apart, in seconds like `60.0` or as an `Interval`
+pub fn data_points<I: Iterator<Item = f64>>(
+    returns: I,
+    interval: impl Into<Interval>,
+    args: &AccumulateArgs,
+) -> DataPoints<I> {
+    DataPoints {
+        returns,
+        acc: Accumulator::new(args),
+        interval: interval.into(),
+        tick: 0,
+    }
+}
+
+/// Hands the items of `iter` to `f` up to `chunk_size` at a time, in one
+/// buffer reused for every chunk, so that producing, processing and writing
+/// them alternate with only a chunk in memory. Stops at the first error.
+pub fn for_each_chunk<T, E>(
+    iter: impl Iterator<Item = T>,
+    chunk_size: usize,
+    mut f: impl FnMut(&[T]) -> std::result::Result<(), E>,
+) -> std::result::Result<(), E> {
+    let mut chunk = Vec::with_capacity(chunk_size);
+    for item in iter {
+        chunk.push(item);
+        if chunk.len() == chunk_size {
+            f(&chunk)?;
+            chunk.clear();
+        }
+    }
+    if !chunk.is_empty() {
+        f(&chunk)?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::gen_returns;
@@ -126,9 +996,12 @@ mod tests {
             yearly_mean: 1.1,
             yearly_stddev: 1.5,
             seed: Some(123456789),
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
         };
 
-        let res = gen_returns(&args);
+        let res = gen_returns(&args).unwrap();
         assert_eq!(vec![
             1.0000429075842392,
             0.999960403828504,
@@ -143,6 +1016,500 @@ mod tests {
         ], res.collect::<Vec<f64>>());
     }
 
+    #[test]
+    fn resume_returns_continues_series() {
+        let mut args = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(60),
+            num_points: 15,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(42),
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        let full: Vec<f64> = gen_returns(&args).unwrap().collect();
+
+        args.num_points = 10;
+        let mut first = gen_returns(&args).unwrap();
+        let mut series: Vec<f64> = first.by_ref().collect();
+        let state: super::RngState = first.rng_state().unwrap().to_string().parse().unwrap();
+        args.num_points = 5;
+        args.seed = None;
+        series.extend(super::resume_returns(&args, &state).unwrap());
+        assert_eq!(full, series);
+    }
+
+    #[test]
+    fn rng_kinds_give_different_series() {
+        use super::{RngKind, TimeBase};
+
+        let mut args = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(86400),
+            num_points: 5,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(1),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let std: Vec<f64> = gen_returns(&args).unwrap().collect();
+        for rng in [RngKind::Xoshiro256PlusPlus, RngKind::Portable, RngKind::Pcg] {
+            args.rng = rng;
+            let mut returns = gen_returns(&args).unwrap();
+            let series: Vec<f64> = returns.by_ref().collect();
+            assert_ne!(std, series);
+            assert_eq!(series, gen_returns(&args).unwrap().collect::<Vec<f64>>());
+            assert_eq!(None, returns.rng_state());
+        }
+        let config: super::GenReturnsArgs = toml::from_str(&toml::to_string(&args).unwrap()).unwrap();
+        assert_eq!(RngKind::Pcg, config.rng);
+    }
+
+    #[test]
+    fn portable_returns_are_pinned() {
+        let args = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(86400),
+            num_points: 4,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(1),
+            rng: super::RngKind::Portable,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        let series: Vec<f64> = gen_returns(&args).unwrap().collect();
+        // Also what the pinned draws of `portable::Normal` give in Python
+        assert_eq!(vec![1.0076807724187706, 0.9988408169643808, 1.0230400531915578, 1.02427419645556], series);
+    }
+
+    #[test]
+    fn for_each_chunk_test() {
+        let mut chunks = Vec::new();
+        super::for_each_chunk(1..=7, 3, |c| {
+            chunks.push(c.to_vec());
+            Ok::<(), ()>(())
+        })
+        .unwrap();
+        assert_eq!(vec![vec![1, 2, 3], vec![4, 5, 6], vec![7]], chunks);
+        assert_eq!(Err(3), super::for_each_chunk(1..=7, 3, |c| Err(c.len())));
+        super::for_each_chunk(0..0, 3, |_| Err(())).unwrap();
+    }
+
+    #[test]
+    fn gen_log_returns_are_logs_of_returns() {
+        let args = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(60),
+            num_points: 100,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(5),
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        let mut returns = gen_returns(&args).unwrap();
+        let mut log_returns = super::gen_log_returns(&args).unwrap();
+        let values: Vec<f64> = returns.by_ref().collect();
+        assert_eq!(values, log_returns.by_ref().map(rand_distr::num_traits::Float::exp).collect::<Vec<f64>>());
+        assert_eq!(returns.rng_state(), log_returns.rng_state());
+    }
+
+    #[test]
+    fn gen_returns_with_custom_rng() {
+        use rand::SeedableRng;
+        use rand::rngs::mock::StepRng;
+        use rand_chacha::ChaCha12Rng;
+
+        let args = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(60),
+            num_points: 4,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(9),
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        let seeded: Vec<f64> = gen_returns(&args).unwrap().collect();
+        let with: Vec<f64> = super::gen_returns_with(&args, ChaCha12Rng::seed_from_u64(9)).unwrap().collect();
+        assert_eq!(seeded, with);
+
+        // A shared generator continues where the previous series stopped
+        let mut rng = ChaCha12Rng::seed_from_u64(9);
+        let first: Vec<f64> = super::gen_returns_with(&args, &mut rng).unwrap().take(2).collect();
+        let second: Vec<f64> = super::gen_returns_with(&args, &mut rng).unwrap().take(2).collect();
+        assert_eq!(seeded, [first, second].concat());
+
+        // A constant mock generator gives a constant series
+        let mocked: Vec<f64> = super::gen_returns_with(&args, StepRng::new(1 << 63, 0)).unwrap().collect();
+        assert!(mocked.iter().all(|&r| r == mocked[0]));
+    }
+
+    #[test]
+    fn f32_series_rounds_f64_series() {
+        let args = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(86400),
+            num_points: 1000,
+            yearly_mean: 1.07,
+            yearly_stddev: 1.2,
+            seed: Some(3),
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        let double: Vec<f64> = gen_returns(&args).unwrap().collect();
+        let single: Vec<f32> = super::gen_returns_as(&args).unwrap().collect();
+        assert_eq!(double.iter().map(|&r| r as f32).collect::<Vec<_>>(), single);
+
+        let acc_args = super::AccumulateArgs {
+            accumulate: true,
+            start_value: 100.0,
+            continuous_leverage: None,
+            pointwise_leverage: Some(2.0),
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        let double = super::accumulate(double.into_iter(), &acc_args);
+        let single = super::accumulate(single.into_iter(), &acc_args);
+        assert_approx_eq!(double[999], single[999] as f64, 1e-3 * double[999]);
+    }
+
+    #[test]
+    fn parse_duration_test() {
+        use super::{parse_duration, parse_whole_seconds};
+
+        assert_eq!(90.0, parse_duration("90").unwrap());
+        assert_eq!(90.0, parse_duration("90s").unwrap());
+        assert_eq!(1800.0, parse_duration("30m").unwrap());
+        assert_eq!(5400.0, parse_duration("1.5h").unwrap());
+        assert_eq!(86400.0, parse_duration("1d").unwrap());
+        assert_eq!(1209600.0, parse_duration("2w").unwrap());
+        assert_eq!(2629746.0, parse_duration("1mo").unwrap());
+        assert_eq!(30.0 * 31556952.0, parse_duration("30y").unwrap());
+        assert_eq!(2, parse_whole_seconds("1.6").unwrap());
+        assert!(parse_duration("1x").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("-1d").is_err());
+        assert!(parse_duration("").is_err());
+    }
+
+    #[test]
+    fn derived_test() {
+        let args = super::GenReturnsArgs {
+            total_seconds: Some(2 * 31556952),
+            interval_seconds: None,
+            num_points: 730,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: None,
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        let derived = args.derived();
+        assert_approx_eq!(365.0, derived.ticks_per_year);
+        assert_approx_eq!(2.0 * 31556952.0, derived.total_seconds);
+        assert_approx_eq!(1.1f64.ln() / 365.0, derived.tick_mu);
+        assert_approx_eq!(1.5f64.ln() / 365.0f64.sqrt(), derived.tick_sigma);
+        // Two years of the yearly parameters
+        assert_approx_eq!(1.21, derived.terminal_quantile(0.0));
+        assert_approx_eq!(1.21 * 1.5f64.powf(2.0f64.sqrt()), derived.terminal_quantile(1.0));
+        assert_approx_eq!((1.21f64.ln() + 1.5f64.ln().powi(2)).exp(), derived.terminal_mean());
+    }
+
+    #[test]
+    fn second_intervals_with_crypto_volatility() {
+        let args = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(1),
+            num_points: 200000,
+            yearly_mean: 1.1,
+            yearly_stddev: 3.0,
+            seed: Some(1),
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        let derived = args.derived();
+        assert_approx_eq!(31556952.0, derived.ticks_per_year);
+        assert_approx_eq!(3.0f64.ln() / 31556952.0f64.sqrt(), derived.tick_sigma);
+        let logs: Vec<f64> = super::gen_log_returns(&args).unwrap().collect();
+        let n = logs.len() as f64;
+        let mean = logs.iter().sum::<f64>() / n;
+        let sigma = (logs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n).sqrt();
+        assert!((sigma / derived.tick_sigma - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn student_t_tails_keep_the_volatility() {
+        let args = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(60),
+            num_points: 200000,
+            yearly_mean: 1.3,
+            yearly_stddev: 2.0,
+            seed: Some(1),
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: Some(4.0),
+        };
+        let moments = |args: &super::GenReturnsArgs| {
+            let logs: Vec<f64> = super::gen_log_returns(args).unwrap().collect();
+            let n = logs.len() as f64;
+            let mean = logs.iter().sum::<f64>() / n;
+            let var = logs.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+            let kurtosis = logs.iter().map(|x| (x - mean).powi(4)).sum::<f64>() / n / var.powi(2);
+            (var.sqrt(), kurtosis)
+        };
+        let (sigma, kurtosis) = moments(&args);
+        assert!((sigma / args.derived().tick_sigma - 1.0).abs() < 0.02);
+        let (normal_sigma, normal_kurtosis) = moments(&super::GenReturnsArgs { tail_df: None, ..args });
+        assert!((normal_sigma / sigma - 1.0).abs() < 0.02);
+        assert!((normal_kurtosis - 3.0).abs() < 0.1);
+        assert!(kurtosis > 4.0);
+
+        // Infinitely many degrees of freedom are the normal distribution
+        let infinite = super::GenReturnsArgs { tail_df: Some(f64::INFINITY), ..args };
+        let tails: Vec<f64> = super::gen_returns(&infinite).unwrap().take(10).collect();
+        let normal: Vec<f64> = super::gen_returns(&super::GenReturnsArgs { tail_df: None, ..args }).unwrap().take(10).collect();
+        assert_eq!(normal, tails);
+    }
+
+    #[test]
+    fn geometric_mean_of_arithmetic() {
+        assert_eq!(1.07, super::geometric_mean(1.07, 1.0));
+        let args = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(31556952),
+            num_points: 1,
+            yearly_mean: super::geometric_mean(1.1, 1.5),
+            yearly_stddev: 1.5,
+            seed: None,
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        assert_approx_eq!(1.1, args.derived().terminal_mean());
+        assert!(args.yearly_mean < 1.1);
+    }
+
+    #[test]
+    fn trading_days_time_base() {
+        let mut args = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(23400),
+            num_points: 252,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: None,
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::TradingDays { days_per_year: 252.0, hours_per_day: 6.5 },
+            tail_df: None,
+        };
+        // Daily bars of 6.5 hour sessions, one year of them
+        let derived = args.derived();
+        assert_approx_eq!(252.0, derived.ticks_per_year);
+        assert_approx_eq!(1.1, derived.terminal_quantile(0.0));
+        assert!(args.validate().is_ok());
+
+        args.time_base = super::TimeBase::TradingDays { days_per_year: 252.0, hours_per_day: 25.0 };
+        assert!(args.validate().is_err());
+        args.time_base = super::TimeBase::TradingDays { days_per_year: 0.0, hours_per_day: 6.5 };
+        assert!(args.validate().is_err());
+    }
+
+    #[test]
+    fn uneven_total_seconds_spreads_exactly() {
+        let args = super::GenReturnsArgs {
+            total_seconds: Some(10),
+            interval_seconds: None,
+            num_points: 3,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(1),
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        let interval = args.tick_interval();
+        assert_eq!(10.0 / 3.0, interval.seconds());
+        assert_eq!(20.0 / 3.0, interval.at(2));
+        assert_eq!(31556952.0 * 3.0 / 10.0, args.time_base.ticks_per_year(interval));
+        assert_eq!(10.0, args.derived().total_seconds);
+
+        let acc_args = super::AccumulateArgs {
+            accumulate: false,
+            start_value: 1.0,
+            continuous_leverage: None,
+            pointwise_leverage: None,
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        let points = super::data_points(gen_returns(&args).unwrap(), interval, &acc_args);
+        let times: Vec<f64> = points.map(|p| p.t_seconds).collect();
+        assert_eq!(vec![10.0 / 3.0, 20.0 / 3.0, 10.0], times);
+
+        // A whole interval in seconds is its own fraction
+        assert_eq!(super::Interval::new(60.0, 1), 60.0.into());
+    }
+
+    #[test]
+    fn degenerate_sizes() {
+        let args = |num_points| super::GenReturnsArgs {
+            total_seconds: Some(600),
+            interval_seconds: None,
+            num_points,
+            yearly_mean: 1.1,
+            yearly_stddev: 1.5,
+            seed: Some(1),
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        // No points: nothing to generate, the interval is the whole time
+        assert_eq!(0, gen_returns(&args(0)).unwrap().count());
+        let empty = args(0).derived();
+        assert_eq!(600.0, empty.interval_seconds);
+        assert_eq!(0.0, empty.total_seconds);
+        assert_eq!((0.0, 0.0), (empty.terminal_mu, empty.terminal_sigma));
+
+        // One point spans the whole time
+        assert_eq!(1, gen_returns(&args(1)).unwrap().count());
+        let single = args(1).derived();
+        assert_eq!((600.0, 600.0), (single.interval_seconds, single.total_seconds));
+        assert_eq!(single.tick_sigma, single.terminal_sigma);
+    }
+
+    #[test]
+    fn validate_test() {
+        let valid = || super::GenReturnsArgs {
+            total_seconds: Some(86400),
+            interval_seconds: None,
+            num_points: 10,
+            yearly_mean: 1.07,
+            yearly_stddev: 1.2,
+            seed: None,
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        assert!(valid().validate().is_ok());
+        assert!(super::GenReturnsArgs { num_points: 0, ..valid() }.validate().is_ok());
+        assert!(super::GenReturnsArgs { total_seconds: Some(0), ..valid() }.validate().is_err());
+        assert!(super::GenReturnsArgs { yearly_mean: 0.0, ..valid() }.validate().is_err());
+        assert!(super::GenReturnsArgs { yearly_stddev: 0.0, ..valid() }.validate().is_err());
+        assert!(super::GenReturnsArgs { yearly_stddev: -1.5, ..valid() }.validate().is_err());
+        assert!(super::GenReturnsArgs { yearly_mean: f64::NAN, ..valid() }.validate().is_err());
+        assert!(super::GenReturnsArgs { yearly_stddev: 1.0, ..valid() }.validate().is_ok());
+        assert!(super::gen_returns(&super::GenReturnsArgs { yearly_stddev: 0.5, ..valid() }).is_err());
+        assert!(super::GenReturnsArgs { tail_df: Some(3.0), ..valid() }.validate().is_ok());
+        assert!(super::GenReturnsArgs { tail_df: Some(2.0), ..valid() }.validate().is_err());
+        assert!(super::GenReturnsArgs { tail_df: Some(f64::NAN), ..valid() }.validate().is_err());
+        let portable = super::GenReturnsArgs { rng: super::RngKind::Portable, ..valid() };
+        assert!(super::GenReturnsArgs { tail_df: Some(3.0), ..portable }.validate().is_err());
+        assert!(super::GenReturnsArgs { tail_df: Some(f64::INFINITY), ..portable }.validate().is_ok());
+
+        let acc = super::AccumulateArgs {
+            accumulate: true,
+            start_value: 1.0,
+            continuous_leverage: None,
+            pointwise_leverage: Some(2.0),
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        assert!(acc.validate().is_ok());
+        assert!(super::AccumulateArgs { pointwise_leverage: Some(f64::NAN), ..acc }.validate().is_err());
+    }
+
+    #[test]
+    fn parse_percent_test() {
+        assert_eq!(-3.0, super::parse_percent("-3%").unwrap());
+        assert_eq!(15.0, super::parse_percent(" 15 ").unwrap());
+        assert!(super::parse_percent("7 %%x").is_err());
+    }
+
+    #[test]
+    fn parse_count_test() {
+        assert_eq!(1_000_000_000, super::parse_count("1_000_000_000").unwrap());
+        assert_eq!(42, super::parse_count(" 42 ").unwrap());
+        assert!(super::parse_count("_1").is_err());
+        assert!(super::parse_count("1e6").is_err());
+    }
+
+    #[test]
+    fn rng_state_parse() {
+        let state = super::RngState {
+            seed: [0xab; 32],
+            word_pos: 1234,
+        };
+        assert_eq!(format!("chacha12:{}:1234", "ab".repeat(32)), state.to_string());
+        assert_eq!(state, format!("chacha12:{}:1234\n", "ab".repeat(32)).parse().unwrap());
+        assert!("chacha12:abab:1234".parse::<super::RngState>().is_err());
+        assert!(format!("pcg:{}:1234", "ab".repeat(32)).parse::<super::RngState>().is_err());
+        assert!(format!("chacha12:{}:x", "ab".repeat(32)).parse::<super::RngState>().is_err());
+    }
+
+    #[test]
+    fn args_round_trip_through_toml() {
+        let gen = super::GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(86400),
+            num_points: 252,
+            yearly_mean: 1.07,
+            yearly_stddev: 1.15,
+            seed: Some(42),
+            rng: super::RngKind::Std,
+            time_base: super::TimeBase::WallClock,
+            tail_df: None,
+        };
+        let toml = toml::to_string(&gen).unwrap();
+        assert_eq!("interval_seconds = 86400\nnum_points = 252\nyearly_mean = 1.07\nyearly_stddev = 1.15\nseed = 42\nrng = \"std\"\ntime_base = \"wall-clock\"\n", toml);
+        let parsed: super::GenReturnsArgs = toml::from_str(&toml).unwrap();
+        assert_eq!(gen.num_points, parsed.num_points);
+        assert_eq!(gen.interval_seconds, parsed.interval_seconds);
+        assert_eq!(gen.seed, parsed.seed);
+
+        let acc = super::AccumulateArgs {
+            accumulate: true,
+            start_value: 100.0,
+            continuous_leverage: None,
+            pointwise_leverage: Some(2.0),
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        let parsed: super::AccumulateArgs = toml::from_str(&toml::to_string(&acc).unwrap()).unwrap();
+        assert_eq!(Some(2.0), parsed.pointwise_leverage);
+        assert_eq!(100.0, parsed.start_value);
+
+        let state = super::RngState {
+            seed: [7; 32],
+            word_pos: u128::MAX,
+        };
+        #[derive(serde::Deserialize, serde::Serialize)]
+        struct Saved {
+            state: super::RngState,
+        }
+        let toml = toml::to_string(&Saved { state: state.clone() }).unwrap();
+        assert_eq!(format!("state = \"{}\"\n", state), toml);
+        assert_eq!(state, toml::from_str::<Saved>(&toml).unwrap().state);
+    }
+
     #[test]
     fn accumulate_test() {
         let args = super::AccumulateArgs {
@@ -151,6 +1518,9 @@ mod tests {
             continuous_leverage: None,
             pointwise_leverage: None,
             initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
         };
         let returns: Vec<f64> = vec![1.04, 1.01, 0.99, 0.98, 1.05, 1.1, 0.4];
         let res = super::accumulate(returns.into_iter(), &args);
@@ -165,6 +1535,57 @@ mod tests {
         ], res);
     }
 
+    #[test]
+    fn accumulate_log_values_test() {
+        let returns = [1.04f64, 0.5, 1.5, 0.98];
+        let leverages = [
+            (None, None, None),
+            (Some(3.0), None, None),
+            (None, Some(2.0), None),
+            (None, None, Some(2.0)),
+            (None, None, Some(0.5)),
+            (Some(-1.0), None, None),
+            (None, Some(-1.0), None),
+            (None, None, Some(-1.0)),
+        ];
+        for (continuous_leverage, pointwise_leverage, initial_leverage) in leverages {
+            let args = super::AccumulateArgs {
+                accumulate: true,
+                start_value: 10.0,
+                continuous_leverage,
+                pointwise_leverage,
+                initial_leverage,
+                log_values: false,
+                decimal_places: None,
+                financing: None,
+            };
+            let values = super::accumulate(returns.into_iter(), &args);
+            let logs = super::accumulate(returns.into_iter(), &super::AccumulateArgs { log_values: true, ..args });
+            for (v, l) in values.into_iter().zip(logs) {
+                if v == 0.0 {
+                    assert_eq!(f64::NEG_INFINITY, l);
+                } else {
+                    assert_approx_eq!(v.ln(), l);
+                }
+            }
+        }
+
+        let args = super::AccumulateArgs {
+            accumulate: true,
+            start_value: 1.0,
+            continuous_leverage: None,
+            pointwise_leverage: None,
+            initial_leverage: None,
+            log_values: true,
+            decimal_places: None,
+            financing: None,
+        };
+        let last = super::accumulate(std::iter::repeat_n(1e10f64, 100), &args).pop().unwrap();
+        assert_approx_eq!(1000.0 * 10f64.ln(), last);
+        let args = super::AccumulateArgs { accumulate: false, ..args };
+        assert_eq!(vec![0.0], super::accumulate([1.0f64].into_iter(), &args));
+    }
+
     #[test]
     fn accumulate_with_continuous_leverage_test() {
         let leverage = 5.0;
@@ -174,19 +1595,134 @@ mod tests {
             continuous_leverage: Some(leverage),
             pointwise_leverage: None,
             initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
         };
         let returns: Vec<f64> = vec![1.04, 1.01, 0.99, 0.98, 1.05, 1.1, 0.4];
         let leveraged_returns: Vec<f64> = returns.clone().iter().map(|r| r.powf(leverage)).collect();
         let res = super::accumulate(returns.into_iter(), &args);
-        assert_eq!(vec![
-            1.0 * leveraged_returns.iter().take(1).product::<f64>(),
-            1.0 * leveraged_returns.iter().take(2).product::<f64>(),
-            1.0 * leveraged_returns.iter().take(3).product::<f64>(),
-            1.0 * leveraged_returns.iter().take(4).product::<f64>(),
-            1.0 * leveraged_returns.iter().take(5).product::<f64>(),
-            1.0 * leveraged_returns.iter().take(6).product::<f64>(),
-            1.0 * leveraged_returns.iter().take(7).product::<f64>(),
-        ], res);
+        assert_eq!(7, res.len());
+        for (i, value) in res.into_iter().enumerate() {
+            assert_approx_eq!(leveraged_returns.iter().take(i + 1).product::<f64>(), value);
+        }
+    }
+
+    #[test]
+    fn accumulate_with_negative_leverage_test() {
+        let args = super::AccumulateArgs {
+            accumulate: true,
+            start_value: 10.0,
+            continuous_leverage: Some(-1.0),
+            pointwise_leverage: None,
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        let returns = [1.5f64, 1.5, 0.5];
+        let values = |args: &super::AccumulateArgs| super::accumulate(returns.into_iter(), args);
+        // One over the price
+        let continuous = values(&args);
+        for (expected, v) in [10.0 / 1.5, 10.0 / 2.25, 10.0 / 1.125].into_iter().zip(continuous) {
+            assert_approx_eq!(expected, v);
+        }
+        // Every rise of 50% halves an inverse fund, and a rise of 50% wipes
+        // out a double inverse one for good
+        let pointwise = super::AccumulateArgs { continuous_leverage: None, pointwise_leverage: Some(-1.0), ..args };
+        assert_eq!(vec![5.0, 2.5, 3.75], values(&pointwise));
+        assert_eq!(vec![0.0, 0.0, 0.0], values(&super::AccumulateArgs { pointwise_leverage: Some(-2.0), ..pointwise }));
+        // A short of the start value with 20 of cash loses more than it
+        // started with once the price more than doubles
+        let initial = super::AccumulateArgs { continuous_leverage: None, initial_leverage: Some(-1.0), ..args };
+        assert_eq!(vec![5.0, -2.5, 8.75], values(&initial));
+        assert_eq!(vec![5.0, -2.5, 8.75], values(&super::AccumulateArgs { decimal_places: Some(2), ..initial }));
+        let logs = values(&super::AccumulateArgs { log_values: true, ..initial });
+        assert_approx_eq!(5f64.ln(), logs[0]);
+        assert!(logs[1].is_nan());
+    }
+
+    #[test]
+    fn accumulate_with_financing_test() {
+        let args = super::AccumulateArgs {
+            accumulate: true,
+            start_value: 10.0,
+            continuous_leverage: None,
+            pointwise_leverage: None,
+            initial_leverage: Some(-1.0),
+            log_values: false,
+            decimal_places: None,
+            financing: Some(super::Financing { yearly_margin_rate: 1.1, yearly_borrow_fee: 1.05, ticks_per_year: 1.0 }),
+        };
+        let returns = [1.5f64, 1.5, 0.5];
+        let values = |args: &super::AccumulateArgs| super::accumulate(returns.into_iter(), args);
+        // A short earns the rebate on its 20 of cash and pays the fee on the
+        // shorted shares: 22 - 15 * 1.05 after the first tick
+        let short = values(&args);
+        assert_approx_eq!(6.25, short[0]);
+        assert_approx_eq!(24.2 - 10.0 * 1.5 * 1.5 * 1.05 * 1.05, short[1]);
+        assert_approx_eq!(26.62 - 10.0 * 1.125 * 1.05f64.powi(3), short[2]);
+        assert_approx_eq!(6.25, values(&super::AccumulateArgs { decimal_places: Some(2), ..args.clone() })[0]);
+        let logs = values(&super::AccumulateArgs { log_values: true, ..args.clone() });
+        assert_approx_eq!(6.25f64.ln(), logs[0]);
+        // An inverse fund holds twice its value as cash every tick
+        let pointwise = super::AccumulateArgs { initial_leverage: None, pointwise_leverage: Some(-1.0), ..args.clone() };
+        assert_approx_eq!(6.25 * (2.2 - 1.575), values(&pointwise)[1]);
+        let continuous = super::AccumulateArgs { initial_leverage: None, continuous_leverage: Some(-1.0), ..args.clone() };
+        assert_approx_eq!(10.0 * 1.21 / 1.575, values(&continuous)[0]);
+
+        // Long leverage pays interest on the loan, unlevered values nothing
+        let long = super::AccumulateArgs { initial_leverage: Some(2.0), ..args.clone() };
+        assert_approx_eq!(30.0 - 11.0, values(&long)[0]);
+        assert_approx_eq!(19.0, values(&super::AccumulateArgs { initial_leverage: None, pointwise_leverage: Some(2.0), ..args.clone() })[0]);
+        assert_eq!(vec![15.0, 22.5, 11.25], values(&super::AccumulateArgs { initial_leverage: None, ..args.clone() }));
+
+        let free = super::Financing { yearly_margin_rate: 0.0, yearly_borrow_fee: 1.0, ticks_per_year: 1.0 };
+        assert!(super::AccumulateArgs { financing: Some(free), ..args }.validate().is_err());
+    }
+
+    #[test]
+    fn accumulate_iter_is_lazy() {
+        let args = super::AccumulateArgs {
+            accumulate: true,
+            start_value: 1.0,
+            continuous_leverage: None,
+            pointwise_leverage: Some(2.0),
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        // An endless series, only as much of it as is taken is accumulated
+        let values: Vec<f64> = super::accumulate_iter(std::iter::repeat(1.5), &args).take(3).collect();
+        assert_eq!(vec![2.0, 4.0, 8.0], values);
+
+        let mut acc = super::Accumulator::new(&super::AccumulateArgs { accumulate: false, ..args });
+        assert_eq!(1.5, acc.push(1.5));
+    }
+
+    #[test]
+    fn data_points_carry_time_and_return() {
+        use super::DataPoint;
+
+        let args = super::AccumulateArgs {
+            accumulate: true,
+            start_value: 10.0,
+            continuous_leverage: None,
+            pointwise_leverage: None,
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        let points: Vec<DataPoint> = super::data_points([1.5, 0.5].into_iter(), 60.0, &args).collect();
+        assert_eq!(
+            vec![
+                DataPoint { t_seconds: 60.0, tick_return: 1.5, value: 15.0, price_level: None },
+                DataPoint { t_seconds: 120.0, tick_return: 0.5, value: 7.5, price_level: None },
+            ],
+            points
+        );
     }
 
     #[test]
@@ -198,6 +1734,9 @@ mod tests {
             continuous_leverage: None,
             pointwise_leverage: None,
             initial_leverage: Some(leverage),
+            log_values: false,
+            decimal_places: None,
+            financing: None,
         };
         let returns: Vec<f64> = vec![1.04, 1.01, 0.99, 0.98, 1.05, 1.1, 0.4];
         let res = super::accumulate(returns.clone().into_iter(), &args);
@@ -207,4 +1746,32 @@ mod tests {
             assert_approx_eq!(50.0 * ret_product - 40.0, acc);
         }
     }
+
+    #[test]
+    fn accumulate_in_decimal_places() {
+        let args = super::AccumulateArgs {
+            accumulate: true,
+            start_value: 100.1,
+            continuous_leverage: None,
+            pointwise_leverage: Some(2.0),
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: Some(2),
+            financing: None,
+        };
+        // 100.10 * 1.1 = 110.11, 110.11 * 1.01 = 111.2111
+        let values: Vec<f64> = super::accumulate([1.05, 1.005].into_iter(), &args);
+        assert_eq!(vec![110.11, 111.21], values);
+
+        let returns: Vec<f64> = vec![1.04, 1.01, 0.99, 0.98, 1.05, 1.1, 0.4];
+        let args = super::AccumulateArgs { pointwise_leverage: None, initial_leverage: Some(5.0), ..args };
+        let exact = super::accumulate(returns.clone().into_iter(), &args);
+        let float = super::accumulate(returns.into_iter(), &super::AccumulateArgs { decimal_places: None, ..args });
+        for (e, f) in std::iter::zip(exact, float) {
+            assert_eq!(e, (e * 100.0).round() / 100.0);
+            assert_approx_eq!(f, e, 0.01);
+        }
+        assert!(super::AccumulateArgs { log_values: true, ..args }.validate().is_err());
+        assert!(super::AccumulateArgs { decimal_places: Some(10), ..args }.validate().is_err());
+    }
 }
@@ -0,0 +1,187 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::returns::{GenReturnsArgs, RngKind, TimeBase, gen_log_returns, gen_returns};
+use crate::stats::Moments;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SelftestArgs {
+    /// Number of returns generated for each case
+    pub num_points: usize,
+
+    /// Seed of the first case, the following cases use the following seeds
+    pub seed: u64,
+
+    /// Allowed deviation from the requested parameters, in standard errors of their estimates
+    pub tolerance: f64,
+}
+
+/// Interval in seconds, yearly mean and yearly stddev of each case. The mean
+/// only converges over many simulated years, so there are no shorter ticks.
+const CASES: [(&str, usize, f64, f64); 4] = [
+    ("1h", 3600, 1.1, 1.2),
+    ("1d", 86400, 1.07, 1.15),
+    ("1w", 604800, 0.8, 1.05),
+    ("1y", 31556952, 1.5, 2.0),
+];
+
+/// The first returns of `gen_returns` with seed 123456789, a 1 second
+/// interval, yearly mean 1.1 and yearly stddev 1.5
+const REFERENCE: [f64; 10] = [
+    1.0000429075842392,
+    0.999960403828504,
+    0.9999473836672608,
+    0.9999852885724231,
+    0.9999308265121937,
+    0.9999956874033457,
+    1.0000545633156286,
+    1.0000529797693074,
+    0.9999630744056991,
+    0.9999348459587809,
+];
+
+/// The outcome of one self-test check
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Check {
+    pub name: String,
+    /// Deviation of the log of the realized value from the log of the
+    /// expected one
+    pub deviation: f64,
+    pub tolerance: f64,
+    pub detail: String,
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.deviation.abs() <= self.tolerance
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = if self.passed() { "ok" } else { "FAILED" };
+        write!(f, "{:<6} {}: {}", status, self.name, self.detail)
+    }
+}
+
+impl SelftestArgs {
+    pub fn validate(&self) -> Result<()> {
+        if self.num_points < 2 {
+            return Err(Error::Invalid("--num-points must be at least 2".to_string()));
+        }
+        if !self.tolerance.is_finite() || self.tolerance <= 0.0 {
+            return Err(Error::Invalid(format!("--tolerance must be a positive number, got {}", self.tolerance)));
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a fixed seed reproduces the reference series and that the
+/// realized yearly mean and stddev of large generated series are within the
+/// tolerance of the requested ones
+pub fn selftest(args: &SelftestArgs) -> Result<Vec<Check>> {
+    let mut checks = vec![reproducibility()?];
+    for (i, (name, interval_seconds, yearly_mean, yearly_stddev)) in CASES.into_iter().enumerate() {
+        let gen_args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(interval_seconds),
+            num_points: args.num_points,
+            yearly_mean,
+            yearly_stddev,
+            seed: Some(args.seed.wrapping_add(i as u64)),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let ticks_per_year = gen_args.derived().ticks_per_year;
+        let (mean, variance) = log_moments(gen_log_returns(&gen_args)?);
+        let n = args.num_points as f64;
+        let yearly_sigma = yearly_stddev.ln();
+        checks.push(compare(
+            format!("{} ticks, yearly mean", name),
+            yearly_mean,
+            (mean * ticks_per_year).exp(),
+            args.tolerance * yearly_sigma * (ticks_per_year / n).sqrt(),
+        ));
+        checks.push(compare(
+            format!("{} ticks, yearly stddev", name),
+            yearly_stddev,
+            (variance * ticks_per_year).sqrt().exp(),
+            args.tolerance * yearly_sigma / (2.0 * n).sqrt(),
+        ));
+    }
+    Ok(checks)
+}
+
+fn reproducibility() -> Result<Check> {
+    let args = GenReturnsArgs {
+        total_seconds: None,
+        interval_seconds: Some(1),
+        num_points: REFERENCE.len(),
+        yearly_mean: 1.1,
+        yearly_stddev: 1.5,
+        seed: Some(123456789),
+        rng: RngKind::Std,
+        time_base: TimeBase::WallClock,
+        tail_df: None,
+    };
+    let deviation = gen_returns(&args)?
+        .zip(REFERENCE)
+        .map(|(r, expected)| (r / expected).ln().abs())
+        .fold(0.0, f64::max);
+    Ok(Check {
+        name: "fixed seed".to_string(),
+        deviation,
+        tolerance: 1e-12,
+        detail: format!("reproduces the reference series within {:e}", deviation),
+    })
+}
+
+/// Compares geometric factors, with the tolerance in log terms
+fn compare(name: String, expected: f64, realized: f64, tolerance: f64) -> Check {
+    Check {
+        name,
+        deviation: (realized / expected).ln(),
+        tolerance,
+        detail: format!("{:.5} (requested {} ± {:.3}%)", realized, expected, 100.0 * tolerance.exp_m1()),
+    }
+}
+
+/// Mean and sample variance of the log returns
+fn log_moments(log_returns: impl Iterator<Item = f64>) -> (f64, f64) {
+    let moments: Moments = log_returns.collect();
+    (moments.mean(), moments.variance())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SelftestArgs, log_moments, selftest};
+    use assert_approx_eq::assert_approx_eq;
+
+    #[test]
+    fn log_moments_test() {
+        let (mean, variance) = log_moments([0.0, 2.0, 4.0].into_iter());
+        assert_approx_eq!(2.0, mean);
+        assert_approx_eq!(4.0, variance);
+    }
+
+    #[test]
+    fn selftest_passes() {
+        let mut args = SelftestArgs {
+            num_points: 10_000,
+            seed: 1,
+            tolerance: 4.0,
+        };
+        let checks = selftest(&args).unwrap();
+        assert_eq!(9, checks.len());
+        assert!(checks.iter().all(|c| c.passed()), "{:?}", checks);
+
+        args.tolerance = 1e-9;
+        assert!(!selftest(&args).unwrap()[1..].iter().any(|c| c.passed()));
+        assert!(args.validate().is_ok());
+        args.num_points = 1;
+        assert!(args.validate().is_err());
+    }
+}
@@ -0,0 +1,284 @@
+//! A small HTTP/JSON API, so that web frontends and notebooks can run
+//! simulations without shelling out. Requests are served one at a time, each
+//! on a connection of its own, and a client silent for `metrics::TIMEOUT` is
+//! dropped rather than holding up the others:
+//!
+//! - `GET /health` answers `{"status": "ok"}`
+//! - `POST /paths` simulates the flags in the body and answers
+//!   `{"paths": [[...], ...]}`, the values (or returns) of every path
+//! - `POST /summary` answers `{"summaries": [...]}` instead, with the count,
+//!   min, max, mean and last value of every path
+//! - `GET /metrics` answers the Prometheus metrics of the server, see
+//!   `metrics::Metrics`
+//!
+//! The body is a JSON object of flags in the format of a config file, e.g.
+//! `{"num_points": 252, "interval": "1d", "accumulate": true, "seed": 1}`,
+//! plus an optional `"paths"` (1 by default). Requests for more than
+//! `max_points` points over all paths are refused, since every path is held
+//! in memory. Errors answer with a 4xx status and `{"error": "..."}`.
+
+use std::io::{self, BufRead, BufReader};
+use std::net::{TcpListener, TcpStream};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config;
+use crate::error::{Error, Result};
+use crate::metrics::{self, Metrics, TIMEOUT};
+use crate::output::Summary;
+use crate::returns::parse_count;
+
+/// Largest request body accepted, in bytes
+const MAX_BODY: usize = 1 << 20;
+
+/// A request, as far as the API looks at it
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// A response: the status code and the body, JSON unless `text`
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+    pub text: bool,
+}
+
+impl Response {
+    fn json(status: u16, body: &impl Serialize) -> Response {
+        let body = serde_json::to_string(body).unwrap_or_else(|e| format!("{{\"error\":\"{}\"}}", e));
+        Response { status, body, text: false }
+    }
+
+    fn error(status: u16, message: impl ToString) -> Response {
+        #[derive(Serialize)]
+        struct ErrorBody {
+            error: String,
+        }
+        Response::json(status, &ErrorBody { error: message.to_string().trim_end().to_string() })
+    }
+}
+
+/// Serves the API on `listener` until it fails. `simulate` runs the
+/// simulation of the flags of a request (as command line arguments) and
+/// returns the values of the given number of paths, of at most `max_points`
+/// points together.
+pub fn serve(
+    listener: TcpListener,
+    max_points: usize,
+    simulate: impl Fn(&[String], usize) -> Result<Vec<Vec<f64>>>,
+) -> io::Result<()> {
+    let metrics = Metrics::new();
+    for stream in listener.incoming() {
+        if let Err(e) = handle(stream?, max_points, &simulate, &metrics) {
+            warn!(error = %e, "failed to serve a request");
+        }
+    }
+    Ok(())
+}
+
+fn handle(
+    mut stream: TcpStream,
+    max_points: usize,
+    simulate: &impl Fn(&[String], usize) -> Result<Vec<Vec<f64>>>,
+    metrics: &Metrics,
+) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let response = match read_request(BufReader::new(&mut stream)) {
+        Ok(request) => {
+            info!(method = request.method, path = request.path, "request");
+            respond(&request, max_points, simulate, metrics)
+        }
+        Err(e) => {
+            metrics.request();
+            metrics.error();
+            Response::error(400, e)
+        }
+    };
+    let content_type = if response.text { metrics::TEXT_CONTENT_TYPE } else { "application/json" };
+    metrics::write_response(&mut stream, response.status, content_type, &response.body)?;
+    Ok(())
+}
+
+/// Reads an HTTP/1.1 request with a body of `Content-Length` bytes, and
+/// headers of at most `metrics::MAX_HEAD` bytes
+pub fn read_request(mut r: impl BufRead) -> Result<Request> {
+    let head = metrics::read_head(&mut r)?;
+    let line = head.first().map_or("", String::as_str);
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(Error::Parse(format!("invalid request line '{}'", line)));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut length = 0;
+    for line in &head[1..] {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| Error::Parse(format!("invalid Content-Length '{}'", value.trim())))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(Error::Invalid(format!("the body must be at most {} bytes, got {}", MAX_BODY, length)));
+    }
+    let mut body = vec![0; length];
+    r.read_exact(&mut body)?;
+    Ok(Request { method, path, body })
+}
+
+/// Answers a request, running `simulate` for the simulation endpoints unless
+/// they ask for more than `max_points` points, and counting it in `metrics`
+pub fn respond(
+    request: &Request,
+    max_points: usize,
+    simulate: &impl Fn(&[String], usize) -> Result<Vec<Vec<f64>>>,
+    metrics: &Metrics,
+) -> Response {
+    #[derive(Serialize)]
+    struct Health {
+        status: &'static str,
+    }
+    #[derive(Serialize)]
+    struct Paths {
+        paths: Vec<Vec<f64>>,
+    }
+    #[derive(Serialize)]
+    struct Summaries {
+        summaries: Vec<Summary>,
+    }
+    metrics.request();
+    let path = request.path.split('?').next().unwrap_or_default();
+    let response = match (request.method.as_str(), path) {
+        ("GET", "/health") => Response::json(200, &Health { status: "ok" }),
+        ("GET", "/metrics") => Response { status: 200, body: metrics.render(), text: true },
+        ("POST", "/paths" | "/summary") => match parse_body(&request.body, max_points).and_then(|(args, paths)| simulate(&args, paths)) {
+            Ok(paths) => {
+                for p in &paths {
+                    metrics.path_completed(p.len());
+                }
+                if path == "/paths" {
+                    Response::json(200, &Paths { paths })
+                } else {
+                    Response::json(200, &Summaries { summaries: paths.iter().map(|p| Summary::of(p)).collect() })
+                }
+            }
+            Err(e) => Response::error(400, e),
+        },
+        (_, "/health" | "/metrics" | "/paths" | "/summary") => {
+            Response::error(405, format!("{} isn't allowed on {}", request.method, path))
+        }
+        _ => Response::error(404, format!("no endpoint {}", path)),
+    };
+    if response.status >= 400 {
+        metrics.error();
+    }
+    response
+}
+
+/// The flags of a request body and its number of paths, of at most
+/// `max_points` points together
+fn parse_body(body: &[u8], max_points: usize) -> Result<(Vec<String>, usize)> {
+    let mut table: toml::Table = match body {
+        [] => toml::Table::new(),
+        body => serde_json::from_slice(body).map_err(|e| Error::Parse(format!("invalid JSON body: {}", e)))?,
+    };
+    let paths = match table.remove("paths") {
+        None => 1,
+        Some(toml::Value::Integer(n)) if n >= 1 => n as usize,
+        Some(value) => return Err(Error::Invalid(format!("paths must be a positive integer, got {}", value))),
+    };
+    // A missing or invalid number of points is left to the flag parsing
+    let num_points = ["num_points", "num-points"]
+        .iter()
+        .filter_map(|key| match table.get(*key)? {
+            toml::Value::Integer(n) => Some(*n as f64),
+            toml::Value::Float(n) => Some(*n),
+            toml::Value::String(s) => parse_count(s).ok().map(|n| n as f64),
+            _ => None,
+        })
+        .fold(0.0, f64::max);
+    if num_points * paths as f64 > max_points as f64 {
+        return Err(Error::Invalid(format!(
+            "at most {} points over all paths, got {} paths of {} points",
+            max_points, paths, num_points
+        )));
+    }
+    Ok((config::table_to_args(table)?, paths))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_request, respond, Request};
+    use crate::error::{Error, Result};
+    use crate::metrics::Metrics;
+
+    const MAX_POINTS: usize = 1000;
+
+    /// One path per requested path, holding the number of flags
+    fn simulate(args: &[String], paths: usize) -> Result<Vec<Vec<f64>>> {
+        if args.iter().any(|a| a == "--fail") {
+            return Err(Error::Invalid("failed".to_string()));
+        }
+        Ok(vec![vec![1.0, args.len() as f64]; paths])
+    }
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        Request { method: method.to_string(), path: path.to_string(), body: body.as_bytes().to_vec() }
+    }
+
+    #[test]
+    fn read_request_test() {
+        let raw = "POST /paths HTTP/1.1\r\nHost: localhost\r\ncontent-length: 13\r\n\r\n{\"seed\": 1}\r\n";
+        let request = read_request(raw.as_bytes()).unwrap();
+        assert_eq!(("POST", "/paths"), (request.method.as_str(), request.path.as_str()));
+        assert_eq!(b"{\"seed\": 1}\r\n".to_vec(), request.body);
+        assert!(read_request("GET /health HTTP/1.1\r\n\r\n".as_bytes()).unwrap().body.is_empty());
+        assert!(read_request("\r\n".as_bytes()).is_err());
+        assert!(read_request("POST /paths HTTP/1.1\r\nContent-Length: x\r\n\r\n".as_bytes()).is_err());
+
+        // An endless header is refused at the limit rather than buffered
+        let long = format!("GET /health HTTP/1.1\r\nX-Long: {}", "a".repeat(1 << 20));
+        let e = read_request(long.as_bytes()).unwrap_err();
+        assert!(e.to_string().contains("at most 8192 bytes"), "{}", e);
+        let fits = format!("GET /health HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(4000));
+        assert!(read_request(fits.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn respond_test() {
+        let metrics = Metrics::new();
+        assert_eq!(r#"{"status":"ok"}"#, respond(&request("GET", "/health", ""), MAX_POINTS, &simulate, &metrics).body);
+
+        // "--seed 1 --accumulate" and two paths
+        let response = respond(&request("POST", "/paths", r#"{"seed": 1, "accumulate": true, "paths": 2}"#), MAX_POINTS, &simulate, &metrics);
+        assert_eq!(200, response.status);
+        assert_eq!(r#"{"paths":[[1.0,3.0],[1.0,3.0]]}"#, response.body);
+        let response = respond(&request("POST", "/summary", ""), MAX_POINTS, &simulate, &metrics);
+        assert_eq!(r#"{"summaries":[{"count":2,"min":0.0,"max":1.0,"mean":0.5,"last":0.0}]}"#, response.body);
+
+        assert_eq!(400, respond(&request("POST", "/paths", "{"), MAX_POINTS, &simulate, &metrics).status);
+        assert_eq!(400, respond(&request("POST", "/paths", r#"{"paths": 0}"#), MAX_POINTS, &simulate, &metrics).status);
+        // 1e10 points are refused before simulating, however they're written
+        for body in [r#"{"num_points": 1e10, "paths": 1000}"#, r#"{"num-points": "1_000", "paths": 2}"#] {
+            let response = respond(&request("POST", "/paths", body), MAX_POINTS, &simulate, &metrics);
+            assert_eq!(400, response.status, "{}", response.body);
+        }
+        assert_eq!(200, respond(&request("POST", "/summary", r#"{"num_points": 500, "paths": 2}"#), MAX_POINTS, &simulate, &metrics).status);
+        let response = respond(&request("POST", "/paths", r#"{"fail": true}"#), MAX_POINTS, &simulate, &metrics);
+        assert_eq!((400, r#"{"error":"failed"}"#.to_string()), (response.status, response.body));
+        assert_eq!(405, respond(&request("GET", "/paths", ""), MAX_POINTS, &simulate, &metrics).status);
+        assert_eq!(404, respond(&request("GET", "/", ""), MAX_POINTS, &simulate, &metrics).status);
+
+        // Five paths and seven errors over the twelve requests so far
+        let response = respond(&request("GET", "/metrics", ""), MAX_POINTS, &simulate, &metrics);
+        assert!(response.text);
+        for line in ["finsim_paths_completed_total 5\n", "finsim_errors_total 7\n", "finsim_requests_total 12\n"] {
+            assert!(response.body.contains(line), "{}", response.body);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::model::{self, LogNormal, ReturnModel};
+use crate::output::Summary;
+use crate::preset::Preset;
+use crate::returns::{AccumulateArgs, DataPoints, Financing, GenReturnsArgs, RngKind, TimeBase, accumulate, data_points};
+#[cfg(feature = "stream")]
+use crate::stream::{self, IterStream};
+
+/// How the accumulated series is leveraged, see `AccumulateArgs`
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Leverage {
+    /// Held constant, releveraging continuously between points
+    Continuous(f64),
+    /// Held constant, releveraging discretely at every point
+    Pointwise(f64),
+    /// Set at t=0 and never releveraged
+    Initial(f64),
+}
+
+/// Builds a `Simulation` step by step, checking the combination of settings
+/// in `build`. Two of the horizon, the interval and the number of points
+/// determine the third.
+///
+/// ```
+/// use finsim::simulation::{Leverage, SimulationBuilder};
+///
+/// let values = SimulationBuilder::new()
+///     .model(1.07, 1.15)
+///     .horizon(30 * 31556952)
+///     .interval(86400)
+///     .seed(1)
+///     .accumulate(100.0)
+///     .leverage(Leverage::Pointwise(2.0))
+///     .build()
+///     .unwrap()
+///     .run()
+///     .unwrap();
+/// assert_eq!(10957, values.len());
+/// ```
+#[derive(Clone)]
+pub struct SimulationBuilder {
+    yearly_mean: f64,
+    yearly_stddev: f64,
+    model: Option<Rc<dyn ReturnModel>>,
+    horizon: Option<usize>,
+    interval: Option<usize>,
+    num_points: Option<usize>,
+    seed: Option<u64>,
+    rng: RngKind,
+    time_base: TimeBase,
+    tail_df: Option<f64>,
+    start_value: Option<f64>,
+    leverage: Option<Leverage>,
+    /// Yearly margin rate and borrow fee, see `Financing`
+    financing: Option<(f64, f64)>,
+    log_values: bool,
+}
+
+impl Default for SimulationBuilder {
+    /// The defaults of the command line: yearly mean 1.0 and stddev 1.5,
+    /// returns not accumulated
+    fn default() -> Self {
+        SimulationBuilder {
+            yearly_mean: 1.0,
+            yearly_stddev: 1.5,
+            model: None,
+            horizon: None,
+            interval: None,
+            num_points: None,
+            seed: None,
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+            start_value: None,
+            leverage: None,
+            financing: None,
+            log_values: false,
+        }
+    }
+}
+
+impl SimulationBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Yearly (geometric) mean and standard deviation as factors, e.g. 1.07
+    /// and 1.15 for +7% and 15%, of log-normal returns
+    pub fn model(mut self, yearly_mean: f64, yearly_stddev: f64) -> Self {
+        self.yearly_mean = yearly_mean;
+        self.yearly_stddev = yearly_stddev;
+        self.model = None;
+        self
+    }
+
+    /// Generates the returns with a model of your own instead
+    pub fn return_model(mut self, model: impl ReturnModel + 'static) -> Self {
+        self.model = Some(Rc::new(model));
+        self
+    }
+
+    /// The yearly mean and stddev of a built-in preset, with its tails and
+    /// wall-clock years for the crypto presets beyond bitcoin
+    pub fn preset(self, preset: Preset) -> Self {
+        let (mean, stddev) = preset.params();
+        let builder = self.model(mean, stddev);
+        match preset.tail_df() {
+            Some(df) => builder.tail_df(df).time_base(TimeBase::WallClock),
+            None => builder,
+        }
+    }
+
+    /// Draws the log returns from Student's t with `df` degrees of freedom
+    /// instead of the normal distribution, see `GenReturnsArgs::tail_df`
+    pub fn tail_df(mut self, df: f64) -> Self {
+        self.tail_df = Some(df);
+        self
+    }
+
+    /// Simulation time in seconds, from the first data point to the last
+    pub fn horizon(mut self, seconds: usize) -> Self {
+        self.horizon = Some(seconds);
+        self
+    }
+
+    /// Time between data points in seconds
+    pub fn interval(mut self, seconds: usize) -> Self {
+        self.interval = Some(seconds);
+        self
+    }
+
+    pub fn num_points(mut self, num_points: usize) -> Self {
+        self.num_points = Some(num_points);
+        self
+    }
+
+    /// Seed for reproducible results, from entropy if not set
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// The random number generator, `RngKind::Std` by default
+    pub fn rng(mut self, rng: RngKind) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// What a year of the yearly mean and stddev is made of,
+    /// `TimeBase::WallClock` by default
+    pub fn time_base(mut self, time_base: TimeBase) -> Self {
+        self.time_base = time_base;
+        self
+    }
+
+    /// Accumulates the returns into values, starting from `start_value` at t=0
+    pub fn accumulate(mut self, start_value: f64) -> Self {
+        self.start_value = Some(start_value);
+        self
+    }
+
+    /// Leverage of the accumulated values, replacing any set before
+    pub fn leverage(mut self, leverage: Leverage) -> Self {
+        self.leverage = Some(leverage);
+        self
+    }
+
+    /// Charges the leverage a yearly margin rate on what it borrows, earned
+    /// on its cash, and a yearly borrow fee on the shares of a short, as
+    /// factors, e.g. 1.06 and 1.005 for 6% and 0.5%, see `Financing`
+    pub fn financing(mut self, yearly_margin_rate: f64, yearly_borrow_fee: f64) -> Self {
+        self.financing = Some((yearly_margin_rate, yearly_borrow_fee));
+        self
+    }
+
+    /// Outputs the natural logs of the values, see `AccumulateArgs::log_values`
+    pub fn log_values(mut self) -> Self {
+        self.log_values = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Simulation> {
+        let (total_seconds, interval_seconds, num_points) = match (self.horizon, self.interval, self.num_points) {
+            (Some(total), None, Some(n)) => (Some(total), None, n),
+            (None, Some(interval), Some(n)) => (None, Some(interval), n),
+            (Some(total), Some(interval), None) => {
+                if interval == 0 {
+                    return Err(Error::Invalid("the interval must be longer than 0 seconds".to_string()));
+                }
+                (None, Some(interval), (total as f64 / interval as f64).round() as usize)
+            }
+            (Some(_), Some(_), Some(_)) => {
+                return Err(Error::Invalid("give at most two of the horizon, the interval and the number of points".to_string()))
+            }
+            _ => return Err(Error::Invalid("give two of the horizon, the interval and the number of points".to_string())),
+        };
+        if self.leverage.is_some() && self.start_value.is_none() {
+            return Err(Error::Invalid("leverage only applies to accumulated values".to_string()));
+        }
+        let gen_returns = GenReturnsArgs {
+            total_seconds,
+            interval_seconds,
+            num_points,
+            yearly_mean: self.yearly_mean,
+            yearly_stddev: self.yearly_stddev,
+            seed: self.seed,
+            rng: self.rng,
+            time_base: self.time_base,
+            tail_df: self.tail_df,
+        };
+        let (continuous_leverage, pointwise_leverage, initial_leverage) = match self.leverage {
+            Some(Leverage::Continuous(l)) => (Some(l), None, None),
+            Some(Leverage::Pointwise(l)) => (None, Some(l), None),
+            Some(Leverage::Initial(l)) => (None, None, Some(l)),
+            None => (None, None, None),
+        };
+        let accumulate = AccumulateArgs {
+            accumulate: self.start_value.is_some(),
+            start_value: self.start_value.unwrap_or(1.0),
+            continuous_leverage,
+            pointwise_leverage,
+            initial_leverage,
+            log_values: self.log_values,
+            decimal_places: None,
+            financing: self.financing.map(|(yearly_margin_rate, yearly_borrow_fee)| Financing {
+                yearly_margin_rate,
+                yearly_borrow_fee,
+                ticks_per_year: gen_returns.time_base.ticks_per_year(gen_returns.tick_interval()),
+            }),
+        };
+        gen_returns.validate()?;
+        accumulate.validate()?;
+        let model = self.model.unwrap_or_else(|| Rc::new(LogNormal::from(&gen_returns)));
+        Ok(Simulation { gen_returns, accumulate, model })
+    }
+}
+
+/// A validated simulation, see `SimulationBuilder`
+#[derive(Clone)]
+pub struct Simulation {
+    gen_returns: GenReturnsArgs,
+    accumulate: AccumulateArgs,
+    model: Rc<dyn ReturnModel>,
+}
+
+impl Simulation {
+    pub fn gen_args(&self) -> &GenReturnsArgs {
+        &self.gen_returns
+    }
+
+    pub fn acc_args(&self) -> &AccumulateArgs {
+        &self.accumulate
+    }
+
+    /// The generated returns
+    pub fn returns(&self) -> Result<Box<dyn Iterator<Item = f64> + '_>> {
+        let args = &self.gen_returns;
+        self.model.returns(args.tick_interval_seconds(), args.num_points, args.seed)
+    }
+
+    /// The generated returns, accumulated if requested
+    pub fn run(&self) -> Result<Vec<f64>> {
+        Ok(accumulate(self.returns()?, &self.accumulate))
+    }
+
+    /// Like `run`, as data points generated one at a time
+    pub fn data_points(&self) -> Result<DataPoints<Box<dyn Iterator<Item = f64> + '_>>> {
+        Ok(data_points(self.returns()?, self.gen_returns.tick_interval(), &self.accumulate))
+    }
+
+    /// The data points of path `i` of `monte_carlo`, seeded with
+    /// `model::path_seed(seed, i)`
+    pub fn path(&self, i: usize) -> Result<DataPoints<Box<dyn Iterator<Item = f64> + '_>>> {
+        let args = &self.gen_returns;
+        let seed = args.seed.map(|s| model::path_seed(s, i));
+        let returns = self.model.returns(args.tick_interval_seconds(), args.num_points, seed)?;
+        Ok(data_points(returns, args.tick_interval(), &self.accumulate))
+    }
+
+    /// Like `data_points`, as a `futures::Stream`
+    #[cfg(feature = "stream")]
+    pub fn stream(&self) -> Result<IterStream<DataPoints<Box<dyn Iterator<Item = f64> + '_>>>> {
+        Ok(stream::stream(self.data_points()?))
+    }
+
+    /// Summaries of `paths` runs, each with its own seed derived from the
+    /// seed, see `model::monte_carlo`
+    pub fn monte_carlo(&self, paths: usize) -> Result<Vec<Summary>> {
+        let args = &self.gen_returns;
+        model::monte_carlo(&*self.model, args.tick_interval_seconds(), args.num_points, &self.accumulate, None, paths, args.seed)
+            .collect()
+    }
+
+    /// Like `monte_carlo`, but keeping every value: row `i` of the result is
+    /// path `i`, one column per tick
+    #[cfg(feature = "ndarray")]
+    pub fn paths(&self, paths: usize) -> Result<ndarray::Array2<f64>> {
+        let args = &self.gen_returns;
+        let mut out = ndarray::Array2::zeros((paths, args.num_points));
+        for (i, mut row) in out.rows_mut().into_iter().enumerate() {
+            for (o, p) in row.iter_mut().zip(self.path(i)?) {
+                *o = p.value;
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Leverage, SimulationBuilder};
+    use crate::error::Result;
+    use crate::model::ReturnModel;
+    use crate::preset::Preset;
+
+    /// Alternating +10% and -10%
+    struct Alternating;
+
+    impl ReturnModel for Alternating {
+        fn returns(&self, _: f64, num_points: usize, _: Option<u64>) -> Result<Box<dyn Iterator<Item = f64> + '_>> {
+            Ok(Box::new([1.1, 0.9].into_iter().cycle().take(num_points)))
+        }
+    }
+
+    #[test]
+    fn build_test() {
+        let sim = SimulationBuilder::new().preset(Preset::Gold).horizon(3600).interval(60).seed(7).build().unwrap();
+        assert_eq!(60, sim.gen_args().num_points);
+        assert_eq!(Some(60), sim.gen_args().interval_seconds);
+        assert_eq!(1.05, sim.gen_args().yearly_mean);
+        assert!(!sim.acc_args().accumulate);
+        assert_eq!(sim.returns().unwrap().collect::<Vec<f64>>(), sim.run().unwrap());
+
+        let sim = SimulationBuilder::new()
+            .num_points(10)
+            .interval(60)
+            .accumulate(100.0)
+            .leverage(Leverage::Initial(3.0))
+            .leverage(Leverage::Continuous(2.0))
+            .build()
+            .unwrap();
+        assert_eq!(Some(2.0), sim.acc_args().continuous_leverage);
+        assert_eq!(None, sim.acc_args().initial_leverage);
+        assert_eq!(10, sim.run().unwrap().len());
+
+        let sim = SimulationBuilder::new().return_model(Alternating).num_points(3).interval(1).build().unwrap();
+        assert_eq!(vec![1.1, 0.9, 1.1], sim.run().unwrap());
+
+        let builder = SimulationBuilder::new().return_model(Alternating).num_points(3).interval(1);
+        let sim = builder.accumulate(1.0).build().unwrap();
+        let summaries = sim.monte_carlo(2).unwrap();
+        assert_eq!(2, summaries.len());
+        assert_eq!(sim.run().unwrap()[2], summaries[1].last);
+        let points: Vec<_> = sim.data_points().unwrap().collect();
+        assert_eq!(sim.run().unwrap(), points.iter().map(|p| p.value).collect::<Vec<f64>>());
+        assert_eq!(3.0, points[2].t_seconds);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn paths_match_monte_carlo() {
+        let sim = SimulationBuilder::new().model(1.07, 1.2).num_points(10).interval(86400).seed(1).accumulate(100.0);
+        let sim = sim.build().unwrap();
+        let paths = sim.paths(3).unwrap();
+        assert_eq!((3, 10), paths.dim());
+        for (row, summary) in paths.rows().into_iter().zip(sim.monte_carlo(3).unwrap()) {
+            assert_eq!(summary.last, row[9]);
+        }
+    }
+
+    #[test]
+    fn build_errors() {
+        assert!(SimulationBuilder::new().num_points(10).build().is_err());
+        assert!(SimulationBuilder::new().num_points(10).horizon(10).interval(1).build().is_err());
+        assert!(SimulationBuilder::new().horizon(10).interval(0).build().is_err());
+        assert!(SimulationBuilder::new().num_points(10).interval(1).model(1.1, 0.5).build().is_err());
+        let unaccumulated = SimulationBuilder::new().num_points(10).interval(1).leverage(Leverage::Pointwise(2.0));
+        assert!(unaccumulated.build().is_err());
+    }
+}
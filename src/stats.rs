@@ -0,0 +1,374 @@
+//! Running statistics that stay accurate over long series. Naively summing
+//! millions of ticks loses the low bits of every term once the total grows,
+//! and `E[x²] - E[x]²` cancels catastrophically when the variance is small
+//! relative to the mean, so sums are compensated and moments are updated
+//! with Welford's algorithm instead.
+//!
+//! Statistics of a whole series that `stats` reports on request, like the
+//! Hurst exponent, are here too.
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+/// Neumaier's variant of Kahan summation: the rounding error of each
+/// addition is carried separately and added back at the end
+#[derive(Clone, Copy, Debug, Default)]
+pub struct KahanSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl KahanSum {
+    pub fn add(&mut self, x: f64) {
+        let t = self.sum + x;
+        if self.sum.abs() >= x.abs() {
+            self.compensation += (self.sum - t) + x;
+        } else {
+            self.compensation += (x - t) + self.sum;
+        }
+        self.sum = t;
+    }
+
+    /// The compensated sum. Once it overflows or meets an infinity or NaN
+    /// the compensation is meaningless, so the plain sum is returned.
+    pub fn value(&self) -> f64 {
+        if self.sum.is_finite() { self.sum + self.compensation } else { self.sum }
+    }
+}
+
+/// Count, mean and central moments up to the fourth, updated one value at a
+/// time (Welford's algorithm, extended to higher moments by Pébay)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Moments {
+    n: f64,
+    mean: f64,
+    m2: f64,
+    m3: f64,
+    m4: f64,
+}
+
+impl Moments {
+    pub fn push(&mut self, x: f64) {
+        let n1 = self.n;
+        self.n += 1.0;
+        let n = self.n;
+        let delta = x - self.mean;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+        let term1 = delta * delta_n * n1;
+        self.mean += delta_n;
+        self.m4 += term1 * delta_n2 * (n * n - 3.0 * n + 3.0) + 6.0 * delta_n2 * self.m2 - 4.0 * delta_n * self.m3;
+        self.m3 += term1 * delta_n * (n - 2.0) - 3.0 * delta_n * self.m2;
+        self.m2 += term1;
+    }
+
+    pub fn count(&self) -> usize {
+        self.n as usize
+    }
+
+    /// NaN when empty
+    pub fn mean(&self) -> f64 {
+        if self.n == 0.0 { f64::NAN } else { self.mean }
+    }
+
+    /// Sample variance, with Bessel's correction
+    pub fn variance(&self) -> f64 {
+        self.m2 / (self.n - 1.0)
+    }
+
+    /// Sample skewness, the third central moment over the population
+    /// variance to the power 1.5
+    pub fn skewness(&self) -> f64 {
+        (self.m3 / self.n) / (self.m2 / self.n).powf(1.5)
+    }
+
+    /// Sample excess kurtosis, the fourth central moment over the squared
+    /// population variance, minus 3
+    pub fn excess_kurtosis(&self) -> f64 {
+        (self.m4 / self.n) / (self.m2 / self.n).powi(2) - 3.0
+    }
+}
+
+impl Extend<f64> for Moments {
+    fn extend<I: IntoIterator<Item = f64>>(&mut self, values: I) {
+        values.into_iter().for_each(|x| self.push(x));
+    }
+}
+
+impl FromIterator<f64> for Moments {
+    fn from_iter<I: IntoIterator<Item = f64>>(values: I) -> Self {
+        let mut moments = Moments::default();
+        moments.extend(values);
+        moments
+    }
+}
+
+/// The `q` quantile of the values, interpolating linearly between the two
+/// nearest, so that 0.5 is the median. NaN when empty.
+pub fn quantile(values: &[f64], q: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let rank = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (below, above) = (sorted[rank.floor() as usize], sorted[rank.ceil() as usize]);
+    below + (above - below) * rank.fract()
+}
+
+/// `count` out of `total` in percent, 0 when `total` is 0
+pub fn percent(count: usize, total: usize) -> f64 {
+    match total {
+        0 => 0.0,
+        n => 100.0 * count as f64 / n as f64,
+    }
+}
+
+/// The compound yearly return of `returns` with `ticks_per_year` ticks a
+/// year, e.g. 0.05 for 5% a year
+pub fn yearly_return(returns: &[f64], ticks_per_year: f64) -> f64 {
+    let mut logs = KahanSum::default();
+    returns.iter().for_each(|r| logs.add(r.ln()));
+    (logs.value() * ticks_per_year / returns.len() as f64).exp() - 1.0
+}
+
+/// The Omega ratio of `returns` at a `threshold` return per tick, e.g. 1.0
+/// for breaking even: the sum of the gains above the threshold over the sum
+/// of the shortfalls below it, the probability-weighted ratio of gains to
+/// losses of the whole distribution rather than its mean and variance.
+/// Infinite without shortfalls, NaN without returns.
+pub fn omega(returns: &[f64], threshold: f64) -> f64 {
+    let (mut gains, mut shortfalls) = (KahanSum::default(), KahanSum::default());
+    for &r in returns {
+        if r > threshold {
+            gains.add(r - threshold);
+        } else {
+            shortfalls.add(threshold - r);
+        }
+    }
+    if returns.is_empty() { f64::NAN } else { gains.value() / shortfalls.value() }
+}
+
+/// Statistics of returns (e.g. 1.01 for +1%) relative to those of a
+/// benchmark over the same ticks
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct BenchmarkStats {
+    /// The covariance with the benchmark over its variance
+    pub beta: f64,
+    pub correlation: f64,
+    /// The yearly standard deviation of the returns minus the benchmark's
+    pub tracking_error: f64,
+    /// The yearly mean of the returns minus the benchmark's, over the
+    /// tracking error
+    pub information_ratio: f64,
+}
+
+impl BenchmarkStats {
+    /// Compares `returns` with the `benchmark` returns of the same ticks,
+    /// with `ticks_per_year` ticks a year. NaN with fewer than 2 ticks, an
+    /// error if the two differ in length.
+    pub fn of(returns: &[f64], benchmark: &[f64], ticks_per_year: f64) -> Result<BenchmarkStats> {
+        if returns.len() != benchmark.len() {
+            return Err(Error::Invalid(format!(
+                "the benchmark has {} returns, the series {}",
+                benchmark.len(),
+                returns.len()
+            )));
+        }
+        let (mut moments_r, mut moments_b, mut active) = (Moments::default(), Moments::default(), Moments::default());
+        // The co-moment, the sum of the products of the deviations from the
+        // means, updated like the second moments of Welford's algorithm
+        let mut co_moment = 0.0;
+        for (&r, &b) in returns.iter().zip(benchmark) {
+            let delta_r = r - moments_r.mean;
+            moments_r.push(r);
+            moments_b.push(b);
+            co_moment += delta_r * (b - moments_b.mean);
+            active.push(r - b);
+        }
+        let tracking_error = (active.variance() * ticks_per_year).sqrt();
+        Ok(BenchmarkStats {
+            beta: co_moment / moments_b.m2,
+            correlation: co_moment / (moments_r.m2 * moments_b.m2).sqrt(),
+            tracking_error,
+            information_ratio: active.mean() * ticks_per_year / tracking_error,
+        })
+    }
+}
+
+/// Smallest window of `hurst`, in ticks
+pub const HURST_MIN_WINDOW: usize = 8;
+
+/// The Hurst exponent of a series of returns (e.g. 1.01 for +1%) by
+/// detrended fluctuation analysis (DFA) of their logs: the profile, the
+/// running sum of the log returns minus their mean, is cut into windows of
+/// 8, 16, 32... ticks, each detrended by a least-squares line, and the
+/// exponent is the slope of the log of the root mean square residual over
+/// the log of the window. Windows go up to a quarter of the series, so that
+/// every size has at least 4 of them. 0.5 for independent returns, above
+/// for persistent ones (long memory) and below for mean-reverting ones. NaN
+/// for fewer than `4 * 2 * HURST_MIN_WINDOW` returns.
+pub fn hurst(returns: &[f64]) -> f64 {
+    let logs: Vec<f64> = returns.iter().map(|r| r.ln()).collect();
+    let mean = logs.iter().sum::<f64>() / logs.len() as f64;
+    let profile: Vec<f64> = logs
+        .iter()
+        .scan(0.0, |sum, x| {
+            *sum += x - mean;
+            Some(*sum)
+        })
+        .collect();
+    let points: Vec<(f64, f64)> = std::iter::successors(Some(HURST_MIN_WINDOW), |n| Some(n * 2))
+        .take_while(|&n| n * 4 <= profile.len())
+        .map(|n| {
+            let squares: f64 = profile.chunks_exact(n).map(detrended_squares).sum();
+            let fluctuation = (squares / (profile.len() / n * n) as f64).sqrt();
+            ((n as f64).ln(), fluctuation.ln())
+        })
+        .collect();
+    if points.len() < 2 {
+        return f64::NAN;
+    }
+    slope(&points)
+}
+
+/// The sum of the squared residuals of `ys` around their least-squares line
+/// over 0, 1, 2...
+fn detrended_squares(ys: &[f64]) -> f64 {
+    let points: Vec<(f64, f64)> = ys.iter().enumerate().map(|(x, &y)| (x as f64, y)).collect();
+    let b = slope(&points);
+    let (mean_x, mean_y) = ((ys.len() - 1) as f64 / 2.0, ys.iter().sum::<f64>() / ys.len() as f64);
+    points.iter().map(|&(x, y)| (y - mean_y - b * (x - mean_x)).powi(2)).sum()
+}
+
+/// The slope of the least-squares line through the points
+fn slope(points: &[(f64, f64)]) -> f64 {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let covariance: f64 = points.iter().map(|&(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    let variance: f64 = points.iter().map(|&(x, _)| (x - mean_x).powi(2)).sum();
+    covariance / variance
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use rand_distr::{Distribution, StandardNormal};
+
+    use super::{BenchmarkStats, KahanSum, Moments, hurst, omega, percent, quantile, yearly_return};
+    use crate::returns::{RngKind, SeededRng};
+
+    #[test]
+    fn kahan_sum_keeps_small_terms() {
+        let mut sum = KahanSum::default();
+        let mut naive = 0.0;
+        for x in std::iter::once(1e16).chain(std::iter::repeat_n(1.0, 10_000)).chain(std::iter::once(-1e16)) {
+            sum.add(x);
+            naive += x;
+        }
+        assert_eq!(10_000.0, sum.value());
+        assert_ne!(10_000.0, naive);
+
+        sum.add(f64::NEG_INFINITY);
+        assert_eq!(f64::NEG_INFINITY, sum.value());
+    }
+
+    #[test]
+    fn moments_of_known_values() {
+        let m: Moments = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0].into_iter().collect();
+        assert_eq!(8, m.count());
+        assert_approx_eq!(5.0, m.mean());
+        assert_approx_eq!(32.0 / 7.0, m.variance());
+        // Central moments 4, 5.25 and 44.5 by hand
+        assert_approx_eq!(5.25 / 8.0, m.skewness());
+        assert_approx_eq!(44.5 / 16.0 - 3.0, m.excess_kurtosis());
+        assert!(Moments::default().mean().is_nan());
+    }
+
+    #[test]
+    fn moments_survive_a_large_offset() {
+        // Variance 1 around 1e9, where E[x²] - E[x]² is all rounding error
+        let m: Moments = (0..1_000_000).map(|i| 1e9 + if i % 2 == 0 { 1.0 } else { -1.0 }).collect();
+        assert_approx_eq!(1e9, m.mean());
+        assert_approx_eq!(1.0, m.variance(), 1e-5);
+        assert_approx_eq!(0.0, m.skewness(), 1e-5);
+        assert_approx_eq!(-2.0, m.excess_kurtosis(), 1e-5);
+    }
+
+    #[test]
+    fn quantiles_interpolate() {
+        assert_eq!(3.0, quantile(&[5.0, 1.0, 3.0], 0.5));
+        assert_eq!(2.0, quantile(&[4.0, 1.0, 3.0, 0.0], 0.5));
+        assert_eq!(0.5, quantile(&[0.0, 1.0, 5.0], 0.25));
+        assert_eq!(5.0, quantile(&[0.0, 1.0, 5.0], 1.0));
+        assert!(quantile(&[], 0.5).is_nan());
+    }
+
+    #[test]
+    fn yearly_return_compounds() {
+        assert_approx_eq!(0.21, yearly_return(&[1.1; 4], 2.0));
+        assert_approx_eq!(0.1, yearly_return(&[1.21, 1.0], 1.0));
+    }
+
+    #[test]
+    fn omega_at_thresholds() {
+        let returns = [1.02, 0.99, 1.01, 0.97];
+        assert_approx_eq!(0.75, omega(&returns, 1.0));
+        // A gain of 0.009 and shortfalls of 0.021, 0.001 and 0.041 at 1.011
+        assert_approx_eq!(0.009 / 0.063, omega(&returns, 1.011));
+        assert_eq!(f64::INFINITY, omega(&[1.1], 1.0));
+        assert!(omega(&[], 1.0).is_nan());
+    }
+
+    #[test]
+    fn percent_of_total() {
+        assert_eq!(25.0, percent(1, 4));
+        assert_eq!(0.0, percent(0, 0));
+    }
+
+    #[test]
+    fn benchmark_stats() {
+        let benchmark = [1.01, 0.98, 1.03, 1.0];
+        // Twice the benchmark's returns, beta 2
+        let returns: Vec<f64> = benchmark.iter().map(|b| 2.0 * b - 1.0).collect();
+        let stats = BenchmarkStats::of(&returns, &benchmark, 4.0).unwrap();
+        assert_approx_eq!(2.0, stats.beta);
+        assert_approx_eq!(1.0, stats.correlation);
+        // Active returns of 0.01, -0.02, 0.03 and 0 with a mean of 0.005
+        let stddev = (0.0013f64 / 3.0).sqrt();
+        assert_approx_eq!(stddev * 2.0, stats.tracking_error);
+        assert_approx_eq!(0.005 * 4.0 / (stddev * 2.0), stats.information_ratio);
+        // Far from 0 the deviations are still accurate
+        let shift = |xs: &[f64]| xs.iter().map(|x| x + 1e9).collect::<Vec<_>>();
+        let shifted = BenchmarkStats::of(&shift(&returns), &shift(&benchmark), 4.0).unwrap();
+        assert_approx_eq!(2.0, shifted.beta, 1e-6);
+        assert_approx_eq!(1.0, shifted.correlation, 1e-6);
+        assert!(BenchmarkStats::of(&returns, &[&benchmark[..], &[1.5]].concat(), 4.0).is_err());
+        assert!(BenchmarkStats::of(&returns[..1], &benchmark[..1], 4.0).unwrap().beta.is_nan());
+    }
+
+    #[test]
+    fn hurst_of_known_processes() {
+        let mut rng = SeededRng::new(RngKind::Pcg, Some(3));
+        let noise: Vec<f64> = (0..1 << 16).map(|_| StandardNormal.sample(&mut rng)).collect();
+        let returns = |logs: &[f64]| logs.iter().map(|x| (0.01 * x).exp()).collect::<Vec<_>>();
+        assert_approx_eq!(0.5, hurst(&returns(&noise)), 0.05);
+        // A random walk as the returns themselves: DFA measures 1.5 for
+        // Brownian motion, and 0 for the differences of white noise
+        let walk: Vec<f64> = noise
+            .iter()
+            .scan(0.0, |sum, x| {
+                *sum += x;
+                Some(*sum)
+            })
+            .collect();
+        assert_approx_eq!(1.5, hurst(&returns(&walk)), 0.1);
+        let differences: Vec<f64> = noise.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(hurst(&returns(&differences)) < 0.2);
+        assert!(hurst(&returns(&noise[..63])).is_nan());
+        assert!(!hurst(&returns(&noise[..64])).is_nan());
+    }
+}
@@ -0,0 +1,150 @@
+//! Custom rebalancing and leverage rules, for strategies the leverage flags
+//! can't express, e.g. deleveraging after a drawdown
+
+/// What a strategy knows before the return of a tick
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TickState {
+    /// 0 for the first return
+    pub tick: usize,
+    /// The value before this tick's return, the start value at tick 0
+    pub value: f64,
+    /// The return of the previous tick, `None` at tick 0
+    pub last_return: Option<f64>,
+}
+
+/// How the value is invested over the next tick
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Allocation {
+    /// Exposure as a multiple of the value, 1 for unleveraged and 0 for cash
+    pub leverage: f64,
+}
+
+/// Decides the allocation for every tick. The value then changes by the
+/// leveraged return, like with `--pointwise-leverage`, and can't go below 0.
+pub trait Strategy {
+    fn on_tick(&mut self, state: &TickState) -> Allocation;
+}
+
+/// The same leverage every tick, the same as `--pointwise-leverage`
+#[derive(Clone, Copy, Debug)]
+pub struct ConstantLeverage(pub f64);
+
+impl Strategy for ConstantLeverage {
+    fn on_tick(&mut self, _: &TickState) -> Allocation {
+        Allocation { leverage: self.0 }
+    }
+}
+
+impl<S: Strategy + ?Sized> Strategy for &mut S {
+    fn on_tick(&mut self, state: &TickState) -> Allocation {
+        (**self).on_tick(state)
+    }
+}
+
+/// Iterator over values accumulated by a strategy, see `accumulate_with`
+pub struct WithStrategy<I, S> {
+    returns: I,
+    strategy: S,
+    state: TickState,
+}
+
+impl<I: Iterator<Item = f64>, S: Strategy> Iterator for WithStrategy<I, S> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let r = self.returns.next()?;
+        let Allocation { leverage } = self.strategy.on_tick(&self.state);
+        self.state.value *= (1.0 + ((r - 1.0) * leverage)).max(0.0);
+        self.state.tick += 1;
+        self.state.last_return = Some(r);
+        Some(self.state.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.returns.size_hint()
+    }
+}
+
+/// Accumulates the returns from `start_value`, asking `strategy` for the
+/// allocation before every return
+pub fn accumulate_with<I: Iterator<Item = f64>, S: Strategy>(
+    returns: I,
+    start_value: f64,
+    strategy: S,
+) -> WithStrategy<I, S> {
+    WithStrategy {
+        returns,
+        strategy,
+        state: TickState {
+            tick: 0,
+            value: start_value,
+            last_return: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Allocation, ConstantLeverage, Strategy, TickState, accumulate_with};
+    use crate::returns::{AccumulateArgs, GenReturnsArgs, RngKind, TimeBase, accumulate, gen_returns};
+
+    #[test]
+    fn constant_leverage_matches_pointwise_leverage() {
+        let args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(86400),
+            num_points: 1000,
+            yearly_mean: 1.07,
+            yearly_stddev: 1.3,
+            seed: Some(5),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let acc_args = AccumulateArgs {
+            accumulate: true,
+            start_value: 100.0,
+            continuous_leverage: None,
+            pointwise_leverage: Some(3.0),
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        let pointwise = accumulate(gen_returns(&args).unwrap(), &acc_args);
+        let strategy: Vec<f64> = accumulate_with(gen_returns(&args).unwrap(), 100.0, ConstantLeverage(3.0)).collect();
+        assert_eq!(pointwise, strategy);
+    }
+
+    /// Unleveraged after a loss, twice leveraged after a gain
+    struct Momentum {
+        seen: Vec<TickState>,
+    }
+
+    impl Strategy for Momentum {
+        fn on_tick(&mut self, state: &TickState) -> Allocation {
+            self.seen.push(*state);
+            let leverage = match state.last_return {
+                Some(r) if r > 1.0 => 2.0,
+                _ => 1.0,
+            };
+            Allocation { leverage }
+        }
+    }
+
+    #[test]
+    fn strategy_sees_every_tick() {
+        let mut momentum = Momentum { seen: Vec::new() };
+        let values: Vec<f64> = accumulate_with([1.5, 0.5, 1.5].into_iter(), 10.0, &mut momentum).collect();
+        assert_eq!(vec![15.0, 0.0, 0.0], values);
+        assert_eq!(
+            TickState {
+                tick: 1,
+                value: 15.0,
+                last_return: Some(1.5)
+            },
+            momentum.seen[1]
+        );
+        assert_eq!(3, momentum.seen.len());
+    }
+}
@@ -0,0 +1,71 @@
+//! Simulations as `futures::Stream`s, for async services consuming long
+//! series tick by tick. The stream only generates a point when polled, so a
+//! slow consumer holds the simulation back instead of a buffer growing.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+/// Items produced before the stream yields to the executor, so that a long
+/// simulation doesn't starve other tasks on the same thread
+const BUDGET: usize = 1024;
+
+/// A stream over the items of an iterator, see `stream`
+pub struct IterStream<I> {
+    iter: I,
+    budget: usize,
+}
+
+impl<I: Iterator + Unpin> Stream for IterStream<I> {
+    type Item = I::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<I::Item>> {
+        if self.budget == 0 {
+            self.budget = BUDGET;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        self.budget -= 1;
+        Poll::Ready(self.iter.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Streams the items of `iter`, e.g. `gen_returns` or `data_points`
+pub fn stream<I: IntoIterator>(iter: I) -> IterStream<I::IntoIter> {
+    IterStream {
+        iter: iter.into_iter(),
+        budget: BUDGET,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    use futures_core::Stream;
+
+    use super::{BUDGET, stream};
+
+    #[test]
+    fn stream_yields_items_and_to_the_executor() {
+        let mut s = pin!(stream(0..BUDGET + 2));
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut items = Vec::new();
+        let mut pending = 0;
+        loop {
+            match s.as_mut().poll_next(&mut cx) {
+                Poll::Ready(Some(i)) => items.push(i),
+                Poll::Ready(None) => break,
+                Poll::Pending => pending += 1,
+            }
+        }
+        assert_eq!((0..BUDGET + 2).collect::<Vec<_>>(), items);
+        assert_eq!(1, pending);
+    }
+}
@@ -0,0 +1,184 @@
+//! Extreme value theory for the worst ticks: the losses beyond a high
+//! threshold are fitted with a generalized Pareto distribution (GPD) by
+//! probability-weighted moments (Hosking & Wallis, 1987), and the fit
+//! extrapolates to losses rarer than the series has, like the tick loss
+//! exceeded once in 100 years. A loss is 1 minus the return, so that 0.1 is
+//! a fall of 10% over a tick. The shape of the fit is the tail index: 0 for
+//! tails that fall off exponentially like those of the lognormal, positive
+//! for fat tails falling off like a power and negative for bounded ones.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::model::{ReturnModel, path_seed};
+use crate::returns::GenReturnsArgs;
+use crate::stats::quantile;
+
+/// Fewest losses beyond the threshold a fit takes
+pub const MIN_EXCEEDANCES: usize = 10;
+
+/// The share of the worst ticks to fit and the periods to extrapolate to
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct TailArgs {
+    /// Share of the ticks with the largest losses to fit, e.g. 0.05 for 5%
+    pub tail: f64,
+    /// Years between the losses of the return levels, e.g. 100 for the tick
+    /// loss exceeded once in 100 years
+    pub return_periods: Vec<f64>,
+}
+
+/// The tick loss exceeded once every `years` on average
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct ReturnLevel {
+    pub years: f64,
+    pub loss: f64,
+}
+
+/// A GPD fitted to the losses beyond `threshold`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TailReport {
+    /// Ticks fitted over, of all paths
+    pub observations: usize,
+    /// Ticks with a loss beyond the threshold
+    pub exceedances: usize,
+    pub threshold: f64,
+    /// The shape, or tail index, of the GPD
+    pub shape: f64,
+    /// The scale of the GPD, in the unit of the losses
+    pub scale: f64,
+    pub return_levels: Vec<ReturnLevel>,
+}
+
+impl TailArgs {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.tail > 0.0 && self.tail < 1.0) {
+            return Err(Error::Invalid(format!("--tail must be above 0% and below 100%, got {}%", self.tail * 100.0)));
+        }
+        if let Some(years) = self.return_periods.iter().find(|&&years| !(years > 0.0 && years.is_finite())) {
+            return Err(Error::Invalid(format!("--return-periods must be positive, got {}", years)));
+        }
+        Ok(())
+    }
+
+    /// Fits the worst `tail` of `returns`, with `ticks_per_year` ticks a year
+    pub fn report(&self, returns: &[f64], ticks_per_year: f64) -> Result<TailReport> {
+        let losses: Vec<f64> = returns.iter().map(|r| 1.0 - r).collect();
+        let threshold = quantile(&losses, 1.0 - self.tail);
+        let excesses: Vec<f64> = losses.iter().filter(|&&loss| loss > threshold).map(|loss| loss - threshold).collect();
+        if excesses.len() < MIN_EXCEEDANCES {
+            return Err(Error::Invalid(format!(
+                "the worst {}% of {} ticks has {} losses beyond the threshold, at least {} are needed",
+                self.tail * 100.0,
+                losses.len(),
+                excesses.len(),
+                MIN_EXCEEDANCES
+            )));
+        }
+        let (shape, scale) = fit_gpd(&excesses);
+        let rate = excesses.len() as f64 / losses.len() as f64;
+        let return_levels = self
+            .return_periods
+            .iter()
+            .map(|&years| {
+                let ticks = years * ticks_per_year;
+                // Losses more frequent than the threshold's are in the data
+                let loss = if ticks * rate <= 1.0 {
+                    quantile(&losses, 1.0 - 1.0 / ticks.max(1.0))
+                } else if shape.abs() < 1e-9 {
+                    threshold + scale * (ticks * rate).ln()
+                } else {
+                    threshold + scale / shape * ((ticks * rate).powf(shape) - 1.0)
+                };
+                // No tick loses more than everything
+                ReturnLevel { years, loss: loss.min(1.0) }
+            })
+            .collect();
+        Ok(TailReport { observations: losses.len(), exceedances: excesses.len(), threshold, shape, scale, return_levels })
+    }
+
+    /// `report` on the returns of `paths` paths of `model` together, with the
+    /// interval, number of points and time base of `gen_args` and path `i`
+    /// seeded with `path_seed(seed, i)`
+    pub fn simulate_paths(
+        &self,
+        model: &dyn ReturnModel,
+        gen_args: &GenReturnsArgs,
+        paths: usize,
+        seed: Option<u64>,
+    ) -> Result<TailReport> {
+        let interval_seconds = gen_args.tick_interval_seconds();
+        let mut returns = Vec::with_capacity(paths * gen_args.num_points);
+        for i in 0..paths {
+            returns.extend(model.returns(interval_seconds, gen_args.num_points, seed.map(|s| path_seed(s, i)))?);
+        }
+        self.report(&returns, gen_args.time_base.ticks_per_year(gen_args.tick_interval()))
+    }
+}
+
+/// The (shape, scale) of a GPD fitted to positive `excesses` by
+/// probability-weighted moments, with the plotting positions (j - 0.35) / n
+/// of Hosking & Wallis. The moments only exist for shapes below 1.
+pub fn fit_gpd(excesses: &[f64]) -> (f64, f64) {
+    let mut sorted = excesses.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let n = sorted.len() as f64;
+    let a0 = sorted.iter().sum::<f64>() / n;
+    let a1 = sorted.iter().enumerate().map(|(j, y)| (1.0 - (j as f64 + 0.65) / n) * y).sum::<f64>() / n;
+    let shape = 2.0 - a0 / (a0 - 2.0 * a1);
+    let scale = 2.0 * a0 * a1 / (a0 - 2.0 * a1);
+    (shape, scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{TailArgs, fit_gpd};
+    use crate::returns::{RngKind, SeededRng};
+
+    #[test]
+    fn fit_gpd_quantiles() {
+        // The quantiles of GPDs of a known shape and scale fit them closely
+        let n = 10_000;
+        let quantiles = |shape: f64, scale: f64| {
+            (0..n)
+                .map(|i| {
+                    let p = (i as f64 + 0.5) / n as f64;
+                    if shape == 0.0 {
+                        -scale * (1.0 - p).ln()
+                    } else {
+                        scale / shape * ((1.0 - p).powf(-shape) - 1.0)
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        for (shape, scale) in [(0.0, 1.0), (0.25, 0.02), (-0.2, 0.5)] {
+            let (fitted_shape, fitted_scale) = fit_gpd(&quantiles(shape, scale));
+            assert_approx_eq!(shape, fitted_shape, 0.01);
+            assert_approx_eq!(scale, fitted_scale, scale * 0.01);
+        }
+    }
+
+    #[test]
+    fn tail_report() {
+        use rand_distr::{Distribution, StudentT};
+
+        let args = TailArgs { tail: 0.05, return_periods: vec![0.001, 1.0, 100.0] };
+        let mut rng = SeededRng::new(RngKind::Pcg, Some(7));
+        let t = StudentT::new(3.0).unwrap();
+        let returns: Vec<f64> = (0..100_000).map(|_| 1.0 + 0.01 * t.sample(&mut rng)).collect();
+        let report = args.report(&returns, 252.0).unwrap();
+        assert_eq!(100_000, report.observations);
+        assert_eq!(5000, report.exceedances);
+        // Student's t with 3 degrees of freedom has a tail index of 1/3
+        assert!((0.2..0.45).contains(&report.shape), "{:?}", report);
+        let levels: Vec<f64> = report.return_levels.iter().map(|level| level.loss).collect();
+        // Once in 0.252 ticks is every tick, the largest gain
+        assert_eq!(levels[0], 1.0 - returns.iter().copied().fold(f64::MIN, f64::max));
+        assert!(report.threshold < levels[1] && levels[1] < levels[2], "{:?}", levels);
+
+        assert!(args.report(&returns[..100], 252.0).is_err());
+        assert!(TailArgs { tail: 0.0, ..args.clone() }.validate().is_err());
+        assert!(TailArgs { return_periods: vec![-1.0], ..args }.validate().is_err());
+    }
+}
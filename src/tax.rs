@@ -0,0 +1,181 @@
+//! Taxes on realized gains, unlike `ReturnsExt::taxed` which taxes every
+//! tick's gain: a taxable position pays tax on its gain over the cost basis
+//! when it's sold at the end of the horizon. Tax-loss harvesting sells it at a
+//! loss at the end of a year instead, buys an equivalent investment with the
+//! proceeds right away and reinvests the tax the realized loss saves. The
+//! wash-sale rule is simplified to the replacement having the same returns
+//! without counting as the same security, and losses offset other gains at
+//! the same rate in full, including a loss left at the end.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::model::{ReturnModel, path_seed};
+use crate::returns::GenReturnsArgs;
+use crate::stats::{Moments, percent, quantile};
+
+/// The tax on gains and when harvesting a loss is worth it
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub struct HarvestArgs {
+    /// Tax on realized gains, and saved on realized losses, e.g. 0.3 for 30%
+    pub tax_rate: f64,
+    /// Smallest loss harvested, as a share of the cost basis, e.g. 0.05 for
+    /// 5%
+    pub threshold: f64,
+}
+
+/// After-tax value at the end of a path of 1 invested at t=0, with and
+/// without harvesting
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct HarvestOutcome {
+    pub harvested: f64,
+    pub held: f64,
+    /// Losses harvested
+    pub harvests: usize,
+}
+
+/// The benefit of harvesting over many paths, as the after-tax value with
+/// harvesting over the one without, minus 1
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct HarvestReport {
+    pub paths: usize,
+    pub harvested_median: f64,
+    pub held_median: f64,
+    pub benefit_mean: f64,
+    pub benefit_median: f64,
+    pub benefit_p10: f64,
+    pub benefit_p90: f64,
+    /// Paths on which harvesting ended ahead
+    pub helped: usize,
+    pub harvests_median: f64,
+}
+
+impl HarvestArgs {
+    pub fn validate(&self) -> Result<()> {
+        if !(0.0..1.0).contains(&self.tax_rate) {
+            return Err(Error::Invalid(format!("--tax-rate must be at least 0% and below 100%, got {}%", self.tax_rate * 100.0)));
+        }
+        if !(0.0..1.0).contains(&self.threshold) {
+            return Err(Error::Invalid(format!(
+                "--harvest-threshold must be at least 0% and below 100%, got {}%",
+                self.threshold * 100.0
+            )));
+        }
+        Ok(())
+    }
+
+    /// Both strategies over one path of returns, with `ticks_per_year` ticks
+    /// a year. A year ends after the tick that crosses a whole number of
+    /// years.
+    pub fn simulate(&self, returns: impl Iterator<Item = f64>, ticks_per_year: f64) -> HarvestOutcome {
+        let (mut value, mut basis, mut held) = (1.0, 1.0, 1.0);
+        let mut harvests = 0;
+        for (tick, r) in returns.enumerate() {
+            value *= r;
+            held *= r;
+            let year_end = ((tick + 1) as f64 / ticks_per_year).floor() > (tick as f64 / ticks_per_year).floor();
+            if year_end && value < basis * (1.0 - self.threshold) {
+                value += (basis - value) * self.tax_rate;
+                basis = value;
+                harvests += 1;
+            }
+        }
+        let after_tax = |value: f64, basis: f64| value - (value - basis) * self.tax_rate;
+        HarvestOutcome { harvested: after_tax(value, basis), held: after_tax(held, 1.0), harvests }
+    }
+
+    /// `simulate` over `paths` paths of `model`, with the interval, number of
+    /// points and time base of `gen_args` and path `i` seeded with
+    /// `path_seed(seed, i)`
+    pub fn simulate_paths(
+        &self,
+        model: &dyn ReturnModel,
+        gen_args: &GenReturnsArgs,
+        paths: usize,
+        seed: Option<u64>,
+    ) -> Result<Vec<HarvestOutcome>> {
+        let interval_seconds = gen_args.tick_interval_seconds();
+        let ticks_per_year = gen_args.time_base.ticks_per_year(gen_args.tick_interval());
+        (0..paths)
+            .map(|i| {
+                let returns = model.returns(interval_seconds, gen_args.num_points, seed.map(|s| path_seed(s, i)))?;
+                Ok(self.simulate(returns, ticks_per_year))
+            })
+            .collect()
+    }
+}
+
+impl HarvestReport {
+    pub fn of(outcomes: &[HarvestOutcome]) -> HarvestReport {
+        let values = |f: fn(&HarvestOutcome) -> f64| outcomes.iter().map(f).collect::<Vec<_>>();
+        let benefits = values(|o| o.harvested / o.held - 1.0);
+        HarvestReport {
+            paths: outcomes.len(),
+            harvested_median: quantile(&values(|o| o.harvested), 0.5),
+            held_median: quantile(&values(|o| o.held), 0.5),
+            benefit_mean: benefits.iter().copied().collect::<Moments>().mean(),
+            benefit_median: quantile(&benefits, 0.5),
+            benefit_p10: quantile(&benefits, 0.1),
+            benefit_p90: quantile(&benefits, 0.9),
+            helped: outcomes.iter().filter(|o| o.harvested > o.held).count(),
+            harvests_median: quantile(&values(|o| o.harvests as f64), 0.5),
+        }
+    }
+
+    /// The share of paths on which harvesting ended ahead in percent
+    pub fn helped_percent(&self) -> f64 {
+        percent(self.helped, self.paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::{HarvestArgs, HarvestReport};
+    use crate::model::LogNormal;
+    use crate::returns::{GenReturnsArgs, RngKind, TimeBase};
+
+    const ARGS: HarvestArgs = HarvestArgs { tax_rate: 0.3, threshold: 0.05 };
+
+    #[test]
+    fn harvesting_reinvests_the_tax_saved() {
+        // Halving realizes a loss of 0.5, saving 0.15 reinvested at a basis of
+        // 0.65, which then doubles to 1.3 and pays 30% on a gain of 0.65
+        let outcome = ARGS.simulate([0.5, 2.0].into_iter(), 1.0);
+        assert_eq!(1, outcome.harvests);
+        assert_approx_eq!(1.105, outcome.harvested, 1e-12);
+        assert_approx_eq!(1.0, outcome.held, 1e-12);
+
+        // Small losses and losses within a year aren't harvested
+        let outcome = ARGS.simulate([0.97, 1.1].into_iter(), 1.0);
+        assert_eq!(0, outcome.harvests);
+        assert_eq!(outcome.held, outcome.harvested);
+        assert_eq!(0, ARGS.simulate([0.5, 2.0].into_iter(), 12.0).harvests);
+
+        assert!(HarvestArgs { tax_rate: 1.0, ..ARGS }.validate().is_err());
+        assert!(HarvestArgs { threshold: -0.1, ..ARGS }.validate().is_err());
+    }
+
+    #[test]
+    fn harvesting_defers_tax_on_growing_paths() {
+        let gen_args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(2629746),
+            num_points: 240,
+            yearly_mean: 1.07,
+            yearly_stddev: 1.2,
+            seed: Some(3),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let model = LogNormal::from(&gen_args);
+        let outcomes = ARGS.simulate_paths(&model, &gen_args, 200, Some(3)).unwrap();
+        let report = HarvestReport::of(&outcomes);
+        assert_eq!(200, report.paths);
+        assert!(report.harvests_median >= 1.0);
+        assert!(report.benefit_median > 0.0);
+        assert!(report.benefit_p10 <= report.benefit_median && report.benefit_median <= report.benefit_p90);
+    }
+}
@@ -0,0 +1,200 @@
+//! Transforms of a return series as iterator adapters, composable in any
+//! order:
+//!
+//! ```
+//! use finsim::prelude::*;
+//!
+//! let args = GenReturnsArgs {
+//!     total_seconds: None,
+//!     interval_seconds: Some(31556952),
+//!     num_points: 30,
+//!     yearly_mean: 1.07,
+//!     yearly_stddev: 1.15,
+//!     seed: Some(1),
+//!     rng: RngKind::Std,
+//!     time_base: TimeBase::WallClock,
+//!     tail_df: None,
+//! };
+//! let values: Vec<f64> = gen_returns(&args)?
+//!     .leveraged(2.0)
+//!     .with_fee(0.003)
+//!     .taxed(0.3)
+//!     .accumulated(100.0)
+//!     .with_contribution(10.0)
+//!     .collect();
+//! assert_eq!(30, values.len());
+//! # Ok::<(), finsim::Error>(())
+//! ```
+//!
+//! The fee and tax apply per tick, so yearly ticks give yearly fees and
+//! taxes.
+
+/// See `ReturnsExt::leveraged`
+pub struct Leveraged<I> {
+    returns: I,
+    leverage: f64,
+}
+
+impl<I: Iterator<Item = f64>> Iterator for Leveraged<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        self.returns.next().map(|r| (1.0 + ((r - 1.0) * self.leverage)).max(0.0))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.returns.size_hint()
+    }
+}
+
+/// See `ReturnsExt::with_fee`
+pub struct WithFee<I> {
+    returns: I,
+    fee: f64,
+}
+
+impl<I: Iterator<Item = f64>> Iterator for WithFee<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        self.returns.next().map(|r| r * (1.0 - self.fee))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.returns.size_hint()
+    }
+}
+
+/// See `ReturnsExt::taxed`
+pub struct Taxed<I> {
+    returns: I,
+    rate: f64,
+}
+
+impl<I: Iterator<Item = f64>> Iterator for Taxed<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        self.returns.next().map(|r| if r > 1.0 { 1.0 + (r - 1.0) * (1.0 - self.rate) } else { r })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.returns.size_hint()
+    }
+}
+
+/// Values accumulated from returns, see `ReturnsExt::accumulated`
+pub struct Accumulated<I> {
+    returns: I,
+    value: f64,
+    contribution: f64,
+}
+
+impl<I> Accumulated<I> {
+    /// Adds `amount` to the value after every tick's return, a withdrawal if
+    /// negative. The value doesn't go below 0.
+    pub fn with_contribution(mut self, amount: f64) -> Self {
+        self.contribution = amount;
+        self
+    }
+}
+
+impl<I: Iterator<Item = f64>> Iterator for Accumulated<I> {
+    type Item = f64;
+
+    fn next(&mut self) -> Option<f64> {
+        let r = self.returns.next()?;
+        self.value = (self.value * r + self.contribution).max(0.0);
+        Some(self.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.returns.size_hint()
+    }
+}
+
+/// Transforms of any iterator over returns
+pub trait ReturnsExt: Iterator<Item = f64> + Sized {
+    /// Leverages every return, releveraging every tick like
+    /// `--pointwise-leverage`. Returns can't go below 0 (total loss).
+    fn leveraged(self, leverage: f64) -> Leveraged<Self> {
+        Leveraged { returns: self, leverage }
+    }
+
+    /// Deducts a fee from every return, as a fraction of the value per tick,
+    /// e.g. 0.003 for 0.3%
+    fn with_fee(self, fee: f64) -> WithFee<Self> {
+        WithFee { returns: self, fee }
+    }
+
+    /// Taxes the gain of every positive return at `rate`, e.g. 0.3 for 30%.
+    /// Losses aren't deducted.
+    fn taxed(self, rate: f64) -> Taxed<Self> {
+        Taxed { returns: self, rate }
+    }
+
+    /// Accumulates the returns into values starting from `start_value`
+    fn accumulated(self, start_value: f64) -> Accumulated<Self> {
+        Accumulated {
+            returns: self,
+            value: start_value,
+            contribution: 0.0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = f64>> ReturnsExt for I {}
+
+#[cfg(test)]
+mod tests {
+    use assert_approx_eq::assert_approx_eq;
+
+    use super::ReturnsExt;
+    use crate::returns::{AccumulateArgs, GenReturnsArgs, RngKind, TimeBase, accumulate, gen_returns};
+
+    #[test]
+    fn leveraged_matches_pointwise_leverage() {
+        let args = GenReturnsArgs {
+            total_seconds: None,
+            interval_seconds: Some(86400),
+            num_points: 1000,
+            yearly_mean: 1.07,
+            yearly_stddev: 1.3,
+            seed: Some(5),
+            rng: RngKind::Std,
+            time_base: TimeBase::WallClock,
+            tail_df: None,
+        };
+        let acc_args = AccumulateArgs {
+            accumulate: true,
+            start_value: 100.0,
+            continuous_leverage: None,
+            pointwise_leverage: Some(3.0),
+            initial_leverage: None,
+            log_values: false,
+            decimal_places: None,
+            financing: None,
+        };
+        let pointwise = accumulate(gen_returns(&args).unwrap(), &acc_args);
+        let adapted: Vec<f64> = gen_returns(&args).unwrap().leveraged(3.0).accumulated(100.0).collect();
+        assert_eq!(pointwise, adapted);
+    }
+
+    #[test]
+    fn transforms_compose() {
+        let returns = [1.5, 0.5];
+        assert_eq!(vec![2.0, 0.0], returns.into_iter().leveraged(2.0).collect::<Vec<f64>>());
+        assert_eq!(vec![1.35, 0.45], returns.into_iter().with_fee(0.1).collect::<Vec<f64>>());
+        assert_eq!(vec![1.25, 0.5], returns.into_iter().taxed(0.5).collect::<Vec<f64>>());
+
+        let values: Vec<f64> = returns.into_iter().taxed(0.5).accumulated(100.0).with_contribution(10.0).collect();
+        assert_eq!(vec![135.0, 77.5], values);
+        let withdrawn: Vec<f64> = returns.into_iter().accumulated(10.0).with_contribution(-10.0).collect();
+        assert_eq!(vec![5.0, 0.0], withdrawn);
+
+        // Order matters: a fee on the underlying is leveraged too
+        let fee_first = returns.into_iter().with_fee(0.1).leveraged(2.0).next().unwrap();
+        assert_approx_eq!(1.7, fee_first);
+        assert_approx_eq!(1.8, returns.into_iter().leveraged(2.0).with_fee(0.1).next().unwrap());
+    }
+}
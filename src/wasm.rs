@@ -0,0 +1,116 @@
+//! JavaScript bindings, for running simulations client-side in the browser.
+//! Build with `wasm-pack build -- --no-default-features --features wasm`.
+//!
+//! ```js
+//! import init, { Simulation } from "./pkg/finsim.js";
+//!
+//! await init();
+//! const sim = new Simulation();
+//! sim.model(1.07, 1.15);
+//! sim.horizon("30y");
+//! sim.interval("1d");
+//! sim.accumulate(100);
+//! sim.leverage("pointwise", 2);
+//! const values = sim.run(); // Float64Array
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::Error;
+use crate::preset::Preset;
+use crate::returns::parse_whole_seconds;
+use crate::simulation::{self, Leverage, SimulationBuilder};
+
+/// The settings of a simulation, see `SimulationBuilder`. Errors in the
+/// settings are thrown by `run` and `monteCarlo`.
+#[wasm_bindgen]
+#[derive(Default)]
+pub struct Simulation {
+    builder: SimulationBuilder,
+}
+
+#[wasm_bindgen]
+impl Simulation {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Simulation {
+        Simulation::default()
+    }
+
+    /// Yearly (geometric) mean and standard deviation as factors, e.g. 1.07
+    /// and 1.15 for +7% and 15%
+    pub fn model(&mut self, yearly_mean: f64, yearly_stddev: f64) {
+        self.update(|b| b.model(yearly_mean, yearly_stddev));
+    }
+
+    /// The yearly mean and stddev of a built-in preset, e.g. `"us-equities"`
+    pub fn preset(&mut self, name: &str) -> Result<(), JsError> {
+        let preset: Preset = name.parse()?;
+        self.update(|b| b.preset(preset));
+        Ok(())
+    }
+
+    /// Simulation time, in seconds or with a unit like `"30y"`
+    pub fn horizon(&mut self, duration: &str) -> Result<(), JsError> {
+        let seconds = parse_whole_seconds(duration)?;
+        self.update(|b| b.horizon(seconds));
+        Ok(())
+    }
+
+    /// Time between data points, in seconds or with a unit like `"1d"`
+    pub fn interval(&mut self, duration: &str) -> Result<(), JsError> {
+        let seconds = parse_whole_seconds(duration)?;
+        self.update(|b| b.interval(seconds));
+        Ok(())
+    }
+
+    #[wasm_bindgen(js_name = numPoints)]
+    pub fn num_points(&mut self, num_points: usize) {
+        self.update(|b| b.num_points(num_points));
+    }
+
+    /// Seed for reproducible results, random if not set
+    pub fn seed(&mut self, seed: u64) {
+        self.update(|b| b.seed(seed));
+    }
+
+    /// Accumulates the returns into values, starting from `start_value`
+    pub fn accumulate(&mut self, start_value: f64) {
+        self.update(|b| b.accumulate(start_value));
+    }
+
+    /// Leverage of the accumulated values, `kind` being `"continuous"`,
+    /// `"pointwise"` or `"initial"`
+    pub fn leverage(&mut self, kind: &str, leverage: f64) -> Result<(), JsError> {
+        let leverage = match kind {
+            "continuous" => Leverage::Continuous(leverage),
+            "pointwise" => Leverage::Pointwise(leverage),
+            "initial" => Leverage::Initial(leverage),
+            _ => return Err(Error::Parse(format!("unknown leverage '{}', expected continuous, pointwise or initial", kind)).into()),
+        };
+        self.update(|b| b.leverage(leverage));
+        Ok(())
+    }
+
+    /// The generated returns, accumulated if requested
+    pub fn run(&self) -> Result<Vec<f64>, JsError> {
+        Ok(self.build()?.run()?)
+    }
+
+    /// The final value of each of `paths` simulated paths, each with its own
+    /// seed derived from the seed like in `finsim mc`
+    #[wasm_bindgen(js_name = monteCarlo)]
+    pub fn monte_carlo(&self, paths: usize) -> Result<Vec<f64>, JsError> {
+        let summaries = self.build()?.monte_carlo(paths)?;
+        Ok(summaries.iter().map(|s| s.last).collect())
+    }
+}
+
+impl Simulation {
+    fn update(&mut self, f: impl FnOnce(SimulationBuilder) -> SimulationBuilder) {
+        self.builder = f(std::mem::take(&mut self.builder));
+    }
+
+    fn build(&self) -> crate::error::Result<simulation::Simulation> {
+        self.builder.clone().build()
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the watched files are checked for changes
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Modification time of each file, `None` for files that can't be read (e.g.
+/// while an editor replaces them)
+pub fn mtimes(paths: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    paths.iter().map(|p| fs::metadata(p).and_then(|m| m.modified()).ok()).collect()
+}
+
+/// Runs `run`, then again every time one of `paths` is modified, forever.
+/// The files are polled, so this works the same on every platform and for
+/// editors that save by replacing the file.
+pub fn watch(paths: &[PathBuf], mut run: impl FnMut(Option<&PathBuf>)) -> ! {
+    let mut seen = mtimes(paths);
+    run(None);
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let current = mtimes(paths);
+        // Wait for a missing file to reappear rather than running without it
+        if current.iter().any(Option::is_none) {
+            continue;
+        }
+        if let Some(i) = current.iter().zip(&seen).position(|(now, before)| now != before) {
+            seen = current;
+            run(Some(&paths[i]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::time::{Duration, SystemTime};
+
+    use super::mtimes;
+
+    #[test]
+    fn mtimes_test() {
+        let path = std::env::temp_dir().join(format!("finsim-watch-test-{}.toml", std::process::id()));
+        let missing = path.with_extension("missing");
+        std::fs::write(&path, "seed = 1\n").unwrap();
+        let before = mtimes(&[path.clone(), missing.clone()]);
+        assert!(before[0].is_some());
+        assert_eq!(None, before[1]);
+
+        let later = SystemTime::now() + Duration::from_secs(10);
+        File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        assert_eq!(vec![Some(later)], mtimes(std::slice::from_ref(&path)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+//! A minimal WebSocket server (RFC 6455) for the text output, so that
+//! finsim can feed trading software under test like a market-data feed. It
+//! waits for one client and sends it every line of output as a text message.
+//! Messages from the client are ignored, and a client silent for
+//! `metrics::TIMEOUT` during the handshake, or not taking a message for as
+//! long, is dropped. The SHA-1 and base64 of the opening
+//! handshake are implemented here rather than pulled in as dependencies.
+
+use std::io::{self, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+use tracing::{info, warn};
+
+use crate::error::{Error, Result};
+use crate::metrics::{self, TIMEOUT};
+
+/// Appended to the client's key before hashing it into the accept key
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// A connected client, written to line by line
+pub struct WebSocket {
+    stream: TcpStream,
+    /// Output after the last complete line
+    line: Vec<u8>,
+}
+
+/// Listens on `addr`, e.g. `127.0.0.1:9000`, until a client completes the
+/// opening handshake. Connections that aren't WebSocket handshakes are
+/// answered with 400 and dropped.
+pub fn accept(addr: &str) -> Result<WebSocket> {
+    let listener = TcpListener::bind(addr)?;
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        match handshake(&mut stream) {
+            Ok(()) => {
+                info!(peer = ?stream.peer_addr().ok(), "WebSocket client connected");
+                return Ok(WebSocket { stream, line: Vec::new() });
+            }
+            Err(e) => {
+                warn!(error = %e, "rejected a connection");
+                let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            }
+        }
+    }
+    Err(Error::Invalid(format!("stopped listening on {}", addr)))
+}
+
+/// Reads the client's opening handshake, of at most `metrics::MAX_HEAD`
+/// bytes, and accepts it
+fn handshake(stream: &mut TcpStream) -> Result<()> {
+    let mut key = None;
+    for line in metrics::read_head(&mut BufReader::new(&*stream))? {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let key = key.ok_or_else(|| Error::Parse("not a WebSocket handshake, Sec-WebSocket-Key is missing".to_string()))?;
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    );
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
+
+/// The Sec-WebSocket-Accept of a Sec-WebSocket-Key
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, GUID).as_bytes()))
+}
+
+/// An unmasked text frame, as servers send them
+fn text_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x81];
+    match payload.len() {
+        n if n < 126 => frame.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(127);
+            frame.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+impl Write for WebSocket {
+    /// Buffers the output and sends every complete line, without its line
+    /// break, as a message
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        while let Some(end) = self.line.iter().position(|&b| b == b'\n') {
+            self.stream.write_all(&text_frame(&self.line[..end]))?;
+            self.line.drain(..=end);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Drop for WebSocket {
+    /// Sends what's left of the output and closes the connection
+    fn drop(&mut self) {
+        if !self.line.is_empty() {
+            let _ = self.stream.write_all(&text_frame(&self.line));
+        }
+        let _ = self.stream.write_all(&[0x88, 0]);
+    }
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (w, word) in w.iter_mut().zip(block.chunks(4)) {
+            *w = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(w);
+            (a, b, c, d, e) = (t, a, b.rotate_left(30), c, d);
+        }
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, h) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            encoded.push(if i <= chunk.len() { ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char } else { '=' });
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accept_key, base64, sha1, text_frame};
+
+    #[test]
+    fn handshake_hashes() {
+        let hex = |data: &[u8]| sha1(data).iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!("a9993e364706816aba3e25717850c26c9cd0d89d", hex(b"abc"));
+        assert_eq!("e61cfffe0d9195a525fc6cf06ca2d77119c24a40", hex(&[b'a'; 200]));
+        assert_eq!("Zm9vYmE=", base64(b"fooba"));
+        assert_eq!("Zm9vYmFy", base64(b"foobar"));
+        // The example of RFC 6455
+        assert_eq!("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=", accept_key("dGhlIHNhbXBsZSBub25jZQ=="));
+    }
+
+    #[test]
+    fn text_frame_lengths() {
+        assert_eq!(vec![0x81, 3, b'1', b'.', b'5'], text_frame(b"1.5"));
+        assert_eq!([0x81, 126, 1, 44], text_frame(&[b'x'; 300])[..4]);
+        assert_eq!([0x81, 127, 0, 0, 0, 0, 0, 1, 0, 0], text_frame(&[b'x'; 65536])[..10]);
+    }
+}